@@ -0,0 +1,16 @@
+// embeds the time of the build, reported by `GET /api/v2/version`.
+// SOURCE_DATE_EPOCH takes precedence for reproducible builds, see https://reproducible-builds.org/specs/source-date-epoch/
+fn main() {
+    let timestamp = std::env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|epoch| epoch.trim().parse::<i64>().ok())
+        .and_then(|secs| chrono::DateTime::from_timestamp(secs, 0))
+        .unwrap_or_else(chrono::Utc::now);
+    println!(
+        "cargo:rustc-env=LTZF_BUILD_TIMESTAMP={}",
+        timestamp.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+}
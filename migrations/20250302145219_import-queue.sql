@@ -0,0 +1,20 @@
+-- two-phase bulk import: a job is opened, filled with chunks of vorgang objects and then committed.
+-- after commit a background task works through the queue and records the outcome per entry.
+CREATE TABLE import_job(
+    id SERIAL PRIMARY KEY,
+    api_id UUID NOT NULL UNIQUE,
+    scraper UUID NOT NULL,
+    created_by INTEGER NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    committed_at TIMESTAMP WITH TIME ZONE DEFAULT NULL, -- NULL means the job still accepts chunks
+    finished_at TIMESTAMP WITH TIME ZONE DEFAULT NULL
+);
+
+CREATE TABLE import_queue(
+    id SERIAL PRIMARY KEY,
+    job_id INTEGER NOT NULL REFERENCES import_job(id) ON DELETE CASCADE,
+    payload JSONB NOT NULL,
+    processed BOOL NOT NULL DEFAULT false,
+    error VARCHAR DEFAULT NULL -- NULL means no error occurred (yet)
+);
+CREATE INDEX import_queue_job_idx ON import_queue(job_id, processed);
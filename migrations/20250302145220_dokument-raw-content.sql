@@ -0,0 +1,7 @@
+-- archive of document content that was submitted inline as base64 encoded pdf or html
+CREATE TABLE dokument_raw_content(
+    dok_id INTEGER PRIMARY KEY REFERENCES dokument(id) ON DELETE CASCADE,
+    mime VARCHAR NOT NULL,
+    content BYTEA NOT NULL,
+    time_stamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
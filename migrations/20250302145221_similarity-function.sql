@@ -0,0 +1,15 @@
+-- configurable similarity measure for near-duplicate detection.
+-- method is one of 'trgm', 'metaphone' or 'levenshtein', all results are normalised to [0, 1].
+-- levenshtein is limited to 255 characters, longer strings are compared by trigrams instead.
+CREATE EXTENSION IF NOT EXISTS fuzzystrmatch;
+
+CREATE OR REPLACE FUNCTION ltzf_similarity(a TEXT, b TEXT, method TEXT)
+RETURNS REAL AS $$
+    SELECT (CASE method
+        WHEN 'metaphone' THEN DIFFERENCE(metaphone(a, 4), metaphone(b, 4)) / 4.0
+        WHEN 'levenshtein' THEN (CASE WHEN GREATEST(length(a), length(b)) <= 255
+            THEN 1.0 - levenshtein(a, b)::real / GREATEST(length(a), length(b), 1)
+            ELSE SIMILARITY(a, b) END)
+        ELSE SIMILARITY(a, b)
+    END)::real
+$$ LANGUAGE SQL IMMUTABLE STRICT;
@@ -0,0 +1,16 @@
+-- votes held during a station, with results per fraktion
+CREATE TABLE abstimmung(
+    id SERIAL PRIMARY KEY,
+    stat_id INTEGER NOT NULL REFERENCES station(id) ON DELETE CASCADE,
+    typ VARCHAR NOT NULL,
+    time_stamp TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE abstimmungsergebnis(
+    abst_id INTEGER NOT NULL REFERENCES abstimmung(id) ON DELETE CASCADE,
+    fraktion VARCHAR NOT NULL,
+    ja INTEGER NOT NULL CHECK (ja >= 0),
+    nein INTEGER NOT NULL CHECK (nein >= 0),
+    enthaltung INTEGER NOT NULL CHECK (enthaltung >= 0),
+    PRIMARY KEY (abst_id, fraktion)
+);
@@ -0,0 +1,24 @@
+-- real-world boundaries of legislative periods per parliament. ende IS NULL means the period is ongoing.
+CREATE TABLE wahlperiode_boundaries(
+    parl INTEGER NOT NULL REFERENCES parlament(id) ON DELETE CASCADE,
+    wp INTEGER NOT NULL,
+    beginn DATE NOT NULL,
+    ende DATE DEFAULT NULL,
+    PRIMARY KEY (parl, wp),
+    CHECK (ende IS NULL OR beginn <= ende)
+);
+
+INSERT INTO wahlperiode_boundaries(parl, wp, beginn, ende)
+SELECT p.id, iv.wp, iv.beginn::date, iv.ende::date FROM (VALUES
+    ('BT', 18, '2013-10-22', '2017-10-24'),
+    ('BT', 19, '2017-10-24', '2021-10-26'),
+    ('BT', 20, '2021-10-26', '2025-03-25'),
+    ('BT', 21, '2025-03-25', NULL),
+    ('BY', 18, '2018-11-05', '2023-10-30'),
+    ('BY', 19, '2023-10-30', NULL),
+    ('NW', 17, '2017-06-01', '2022-06-01'),
+    ('NW', 18, '2022-06-01', NULL),
+    ('HE', 20, '2019-01-18', '2024-01-18'),
+    ('HE', 21, '2024-01-18', NULL)
+) AS iv(pv, wp, beginn, ende)
+INNER JOIN parlament p ON p.value = iv.pv;
@@ -0,0 +1,8 @@
+-- explicit links between a TOP and the Vorgänge it deals with, in addition to
+-- the links derived from shared documents
+CREATE TABLE rel_top_vorgang(
+    top_id INTEGER NOT NULL REFERENCES top(id) ON DELETE CASCADE,
+    vg_id INTEGER NOT NULL REFERENCES vorgang(id) ON DELETE CASCADE,
+    PRIMARY KEY (top_id, vg_id)
+);
+CREATE INDEX rel_top_vorgang_vg_idx ON rel_top_vorgang(vg_id);
@@ -0,0 +1,107 @@
+-- global generation counter, incremented on every insert, update or delete of a
+-- vorgang, sitzung or dokument. Sync clients remember the last generation
+-- they have seen and only request the changes since.
+-- A sequence instead of a counter row, so concurrent writers do not serialise on it.
+-- Sequence values are handed out before the writing transaction commits, so a reader has to
+-- stay below the generations of transactions that are still running, see mark_generation_in_flight.
+CREATE SEQUENCE data_generation_seq AS INT8 START WITH 1;
+
+ALTER TABLE vorgang
+    ADD COLUMN generation INT8 NOT NULL DEFAULT 0,
+    ADD COLUMN generation_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+ALTER TABLE sitzung
+    ADD COLUMN generation INT8 NOT NULL DEFAULT 0,
+    ADD COLUMN generation_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+ALTER TABLE dokument
+    ADD COLUMN generation INT8 NOT NULL DEFAULT 0,
+    ADD COLUMN generation_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+
+CREATE INDEX vorgang_generation_idx ON vorgang(generation);
+CREATE INDEX sitzung_generation_idx ON sitzung(generation);
+CREATE INDEX dokument_generation_idx ON dokument(generation);
+
+-- deleted objects, so sync clients learn about deletions as well
+CREATE TABLE data_tombstone (
+    api_id UUID PRIMARY KEY,
+    generation INT8 NOT NULL,
+    generation_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
+CREATE INDEX data_tombstone_generation_idx ON data_tombstone(generation);
+
+-- Before a transaction takes its first generation it takes a shared advisory lock
+-- (1280596551, last_value of the sequence), which it holds until it ends.
+-- Every generation of the transaction is larger than the locked value, so readers find the
+-- lowest generation that may still become visible in pg_locks (see api::export::delta_since).
+-- Generations have to stay below 2^31 for the lock key, which is plenty.
+CREATE OR REPLACE FUNCTION mark_generation_in_flight()
+RETURNS VOID
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF current_setting('ltzf.generation_in_flight', true) IS DISTINCT FROM 'on' THEN
+        PERFORM pg_advisory_xact_lock_shared(1280596551, (SELECT last_value FROM data_generation_seq)::INT4);
+        PERFORM set_config('ltzf.generation_in_flight', 'on', true);
+    END IF;
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION bump_generation()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW IS NOT DISTINCT FROM OLD THEN
+        RETURN NEW; -- nothing changed, sync clients do not need to fetch the row again
+    END IF;
+    IF TG_OP = 'INSERT' THEN
+        DELETE FROM data_tombstone WHERE api_id = NEW.api_id;
+    END IF;
+    PERFORM mark_generation_in_flight();
+    NEW.generation = nextval('data_generation_seq');
+    NEW.generation_at = NOW();
+    RETURN NEW; -- BEFORE trigger passes on the modified row
+END;
+$$;
+
+CREATE OR REPLACE FUNCTION record_tombstone()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    PERFORM mark_generation_in_flight();
+    INSERT INTO data_tombstone(api_id, generation)
+    VALUES (OLD.api_id, nextval('data_generation_seq'))
+    ON CONFLICT (api_id) DO UPDATE SET generation = EXCLUDED.generation, generation_at = NOW();
+    RETURN OLD;
+END;
+$$;
+
+CREATE TRIGGER trg_generation_vorgang
+BEFORE INSERT OR UPDATE ON vorgang
+FOR EACH ROW
+EXECUTE PROCEDURE bump_generation();
+
+CREATE TRIGGER trg_generation_sitzung
+BEFORE INSERT OR UPDATE ON sitzung
+FOR EACH ROW
+EXECUTE PROCEDURE bump_generation();
+
+CREATE TRIGGER trg_generation_dokument
+BEFORE INSERT OR UPDATE ON dokument
+FOR EACH ROW
+EXECUTE PROCEDURE bump_generation();
+
+CREATE TRIGGER trg_tombstone_vorgang
+AFTER DELETE ON vorgang
+FOR EACH ROW
+EXECUTE PROCEDURE record_tombstone();
+
+CREATE TRIGGER trg_tombstone_sitzung
+AFTER DELETE ON sitzung
+FOR EACH ROW
+EXECUTE PROCEDURE record_tombstone();
+
+CREATE TRIGGER trg_tombstone_dokument
+AFTER DELETE ON dokument
+FOR EACH ROW
+EXECUTE PROCEDURE record_tombstone();
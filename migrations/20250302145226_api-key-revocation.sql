@@ -0,0 +1,5 @@
+-- point in time a key was deliberately revoked by an administrator.
+-- Rotation and expiry are still tracked by a self-reference in deleted_by only.
+ALTER TABLE api_keys ADD COLUMN revoked_at TIMESTAMP WITH TIME ZONE DEFAULT NULL;
+
+UPDATE api_keys SET revoked_at = NOW() WHERE deleted_by IS NOT NULL AND deleted_by <> id;
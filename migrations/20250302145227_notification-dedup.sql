@@ -0,0 +1,5 @@
+-- remembers recently sent notifications so repeated alerts about the same entities are suppressed
+CREATE TABLE notification_dedup_cache (
+    entity_hash VARCHAR PRIMARY KEY,
+    sent_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW()
+);
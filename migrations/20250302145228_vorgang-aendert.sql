@@ -0,0 +1,9 @@
+-- amendment graph: which Vorgang amends which other Vorgang
+CREATE TABLE rel_vorgang_aendert (
+    amending_vg_id INTEGER NOT NULL REFERENCES vorgang(id) ON DELETE CASCADE,
+    amended_vg_id INTEGER NOT NULL REFERENCES vorgang(id) ON DELETE CASCADE,
+    article_ref VARCHAR,
+    in_force_since DATE,
+    PRIMARY KEY (amending_vg_id, amended_vg_id)
+);
+CREATE INDEX rel_vorgang_aendert_amended_idx ON rel_vorgang_aendert(amended_vg_id);
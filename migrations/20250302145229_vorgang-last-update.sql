@@ -0,0 +1,100 @@
+-- time of the last change to a vorgang, one of its stationen or their dokumente
+ALTER TABLE vorgang
+    ADD COLUMN last_update TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW();
+CREATE INDEX vorgang_last_update_idx ON vorgang(last_update);
+
+-- a merge rewrites the vorgang row even if the data is the same, so only a change
+-- to a column other than the bookkeeping ones moves last_update.
+-- A last_update set explicitly (by the triggers below) is kept.
+CREATE OR REPLACE FUNCTION vorgang_touch_last_update()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' THEN
+        IF NEW.last_update IS DISTINCT FROM OLD.last_update THEN
+            RETURN NEW;
+        END IF;
+        IF (to_jsonb(NEW) - 'last_update' - 'generation' - 'generation_at' - 'created_at')
+            IS NOT DISTINCT FROM (to_jsonb(OLD) - 'last_update' - 'generation' - 'generation_at' - 'created_at') THEN
+            RETURN NEW;
+        END IF;
+    END IF;
+    NEW.last_update = NOW();
+    RETURN NEW; -- BEFORE trigger passes on the modified row
+END;
+$$;
+
+CREATE TRIGGER trg_last_update_vorgang
+BEFORE INSERT OR UPDATE ON vorgang
+FOR EACH ROW
+EXECUTE PROCEDURE vorgang_touch_last_update();
+
+-- stationen are only deleted together with their vorgang, so inserts and updates suffice
+CREATE OR REPLACE FUNCTION station_touch_vorgang()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF TG_OP = 'UPDATE' AND NEW IS NOT DISTINCT FROM OLD THEN
+        RETURN NULL;
+    END IF;
+    UPDATE vorgang SET last_update = NOW() WHERE id = NEW.vg_id;
+    RETURN NULL; -- AFTER trigger should return NULL
+END;
+$$;
+
+CREATE TRIGGER trg_last_update_station
+AFTER INSERT OR UPDATE ON station
+FOR EACH ROW
+EXECUTE PROCEDURE station_touch_vorgang();
+
+-- a dokument or stellungnahme newly attached to a station
+CREATE OR REPLACE FUNCTION station_dokument_touch_vorgang()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    UPDATE vorgang SET last_update = NOW()
+    WHERE id = (SELECT vg_id FROM station WHERE id = NEW.stat_id);
+    RETURN NULL; -- AFTER trigger should return NULL
+END;
+$$;
+
+CREATE TRIGGER trg_last_update_station_dokument
+AFTER INSERT ON rel_station_dokument
+FOR EACH ROW
+EXECUTE PROCEDURE station_dokument_touch_vorgang();
+
+CREATE TRIGGER trg_last_update_station_stln
+AFTER INSERT ON rel_station_stln
+FOR EACH ROW
+EXECUTE PROCEDURE station_dokument_touch_vorgang();
+
+-- a changed dokument touches every vorgang with a station referencing it
+CREATE OR REPLACE FUNCTION dokument_touch_vorgang()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF NEW IS NOT DISTINCT FROM OLD THEN
+        RETURN NULL;
+    END IF;
+    UPDATE vorgang SET last_update = NOW()
+    WHERE id IN (
+        SELECT s.vg_id FROM station s
+        JOIN rel_station_dokument rsd ON rsd.stat_id = s.id
+        WHERE rsd.dok_id = NEW.id
+        UNION
+        SELECT s.vg_id FROM station s
+        JOIN rel_station_stln rss ON rss.stat_id = s.id
+        WHERE rss.dok_id = NEW.id
+    );
+    RETURN NULL; -- AFTER trigger should return NULL
+END;
+$$;
+
+CREATE TRIGGER trg_last_update_dokument
+AFTER UPDATE ON dokument
+FOR EACH ROW
+EXECUTE PROCEDURE dokument_touch_vorgang();
@@ -0,0 +1,16 @@
+-- Vorgänge with ambiguous merge candidates, waiting for a decision by an administrator
+CREATE TABLE review_queue (
+    id UUID PRIMARY KEY,
+    incoming_payload JSONB NOT NULL,
+    candidates JSONB NOT NULL,
+    status VARCHAR NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'merged', 'inserted')),
+    scraper_id UUID NOT NULL,
+    collector_key INTEGER NOT NULL REFERENCES api_keys(id) ON DELETE CASCADE,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    resolved_at TIMESTAMP WITH TIME ZONE,
+    resolved_by INTEGER REFERENCES api_keys(id) ON DELETE SET NULL
+);
+CREATE INDEX review_queue_pending_idx ON review_queue(created_at) WHERE status = 'pending';
+-- a Vorgang submitted again while still pending replaces the queued payload
+CREATE UNIQUE INDEX review_queue_pending_api_id_idx ON review_queue((incoming_payload->>'api_id'))
+    WHERE status = 'pending';
@@ -0,0 +1,9 @@
+-- enumeration values that were added automatically because a scraper submitted them,
+-- see LTZF_QUARANTINE_UNKNOWN_ENUMS. An administrator approves or removes them.
+CREATE TABLE quarantined_enum_values (
+    table_name VARCHAR NOT NULL,
+    value VARCHAR NOT NULL,
+    first_seen TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    seen_count INTEGER NOT NULL DEFAULT 1,
+    PRIMARY KEY (table_name, value)
+);
@@ -0,0 +1,7 @@
+-- parliamentary group of an Autor, e.g. the Fraktion submitting an Antrag
+CREATE TABLE fraktion (
+    id SERIAL PRIMARY KEY,
+    value VARCHAR NOT NULL UNIQUE
+);
+ALTER TABLE autor ADD COLUMN fraktion INTEGER REFERENCES fraktion(id) ON DELETE SET NULL;
+CREATE INDEX autor_fraktion_idx ON autor(fraktion);
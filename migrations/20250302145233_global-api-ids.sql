@@ -0,0 +1,38 @@
+-- api_ids are unique across Vorgang, Sitzung and Dokument, not only within their own table
+CREATE TABLE global_api_ids (
+    api_id UUID PRIMARY KEY,
+    entity_type TEXT NOT NULL
+);
+INSERT INTO global_api_ids(api_id, entity_type)
+SELECT api_id, 'vorgang' FROM vorgang
+UNION ALL SELECT api_id, 'sitzung' FROM sitzung
+UNION ALL SELECT api_id, 'dokument' FROM dokument
+ON CONFLICT DO NOTHING;
+
+CREATE OR REPLACE FUNCTION register_global_api_id() RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO global_api_ids(api_id, entity_type) VALUES (NEW.api_id, TG_TABLE_NAME);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION release_global_api_id() RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM global_api_ids WHERE api_id = OLD.api_id AND entity_type = TG_TABLE_NAME;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vorgang_register_api_id AFTER INSERT ON vorgang
+FOR EACH ROW EXECUTE FUNCTION register_global_api_id();
+CREATE TRIGGER sitzung_register_api_id AFTER INSERT ON sitzung
+FOR EACH ROW EXECUTE FUNCTION register_global_api_id();
+CREATE TRIGGER dokument_register_api_id AFTER INSERT ON dokument
+FOR EACH ROW EXECUTE FUNCTION register_global_api_id();
+
+CREATE TRIGGER vorgang_release_api_id AFTER DELETE ON vorgang
+FOR EACH ROW EXECUTE FUNCTION release_global_api_id();
+CREATE TRIGGER sitzung_release_api_id AFTER DELETE ON sitzung
+FOR EACH ROW EXECUTE FUNCTION release_global_api_id();
+CREATE TRIGGER dokument_release_api_id AFTER DELETE ON dokument
+FOR EACH ROW EXECUTE FUNCTION release_global_api_id();
@@ -0,0 +1,4 @@
+-- the Parlament is stored on the sitzung itself, derived from its Gremium
+ALTER TABLE sitzung ADD COLUMN p_id INTEGER REFERENCES parlament(id);
+UPDATE sitzung s SET p_id = g.parl FROM gremium g WHERE g.id = s.gr_id;
+ALTER TABLE sitzung ALTER COLUMN p_id SET NOT NULL;
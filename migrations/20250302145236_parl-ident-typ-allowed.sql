@@ -0,0 +1,12 @@
+-- which identifier types are meaningful for which parliament. Parliaments without entries are not checked.
+CREATE TABLE parl_ident_typ_allowed(
+    parl INTEGER NOT NULL REFERENCES parlament(id) ON DELETE CASCADE,
+    typ INTEGER NOT NULL REFERENCES vg_ident_typ(id) ON DELETE CASCADE,
+    PRIMARY KEY (parl, typ)
+);
+
+-- Vorgangsnummern are only assigned by the DIP of Bundestag and Bundesrat
+INSERT INTO parl_ident_typ_allowed(parl, typ)
+SELECT p.id, t.id FROM parlament p, vg_ident_typ t
+WHERE t.value IN ('initdrucks', 'api-id', 'sonstig')
+OR (t.value = 'vorgnr' AND p.value IN ('BT', 'BR'));
@@ -0,0 +1,2 @@
+-- optional restriction of a key to the networks its scraper runs in. NULL means any address.
+ALTER TABLE api_keys ADD COLUMN allowed_ips CIDR[] DEFAULT NULL;
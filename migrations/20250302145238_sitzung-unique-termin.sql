@@ -0,0 +1,46 @@
+-- the same physical Sitzung may be submitted by several scrapers under different api_ids.
+
+-- existing duplicates are merged into the oldest Sitzung of their group before adding the constraint
+CREATE TEMPORARY TABLE sitzung_dup ON COMMIT DROP AS
+SELECT id AS dup, keep FROM (
+    SELECT id, MIN(id) OVER (PARTITION BY gr_id, termin, nummer) AS keep
+    FROM sitzung
+) s WHERE id <> keep;
+
+-- TOPs with a counterpart of the same nummer hand over their Dokumente and Vorgänge,
+-- the others move to the kept Sitzung
+CREATE TEMPORARY TABLE top_dup ON COMMIT DROP AS
+SELECT t.id AS dup, MIN(k.id) AS keep
+FROM top t
+INNER JOIN sitzung_dup d ON d.dup = t.sid
+INNER JOIN top k ON k.sid = d.keep AND k.nummer = t.nummer
+GROUP BY t.id;
+
+INSERT INTO tops_doks(top_id, dok_id)
+SELECT td.keep, x.dok_id FROM tops_doks x INNER JOIN top_dup td ON td.dup = x.top_id
+ON CONFLICT DO NOTHING;
+INSERT INTO rel_top_vorgang(top_id, vg_id)
+SELECT td.keep, x.vg_id FROM rel_top_vorgang x INNER JOIN top_dup td ON td.dup = x.top_id
+ON CONFLICT DO NOTHING;
+UPDATE top t SET sid = d.keep FROM sitzung_dup d
+WHERE t.sid = d.dup AND NOT EXISTS (SELECT 1 FROM top_dup td WHERE td.dup = t.id);
+
+INSERT INTO rel_sitzung_doks(sid, did)
+SELECT d.keep, r.did FROM rel_sitzung_doks r INNER JOIN sitzung_dup d ON d.dup = r.sid
+ON CONFLICT DO NOTHING;
+INSERT INTO rel_sitzung_experten(sid, eid)
+SELECT d.keep, r.eid FROM rel_sitzung_experten r INNER JOIN sitzung_dup d ON d.dup = r.sid
+ON CONFLICT DO NOTHING;
+
+-- the scraper log keeps the most recent touch per scraper
+INSERT INTO scraper_touched_sitzung(time_stamp, sid, scraper, collector_key)
+SELECT DISTINCT ON (d.keep, s.scraper) s.time_stamp, d.keep, s.scraper, s.collector_key
+FROM scraper_touched_sitzung s INNER JOIN sitzung_dup d ON d.dup = s.sid
+ORDER BY d.keep, s.scraper, s.time_stamp DESC
+ON CONFLICT (sid, scraper) DO UPDATE
+SET time_stamp = GREATEST(scraper_touched_sitzung.time_stamp, EXCLUDED.time_stamp);
+
+-- the remaining rows of the duplicates cascade
+DELETE FROM sitzung WHERE id IN (SELECT dup FROM sitzung_dup);
+
+ALTER TABLE sitzung ADD CONSTRAINT sitzung_gremium_termin_nummer UNIQUE (gr_id, termin, nummer);
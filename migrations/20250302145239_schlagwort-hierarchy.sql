@@ -0,0 +1,4 @@
+-- Schlagworte form a tree, filtering by a Schlagwort includes all of its descendants
+ALTER TABLE schlagwort ADD COLUMN parent_id INTEGER REFERENCES schlagwort(id) ON DELETE SET NULL;
+ALTER TABLE schlagwort ADD CONSTRAINT schlagwort_not_own_parent CHECK (parent_id <> id);
+CREATE INDEX schlagwort_parent_idx ON schlagwort(parent_id);
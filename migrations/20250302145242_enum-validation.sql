@@ -0,0 +1,33 @@
+-- Enumeration values are validated by the database instead of relying on the application.
+-- References are looked up by value, an unknown value yields NULL which is reported as a
+-- check violation naming the column instead of a generic NOT NULL violation.
+CREATE OR REPLACE FUNCTION check_enum_reference() RETURNS TRIGGER AS $$
+BEGIN
+    IF to_jsonb(NEW) ->> TG_ARGV[0] IS NULL THEN
+        RAISE EXCEPTION 'unknown % in %.%', TG_ARGV[1], TG_TABLE_NAME, TG_ARGV[0]
+        USING ERRCODE = 'check_violation',
+            CONSTRAINT = TG_TABLE_NAME || '_' || TG_ARGV[0] || '_known',
+            TABLE = TG_TABLE_NAME,
+            COLUMN = TG_ARGV[0];
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vorgang_typ_known BEFORE INSERT OR UPDATE OF typ ON vorgang
+FOR EACH ROW EXECUTE FUNCTION check_enum_reference('typ', 'vorgangstyp');
+CREATE TRIGGER station_typ_known BEFORE INSERT OR UPDATE OF typ ON station
+FOR EACH ROW EXECUTE FUNCTION check_enum_reference('typ', 'stationstyp');
+CREATE TRIGGER dokument_typ_known BEFORE INSERT OR UPDATE OF typ ON dokument
+FOR EACH ROW EXECUTE FUNCTION check_enum_reference('typ', 'dokumententyp');
+CREATE TRIGGER rel_vorgang_ident_typ_known BEFORE INSERT OR UPDATE OF typ ON rel_vorgang_ident
+FOR EACH ROW EXECUTE FUNCTION check_enum_reference('typ', 'vg_ident_typ');
+CREATE TRIGGER gremium_parl_known BEFORE INSERT OR UPDATE OF parl ON gremium
+FOR EACH ROW EXECUTE FUNCTION check_enum_reference('parl', 'parlament');
+
+-- the values themselves must be usable as enum variants
+ALTER TABLE vorgangstyp ADD CONSTRAINT vorgangstyp_value_valid CHECK (value <> '' AND value = btrim(value));
+ALTER TABLE stationstyp ADD CONSTRAINT stationstyp_value_valid CHECK (value <> '' AND value = btrim(value));
+ALTER TABLE dokumententyp ADD CONSTRAINT dokumententyp_value_valid CHECK (value <> '' AND value = btrim(value));
+ALTER TABLE vg_ident_typ ADD CONSTRAINT vg_ident_typ_value_valid CHECK (value <> '' AND value = btrim(value));
+ALTER TABLE parlament ADD CONSTRAINT parlament_value_valid CHECK (value <> '' AND value = btrim(value));
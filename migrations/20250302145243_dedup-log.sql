@@ -0,0 +1,7 @@
+-- one row per run of the Dokument deduplication job
+CREATE TABLE dedup_log(
+    id SERIAL PRIMARY KEY,
+    run_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT NOW(),
+    groups INTEGER NOT NULL,
+    merged INTEGER NOT NULL
+);
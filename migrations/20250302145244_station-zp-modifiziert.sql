@@ -0,0 +1,23 @@
+-- zp_modifiziert of a station only moves when its data changes, not on every merge.
+-- A timestamp supplied by the scraper is kept, otherwise it is set to NOW() if any
+-- column except the bookkeeping timestamps differs.
+CREATE OR REPLACE FUNCTION station_touch_zp_modifiziert()
+RETURNS TRIGGER
+LANGUAGE plpgsql
+AS $$
+BEGIN
+    IF NEW.zp_modifiziert IS DISTINCT FROM OLD.zp_modifiziert THEN
+        RETURN NEW;
+    END IF;
+    IF (to_jsonb(NEW) - 'zp_modifiziert' - 'created_at')
+        IS DISTINCT FROM (to_jsonb(OLD) - 'zp_modifiziert' - 'created_at') THEN
+        NEW.zp_modifiziert = NOW();
+    END IF;
+    RETURN NEW; -- BEFORE trigger passes on the modified row
+END;
+$$;
+
+CREATE TRIGGER trg_zp_modifiziert_station
+BEFORE UPDATE ON station
+FOR EACH ROW
+EXECUTE PROCEDURE station_touch_zp_modifiziert();
@@ -0,0 +1,24 @@
+-- every change of a Vorgang, Sitzung or Dokument is announced on the `entity_changed` channel,
+-- so all server instances can drop their cached responses for that entity type.
+-- Identical notifications within one transaction are delivered only once by postgres.
+CREATE OR REPLACE FUNCTION notify_entity_changed() RETURNS TRIGGER AS $$
+DECLARE
+    changed_id UUID;
+BEGIN
+    IF TG_OP = 'DELETE' THEN
+        changed_id = OLD.api_id;
+    ELSE
+        changed_id = NEW.api_id;
+    END IF;
+    PERFORM pg_notify('entity_changed',
+        json_build_object('type', TG_TABLE_NAME, 'api_id', changed_id)::text);
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER vorgang_notify_changed AFTER INSERT OR UPDATE OR DELETE ON vorgang
+FOR EACH ROW EXECUTE FUNCTION notify_entity_changed();
+CREATE TRIGGER sitzung_notify_changed AFTER INSERT OR UPDATE OR DELETE ON sitzung
+FOR EACH ROW EXECUTE FUNCTION notify_entity_changed();
+CREATE TRIGGER dokument_notify_changed AFTER INSERT OR UPDATE OR DELETE ON dokument
+FOR EACH ROW EXECUTE FUNCTION notify_entity_changed();
@@ -0,0 +1,23 @@
+-- existing Vorgang links are normalised like utils::links::normalise_url does for new ones:
+-- scheme and host lowercased, default port removed, trailing slashes stripped from the path
+-- (the root path stays `/`) and an empty query string removed.
+-- Only http(s) links without user info are touched, everything else is kept as stored.
+CREATE TEMPORARY TABLE vorgang_link_norm ON COMMIT DROP AS
+SELECT vg_id, link, LOWER(m[1]) || '://'
+    || regexp_replace(LOWER(m[2]), CASE WHEN LOWER(m[1]) = 'https' THEN ':443$' ELSE ':80$' END, '')
+    || CASE WHEN rtrim(m[3], '/') = '' THEN '/' ELSE rtrim(m[3], '/') END
+    || CASE WHEN m[4] = '?' THEN '' ELSE COALESCE(m[4], '') END
+    || COALESCE(m[5], '') AS normalised
+FROM (
+    SELECT vg_id, link,
+    regexp_match(TRIM(link), '^([A-Za-z]+)://([^/?#@]*)([^?#]*)(\?[^#]*)?(#.*)?$') AS m
+    FROM rel_vorgang_links
+) l
+WHERE m IS NOT NULL AND LOWER(m[1]) IN ('http', 'https');
+
+-- links that become equal are stored once
+DELETE FROM rel_vorgang_links r USING vorgang_link_norm n
+WHERE r.vg_id = n.vg_id AND r.link = n.link AND n.link <> n.normalised;
+INSERT INTO rel_vorgang_links(vg_id, link)
+SELECT DISTINCT vg_id, normalised FROM vorgang_link_norm WHERE link <> normalised
+ON CONFLICT DO NOTHING;
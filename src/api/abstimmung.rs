@@ -0,0 +1,418 @@
+//! Votes (Abstimmungen) attached to Stationen.
+//!
+//! - `GET /api/v2/vorgang/{vorgang_id}/abstimmungen` lists all votes of a Vorgang through its Stationen
+//! - `POST /api/v2/station/{station_id}/abstimmung` submits a vote for a Station
+
+use std::collections::BTreeSet;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::error::DataValidationError;
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Abstimmungsergebnis {
+    pub fraktion: String,
+    pub ja: u32,
+    pub nein: u32,
+    pub enthaltung: u32,
+}
+
+impl Abstimmungsergebnis {
+    /// ja, nein and enthaltung as stored in the database
+    fn db_counts(&self) -> Result<(i32, i32, i32)> {
+        let count = |field: &str, value: u32| {
+            i32::try_from(value).map_err(|e| {
+                crate::LTZFError::from(DataValidationError::InvalidFormat {
+                    field: format!("ergebnisse.{field}"),
+                    message: e.to_string(),
+                })
+            })
+        };
+        Ok((
+            count("ja", self.ja)?,
+            count("nein", self.nein)?,
+            count("enthaltung", self.enthaltung)?,
+        ))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Abstimmung {
+    pub abstimmung_id: i32,
+    pub typ: String,
+    pub ergebnisse: Vec<Abstimmungsergebnis>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AbstimmungSubmission {
+    pub typ: String,
+    pub ergebnisse: Vec<Abstimmungsergebnis>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/vorgang/{vorgang_id}/abstimmungen",
+            get(abstimmungen_get),
+        )
+        .route(
+            "/api/v2/station/{station_id}/abstimmung",
+            post(abstimmung_post),
+        )
+        .with_state(server)
+}
+
+/// AbstimmungenGet - GET /api/v2/vorgang/{vorgang_id}/abstimmungen
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn abstimmungen_get(
+    State(server): State<LTZFArc>,
+    Path(vorgang_id): Path<Uuid>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(vg_id) = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let result = abstimmungen_by_vorgang(vg_id, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Successful retrieval");
+    Ok(Json(result).into_response())
+}
+
+/// AbstimmungPost - POST /api/v2/station/{station_id}/abstimmung
+#[instrument(skip_all, fields(stat=%station_id))]
+async fn abstimmung_post(
+    State(server): State<LTZFArc>,
+    Path(station_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<AbstimmungSubmission>,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Collector, APIScope::Admin, APIScope::KeyAdder],
+    )
+    .await?;
+    let fraktionen: BTreeSet<_> = body.ergebnisse.iter().map(|e| &e.fraktion).collect();
+    if fraktionen.len() != body.ergebnisse.len() {
+        info!("Semantically bad request: a fraktion has more than one result");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if body.ergebnisse.iter().any(|e| e.db_counts().is_err()) {
+        info!("Unprocessable request: a result is too large to be stored");
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(stat_id) = sqlx::query!("SELECT id FROM station WHERE api_id = $1", station_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Station was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let abstimmung_id = insert_abstimmung(stat_id, &body, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(target: "obj", "Inserted Abstimmung {} for Station {}", abstimmung_id, station_id);
+    Ok((
+        StatusCode::CREATED,
+        Json(serde_json::json!({ "abstimmung_id": abstimmung_id })),
+    )
+        .into_response())
+}
+
+pub async fn insert_abstimmung(
+    stat_id: i32,
+    abst: &AbstimmungSubmission,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<i32> {
+    let id = sqlx::query!(
+        "INSERT INTO abstimmung(stat_id, typ) VALUES ($1, $2) RETURNING id",
+        stat_id,
+        abst.typ
+    )
+    .map(|r| r.id)
+    .fetch_one(&mut **tx)
+    .await?;
+    insert_ergebnisse(id, &abst.ergebnisse, tx).await?;
+    Ok(id)
+}
+
+async fn insert_ergebnisse(
+    id: i32,
+    ergebnisse: &[Abstimmungsergebnis],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    let (mut fraktion, mut ja, mut nein, mut enthaltung) = (vec![], vec![], vec![], vec![]);
+    for e in ergebnisse.iter() {
+        let (j, n, en) = e.db_counts()?;
+        fraktion.push(e.fraktion.clone());
+        ja.push(j);
+        nein.push(n);
+        enthaltung.push(en);
+    }
+    sqlx::query!(
+        "INSERT INTO abstimmungsergebnis(abst_id, fraktion, ja, nein, enthaltung)
+        SELECT $1, fr, j, n, e FROM UNNEST($2::text[], $3::int4[], $4::int4[], $5::int4[]) as iv(fr, j, n, e)",
+        id,
+        &fraktion[..],
+        &ja[..],
+        &nein[..],
+        &enthaltung[..]
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// an Abstimmung together with its Station, see `abstimmungen_by_station`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationAbstimmung {
+    pub station: Uuid,
+    pub time_stamp: chrono::DateTime<chrono::Utc>,
+    pub abstimmung: Abstimmung,
+}
+
+/// the Abstimmungen of a Vorgang with the api_id of their Station.
+/// Deleting the Stationen deletes their Abstimmungen, so a Vorgang that is replaced keeps them
+/// via this and `restore_abstimmungen`.
+pub async fn abstimmungen_by_station(
+    vg_id: i32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<StationAbstimmung>> {
+    let stationen = sqlx::query!(
+        "SELECT a.id, s.api_id, a.time_stamp FROM abstimmung a
+        INNER JOIN station s ON s.id = a.stat_id
+        WHERE s.vg_id = $1",
+        vg_id
+    )
+    .map(|r| (r.id, (r.api_id, r.time_stamp)))
+    .fetch_all(&mut **tx)
+    .await?;
+    let stationen: std::collections::BTreeMap<_, _> = stationen.into_iter().collect();
+    Ok(abstimmungen_by_vorgang(vg_id, tx)
+        .await?
+        .into_iter()
+        .filter_map(|abstimmung| {
+            let (station, time_stamp) = *stationen.get(&abstimmung.abstimmung_id)?;
+            Some(StationAbstimmung {
+                station,
+                time_stamp,
+                abstimmung,
+            })
+        })
+        .collect())
+}
+
+/// attaches the Abstimmungen to the Stationen with the same api_id again, keeping their ids.
+/// Returns the api_ids of the Stationen that do not exist anymore, whose Abstimmungen are not restored.
+pub async fn restore_abstimmungen(
+    saved: &[StationAbstimmung],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<Uuid>> {
+    let mut missing = vec![];
+    for sa in saved {
+        let restored = sqlx::query!(
+            "INSERT INTO abstimmung(id, stat_id, typ, time_stamp)
+            SELECT $1, id, $2, $3 FROM station WHERE api_id = $4
+            RETURNING id",
+            sa.abstimmung.abstimmung_id,
+            sa.abstimmung.typ,
+            sa.time_stamp,
+            sa.station
+        )
+        .fetch_optional(&mut **tx)
+        .await?;
+        if restored.is_none() {
+            missing.push(sa.station);
+            continue;
+        }
+        insert_ergebnisse(sa.abstimmung.abstimmung_id, &sa.abstimmung.ergebnisse, tx).await?;
+    }
+    Ok(missing)
+}
+
+pub async fn abstimmungen_by_vorgang(
+    vg_id: i32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<Abstimmung>> {
+    let mut abstimmungen = sqlx::query!(
+        "SELECT a.id, a.typ FROM abstimmung a
+        INNER JOIN station s ON s.id = a.stat_id
+        WHERE s.vg_id = $1
+        ORDER BY s.zp_start ASC, a.id ASC",
+        vg_id
+    )
+    .map(|r| Abstimmung {
+        abstimmung_id: r.id,
+        typ: r.typ,
+        ergebnisse: vec![],
+    })
+    .fetch_all(&mut **tx)
+    .await?;
+    let ids: Vec<_> = abstimmungen.iter().map(|a| a.abstimmung_id).collect();
+    let ergebnisse = sqlx::query!(
+        "SELECT abst_id, fraktion, ja, nein, enthaltung FROM abstimmungsergebnis
+        WHERE abst_id = ANY($1::int4[])
+        ORDER BY fraktion ASC",
+        &ids[..]
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    for e in ergebnisse {
+        if let Some(a) = abstimmungen
+            .iter_mut()
+            .find(|a| a.abstimmung_id == e.abst_id)
+        {
+            a.ergebnisse.push(Abstimmungsergebnis {
+                fraktion: e.fraktion,
+                // non-negative by the check constraints of abstimmungsergebnis
+                ja: e.ja as u32,
+                nein: e.nein as u32,
+                enthaltung: e.enthaltung as u32,
+            });
+        }
+    }
+    debug!(
+        "Found {} Abstimmungen for Vorgang {}",
+        abstimmungen.len(),
+        vg_id
+    );
+    Ok(abstimmungen)
+}
+
+#[cfg(test)]
+mod test_abstimmung {
+    use axum::http::Method;
+    use axum_extra::extract::{CookieJar, Host};
+    use openapi::apis::data_administration_vorgang::DataAdministrationVorgang;
+    use openapi::models;
+
+    use super::*;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_abstimmung_roundtrip() {
+        let scenario = TestSetup::new("test_abstimmung_roundtrip").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        server
+            .vorgang_id_put(
+                &Method::PUT,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &(APIScope::KeyAdder, 1),
+                &models::VorgangIdPutPathParams {
+                    vorgang_id: vorgang.api_id,
+                },
+                &vorgang,
+            )
+            .await
+            .unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (vg_id, stat_id) = sqlx::query!(
+            "SELECT v.id as vg_id, s.id as stat_id FROM vorgang v
+            INNER JOIN station s ON s.vg_id = v.id WHERE v.api_id = $1 LIMIT 1",
+            vorgang.api_id
+        )
+        .map(|r| (r.vg_id, r.stat_id))
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        assert!(
+            abstimmungen_by_vorgang(vg_id, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let submission = AbstimmungSubmission {
+            typ: "namentlich".to_string(),
+            ergebnisse: vec![
+                Abstimmungsergebnis {
+                    fraktion: "A-Fraktion".to_string(),
+                    ja: 12,
+                    nein: 3,
+                    enthaltung: 1,
+                },
+                Abstimmungsergebnis {
+                    fraktion: "B-Fraktion".to_string(),
+                    ja: 0,
+                    nein: 20,
+                    enthaltung: 0,
+                },
+            ],
+        };
+        let id = insert_abstimmung(stat_id, &submission, &mut tx)
+            .await
+            .unwrap();
+        let result = abstimmungen_by_vorgang(vg_id, &mut tx).await.unwrap();
+        let expected = vec![Abstimmung {
+            abstimmung_id: id,
+            typ: submission.typ.clone(),
+            ergebnisse: submission.ergebnisse.clone(),
+        }];
+        assert_eq!(result, expected);
+        tx.commit().await.unwrap();
+
+        // replacing the Vorgang keeps the Abstimmungen of its Stationen
+        let put = |vorgang: models::Vorgang| async move {
+            server
+                .vorgang_id_put(
+                    &Method::PUT,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &(APIScope::KeyAdder, 1),
+                    &models::VorgangIdPutPathParams {
+                        vorgang_id: vorgang.api_id,
+                    },
+                    &vorgang,
+                )
+                .await
+        };
+        let mut changed = vorgang.clone();
+        changed.titel = "Geänderter Titel".to_string();
+        put(changed.clone()).await.unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let vg_id = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang.api_id)
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(
+            abstimmungen_by_vorgang(vg_id, &mut tx).await.unwrap(),
+            expected
+        );
+        tx.rollback().await.unwrap();
+
+        // but refuses to delete a Station with Abstimmungen
+        changed.stationen.clear();
+        let error = put(changed).await.unwrap_err();
+        assert!(error.is_conflict(), "{error}");
+
+        let mut too_large = submission.ergebnisse[0].clone();
+        too_large.ja = u32::MAX;
+        assert!(too_large.db_counts().is_err());
+        scenario.teardown().await;
+    }
+}
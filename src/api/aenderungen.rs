@@ -0,0 +1,177 @@
+//! The amendment graph between Vorgänge, as submitted in `Vorgang.aendert`.
+//! The field is not in the generated model, it is read from the submitted body and added to
+//! the responses through `utils::spec_ext`.
+//!
+//! - `GET /api/v2/vorgang/{vorgang_id}/aendert` lists the Vorgänge amended by this Vorgang
+//! - `GET /api/v2/vorgang/{vorgang_id}/geaendert_durch` lists the Vorgänge amending this Vorgang
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::{HandlerResult, internal_error};
+use crate::db::retrieve;
+use crate::error::DataValidationError;
+use crate::utils::spec_ext;
+use crate::{LTZFArc, Result};
+
+/// an entry of `Vorgang.aendert`: the amended Vorgang and where and since when it is amended
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Aenderung {
+    pub api_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub article_ref: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub in_force_since: Option<chrono::NaiveDate>,
+}
+
+/// the `aendert` list submitted with the Vorgang `api_id`, None if there is none
+pub fn submitted(api_id: Uuid) -> Result<Option<Vec<Aenderung>>> {
+    let api_id = api_id.to_string();
+    let Some(value) = spec_ext::submitted("aendert", |vg| {
+        vg.get("api_id").and_then(|v| v.as_str()) == Some(api_id.as_str())
+    }) else {
+        return Ok(None);
+    };
+    serde_json::from_value(value).map_err(|e| {
+        DataValidationError::InvalidFormat {
+            field: "aendert".to_string(),
+            message: e.to_string(),
+        }
+        .into()
+    })
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/vorgang/{vorgang_id}/aendert", get(vorgang_aendert))
+        .route(
+            "/api/v2/vorgang/{vorgang_id}/geaendert_durch",
+            get(vorgang_geaendert_durch),
+        )
+        .with_state(server)
+}
+
+/// VorgangAendert - GET /api/v2/vorgang/{vorgang_id}/aendert
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn vorgang_aendert(
+    State(server): State<LTZFArc>,
+    Path(vorgang_id): Path<Uuid>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = vorgang_db_id(vorgang_id, &mut tx).await? else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let amended = retrieve::vorgang_aendert(id, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Vorgang amends {} Vorgänge", amended.len());
+    Ok(Json(amended).into_response())
+}
+
+/// VorgangGeaendertDurch - GET /api/v2/vorgang/{vorgang_id}/geaendert_durch
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn vorgang_geaendert_durch(
+    State(server): State<LTZFArc>,
+    Path(vorgang_id): Path<Uuid>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = vorgang_db_id(vorgang_id, &mut tx).await? else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let amending = retrieve::vorgang_geaendert_durch(id, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Vorgang is amended by {} Vorgänge", amending.len());
+    Ok(Json(amending).into_response())
+}
+
+async fn vorgang_db_id(
+    api_id: Uuid,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> std::result::Result<Option<i32>, StatusCode> {
+    sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", api_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut **tx)
+        .await
+        .map_err(internal_error)
+}
+
+#[cfg(test)]
+mod test_aenderungen {
+    use uuid::Uuid;
+
+    use axum::body::Body;
+    use axum::extract::Request;
+    use serde_json::json;
+
+    use super::Aenderung;
+    use crate::db::merge::execute::run_integration;
+    use crate::db::retrieve;
+    use crate::utils::spec_ext::with_request;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_vorgang_aendert() {
+        let scenario = TestSetup::new("test_vorgang_aendert").await;
+        let server = &scenario.server;
+        let amended = generate::random::vorgang(11);
+        run_integration(&amended, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let relation = Aenderung {
+            api_id: amended.api_id,
+            article_ref: Some("Art. 3 Abs. 2".to_string()),
+            in_force_since: chrono::NaiveDate::from_ymd_opt(2024, 1, 1),
+        };
+        let amending = generate::default_vorgang();
+        let mut body = serde_json::to_value(&amending).unwrap();
+        // unknown Vorgänge are skipped
+        body["aendert"] = json!([relation, { "api_id": Uuid::nil() }]);
+        let request = Request::builder()
+            .method("PUT")
+            .uri("/api/v2/vorgang")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let (result, _) =
+            with_request(request, run_integration(&amending, Uuid::nil(), 1, server)).await;
+        result.unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let ids = sqlx::query!(
+            "SELECT id, api_id FROM vorgang WHERE api_id = ANY($1::uuid[])",
+            &[amending.api_id, amended.api_id][..]
+        )
+        .map(|r| (r.api_id, r.id))
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+        let id_of = |api_id| ids.iter().find(|(a, _)| *a == api_id).unwrap().1;
+
+        let stored = retrieve::vorgang_aendert(id_of(amending.api_id), &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(stored, vec![relation]);
+        let amending_list = retrieve::vorgang_geaendert_durch(id_of(amended.api_id), &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(amending_list.len(), 1);
+        assert_eq!(amending_list[0].api_id, amending.api_id);
+        assert!(
+            retrieve::vorgang_aendert(id_of(amended.api_id), &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -70,25 +70,29 @@ async fn internal_extract_claims(
     let tag = crate::utils::auth::keytag_of(key);
     debug!("Authenticating Key: `{}`", tag);
 
-    if let Some((id, deleted_by, expiry, scope, salt, hash)) = sqlx::query!(
-        "SELECT k.id, k.deleted_by, k.expires_at, value as scope, k.salt, k.key_hash
+    if let Some((id, deleted_by, revoked_at, expiry, scope, salt, hash, allowed_ips)) =
+        sqlx::query!(
+        "SELECT k.id, k.deleted_by, k.revoked_at, k.expires_at, value as scope, k.salt, k.key_hash,
+        k.allowed_ips::text[] as allowed_ips
         FROM api_keys k
         INNER JOIN api_scope s ON s.id = k.scope
         WHERE keytag = $1",
         tag
     )
-    .map(|r| {
-        (
-            r.id,
-            r.deleted_by,
-            r.expires_at,
-            r.scope,
-            r.salt.to_string(),
-            r.key_hash,
-        )
-    })
-    .fetch_optional(&server.sqlx_db)
-    .await?
+        .map(|r| {
+            (
+                r.id,
+                r.deleted_by,
+                r.revoked_at,
+                r.expires_at,
+                r.scope,
+                r.salt.to_string(),
+                r.key_hash,
+                r.allowed_ips,
+            )
+        })
+        .fetch_optional(&server.sqlx_db)
+        .await?
     {
         let incoming_hash = crate::utils::auth::hash_full_key(&salt, key);
         if hash != incoming_hash {
@@ -98,6 +102,15 @@ async fn internal_extract_claims(
                     reason: format!("API Key is not valid. Tag: {tag}"),
                 }),
             })
+        } else if let Some(revoked_at) = revoked_at {
+            warn!("Key was revoked at {revoked_at}");
+            Err(LTZFError::Validation {
+                source: Box::new(crate::error::DataValidationError::Unauthorized {
+                    reason: format!(
+                        "API Key was valid but is revoked since {revoked_at}. Tag: {tag}"
+                    ),
+                }),
+            })
         } else if let Some(deleted_by) = deleted_by {
             if deleted_by == id {
                 warn!("API Key was valid but was either rotated or expired");
@@ -132,6 +145,13 @@ async fn internal_extract_claims(
                     reason: format!("API Key was valid but has expired. Tag: {tag}"),
                 }),
             })
+        } else if let Some(ip) = ip_outside_allowlist(allowed_ips.as_deref(), headers, server) {
+            warn!("Key was used from {ip}, which is not in its allowlist");
+            let error = crate::error::DataValidationError::IpNotAllowed { ip, keytag: tag };
+            crate::utils::client_addr::reject_ip(error.to_string());
+            Err(LTZFError::Validation {
+                source: Box::new(error),
+            })
         } else {
             let scope = (APIScope::try_from(scope.as_str()).unwrap(), id);
             sqlx::query!(
@@ -154,6 +174,63 @@ async fn internal_extract_claims(
     }
 }
 
+/// the client address if the key is restricted to `allowed_ips` and the client is not within them.
+/// An address that cannot be determined never matches.
+fn ip_outside_allowlist(
+    allowed_ips: Option<&[String]>,
+    headers: &axum::http::header::HeaderMap,
+    server: &LTZFServer,
+) -> Option<String> {
+    let allowed_ips = allowed_ips?;
+    let Some(ip) = crate::utils::auth::client_ip(
+        headers,
+        crate::utils::client_addr::peer_addr(),
+        server.config.trusted_proxy_count,
+    ) else {
+        return Some("an unknown address".to_string());
+    };
+    let allowed = allowed_ips
+        .iter()
+        .filter_map(|net| net.parse::<ipnet::IpNet>().ok())
+        .any(|net| net.contains(&ip));
+    if allowed { None } else { Some(ip.to_string()) }
+}
+
+/// the claims of the `X-API-Key` of a GET or HEAD request, None if the key is invalid.
+/// The `touched_by` middleware authenticates the key and keeps them in the request
+/// extensions, so the response cache inside of it does not authenticate it again
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ReadingClaims(pub Option<crate::api::Claims>);
+
+/// authorization for handlers outside of the generated server:
+/// extracts the claims from the `X-API-Key` header and checks them against the allowed scopes
+pub(crate) async fn authorize_scopes(
+    server: &LTZFServer,
+    headers: &axum::http::header::HeaderMap,
+    scopes: &[APIScope],
+) -> std::result::Result<crate::api::Claims, axum::http::StatusCode> {
+    match internal_extract_claims(server, headers, "x-api-key").await {
+        Ok(claims) if scopes.contains(&claims.0) => Ok(claims),
+        Ok(_) => {
+            warn!("Permission level too low");
+            Err(axum::http::StatusCode::FORBIDDEN)
+        }
+        Err(LTZFError::Validation { source })
+            if matches!(
+                *source,
+                crate::error::DataValidationError::IpNotAllowed { .. }
+            ) =>
+        {
+            warn!("Authorization failed: {}", source);
+            Err(axum::http::StatusCode::FORBIDDEN)
+        }
+        Err(error) => {
+            warn!("Authorization failed: {}", error);
+            Err(axum::http::StatusCode::UNAUTHORIZED)
+        }
+    }
+}
+
 #[async_trait]
 impl ApiKeyAuthHeader for LTZFServer {
     type Claims = crate::api::Claims;
@@ -226,7 +303,11 @@ impl AuthentifizierungKeyadderSchnittstellen<LTZFError> for LTZFServer {
             x_total_pages: Some(prp.x_total_pages),
             x_page: Some(prp.x_page),
             x_per_page: Some(prp.x_per_page),
-            link: Some(prp.generate_link_header("/api/v2/auth/keys")),
+            link: Some(prp.generate_link_header(
+                &self.public_base_url(),
+                "/api/v2/auth/keys",
+                &crate::api::filter_query(query_params),
+            )),
         });
     }
 
@@ -347,7 +428,7 @@ impl AuthentifizierungKeyadderSchnittstellen<LTZFError> for LTZFServer {
             });
         }
         sqlx::query!(
-            "UPDATE api_keys SET deleted_by=$1 WHERE keytag=$2",
+            "UPDATE api_keys SET deleted_by=$1, revoked_at=NOW() WHERE keytag=$2",
             claims.1,
             header_params.api_key_delete
         )
@@ -381,34 +462,23 @@ impl AuthentifizierungKeyadderSchnittstellen<LTZFError> for LTZFServer {
             });
         }
         let mut tx = self.sqlx_db.begin().await?;
-        let (key, salt) = crate::utils::auth::find_new_key(&mut tx).await?;
-        let tag = crate::utils::auth::keytag_of(&key);
-
-        let key_digest = crate::utils::auth::hash_full_key(&salt, &key);
-
-        sqlx::query!(
-            "INSERT INTO api_keys(key_hash, created_by, expires_at, scope, salt, keytag)
-        VALUES
-        ($1, $2, $3, (SELECT id FROM api_scope WHERE value = $4), $5, $6)",
-            key_digest,
-            claims.1,
+        let created = crate::api::keys::create_key(
+            APIScope::try_from(body.scope.to_string().as_str())?,
             body.expires_at
                 .unwrap_or(chrono::Utc::now() + chrono::Duration::days(365)),
-            body.scope.to_string(),
-            salt,
-            tag
+            claims.1,
+            &mut tx,
         )
-        .execute(&mut *tx)
         .await?;
         tx.commit().await?;
 
         tracing::info!(
             "Generated Fresh API Key with Scope: {} and keytag {}",
             body.scope,
-            tag
+            created.keytag
         );
         Ok(AuthPostResponse::Status201_APIKeyWasCreatedSuccessfully(
-            key,
+            created.key,
         ))
     }
 }
@@ -524,8 +594,7 @@ impl Authentifizierung<LTZFError> for LTZFServer {
         claims: &Self::Claims,
     ) -> Result<AuthStatusResponse> {
         let db_row = sqlx::query!(
-            "SELECT * FROM 
-        api_keys WHERE id = $1",
+            "SELECT keytag, rotated_for, expires_at FROM api_keys WHERE id = $1",
             claims.1
         )
         .fetch_one(&self.sqlx_db)
@@ -578,23 +647,27 @@ mod auth_test {
     }
     async fn fetch_key_row(server: &LTZFServer, keytag: String) -> KeyRow {
         let mut tx = server.sqlx_db.begin().await.unwrap();
-        let index = sqlx::query!("SELECT * FROM api_keys WHERE keytag = $1", keytag)
-            .map(|r| KeyRow {
-                id: r.id,
-                created_by: r.created_by,
-                deleted_by: r.deleted_by,
-                key_hash: r.key_hash,
-                created_at: r.created_at,
-                expires_at: r.expires_at,
-                last_used: r.last_used,
-                scope: r.scope,
-                rotated_for: r.rotated_for,
-                salt: r.salt,
-                keytag: r.keytag,
-            })
-            .fetch_one(&mut *tx)
-            .await
-            .unwrap();
+        let index = sqlx::query!(
+            "SELECT id, created_by, deleted_by, key_hash, created_at, expires_at,
+            last_used, scope, rotated_for, salt, keytag FROM api_keys WHERE keytag = $1",
+            keytag
+        )
+        .map(|r| KeyRow {
+            id: r.id,
+            created_by: r.created_by,
+            deleted_by: r.deleted_by,
+            key_hash: r.key_hash,
+            created_at: r.created_at,
+            expires_at: r.expires_at,
+            last_used: r.last_used,
+            scope: r.scope,
+            rotated_for: r.rotated_for,
+            salt: r.salt,
+            keytag: r.keytag,
+        })
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
         tx.commit().await.unwrap();
         index
     }
@@ -0,0 +1,161 @@
+//! Retrieval of several Vorgänge by their api_id in one request, for clients holding
+//! a list of ids from an external source that would otherwise issue one GET per id.
+//! Since a single request is much more expensive than a GET, it has its own, stricter rate limit.
+//!
+//! - `POST /api/v2/vorgang/batch` takes `{"ids": [...]}` (at most 100) and returns
+//!   `[{"api_id": ..., "vorgang": {...} | null}]` in the order of the ids, `null` for unknown ids
+
+use std::sync::Arc;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tower_governor::GovernorLayer;
+use tower_governor::governor::GovernorConfigBuilder;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::{HandlerResult, internal_error};
+use crate::db::retrieve;
+use crate::utils::rate_limit::ProxyAwareKeyExtractor;
+use crate::{LTZFArc, Result};
+
+/// maximum number of ids per request
+pub const MAX_BATCH_SIZE: usize = 100;
+/// seconds until a client may send another batch request once its burst is used up
+const BATCH_LIMIT_INTERVAL: u64 = 10;
+const BATCH_LIMIT_BURST: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchRequest {
+    pub ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BatchEntry {
+    pub api_id: Uuid,
+    pub vorgang: Option<models::Vorgang>,
+}
+
+/// the router and a function dropping the stale entries of its rate limiter,
+/// to be run by the periodic cleanup of the global rate limiter
+pub(crate) fn router(server: LTZFArc) -> (axum::Router, impl Fn() + Send + 'static) {
+    let config = Arc::new(
+        GovernorConfigBuilder::default()
+            .const_per_second(BATCH_LIMIT_INTERVAL)
+            .const_burst_size(BATCH_LIMIT_BURST)
+            .key_extractor(ProxyAwareKeyExtractor {
+                trusted_proxy_count: server.config.trusted_proxy_count,
+            })
+            .finish()
+            .unwrap(),
+    );
+    let limiter = config.limiter().clone();
+    let router = axum::Router::new()
+        .route("/api/v2/vorgang/batch", post(vorgang_batch))
+        .layer(GovernorLayer { config })
+        .with_state(server);
+    (router, move || limiter.retain_recent())
+}
+
+/// VorgangBatch - POST /api/v2/vorgang/batch
+#[instrument(skip_all, fields(n=request.ids.len()))]
+async fn vorgang_batch(
+    State(server): State<LTZFArc>,
+    Json(request): Json<BatchRequest>,
+) -> HandlerResult {
+    if request.ids.len() > MAX_BATCH_SIZE {
+        warn!(
+            "Batch of {} ids exceeds the maximum of {MAX_BATCH_SIZE}",
+            request.ids.len()
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let entries = vorgaenge_by_api_ids(&request.ids, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(
+        "Found {}/{} requested Vorgänge",
+        entries.iter().filter(|e| e.vorgang.is_some()).count(),
+        entries.len()
+    );
+    Ok(Json(entries).into_response())
+}
+
+/// one entry per requested id in the same order, `vorgang` is None for unknown ids
+pub async fn vorgaenge_by_api_ids(
+    api_ids: &[Uuid],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<BatchEntry>> {
+    let found = sqlx::query!(
+        "SELECT id, api_id FROM vorgang WHERE api_id = ANY($1::uuid[])",
+        api_ids
+    )
+    .map(|r| (r.api_id, r.id))
+    .fetch_all(&mut **tx)
+    .await?;
+    let ids: Vec<i32> = found.iter().map(|(_, id)| *id).collect();
+    let vorgaenge = retrieve::vorgaenge_by_ids(&ids, tx).await?;
+    let found: std::collections::HashMap<Uuid, i32> = found.into_iter().collect();
+    Ok(api_ids
+        .iter()
+        .map(|api_id| BatchEntry {
+            api_id: *api_id,
+            vorgang: found.get(api_id).and_then(|id| vorgaenge.get(id).cloned()),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test_batch {
+    use uuid::Uuid;
+
+    use super::vorgaenge_by_api_ids;
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_vorgaenge_by_api_ids() {
+        let scenario = TestSetup::new("test_vorgaenge_by_api_ids").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let other = generate::random::vorgang(3);
+        for vg in [&vorgang, &other] {
+            run_integration(vg, Uuid::nil(), 1, server).await.unwrap();
+        }
+
+        let unknown = Uuid::now_v7();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let entries = vorgaenge_by_api_ids(&[unknown, vorgang.api_id, other.api_id], &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        // every Vorgang gets its own Stationen
+        let stations_of = |i: usize| {
+            let vg = entries[i].vorgang.as_ref().unwrap();
+            assert!(!vg.stationen.is_empty());
+            vg.stationen.iter().map(|s| s.api_id).collect::<Vec<_>>()
+        };
+        let (first, second) = (stations_of(1), stations_of(2));
+        assert!(first.iter().all(|s| !second.contains(s)));
+        assert_eq!(
+            entries[2].vorgang.as_ref().map(|v| v.api_id),
+            Some(other.api_id)
+        );
+        assert_eq!(entries[0].api_id, unknown);
+        assert!(entries[0].vorgang.is_none());
+        assert_eq!(entries[1].api_id, vorgang.api_id);
+        assert_eq!(
+            entries[1].vorgang.as_ref().map(|v| v.api_id),
+            Some(vorgang.api_id)
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
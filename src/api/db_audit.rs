@@ -0,0 +1,321 @@
+//! Diagnostics on the database, backed by the PostgreSQL statistics views.
+//! The index audit is meant as a basis for deciding which indexes to add or drop in a migration,
+//! the transaction list allows cancelling runaway queries that block the pool without access to the database host.
+//!
+//! - `GET /api/v2/admin/db/index_audit` reports tables without indexes that are scanned sequentially,
+//!   unused indexes and indexes with a low cache hit ratio
+//! - `GET /api/v2/admin/db/transactions` lists active queries waiting for more than five seconds (Admin only)
+//! - `DELETE /api/v2/admin/db/transactions/{pid}` cancels the current query of a backend (Admin only)
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+/// tables with more sequential scans than this and no index are reported
+const SEQ_SCAN_THRESHOLD: i64 = 100;
+/// indexes with a lower share of buffer hits are reported
+const CACHE_HIT_THRESHOLD: f64 = 0.9;
+/// queries are cut to this many characters in the transaction list
+const QUERY_PREVIEW_CHARS: i32 = 200;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SeqScanTable {
+    pub table: String,
+    pub seq_scan: i64,
+    pub live_tuples: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UnusedIndex {
+    pub table: String,
+    pub index: String,
+    pub size_bytes: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CacheHitIndex {
+    pub table: String,
+    pub index: String,
+    pub hit_ratio: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct IndexAudit {
+    pub high_seq_scans: Vec<SeqScanTable>,
+    pub unused_indexes: Vec<UnusedIndex>,
+    pub low_cache_hit: Vec<CacheHitIndex>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockingTransaction {
+    pub pid: i32,
+    pub state: Option<String>,
+    pub query_start: Option<DateTime<Utc>>,
+    pub wait_event: Option<String>,
+    pub query: Option<String>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/db/index_audit", get(index_audit_get))
+        .route("/api/v2/admin/db/transactions", get(transactions_get))
+        .route(
+            "/api/v2/admin/db/transactions/{pid}",
+            delete(transactions_cancel),
+        )
+        .with_state(server)
+}
+
+/// IndexAudit - GET /api/v2/admin/db/index_audit
+#[instrument(skip_all)]
+async fn index_audit_get(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let audit = index_audit(&mut tx).await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(
+        "Index audit: {} tables with sequential scans, {} unused indexes, {} indexes with low cache hits",
+        audit.high_seq_scans.len(),
+        audit.unused_indexes.len(),
+        audit.low_cache_hit.len()
+    );
+    Ok(Json(audit).into_response())
+}
+
+/// TransactionsGet - GET /api/v2/admin/db/transactions
+#[instrument(skip_all)]
+async fn transactions_get(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin]).await?;
+    let transactions = blocking_transactions(&server.sqlx_db)
+        .await
+        .map_err(internal_error)?;
+    info!("{} transactions are waiting", transactions.len());
+    Ok(Json(transactions).into_response())
+}
+
+/// TransactionsCancel - DELETE /api/v2/admin/db/transactions/{pid}
+#[instrument(skip_all, fields(pid=%pid))]
+async fn transactions_cancel(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(pid): Path<i32>,
+) -> HandlerResult {
+    let claims = authorize_scopes(&server, &headers, &[APIScope::Admin]).await?;
+    warn!(
+        "Key {} requested to cancel the query of backend {pid}",
+        claims.1
+    );
+    let cancelled = cancel_backend(pid, &server.sqlx_db)
+        .await
+        .map_err(internal_error)?;
+    if !cancelled {
+        warn!("Backend {pid} does not exist or could not be signalled");
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!("Cancelled the query of backend {pid}");
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// active queries of this database that have been waiting for more than five seconds
+pub async fn blocking_transactions(
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<BlockingTransaction>> {
+    Ok(sqlx::query_as!(
+        BlockingTransaction,
+        "SELECT pid as \"pid!\", state, query_start, wait_event, left(query, $1) as query
+        FROM pg_stat_activity
+        WHERE datname = current_database() AND state = 'active' AND wait_event IS NOT NULL
+        AND now() - query_start > interval '5 seconds'
+        ORDER BY query_start ASC",
+        QUERY_PREVIEW_CHARS
+    )
+    .fetch_all(executor)
+    .await?)
+}
+
+/// returns whether the backend was signalled. Only backends listed by `blocking_transactions`
+/// are signalled, so other databases and applications on the cluster are left alone
+pub async fn cancel_backend(pid: i32, pool: &sqlx::PgPool) -> Result<bool> {
+    if !blocking_transactions(pool)
+        .await?
+        .iter()
+        .any(|t| t.pid == pid)
+    {
+        return Ok(false);
+    }
+    Ok(
+        sqlx::query!("SELECT pg_cancel_backend($1) as \"cancelled!\"", pid)
+            .map(|r| r.cancelled)
+            .fetch_one(pool)
+            .await?,
+    )
+}
+
+pub async fn index_audit(tx: &mut sqlx::PgTransaction<'_>) -> Result<IndexAudit> {
+    // idx_scan is NULL for tables without any index
+    let high_seq_scans = sqlx::query!(
+        "SELECT relname::text as \"table!\", seq_scan as \"seq_scan!\", n_live_tup as \"live_tuples!\"
+        FROM pg_stat_user_tables
+        WHERE seq_scan > $1 AND idx_scan IS NULL
+        ORDER BY seq_scan DESC",
+        SEQ_SCAN_THRESHOLD
+    )
+    .map(|r| SeqScanTable {
+        table: r.table,
+        seq_scan: r.seq_scan,
+        live_tuples: r.live_tuples,
+    })
+    .fetch_all(&mut **tx)
+    .await?;
+
+    // unique indexes enforce constraints and are never candidates for removal
+    let unused_indexes = sqlx::query!(
+        "SELECT s.relname::text as \"table!\", s.indexrelname::text as \"index!\",
+        pg_relation_size(s.indexrelid) as \"size_bytes!\"
+        FROM pg_stat_user_indexes s
+        INNER JOIN pg_index i ON i.indexrelid = s.indexrelid
+        WHERE s.idx_scan = 0 AND NOT i.indisunique
+        ORDER BY pg_relation_size(s.indexrelid) DESC"
+    )
+    .map(|r| UnusedIndex {
+        table: r.table,
+        index: r.index,
+        size_bytes: r.size_bytes,
+    })
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let low_cache_hit = sqlx::query!(
+        "SELECT relname::text as \"table!\", indexrelname::text as \"index!\",
+        (idx_blks_hit::float8 / (idx_blks_hit + idx_blks_read)) as \"hit_ratio!\"
+        FROM pg_statio_user_indexes
+        WHERE idx_blks_hit + idx_blks_read > 0
+        AND idx_blks_hit::float8 / (idx_blks_hit + idx_blks_read) < $1
+        ORDER BY 3 ASC",
+        CACHE_HIT_THRESHOLD
+    )
+    .map(|r| CacheHitIndex {
+        table: r.table,
+        index: r.index,
+        hit_ratio: r.hit_ratio,
+    })
+    .fetch_all(&mut **tx)
+    .await?;
+
+    Ok(IndexAudit {
+        high_seq_scans,
+        unused_indexes,
+        low_cache_hit,
+    })
+}
+
+#[cfg(test)]
+mod test_db_audit {
+    use super::{SEQ_SCAN_THRESHOLD, blocking_transactions, cancel_backend, index_audit};
+    use crate::utils::testing::TestSetup;
+
+    #[tokio::test]
+    async fn test_index_audit() {
+        let scenario = TestSetup::new("test_index_audit").await;
+        let pool = &scenario.server.sqlx_db;
+        // a table without index that is only ever read sequentially
+        let mut conn = pool.acquire().await.unwrap();
+        sqlx::query("CREATE TABLE audit_probe(x INT4)")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO audit_probe SELECT generate_series(1, 10)")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        for _ in 0..SEQ_SCAN_THRESHOLD + 20 {
+            sqlx::query("SELECT SUM(x) FROM audit_probe")
+                .execute(&mut *conn)
+                .await
+                .unwrap();
+        }
+        // statistics are flushed by the scanning backend once it is idle
+        sqlx::query("SELECT pg_stat_force_next_flush()")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        drop(conn);
+
+        let mut probe = None;
+        for _ in 0..50 {
+            let mut tx = pool.begin().await.unwrap();
+            let audit = index_audit(&mut tx).await.unwrap();
+            tx.rollback().await.unwrap();
+            assert!(audit.low_cache_hit.iter().all(|i| i.hit_ratio < 0.9));
+            assert!(audit.high_seq_scans.iter().all(|t| t.seq_scan > 100));
+            probe = audit
+                .high_seq_scans
+                .into_iter()
+                .find(|t| t.table == "audit_probe");
+            if probe.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let probe = probe.expect("the sequentially scanned table was not reported");
+        assert!(probe.seq_scan >= SEQ_SCAN_THRESHOLD + 20);
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_cancel_waiting_transaction() {
+        let scenario = TestSetup::new("test_cancel_waiting_transaction").await;
+        let pool = &scenario.server.sqlx_db;
+        let mut blocker = pool.begin().await.unwrap();
+        sqlx::query!("LOCK TABLE vorgang IN ACCESS EXCLUSIVE MODE")
+            .execute(&mut *blocker)
+            .await
+            .unwrap();
+        let (pid_tx, pid_rx) = tokio::sync::oneshot::channel();
+        let waiting = {
+            let pool = pool.clone();
+            tokio::spawn(async move {
+                let mut conn = pool.acquire().await.unwrap();
+                let pid = sqlx::query!("SELECT pg_backend_pid() as \"pid!\"")
+                    .map(|r| r.pid)
+                    .fetch_one(&mut *conn)
+                    .await
+                    .unwrap();
+                pid_tx.send(pid).unwrap();
+                sqlx::query!("SELECT COUNT(*) as c FROM vorgang")
+                    .fetch_one(&mut *conn)
+                    .await
+            })
+        };
+        let pid = pid_rx.await.unwrap();
+        // not yet waiting for five seconds
+        assert!(!cancel_backend(pid, pool).await.unwrap());
+
+        let mut stuck = None;
+        for _ in 0..100 {
+            let transactions = blocking_transactions(pool).await.unwrap();
+            stuck = transactions.into_iter().find(|t| t.pid == pid);
+            if stuck.is_some() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        let stuck = stuck.expect("the waiting query is listed");
+        assert_eq!(stuck.wait_event.as_deref(), Some("relation"));
+        assert!(cancel_backend(pid, pool).await.unwrap());
+        assert!(waiting.await.unwrap().is_err(), "the query was cancelled");
+
+        assert!(!cancel_backend(-1, pool).await.unwrap());
+        blocker.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,143 @@
+//! The OpenAPI specification the server was generated from, so the documentation is always
+//! the one of the running version.
+//!
+//! - `GET /api/v2/openapi.json` returns the specification as json
+//! - `GET /api/v2/docs` serves a Swagger UI for the specification, loaded from `LTZF_SWAGGER_UI_URL`
+//!
+//! The hand-written routers and the fields not yet in the pinned specification are described in
+//! `openapi-extensions.yml`, which is merged into the generated specification.
+
+use std::sync::LazyLock;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use serde_json::Value;
+use tracing::instrument;
+
+use crate::LTZFArc;
+use crate::api::HandlerResult;
+
+/// the specification `oapigen.sh` generated the `openapi` crate from
+const SPEC_YAML: &str = include_str!("../../oapi-generator/openapi.yml");
+/// the routes and fields added by hand on top of it
+const EXTENSIONS_YAML: &str = include_str!("openapi-extensions.yml");
+
+static SPEC_JSON: LazyLock<std::result::Result<Value, String>> = LazyLock::new(|| {
+    let mut spec: Value = serde_yaml::from_str(SPEC_YAML).map_err(|e| e.to_string())?;
+    let extensions: Value = serde_yaml::from_str(EXTENSIONS_YAML).map_err(|e| e.to_string())?;
+    merge_spec(&mut spec, extensions);
+    Ok(spec)
+});
+
+/// merges `extension` into `spec`: mappings key by key, lists are appended
+fn merge_spec(spec: &mut Value, extension: Value) {
+    match (spec, extension) {
+        (Value::Object(spec), Value::Object(extension)) => {
+            for (key, value) in extension {
+                match spec.get_mut(&key) {
+                    Some(existing) => merge_spec(existing, value),
+                    None => {
+                        spec.insert(key, value);
+                    }
+                }
+            }
+        }
+        (Value::Array(spec), Value::Array(extension)) => spec.extend(extension),
+        (spec, extension) => *spec = extension,
+    }
+}
+
+fn swagger_ui_page(ui_url: &str) -> String {
+    let ui_url = ui_url.trim_end_matches('/');
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="de">
+<head>
+    <meta charset="utf-8" />
+    <title>LTZF API</title>
+    <link rel="stylesheet" href="{ui_url}/swagger-ui.css" />
+</head>
+<body>
+    <div id="swagger-ui"></div>
+    <script src="{ui_url}/swagger-ui-bundle.js" crossorigin></script>
+    <script>
+        window.onload = () => {{
+            window.ui = SwaggerUIBundle({{ url: "/api/v2/openapi.json", dom_id: "#swagger-ui" }});
+        }};
+    </script>
+</body>
+</html>
+"##
+    )
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/openapi.json", get(openapi_spec))
+        .route("/api/v2/docs", get(swagger_ui))
+        .with_state(server)
+}
+
+/// OpenapiSpec - GET /api/v2/openapi.json
+#[instrument(skip_all)]
+async fn openapi_spec() -> HandlerResult {
+    match &*SPEC_JSON {
+        Ok(spec) => Ok(Json(spec).into_response()),
+        Err(e) => {
+            tracing::error!("The embedded OpenAPI specification is malformed: {e}");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// SwaggerUi - GET /api/v2/docs
+#[instrument(skip_all)]
+async fn swagger_ui(State(server): State<LTZFArc>) -> HandlerResult {
+    Ok(Html(swagger_ui_page(&server.config.swagger_ui_url)).into_response())
+}
+
+#[cfg(test)]
+mod test_docs {
+    use super::{SPEC_JSON, swagger_ui_page};
+
+    #[test]
+    fn test_spec_converts_to_json() {
+        let spec = SPEC_JSON.as_ref().unwrap();
+        assert!(spec.get("openapi").is_some());
+        assert!(spec["paths"].get("/api/v2/vorgang").is_some());
+    }
+
+    #[test]
+    fn test_spec_contains_extensions() {
+        let spec = SPEC_JSON.as_ref().unwrap();
+        // side routers are added next to the generated paths
+        assert!(spec["paths"].get("/api/v2/export/delta").is_some());
+        assert!(spec["paths"]["/api/v2/vorgang"].get("get").is_some());
+        // parameters are appended to the generated ones, not replacing them
+        let parameters = spec["paths"]["/api/v2/vorgang"]["get"]["parameters"]
+            .as_array()
+            .unwrap();
+        assert!(parameters.iter().any(|p| p["name"] == "sort"));
+        assert!(parameters.iter().any(|p| p["name"] == "per_page"));
+        assert!(
+            spec["components"]["schemas"]["Vorgang"]["properties"]
+                .get("last_update")
+                .is_some()
+        );
+        assert!(
+            spec["components"]["schemas"]["Vorgang"]["properties"]
+                .get("titel")
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_swagger_ui_location() {
+        let page = swagger_ui_page("https://static.example.com/swagger/");
+        assert!(page.contains("https://static.example.com/swagger/swagger-ui-bundle.js"));
+        assert!(page.contains("https://static.example.com/swagger/swagger-ui.css"));
+    }
+}
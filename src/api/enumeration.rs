@@ -0,0 +1,196 @@
+//! Renaming of single enumeration values, e.g. to fix a typo in a Schlagwort, without
+//! going through the object list and replacement rules of `enum_put`.
+//!
+//! - `PATCH /api/v2/enumeration/{name}/{item}` renames `item` to the `new_value` of the json body
+
+use std::str::FromStr;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::patch;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct EnumRename {
+    pub new_value: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameOutcome {
+    Renamed,
+    NotFound,
+    Conflict,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/enumeration/{name}/{item}", patch(enum_rename))
+        .with_state(server)
+}
+
+/// EnumRename - PATCH /api/v2/enumeration/{name}/{item}
+#[instrument(skip_all, fields(name=%name, item=%item))]
+async fn enum_rename(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path((name, item)): Path<(models::EnumerationNames, String)>,
+    Json(body): Json<EnumRename>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let Some(new_value) = normalised_value(name, &body.new_value) else {
+        warn!("`{}` is not a valid value for {name}", body.new_value);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let outcome = rename_enum_value(name, &item, &new_value, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    match outcome {
+        RenameOutcome::Renamed => {
+            tx.commit().await.map_err(internal_error)?;
+            info!(target: "obj", "Renamed {name} entry `{item}` to `{new_value}`");
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        RenameOutcome::NotFound => {
+            warn!("Entry does not exist");
+            Err(StatusCode::NOT_FOUND)
+        }
+        RenameOutcome::Conflict => {
+            warn!("Entry `{new_value}` already exists");
+            Err(StatusCode::CONFLICT)
+        }
+    }
+}
+
+/// brings `value` into the form it is stored in. Values of enumerations that are
+/// represented by a fixed enum in the API have to be one of its variants.
+fn normalised_value(name: models::EnumerationNames, value: &str) -> Option<String> {
+    use models::EnumerationNames::*;
+    let value = value.trim();
+    let valid = match name {
+        Schlagworte => !value.is_empty(),
+        Parlamente => models::Parlament::from_str(&value.to_uppercase()).is_ok(),
+        Stationstypen => models::Stationstyp::from_str(value).is_ok(),
+        Vorgangstypen => models::Vorgangstyp::from_str(value).is_ok(),
+        Dokumententypen => models::Doktyp::from_str(value).is_ok(),
+        Vgidtypen => models::VgIdentTyp::from_str(value).is_ok(),
+    };
+    if !valid {
+        return None;
+    }
+    Some(match name {
+        Parlamente => value.to_uppercase(),
+        Schlagworte => value.to_lowercase(),
+        _ => value.to_string(),
+    })
+}
+
+pub(crate) fn enum_table(name: models::EnumerationNames) -> &'static str {
+    use models::EnumerationNames::*;
+    match name {
+        Schlagworte => "schlagwort",
+        Stationstypen => "stationstyp",
+        Parlamente => "parlament",
+        Vorgangstypen => "vorgangstyp",
+        Dokumententypen => "dokumententyp",
+        Vgidtypen => "vg_ident_typ",
+    }
+}
+
+/// renames `item` of the enumeration `name` to `new_value`. All references keep pointing to the entry.
+pub async fn rename_enum_value(
+    name: models::EnumerationNames,
+    item: &str,
+    new_value: &str,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<RenameOutcome> {
+    let table = enum_table(name);
+    let taken: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {table} WHERE value = $1)"
+    ))
+    .bind(new_value)
+    .fetch_one(&mut **tx)
+    .await?;
+    if taken {
+        return Ok(RenameOutcome::Conflict);
+    }
+    let renamed = sqlx::query(&format!("UPDATE {table} SET value = $1 WHERE value = $2"))
+        .bind(new_value)
+        .bind(item)
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    if renamed == 0 {
+        return Ok(RenameOutcome::NotFound);
+    }
+    Ok(RenameOutcome::Renamed)
+}
+
+#[cfg(test)]
+mod test_enumeration {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::{RenameOutcome, normalised_value, rename_enum_value};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_normalised_value() {
+        use models::EnumerationNames::*;
+        assert_eq!(
+            normalised_value(Schlagworte, " Energie "),
+            Some("energie".to_string())
+        );
+        assert_eq!(normalised_value(Schlagworte, "  "), None);
+        assert_eq!(normalised_value(Parlamente, "by"), Some("BY".to_string()));
+        assert_eq!(normalised_value(Parlamente, "XX"), None);
+        assert_eq!(normalised_value(Stationstypen, "kein-stationstyp"), None);
+    }
+
+    #[tokio::test]
+    async fn test_rename_enum_value() {
+        let scenario = TestSetup::new("test_rename_enum_value").await;
+        let server = &scenario.server;
+        run_integration(&generate::default_vorgang(), Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let sw = models::EnumerationNames::Schlagworte;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        assert_eq!(
+            rename_enum_value(sw, "gibtsnicht", "neu", &mut tx)
+                .await
+                .unwrap(),
+            RenameOutcome::NotFound
+        );
+        assert_eq!(
+            rename_enum_value(sw, "drache", "schuppen", &mut tx)
+                .await
+                .unwrap(),
+            RenameOutcome::Conflict
+        );
+        assert_eq!(
+            rename_enum_value(sw, "drache", "drachen", &mut tx)
+                .await
+                .unwrap(),
+            RenameOutcome::Renamed
+        );
+        let values = sqlx::query!("SELECT value FROM schlagwort")
+            .map(|r| r.value)
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap();
+        assert!(values.contains(&"drachen".to_string()));
+        assert!(!values.contains(&"drache".to_string()));
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,320 @@
+//! Incremental export for sync clients.
+//!
+//! Every insert or update that changes a Vorgang, Sitzung or Dokument stamps the row with the
+//! next value of `data_generation_seq`, deleting one leaves a row in `data_tombstone`.
+//! Sequence values are handed out before the writing transaction commits, so the returned
+//! generation stays below the lowest generation of a still running transaction. Otherwise a
+//! client could continue past a change that only becomes visible later.
+//! Clients remember the value of the `X-Generation` header and pass it as
+//! `since_generation` on their next run. If `X-Delta-Complete` is false, the response was cut
+//! off after `limit` objects and the client asks again with the returned generation.
+//!
+//! - `GET /api/v2/export/delta?since_generation=N&limit=M` returns the objects modified after generation N
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::db::retrieve;
+use crate::{LTZFArc, Result};
+
+/// changes older than this are not served as a delta, the client has to do a full sync instead
+pub const DELTA_WINDOW_DAYS: i64 = 7;
+/// first key of the advisory locks held by transactions that take generations,
+/// see `mark_generation_in_flight` in the data generation migration
+const GENERATION_IN_FLIGHT_LOCK: i64 = 1280596551;
+/// objects per delta response unless the client asks for fewer
+pub const DELTA_DEFAULT_LIMIT: i64 = 256;
+pub const DELTA_MAX_LIMIT: i64 = 1024;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DeltaQuery {
+    pub since_generation: i64,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Default)]
+pub struct Delta {
+    pub vorgaenge: Vec<models::Vorgang>,
+    pub sitzungen: Vec<models::Sitzung>,
+    pub dokumente: Vec<models::Dokument>,
+    /// api_ids of deleted Vorgänge, Sitzungen and Dokumente
+    pub geloescht: Vec<Uuid>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/export/delta", get(export_delta))
+        .with_state(server)
+}
+
+/// ExportDelta - GET /api/v2/export/delta
+#[instrument(skip_all, fields(since=%query.since_generation))]
+async fn export_delta(
+    State(server): State<LTZFArc>,
+    Query(query): Query<DeltaQuery>,
+    headers: HeaderMap,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Collector, APIScope::Admin, APIScope::KeyAdder],
+    )
+    .await?;
+    let limit = query
+        .limit
+        .map(|l| l.clamp(1, DELTA_MAX_LIMIT))
+        .unwrap_or(DELTA_DEFAULT_LIMIT);
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some((generation, complete, delta)) = delta_since(query.since_generation, limit, &mut tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!(
+            "Generation {} lies outside of the delta window of {} days",
+            query.since_generation, DELTA_WINDOW_DAYS
+        );
+        return Err(StatusCode::GONE);
+    };
+    tx.commit().await.map_err(internal_error)?;
+    info!(
+        "Exported {} Vorgänge, {} Sitzungen and {} Dokumente",
+        delta.vorgaenge.len(),
+        delta.sitzungen.len(),
+        delta.dokumente.len()
+    );
+    let mut response_headers = HeaderMap::new();
+    for (name, value) in [
+        ("x-generation", generation.to_string()),
+        ("x-delta-complete", complete.to_string()),
+    ] {
+        response_headers.insert(
+            name,
+            HeaderValue::from_str(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+    Ok((response_headers, Json(delta)).into_response())
+}
+
+/// returns the generation up to which the delta goes, whether it contains all changes after `since`
+/// and at most `limit` objects modified after `since`, oldest first.
+/// Returns None if there are changes after `since` that are older than the delta window,
+/// meaning the delta would be incomplete.
+pub async fn delta_since(
+    since: i64,
+    limit: i64,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Option<(i64, bool, Delta)>> {
+    // the sequence is read before the locks: a transaction that takes its first generation after
+    // this read gets a larger generation, one that took it before already holds its lock
+    let last_value = sqlx::query!(
+        "SELECT CASE WHEN is_called THEN last_value ELSE 0 END AS \"generation!\"
+        FROM data_generation_seq"
+    )
+    .map(|r| r.generation)
+    .fetch_one(&mut **tx)
+    .await?;
+    let in_flight = sqlx::query!(
+        "SELECT MIN(objid::INT8) AS floor FROM pg_locks
+        WHERE locktype = 'advisory' AND classid::INT8 = $1 AND objsubid = 2
+        AND pid <> pg_backend_pid()",
+        GENERATION_IN_FLIGHT_LOCK
+    )
+    .map(|r| r.floor)
+    .fetch_one(&mut **tx)
+    .await?;
+    let current = match in_flight {
+        Some(floor) => last_value.min(floor - 1),
+        None => last_value,
+    };
+    let window = chrono::Utc::now() - chrono::Duration::days(DELTA_WINDOW_DAYS);
+    let outdated = sqlx::query!(
+        "SELECT EXISTS(SELECT 1 FROM vorgang WHERE generation > $1 AND generation_at < $2)
+        OR EXISTS(SELECT 1 FROM sitzung WHERE generation > $1 AND generation_at < $2)
+        OR EXISTS(SELECT 1 FROM dokument WHERE generation > $1 AND generation_at < $2)
+        OR EXISTS(SELECT 1 FROM data_tombstone WHERE generation > $1 AND generation_at < $2) as outdated",
+        since,
+        window
+    )
+    .map(|r| r.outdated.unwrap_or(false))
+    .fetch_one(&mut **tx)
+    .await?;
+    if outdated {
+        return Ok(None);
+    }
+
+    // generations are unique across the three tables, so cutting off after `limit` rows
+    // and continuing from the last included generation neither skips nor repeats objects
+    let mut changed = sqlx::query!(
+        "SELECT 'vorgang' AS \"kind!\", id AS \"id?\", NULL::UUID AS \"api_id?\", generation AS \"generation!\"
+            FROM vorgang WHERE generation > $1 AND generation <= $3
+        UNION ALL SELECT 'sitzung', id, NULL, generation FROM sitzung
+            WHERE generation > $1 AND generation <= $3
+        UNION ALL SELECT 'dokument', id, NULL, generation FROM dokument
+            WHERE generation > $1 AND generation <= $3
+        UNION ALL SELECT 'geloescht', NULL, api_id, generation FROM data_tombstone
+            WHERE generation > $1 AND generation <= $3
+        ORDER BY 4 ASC
+        LIMIT $2",
+        since,
+        limit + 1,
+        current
+    )
+    .map(|r| (r.kind, r.id, r.api_id, r.generation))
+    .fetch_all(&mut **tx)
+    .await?;
+    let complete = changed.len() as i64 <= limit;
+    changed.truncate(limit as usize);
+    let generation = if complete {
+        current.max(since)
+    } else {
+        changed.last().map(|c| c.3).unwrap_or(since)
+    };
+
+    let mut delta = Delta::default();
+    for (kind, id, api_id, _) in changed {
+        match (kind.as_str(), id, api_id) {
+            ("vorgang", Some(id), _) => {
+                delta.vorgaenge.push(retrieve::vorgang_by_id(id, tx).await?)
+            }
+            ("sitzung", Some(id), _) => {
+                delta.sitzungen.push(retrieve::sitzung_by_id(id, tx).await?)
+            }
+            ("dokument", Some(id), _) => delta
+                .dokumente
+                .push(retrieve::dokument_by_id(id, tx).await?),
+            (_, _, Some(api_id)) => delta.geloescht.push(api_id),
+            _ => unreachable!("every delta row has an id or an api_id"),
+        }
+    }
+    Ok(Some((generation, complete, delta)))
+}
+
+#[cfg(test)]
+mod test_export {
+    use uuid::Uuid;
+
+    use super::delta_since;
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_delta_since() {
+        let scenario = TestSetup::new("test_delta_since").await;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (start, complete, delta) = delta_since(0, 1024, &mut tx).await.unwrap().unwrap();
+        assert!(complete);
+        assert!(delta.vorgaenge.is_empty());
+        tx.commit().await.unwrap();
+
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (current, complete, delta) = delta_since(start, 1024, &mut tx).await.unwrap().unwrap();
+        assert!(current > start && complete);
+        assert_eq!(delta.vorgaenge.len(), 1);
+        assert_eq!(delta.vorgaenge[0].api_id, vorgang.api_id);
+        assert!(!delta.dokumente.is_empty());
+
+        let (_, _, delta) = delta_since(current, 1024, &mut tx).await.unwrap().unwrap();
+        assert!(delta.vorgaenge.is_empty() && delta.dokumente.is_empty());
+
+        // updates that do not change anything keep the generation
+        sqlx::query!("UPDATE vorgang SET titel = titel")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let (unchanged, _, delta) = delta_since(current, 1024, &mut tx).await.unwrap().unwrap();
+        assert_eq!(unchanged, current);
+        assert!(delta.vorgaenge.is_empty());
+
+        // a limited delta is continued from the returned generation
+        let total = delta_since(start, 1024, &mut tx)
+            .await
+            .unwrap()
+            .map(|(_, _, d)| d.vorgaenge.len() + d.sitzungen.len() + d.dokumente.len())
+            .unwrap();
+        let (mut since, mut seen) = (start, 0);
+        loop {
+            let (generation, complete, d) = delta_since(since, 1, &mut tx).await.unwrap().unwrap();
+            seen += d.vorgaenge.len() + d.sitzungen.len() + d.dokumente.len();
+            since = generation;
+            if complete {
+                break;
+            }
+        }
+        assert_eq!((seen, since), (total, current));
+
+        // deleted objects are reported by their api_id
+        sqlx::query!("DELETE FROM vorgang WHERE api_id = $1", vorgang.api_id)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        let (deleted, _, delta) = delta_since(current, 1024, &mut tx).await.unwrap().unwrap();
+        assert!(deleted > current);
+        assert_eq!(delta.geloescht, vec![vorgang.api_id]);
+        assert!(delta.vorgaenge.is_empty());
+
+        // changes that are older than the delta window cannot be served
+        sqlx::query("ALTER TABLE vorgang DISABLE TRIGGER trg_generation_vorgang")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        sqlx::query!("UPDATE vorgang SET generation_at = NOW() - '8 days'::interval")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        assert!(delta_since(start, 1024, &mut tx).await.unwrap().is_none());
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_delta_stays_below_running_writers() {
+        let scenario = TestSetup::new("test_delta_stays_below_running_writers").await;
+        let server = &scenario.server;
+        run_integration(&generate::default_vorgang(), Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let mut reader = server.sqlx_db.begin().await.unwrap();
+        let (before, _, _) = delta_since(0, 1024, &mut reader).await.unwrap().unwrap();
+        reader.rollback().await.unwrap();
+
+        // a change that is not committed yet must not be skipped by the next delta
+        let mut writer = server.sqlx_db.begin().await.unwrap();
+        sqlx::query!("UPDATE vorgang SET titel = titel || ' (geändert)'")
+            .execute(&mut *writer)
+            .await
+            .unwrap();
+        let mut reader = server.sqlx_db.begin().await.unwrap();
+        let (generation, complete, delta) = delta_since(before, 1024, &mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(complete && delta.vorgaenge.is_empty());
+        assert_eq!(generation, before);
+        reader.rollback().await.unwrap();
+        writer.commit().await.unwrap();
+
+        let mut reader = server.sqlx_db.begin().await.unwrap();
+        let (generation, _, delta) = delta_since(before, 1024, &mut reader)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(generation > before);
+        assert_eq!(delta.vorgaenge.len(), 1);
+        reader.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
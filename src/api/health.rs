@@ -0,0 +1,123 @@
+//! Runtime health information complementing the `status` endpoint.
+//!
+//! - `GET /api/v2/health` reports the state of the database pool, with 503 if the database
+//!   does not answer
+//! - `GET /api/v2/version` reports the versions of the server and the database schema
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, instrument, warn};
+
+use crate::api::{HandlerResult, LTZFServer, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HealthStatus {
+    pub pool_max: u32,
+    pub pool_size: u32,
+    pub pool_idle: u32,
+    pub pool_acquire_timeout_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VersionInfo {
+    pub server_version: String,
+    /// the latest successfully applied migration, None before the first one
+    pub schema_version: Option<i64>,
+    pub build_timestamp: String,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/health", get(health_get))
+        .route("/api/v2/version", get(version_get))
+        .with_state(server)
+}
+
+pub fn health_status(server: &LTZFServer) -> HealthStatus {
+    let options = server.sqlx_db.options();
+    HealthStatus {
+        pool_max: options.get_max_connections(),
+        pool_size: server.sqlx_db.size(),
+        pool_idle: server.sqlx_db.num_idle() as u32,
+        pool_acquire_timeout_ms: options.get_acquire_timeout().as_millis() as u64,
+    }
+}
+
+/// whether a connection of the pool answers a query within the acquire timeout
+pub async fn database_reachable(server: &LTZFServer) -> bool {
+    match sqlx::query("SELECT 1").execute(&server.sqlx_db).await {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("Health check could not reach the database: {e}");
+            false
+        }
+    }
+}
+
+/// HealthGet - GET /api/v2/health
+#[instrument(skip_all)]
+async fn health_get(State(server): State<LTZFArc>) -> HandlerResult {
+    let status = health_status(&server);
+    debug!("Health Requested: {:?}", status);
+    if !database_reachable(&server).await {
+        return Ok((StatusCode::SERVICE_UNAVAILABLE, Json(status)).into_response());
+    }
+    Ok(Json(status).into_response())
+}
+
+pub async fn version_info(server: &LTZFServer) -> Result<VersionInfo> {
+    let schema_version =
+        sqlx::query!("SELECT MAX(version) as version FROM _sqlx_migrations WHERE success")
+            .map(|r| r.version)
+            .fetch_one(&server.sqlx_db)
+            .await?;
+    Ok(VersionInfo {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        schema_version,
+        build_timestamp: env!("LTZF_BUILD_TIMESTAMP").to_string(),
+    })
+}
+
+/// VersionGet - GET /api/v2/version
+#[instrument(skip_all)]
+async fn version_get(State(server): State<LTZFArc>) -> HandlerResult {
+    let version = version_info(&server).await.map_err(internal_error)?;
+    debug!("Version Requested: {:?}", version);
+    Ok(Json(version).into_response())
+}
+
+#[cfg(test)]
+mod test_health {
+    use super::{database_reachable, health_status, version_info};
+    use crate::utils::testing::TestSetup;
+
+    #[tokio::test]
+    async fn test_health_status() {
+        let scenario = TestSetup::new("test_health_status").await;
+        let status = health_status(&scenario.server);
+        assert!(status.pool_size <= status.pool_max);
+        assert!(status.pool_idle <= status.pool_size);
+        assert!(database_reachable(&scenario.server).await);
+        scenario.server.sqlx_db.close().await;
+        assert!(!database_reachable(&scenario.server).await);
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_version_info() {
+        let scenario = TestSetup::new("test_version_info").await;
+        let version = version_info(&scenario.server).await.unwrap();
+        assert_eq!(version.server_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(
+            version.schema_version,
+            crate::MIGRATOR.iter().map(|m| m.version).max()
+        );
+        assert!(chrono::DateTime::parse_from_rfc3339(&version.build_timestamp).is_ok());
+        scenario.teardown().await;
+    }
+}
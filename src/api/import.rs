@@ -0,0 +1,414 @@
+//! Two-phase import of large Vorgang sets.
+//!
+//! These endpoints are not part of the generated OpenAPI server and are
+//! therefore implemented as plain axum handlers and merged into the router in main.
+//!
+//! 1. `POST /api/v2/import/begin` opens a job for the scraper given in `X-Scraper-Id`
+//! 2. `PUT /api/v2/import/{job_id}/chunk` enqueues a batch of Vorgang objects
+//! 3. `POST /api/v2/import/{job_id}/commit` closes the job and starts processing it in the background
+//! 4. `GET /api/v2/import/{job_id}/status` reports the state and progress of the job
+//!
+//! Jobs and their queues live in the database, committed jobs that were not finished
+//! when the server stopped are resumed at startup (see `resume_import_jobs`).
+
+use std::str::FromStr;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post, put};
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{Instrument, debug, error, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::HandlerResult;
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::internal_error;
+use crate::db::KeyIndex;
+use crate::db::merge;
+use crate::error::LTZFError;
+use crate::{LTZFArc, LTZFServer, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportJob {
+    pub job_id: Uuid,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportState {
+    /// still accepts chunks
+    Open,
+    /// committed, the queue is being worked through
+    Committed,
+    /// all entries were processed
+    Finished,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ImportStatus {
+    pub state: ImportState,
+    pub processed: i64,
+    pub total: i64,
+    pub errors: Vec<String>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/import/begin", post(import_begin))
+        .route("/api/v2/import/{job_id}/chunk", put(import_chunk))
+        .route("/api/v2/import/{job_id}/commit", post(import_commit))
+        .route("/api/v2/import/{job_id}/status", get(import_status))
+        .with_state(server)
+}
+
+async fn authorize(
+    server: &LTZFServer,
+    headers: &HeaderMap,
+) -> std::result::Result<crate::api::Claims, StatusCode> {
+    authorize_scopes(server, headers, &[APIScope::Admin, APIScope::KeyAdder]).await
+}
+
+/// ImportBegin - POST /api/v2/import/begin
+#[instrument(skip_all)]
+async fn import_begin(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    let claims = authorize(&server, &headers).await?;
+    let scraper = headers
+        .get("x-scraper-id")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| Uuid::from_str(h).ok());
+    let Some(scraper) = scraper else {
+        warn!("Import job requested without a valid X-Scraper-Id header");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let job_id = Uuid::now_v7();
+    sqlx::query!(
+        "INSERT INTO import_job(api_id, scraper, created_by) VALUES ($1, $2, $3)",
+        job_id,
+        scraper,
+        claims.1
+    )
+    .execute(&server.sqlx_db)
+    .await
+    .map_err(internal_error)?;
+    info!(target: "obj", "Opened import job {} for scraper {}", job_id, scraper);
+    Ok((StatusCode::CREATED, Json(ImportJob { job_id })).into_response())
+}
+
+/// ImportChunk - PUT /api/v2/import/{job_id}/chunk
+#[instrument(skip_all, fields(job=%job_id, n=body.len()))]
+async fn import_chunk(
+    State(server): State<LTZFArc>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(body): Json<Vec<models::Vorgang>>,
+) -> HandlerResult {
+    authorize(&server, &headers).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let job = sqlx::query!(
+        "SELECT id, committed_at FROM import_job WHERE api_id = $1 FOR UPDATE",
+        job_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    let Some(job) = job else {
+        debug!("Import job {} not found", job_id);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if job.committed_at.is_some() {
+        warn!(
+            "Tried to add a chunk to the already committed import job {}",
+            job_id
+        );
+        return Err(StatusCode::CONFLICT);
+    }
+    let payload = serde_json::to_string(&body)
+        .map_err(|e| internal_error(LTZFError::other(e.to_string())))?;
+    sqlx::query!(
+        "INSERT INTO import_queue(job_id, payload)
+        SELECT $1, p FROM jsonb_array_elements($2::text::jsonb) p",
+        job.id,
+        payload
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(
+        "Enqueued {} Vorgang objects into import job {}",
+        body.len(),
+        job_id
+    );
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// ImportCommit - POST /api/v2/import/{job_id}/commit
+#[instrument(skip_all, fields(job=%job_id))]
+async fn import_commit(
+    State(server): State<LTZFArc>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> HandlerResult {
+    authorize(&server, &headers).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let job = sqlx::query!(
+        "SELECT id, scraper, created_by, committed_at FROM import_job WHERE api_id = $1 FOR UPDATE",
+        job_id
+    )
+    .fetch_optional(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    let Some(job) = job else {
+        debug!("Import job {} not found", job_id);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    if job.committed_at.is_some() {
+        warn!("Import job {} was already committed", job_id);
+        return Err(StatusCode::CONFLICT);
+    }
+    sqlx::query!(
+        "UPDATE import_job SET committed_at = NOW() WHERE id = $1",
+        job.id
+    )
+    .execute(&mut *tx)
+    .await
+    .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+
+    spawn_import_job(server.clone(), job.id, job.scraper, job.created_by);
+    info!("Committed import job {}, processing started", job_id);
+    Ok(StatusCode::ACCEPTED.into_response())
+}
+
+/// ImportStatus - GET /api/v2/import/{job_id}/status
+#[instrument(skip_all, fields(job=%job_id))]
+async fn import_status(
+    State(server): State<LTZFArc>,
+    Path(job_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> HandlerResult {
+    authorize(&server, &headers).await?;
+    let Some(id) = sqlx::query!("SELECT id FROM import_job WHERE api_id = $1", job_id)
+        .map(|r| r.id)
+        .fetch_optional(&server.sqlx_db)
+        .await
+        .map_err(internal_error)?
+    else {
+        debug!("Import job {} not found", job_id);
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let status = import_job_status(&server, id)
+        .await
+        .map_err(internal_error)?;
+    Ok(Json(status).into_response())
+}
+
+/// processes the committed import job in the background
+fn spawn_import_job(server: LTZFArc, job: i32, scraper: Uuid, collector_key: KeyIndex) {
+    let current = tracing::span::Span::current().clone();
+    tokio::spawn(
+        async move {
+            if let Err(e) = process_import_job(&server, job, scraper, collector_key).await {
+                error!("Processing of import job {} was aborted: {}", job, e);
+            }
+        }
+        .instrument(current),
+    );
+}
+
+/// committed import jobs that were not finished, as (id, scraper, created_by)
+pub(crate) async fn unfinished_import_jobs(
+    server: &LTZFServer,
+) -> Result<Vec<(i32, Uuid, KeyIndex)>> {
+    let jobs = sqlx::query!(
+        "SELECT id, scraper, created_by FROM import_job
+        WHERE committed_at IS NOT NULL AND finished_at IS NULL ORDER BY id ASC"
+    )
+    .map(|r| (r.id, r.scraper, r.created_by))
+    .fetch_all(&server.sqlx_db)
+    .await?;
+    Ok(jobs)
+}
+
+/// continues the processing of all jobs that were committed but not finished,
+/// e.g. because the server was restarted while they were processed
+pub async fn resume_import_jobs(server: LTZFArc) -> Result<()> {
+    let jobs = unfinished_import_jobs(&server).await?;
+    if !jobs.is_empty() {
+        info!("Resuming {} unfinished import jobs", jobs.len());
+    }
+    for (job, scraper, collector_key) in jobs {
+        spawn_import_job(server.clone(), job, scraper, collector_key);
+    }
+    Ok(())
+}
+
+pub(crate) async fn import_job_status(server: &LTZFServer, job: i32) -> Result<ImportStatus> {
+    let times = sqlx::query!(
+        "SELECT committed_at, finished_at FROM import_job WHERE id = $1",
+        job
+    )
+    .fetch_one(&server.sqlx_db)
+    .await?;
+    let state = match (times.committed_at, times.finished_at) {
+        (_, Some(_)) => ImportState::Finished,
+        (Some(_), None) => ImportState::Committed,
+        (None, None) => ImportState::Open,
+    };
+    let counts = sqlx::query!(
+        "SELECT COUNT(*) FILTER (WHERE processed) as \"processed!\", COUNT(*) as \"total!\"
+        FROM import_queue WHERE job_id = $1",
+        job
+    )
+    .fetch_one(&server.sqlx_db)
+    .await?;
+    let errors = sqlx::query!(
+        "SELECT error as \"error!\" FROM import_queue WHERE job_id = $1 AND error IS NOT NULL ORDER BY id ASC",
+        job
+    )
+    .map(|r| r.error)
+    .fetch_all(&server.sqlx_db)
+    .await?;
+    Ok(ImportStatus {
+        state,
+        processed: counts.processed,
+        total: counts.total,
+        errors,
+    })
+}
+
+/// works through all unprocessed entries of an import job in order of insertion.
+/// Each entry is integrated in its own transaction, failures are recorded on the entry
+/// and do not abort the job.
+pub(crate) async fn process_import_job(
+    server: &LTZFServer,
+    job: i32,
+    scraper: Uuid,
+    collector_key: KeyIndex,
+) -> Result<()> {
+    let entries = sqlx::query!(
+        "SELECT id FROM import_queue WHERE job_id = $1 AND NOT processed ORDER BY id ASC",
+        job
+    )
+    .map(|r| r.id)
+    .fetch_all(&server.sqlx_db)
+    .await?;
+    debug!("Processing {} entries of import job {}", entries.len(), job);
+    for entry in entries {
+        let payload = sqlx::query!(
+            "SELECT payload::text as \"payload!\" FROM import_queue WHERE id = $1",
+            entry
+        )
+        .map(|r| r.payload)
+        .fetch_one(&server.sqlx_db)
+        .await?;
+        let outcome = match serde_json::from_str::<models::Vorgang>(&payload) {
+            Ok(vg) => merge::execute::run_integration(&vg, scraper, collector_key, server).await,
+            Err(e) => Err(LTZFError::other(format!(
+                "Malformed import queue entry: {e}"
+            ))),
+        };
+        let error = outcome.err().map(|e| {
+            warn!("Import of queue entry {} failed: {}", entry, e);
+            e.to_string()
+        });
+        sqlx::query!(
+            "UPDATE import_queue SET processed = true, error = $2 WHERE id = $1",
+            entry,
+            error
+        )
+        .execute(&server.sqlx_db)
+        .await?;
+    }
+    sqlx::query!(
+        "UPDATE import_job SET finished_at = NOW() WHERE id = $1",
+        job
+    )
+    .execute(&server.sqlx_db)
+    .await?;
+    info!(target: "obj", "Finished import job {}", job);
+    Ok(())
+}
+
+#[cfg(test)]
+mod import_test {
+    use uuid::Uuid;
+
+    use super::{ImportState, import_job_status, process_import_job, unfinished_import_jobs};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_process_import_job() {
+        let scenario = TestSetup::new("test_process_import_job").await;
+        let server = &scenario.server;
+        let vgs = vec![generate::default_vorgang(), generate::random::vorgang(17)];
+        let job = sqlx::query!(
+            "INSERT INTO import_job(api_id, scraper, created_by, committed_at) VALUES ($1, $2, 1, NOW()) RETURNING id",
+            Uuid::now_v7(),
+            Uuid::nil()
+        )
+        .map(|r| r.id)
+        .fetch_one(&server.sqlx_db)
+        .await
+        .unwrap();
+        let mut payloads: Vec<String> = vgs
+            .iter()
+            .map(|vg| serde_json::to_string(vg).unwrap())
+            .collect();
+        payloads.push("{\"not\": \"a vorgang\"}".to_string());
+        sqlx::query!(
+            "INSERT INTO import_queue(job_id, payload) SELECT $1, p::jsonb FROM UNNEST($2::text[]) p",
+            job,
+            &payloads[..]
+        )
+        .execute(&server.sqlx_db)
+        .await
+        .unwrap();
+
+        let status = import_job_status(server, job).await.unwrap();
+        assert_eq!((status.processed, status.total), (0, 3));
+        assert_eq!(status.state, ImportState::Committed);
+        let open = sqlx::query!(
+            "INSERT INTO import_job(api_id, scraper, created_by) VALUES ($1, $2, 1) RETURNING id",
+            Uuid::now_v7(),
+            Uuid::nil()
+        )
+        .map(|r| r.id)
+        .fetch_one(&server.sqlx_db)
+        .await
+        .unwrap();
+        assert_eq!(
+            import_job_status(server, open).await.unwrap().state,
+            ImportState::Open
+        );
+        let unfinished = unfinished_import_jobs(server).await.unwrap();
+        assert_eq!(unfinished, vec![(job, Uuid::nil(), 1)]);
+
+        process_import_job(server, job, Uuid::nil(), 1)
+            .await
+            .unwrap();
+
+        let status = import_job_status(server, job).await.unwrap();
+        assert_eq!((status.processed, status.total), (3, 3));
+        assert_eq!(status.state, ImportState::Finished);
+        assert!(unfinished_import_jobs(server).await.unwrap().is_empty());
+        assert_eq!(status.errors.len(), 1, "{:?}", status.errors);
+        for vg in vgs.iter() {
+            let found = sqlx::query!("SELECT 1 as x FROM vorgang WHERE api_id = $1", vg.api_id)
+                .fetch_optional(&server.sqlx_db)
+                .await
+                .unwrap();
+            assert!(found.is_some(), "Vorgang {} was not imported", vg.api_id);
+        }
+        let finished = sqlx::query!("SELECT finished_at FROM import_job WHERE id = $1", job)
+            .map(|r| r.finished_at)
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap();
+        assert!(finished.is_some());
+        scenario.teardown().await;
+    }
+}
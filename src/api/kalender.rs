@@ -0,0 +1,231 @@
+//! Submission of the Sitzungen of a whole ISO week in one request, since calendar scrapers
+//! usually collect week by week and would otherwise need one `kal_date_put` per day.
+//!
+//! - `PUT /api/v2/kalender/{parlament}/{year}/{week}` replaces the Sitzungen of the given parliament
+//!   in the week from Monday to Sunday by a json array, like `kal_date_put` does for a single day
+
+use std::str::FromStr;
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::put;
+use chrono::NaiveDate;
+use openapi::models;
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::db::KeyIndex;
+use crate::db::merge;
+use crate::error::LTZFError;
+use crate::{LTZFArc, LTZFServer, Result};
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/kalender/{parlament}/{year}/{week}",
+            put(kal_week_put),
+        )
+        .with_state(server)
+}
+
+/// first and last day (Monday and Sunday) of an ISO week, None if the week does not exist
+pub fn iso_week_range(year: i32, week: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let monday = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Mon)?;
+    let sunday = NaiveDate::from_isoywd_opt(year, week, chrono::Weekday::Sun)?;
+    Some((monday, sunday))
+}
+
+/// KalWeekPut - PUT /api/v2/kalender/{parlament}/{year}/{week}
+#[instrument(skip_all, fields(parl=%parlament, year=%year, week=%week))]
+async fn kal_week_put(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path((parlament, year, week)): Path<(String, i32, u32)>,
+    Json(body): Json<Vec<models::Sitzung>>,
+) -> HandlerResult {
+    let claims = authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Admin, APIScope::KeyAdder, APIScope::Collector],
+    )
+    .await?;
+    let Ok(parlament) = models::Parlament::from_str(&parlament) else {
+        warn!("Unknown parlament `{parlament}`");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let Some(week_range) = iso_week_range(year, week) else {
+        warn!("Week {week} does not exist in {year}");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let scraper = headers
+        .get("x-scraper-id")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| Uuid::from_str(h).ok());
+    let Some(scraper) = scraper else {
+        warn!("Sitzungen submitted without a valid X-Scraper-Id header");
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let last_upd_day = chrono::Utc::now()
+        .date_naive()
+        .checked_sub_days(chrono::Days::new(1))
+        .unwrap();
+    if claims.0 == APIScope::Collector && week_range.1 < last_upd_day {
+        warn!(
+            "Permission not Granted because you are only a collector and {} < {}",
+            week_range.1, last_upd_day
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let merged = kal_week_merge(
+        &body,
+        parlament,
+        week_range,
+        last_upd_day,
+        scraper,
+        claims.1,
+        &server,
+    )
+    .await
+    .map_err(internal_error)?;
+    info!("Merged {merged} sessions into the database");
+    Ok(StatusCode::CREATED.into_response())
+}
+
+/// replaces the Sitzungen of `parlament` taking place in `week_range` and not before `not_before`
+/// by the submitted ones, the others are filtered out. Returns the number of merged Sitzungen.
+pub async fn kal_week_merge(
+    body: &[models::Sitzung],
+    parlament: models::Parlament,
+    week_range: (NaiveDate, NaiveDate),
+    not_before: NaiveDate,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    server: &LTZFServer,
+) -> Result<usize> {
+    let from = week_range.0.max(not_before);
+    if from > week_range.1 {
+        return Ok(0);
+    }
+    let len = body.len();
+    let body: Vec<_> = body
+        .iter()
+        .filter(|&s| {
+            let date = s.termin.date_naive();
+            date >= from && date <= week_range.1 && s.gremium.parlament == parlament
+        })
+        .cloned()
+        .collect();
+    if len != body.len() {
+        debug!(
+            "Filtered {}/{} Sitzungen due to date and parlament equality constraints",
+            len - body.len(),
+            len
+        );
+    }
+
+    // the submission replaces the week: entries are merged into existing ones,
+    // sitzungen of that week only this scraper submitted and that are missing now are deleted
+    let range = (
+        from.and_time(chrono::NaiveTime::MIN).and_utc(),
+        (week_range.1 + chrono::Days::new(1))
+            .and_time(chrono::NaiveTime::MIN)
+            .and_utc(),
+    );
+    let body = &body;
+    crate::db::retry_on_conflict(|| async move {
+        let mut tx = crate::db::begin_merge(server).await?;
+        merge::sitzung::replace_sitzungen_in_range(
+            body,
+            parlament,
+            range,
+            scraper_id,
+            collector_key,
+            &mut tx,
+            server,
+        )
+        .await?;
+        tx.commit().await?;
+        Ok::<_, LTZFError>(())
+    })
+    .await?;
+    info!(target: "obj", "Merged sitzungen into db: {:?}", body);
+    Ok(body.len())
+}
+
+#[cfg(test)]
+mod test_kalender_week {
+    use chrono::{Datelike, NaiveDate};
+    use uuid::Uuid;
+
+    use super::{iso_week_range, kal_week_merge};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_iso_week_range() {
+        assert_eq!(
+            iso_week_range(2025, 1),
+            Some((
+                NaiveDate::from_ymd_opt(2024, 12, 30).unwrap(),
+                NaiveDate::from_ymd_opt(2025, 1, 5).unwrap()
+            ))
+        );
+        assert!(iso_week_range(2020, 53).is_some());
+        assert_eq!(iso_week_range(2025, 53), None);
+        assert_eq!(iso_week_range(2025, 0), None);
+    }
+
+    #[tokio::test]
+    async fn test_kal_week_merge() {
+        let scenario = TestSetup::new("test_kal_week_merge").await;
+        let server = &scenario.server;
+        let mut inside = generate::default_sitzung();
+        let date = chrono::Utc::now().date_naive() + chrono::Days::new(7);
+        inside.termin = date.and_hms_opt(10, 0, 0).unwrap().and_utc();
+        let mut outside = inside.clone();
+        outside.api_id = Some(Uuid::now_v7());
+        outside.termin = inside.termin + chrono::Days::new(7);
+        let parlament = inside.gremium.parlament;
+        // a cancelled Sitzung of the same week, missing from the submission
+        let mut cancelled = inside.clone();
+        cancelled.api_id = Some(Uuid::now_v7());
+        cancelled.nummer += 1;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        crate::db::insert::insert_sitzung(&cancelled, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let week = iso_week_range(date.iso_week().year(), date.iso_week().week()).unwrap();
+        let merged = kal_week_merge(
+            &[inside.clone(), outside],
+            parlament,
+            week,
+            chrono::Utc::now().date_naive(),
+            Uuid::nil(),
+            1,
+            server,
+        )
+        .await
+        .unwrap();
+        assert_eq!(merged, 1);
+        let count = sqlx::query!("SELECT COUNT(*) as \"c!\" FROM sitzung")
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap()
+            .c;
+        assert_eq!(count, 1);
+        let cancelled_left = sqlx::query!(
+            "SELECT 1 as x FROM sitzung WHERE api_id = $1",
+            cancelled.api_id.unwrap()
+        )
+        .fetch_optional(&server.sqlx_db)
+        .await
+        .unwrap();
+        assert!(cancelled_left.is_none());
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,435 @@
+//! Administration of the API keys.
+//!
+//! - `GET /api/v2/admin/keys` lists all active keys, identified by their keytag only
+//! - `DELETE /api/v2/admin/keys/{keytag}` revokes a key, which is rejected from then on
+//! - `PUT /api/v2/admin/keys/{keytag}/allowed_ips` restricts a key to a list of networks, `null` lifts the restriction
+//!
+//! Keys are created with `POST /api/v2/auth` of the generated server, see `create_key`.
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get, put};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{Claims, HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeyInfo {
+    pub keytag: String,
+    pub scope: String,
+    pub created_at: DateTime<Utc>,
+    pub last_used: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CreatedKey {
+    /// the full key, only the hash is stored
+    pub key: String,
+    pub keytag: String,
+    pub scope: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AllowedIps {
+    /// networks in CIDR notation, a single address is a network of its own
+    pub allowed_ips: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Revocation {
+    Revoked,
+    NotFound,
+    OwnKey,
+    PermissionTooLow,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/keys", get(keys_list))
+        .route("/api/v2/admin/keys/{keytag}", delete(keys_revoke))
+        .route(
+            "/api/v2/admin/keys/{keytag}/allowed_ips",
+            put(keys_allowed_ips),
+        )
+        .with_state(server)
+}
+
+/// KeysList - GET /api/v2/admin/keys
+#[instrument(skip_all)]
+async fn keys_list(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let keys = active_keys(&mut tx).await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Listed {} active keys", keys.len());
+    Ok(Json(keys).into_response())
+}
+
+/// KeysRevoke - DELETE /api/v2/admin/keys/{keytag}
+#[instrument(skip_all, fields(keytag=%keytag))]
+async fn keys_revoke(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(keytag): Path<String>,
+) -> HandlerResult {
+    let claims =
+        authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let outcome = revoke_key(&keytag, claims, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    match outcome {
+        Revocation::Revoked => {
+            info!("Revoked key");
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        Revocation::NotFound => {
+            warn!("No active key with this keytag");
+            Err(StatusCode::NOT_FOUND)
+        }
+        Revocation::OwnKey => {
+            warn!("Refusing to revoke the key used for this request");
+            Err(StatusCode::BAD_REQUEST)
+        }
+        Revocation::PermissionTooLow => {
+            warn!("Admin keys cannot revoke keyadder keys");
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// KeysAllowedIps - PUT /api/v2/admin/keys/{keytag}/allowed_ips
+#[instrument(skip_all, fields(keytag=%keytag))]
+async fn keys_allowed_ips(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(keytag): Path<String>,
+    Json(body): Json<AllowedIps>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let networks = match body.allowed_ips.as_ref().map(|ips| parse_networks(ips)) {
+        None => None,
+        Some(Ok(networks)) => Some(networks),
+        Some(Err(invalid)) => {
+            warn!("Not a network in CIDR notation: {invalid}");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let found = set_allowed_ips(&keytag, networks.as_deref(), &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    if !found {
+        warn!("No active key with this keytag");
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!("Set allowed networks of key to {:?}", networks);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// normalises addresses and networks to CIDR notation, returning the first invalid entry on error
+fn parse_networks(ips: &[String]) -> std::result::Result<Vec<String>, String> {
+    ips.iter()
+        .map(|ip| {
+            ip.parse::<ipnet::IpNet>()
+                .or_else(|_| ip.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .map(|net| net.trunc().to_string())
+                .map_err(|_| ip.clone())
+        })
+        .collect()
+}
+
+/// generates and stores a new key created by the key `created_by`
+pub async fn create_key(
+    scope: APIScope,
+    expires_at: DateTime<Utc>,
+    created_by: i32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<CreatedKey> {
+    let (key, salt) = crate::utils::auth::find_new_key(tx).await?;
+    let keytag = crate::utils::auth::keytag_of(&key);
+    sqlx::query!(
+        "INSERT INTO api_keys(key_hash, created_by, expires_at, scope, salt, keytag)
+        VALUES ($1, $2, $3, (SELECT id FROM api_scope WHERE value = $4), $5, $6)",
+        crate::utils::auth::hash_full_key(&salt, &key),
+        created_by,
+        expires_at,
+        scope.to_string(),
+        salt,
+        keytag
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(CreatedKey {
+        key,
+        keytag,
+        scope: scope.to_string(),
+        expires_at,
+    })
+}
+
+/// sets or, with `None`, clears the allowed networks of the active key with `keytag`.
+/// Returns false if there is no such key.
+pub async fn set_allowed_ips(
+    keytag: &str,
+    networks: Option<&[String]>,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<bool> {
+    let updated = sqlx::query!(
+        "UPDATE api_keys SET allowed_ips = $2::text[]::cidr[]
+        WHERE keytag = $1 AND deleted_by IS NULL AND revoked_at IS NULL",
+        keytag,
+        networks as Option<&[String]>
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(updated.rows_affected() > 0)
+}
+
+/// lists all keys that are neither revoked, rotated nor expired. The hash is never exposed.
+pub async fn active_keys(tx: &mut sqlx::PgTransaction<'_>) -> Result<Vec<KeyInfo>> {
+    Ok(sqlx::query!(
+        "SELECT k.keytag, s.value as scope, k.created_at, k.last_used, k.expires_at
+        FROM api_keys k
+        INNER JOIN api_scope s ON s.id = k.scope
+        WHERE k.deleted_by IS NULL AND k.revoked_at IS NULL AND k.expires_at > NOW()
+        ORDER BY k.created_at ASC"
+    )
+    .map(|r| KeyInfo {
+        keytag: r.keytag,
+        scope: r.scope,
+        created_at: r.created_at,
+        last_used: r.last_used,
+        expires_at: r.expires_at,
+    })
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// revokes the active key with `keytag` on behalf of `claims`.
+/// Keys cannot revoke themselves and only keyadder keys may revoke other keyadder keys.
+pub async fn revoke_key(
+    keytag: &str,
+    claims: Claims,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Revocation> {
+    let Some(target) = sqlx::query!(
+        "SELECT k.id, s.value as scope FROM api_keys k
+        INNER JOIN api_scope s ON s.id = k.scope
+        WHERE k.keytag = $1 AND k.deleted_by IS NULL AND k.revoked_at IS NULL",
+        keytag
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    else {
+        return Ok(Revocation::NotFound);
+    };
+    if target.id == claims.1 {
+        return Ok(Revocation::OwnKey);
+    }
+    if APIScope::try_from(target.scope.as_str())? == APIScope::KeyAdder
+        && claims.0 != APIScope::KeyAdder
+    {
+        return Ok(Revocation::PermissionTooLow);
+    }
+    sqlx::query!(
+        "UPDATE api_keys SET deleted_by = $1, revoked_at = NOW() WHERE id = $2",
+        claims.1,
+        target.id
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(Revocation::Revoked)
+}
+
+#[cfg(test)]
+mod test_keys {
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+
+    use super::{Revocation, active_keys, create_key, parse_networks, revoke_key, set_allowed_ips};
+    use crate::api::auth::{APIScope, authorize_scopes};
+    use crate::utils::auth::{find_new_key, hash_full_key, keytag_of};
+    use crate::utils::testing::TestSetup;
+
+    #[tokio::test]
+    async fn test_create_key() {
+        let scenario = TestSetup::new("test_create_key").await;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let expires_at = chrono::Utc::now() + chrono::Duration::days(30);
+        let created = create_key(APIScope::Collector, expires_at, 1, &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(created.scope, "collector");
+        assert_eq!(created.keytag, keytag_of(&created.key));
+        let stored = sqlx::query!(
+            "SELECT key_hash, salt FROM api_keys WHERE keytag = $1",
+            created.keytag
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        assert_ne!(stored.key_hash, created.key);
+        assert_eq!(stored.key_hash, hash_full_key(&stored.salt, &created.key));
+        let keys = active_keys(&mut tx).await.unwrap();
+        let info = keys.iter().find(|k| k.keytag == created.keytag).unwrap();
+        assert_eq!(info.expires_at, created.expires_at);
+        tx.commit().await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_str(&created.key).unwrap());
+        let claims = authorize_scopes(server, &headers, &[APIScope::Collector])
+            .await
+            .unwrap();
+        assert_eq!(claims.0, APIScope::Collector);
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_key_revocation() {
+        let scenario = TestSetup::new("test_key_revocation").await;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (key, salt) = find_new_key(&mut tx).await.unwrap();
+        let tag = keytag_of(&key);
+        sqlx::query!(
+            "INSERT INTO api_keys(key_hash, created_by, expires_at, scope, salt, keytag)
+            VALUES ($1, 1, NOW() + '1 day'::interval, (SELECT id FROM api_scope WHERE value = 'collector'), $2, $3)",
+            hash_full_key(&salt, &key),
+            salt,
+            tag
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let keys = active_keys(&mut tx).await.unwrap();
+        assert!(
+            keys.iter()
+                .any(|k| k.keytag == tag && k.scope == "collector")
+        );
+        tx.commit().await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_str(&key).unwrap());
+        let claims = authorize_scopes(server, &headers, &[APIScope::Collector])
+            .await
+            .unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        assert_eq!(
+            revoke_key(&tag, claims, &mut tx).await.unwrap(),
+            Revocation::OwnKey
+        );
+        assert_eq!(
+            revoke_key(&tag, (APIScope::Admin, 1), &mut tx)
+                .await
+                .unwrap(),
+            Revocation::Revoked
+        );
+        assert_eq!(
+            revoke_key(&tag, (APIScope::Admin, 1), &mut tx)
+                .await
+                .unwrap(),
+            Revocation::NotFound
+        );
+        assert!(
+            !active_keys(&mut tx)
+                .await
+                .unwrap()
+                .iter()
+                .any(|k| k.keytag == tag)
+        );
+        tx.commit().await.unwrap();
+
+        assert!(
+            authorize_scopes(server, &headers, &[APIScope::Collector])
+                .await
+                .is_err()
+        );
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_key_allowed_ips() {
+        let mut scenario = TestSetup::new("test_key_allowed_ips").await;
+        scenario.server.config.trusted_proxy_count = 1;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (key, salt) = find_new_key(&mut tx).await.unwrap();
+        let tag = keytag_of(&key);
+        sqlx::query!(
+            "INSERT INTO api_keys(key_hash, created_by, expires_at, scope, salt, keytag)
+            VALUES ($1, 1, NOW() + '1 day'::interval, (SELECT id FROM api_scope WHERE value = 'collector'), $2, $3)",
+            hash_full_key(&salt, &key),
+            salt,
+            tag
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let networks =
+            parse_networks(&["10.1.2.3/8".to_string(), "2001:db8::1".to_string()]).unwrap();
+        assert_eq!(networks, vec!["10.0.0.0/8", "2001:db8::1/128"]);
+        assert!(parse_networks(&["kein netz".to_string()]).is_err());
+        assert!(
+            set_allowed_ips(&tag, Some(&networks[..]), &mut tx)
+                .await
+                .unwrap()
+        );
+        assert!(!set_allowed_ips("unbekannt", None, &mut tx).await.unwrap());
+        tx.commit().await.unwrap();
+
+        let request_from = |ip: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert("x-api-key", HeaderValue::from_str(&key).unwrap());
+            headers.insert("x-forwarded-for", HeaderValue::from_static(ip));
+            headers
+        };
+        assert_eq!(
+            authorize_scopes(server, &request_from("192.168.0.1"), &[APIScope::Collector]).await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        assert!(
+            authorize_scopes(server, &request_from("10.200.0.1"), &[APIScope::Collector])
+                .await
+                .is_ok()
+        );
+        // the client may prepend anything, only the entry of the proxy counts
+        assert_eq!(
+            authorize_scopes(
+                server,
+                &request_from("10.0.0.1, 192.168.0.1"),
+                &[APIScope::Collector]
+            )
+            .await,
+            Err(StatusCode::FORBIDDEN)
+        );
+        // without trusted proxies the header is set by the client and ignored
+        let mut direct = scenario.server.clone();
+        direct.config.trusted_proxy_count = 0;
+        assert_eq!(
+            authorize_scopes(&direct, &request_from("10.200.0.1"), &[APIScope::Collector]).await,
+            Err(StatusCode::FORBIDDEN)
+        );
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        assert!(set_allowed_ips(&tag, None, &mut tx).await.unwrap());
+        tx.commit().await.unwrap();
+        assert!(
+            authorize_scopes(server, &request_from("192.168.0.1"), &[APIScope::Collector])
+                .await
+                .is_ok()
+        );
+        scenario.teardown().await;
+    }
+}
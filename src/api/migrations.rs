@@ -0,0 +1,223 @@
+//! Inspection of the database migrations embedded into the binary.
+//!
+//! - `GET /api/v2/admin/migrations/status` lists all migrations and whether they are applied
+//! - `GET /api/v2/admin/changelog?limit=` lists the migrations recorded in the database, latest first (Admin only)
+//!
+//! Pending migrations are tried with `--migrations-dry-run` instead of an endpoint,
+//! the server itself applies them on startup and DDL is not run on behalf of requests.
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::migrate::Migrate;
+use tracing::{info, instrument, warn};
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::error::DataValidationError;
+use crate::{LTZFArc, LTZFServer, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+    pub checksum_ok: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DryRunResult {
+    pub version: i64,
+    pub description: String,
+    pub error: Option<String>,
+    /// migrations that run outside of a transaction cannot be rolled back and are not tried
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ChangelogEntry {
+    pub version: i64,
+    pub description: String,
+    pub installed_on: DateTime<Utc>,
+    pub success: bool,
+    /// hex encoded
+    pub checksum: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ChangelogQuery {
+    pub limit: Option<i64>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/migrations/status", get(migrations_status))
+        .route("/api/v2/admin/changelog", get(changelog_get))
+        .with_state(server)
+}
+
+/// MigrationsStatus - GET /api/v2/admin/migrations/status
+#[instrument(skip_all)]
+async fn migrations_status(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let status = migration_status(&server).await.map_err(internal_error)?;
+    info!("Successful retrieval");
+    Ok(Json(status).into_response())
+}
+
+/// ChangelogGet - GET /api/v2/admin/changelog
+#[instrument(skip_all, fields(limit=?query.limit))]
+async fn changelog_get(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Query(query): Query<ChangelogQuery>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin]).await?;
+    let entries = changelog(query.limit, &server)
+        .await
+        .map_err(internal_error)?;
+    info!("Listed {} migrations", entries.len());
+    Ok(Json(entries).into_response())
+}
+
+/// the migrations recorded by sqlx, latest first. `limit` restricts the list to the latest ones.
+pub async fn changelog(limit: Option<i64>, server: &LTZFServer) -> Result<Vec<ChangelogEntry>> {
+    if let Some(limit) = limit.filter(|l| *l < 1) {
+        return Err(DataValidationError::InvalidQueryParameter {
+            parameter: "limit".to_string(),
+            message: format!("{limit} is not positive"),
+        }
+        .into());
+    }
+    let mut conn = server.sqlx_db.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    Ok(sqlx::query!(
+        "SELECT version, description, installed_on, success, encode(checksum, 'hex') as \"checksum!\"
+        FROM _sqlx_migrations ORDER BY version DESC LIMIT $1",
+        limit
+    )
+    .map(|r| ChangelogEntry {
+        version: r.version,
+        description: r.description,
+        installed_on: r.installed_on,
+        success: r.success,
+        checksum: r.checksum,
+    })
+    .fetch_all(&mut *conn)
+    .await?)
+}
+
+/// compares the embedded migrations with the ones recorded in the database
+pub async fn migration_status(server: &LTZFServer) -> Result<Vec<MigrationStatus>> {
+    let mut conn = server.sqlx_db.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+    Ok(crate::MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| {
+            let record = applied.iter().find(|a| a.version == m.version);
+            MigrationStatus {
+                version: m.version,
+                description: m.description.to_string(),
+                applied: record.is_some(),
+                checksum_ok: record.is_none_or(|a| a.checksum == m.checksum),
+            }
+        })
+        .collect())
+}
+
+/// applies all pending migrations inside a single transaction that is rolled back afterwards.
+/// Migrations after the first failing or skipped one are not attempted, since they might depend on it.
+pub async fn dry_run_pending(pool: &sqlx::PgPool) -> Result<Vec<DryRunResult>> {
+    let mut conn = pool.acquire().await?;
+    conn.ensure_migrations_table().await?;
+    let applied = conn.list_applied_migrations().await?;
+    drop(conn);
+    let mut tx = pool.begin().await?;
+    let mut results = vec![];
+    for m in crate::MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+    {
+        if m.no_tx {
+            info!(
+                "Migration {} runs outside of a transaction, stopping",
+                m.version
+            );
+            results.push(DryRunResult {
+                version: m.version,
+                description: m.description.to_string(),
+                error: None,
+                skipped: true,
+            });
+            break;
+        }
+        let error = sqlx::raw_sql(&m.sql)
+            .execute(&mut *tx)
+            .await
+            .err()
+            .map(|e| e.to_string());
+        let failed = error.is_some();
+        if failed {
+            warn!("Migration {} would fail: {:?}", m.version, error);
+        }
+        results.push(DryRunResult {
+            version: m.version,
+            description: m.description.to_string(),
+            error,
+            skipped: false,
+        });
+        if failed {
+            break;
+        }
+    }
+    tx.rollback().await?;
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test_migrations {
+    use super::{changelog, dry_run_pending, migration_status};
+    use crate::utils::testing::TestSetup;
+
+    #[tokio::test]
+    async fn test_migration_status() {
+        let scenario = TestSetup::new("test_migration_status").await;
+        let status = migration_status(&scenario.server).await.unwrap();
+        assert!(!status.is_empty());
+        assert!(status.iter().all(|m| m.applied && m.checksum_ok));
+        // everything is applied, so there is nothing to try
+        assert!(
+            dry_run_pending(&scenario.server.sqlx_db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_changelog() {
+        let scenario = TestSetup::new("test_changelog").await;
+        let entries = changelog(None, &scenario.server).await.unwrap();
+        let latest = crate::MIGRATOR.iter().map(|m| m.version).max().unwrap();
+        assert_eq!(entries[0].version, latest);
+        assert!(entries.windows(2).all(|w| w[0].version > w[1].version));
+        assert!(entries.iter().all(|e| e.success && !e.checksum.is_empty()));
+        let entries = changelog(Some(2), &scenario.server).await.unwrap();
+        assert_eq!(entries.len(), 2);
+        for invalid in [0, -1] {
+            let error = changelog(Some(invalid), &scenario.server)
+                .await
+                .unwrap_err();
+            assert!(error.is_bad_request());
+        }
+        scenario.teardown().await;
+    }
+}
@@ -12,6 +12,7 @@ use tracing::info;
 use tracing::instrument;
 
 use super::PaginationResponsePart;
+use super::auth::APIScope;
 
 #[async_trait]
 impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
@@ -28,7 +29,8 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             "SELECT a.id FROM autor a WHERE
             ($1::text IS NULL AND person IS NULL OR person LIKE CONCAT('%',$1,'%')) AND
             organisation LIKE CONCAT('%',$2::text,'%') AND
-            ($3::text IS NULL AND fachgebiet IS NULL OR fachgebiet LIKE CONCAT('%', $3, '%'))",
+            ($3::text IS NULL AND fachgebiet IS NULL OR fachgebiet LIKE CONCAT('%', $3, '%'))
+            ORDER BY a.id",
             query_params.person,
             query_params.org,
             query_params.fach,
@@ -37,6 +39,7 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         .fetch_all(&mut *tx)
         .await?;
 
+        // the pages follow the id order, so they neither overlap nor skip entries
         let prp = PaginationResponsePart::new(
             result.len() as i32,
             query_params.page,
@@ -44,7 +47,8 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         );
         let result = &result[prp.start()..prp.end()];
         let output = sqlx::query!(
-            "SELECT * FROM autor WHERE id = ANY($1::int4[])",
+            "SELECT a.* FROM autor a WHERE a.id = ANY($1::int4[])
+            ORDER BY a.id",
             &result[..]
         )
         .map(|r| models::Autor {
@@ -76,7 +80,11 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             x_total_pages: Some(prp.x_total_pages),
             x_page: Some(prp.x_page),
             x_per_page: Some(prp.x_per_page),
-            link: Some(prp.generate_link_header("/api/v2/autoren")),
+            link: Some(prp.generate_link_header(
+                &self.public_base_url(),
+                "/api/v2/autoren",
+                &crate::api::filter_query(query_params),
+            )),
         });
     }
 
@@ -94,7 +102,8 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             INNER JOIN parlament p ON p.id = g.parl 
             WHERE p.value = COALESCE($1, p.value) AND
             g.wp = COALESCE($2, g.wp) AND
-            ($3::text IS NULL OR g.name LIKE CONCAT('%',$3,'%'))",
+            ($3::text IS NULL OR g.name LIKE CONCAT('%',$3,'%'))
+            ORDER BY g.id",
             query_params.p.map(|x| x.to_string()),
             query_params.wp,
             query_params.gr
@@ -119,7 +128,8 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         let result = sqlx::query!(
             "SELECT g.link, g.name, g.wp, p.value as parl FROM gremium g
         INNER JOIN parlament p ON p.id = g.parl
-        WHERE g.id = ANY($1::int4[])",
+        WHERE g.id = ANY($1::int4[])
+        ORDER BY g.id",
             &selected_ids[..]
         )
         .map(|r| models::Gremium {
@@ -140,7 +150,11 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             x_total_pages: Some(prp.x_total_pages),
             x_page: Some(prp.x_page),
             x_per_page: Some(prp.x_per_page),
-            link: Some(prp.generate_link_header("/api/v2/gremien")),
+            link: Some(prp.generate_link_header(
+                &self.public_base_url(),
+                "/api/v2/gremien",
+                &crate::api::filter_query(query_params),
+            )),
         })
     }
 
@@ -178,7 +192,7 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             .drain(..),
         );
         let mut filtered_ids = sqlx::query(&format!(
-            "SELECT v.id FROM {} v WHERE v.value LIKE CONCAT('%',$1::text,'%')",
+            "SELECT v.id FROM {} v WHERE v.value LIKE CONCAT('%',$1::text,'%') ORDER BY v.id",
             enum_tables[&path_params.name]
         ))
         .bind::<_>(contains)
@@ -202,7 +216,7 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         );
         let select_few: Vec<i32> = filtered_ids.drain(prp.start()..prp.end()).collect();
         let values: Vec<String> = sqlx::query(&format!(
-            "SELECT v.value FROM {} v WHERE v.id = ANY($1::int4[])",
+            "SELECT v.value FROM {} v WHERE v.id = ANY($1::int4[]) ORDER BY v.id",
             enum_tables[&path_params.name]
         ))
         .bind::<_>(select_few)
@@ -219,9 +233,11 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
             x_total_pages: Some(prp.x_total_pages),
             x_page: Some(prp.x_page),
             x_per_page: Some(prp.x_per_page),
-            link: Some(
-                prp.generate_link_header(&format!("/api/v2/enumeration/{}", path_params.name)),
-            ),
+            link: Some(prp.generate_link_header(
+                &self.public_base_url(),
+                &format!("/api/v2/enumeration/{}", path_params.name),
+                &crate::api::filter_query(query_params),
+            )),
         });
     }
 
@@ -234,6 +250,12 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         _cookies: &CookieJar,
         path_params: &models::DokumentGetByIdPathParams,
     ) -> Result<DokumentGetByIdResponse> {
+        // admin-only info, the key of the request is checked by the `touched_by` middleware
+        let scope = if crate::utils::touched_by::is_privileged() {
+            APIScope::Admin
+        } else {
+            APIScope::Collector
+        };
         let mut tx = self.sqlx_db.begin().await?;
         let did = sqlx::query!(
             "SELECT id FROM dokument WHERE api_id = $1",
@@ -243,7 +265,7 @@ impl MiscellaneousUnauthorisiert<LTZFError> for LTZFServer {
         .fetch_optional(&mut *tx)
         .await?;
         if let Some(did) = did {
-            let dok = crate::db::retrieve::dokument_by_id(did, &mut tx).await?;
+            let dok = crate::db::retrieve::dokument_by_id_scoped(did, scope, &mut tx).await?;
             tx.commit().await?;
             info!("Document found");
             return Ok(DokumentGetByIdResponse::Status200_Success {
@@ -302,6 +324,153 @@ mod test_unauthorisiert {
         scenario.teardown().await;
     }
 
+    #[tokio::test]
+    async fn test_autor_get_pagination() {
+        let scenario = TestSetup::new("autor_get_pagination").await;
+        let server = &scenario.server;
+        for i in 0..40 {
+            sqlx::query!(
+                "INSERT INTO autor(person, organisation) VALUES ($1, 'Seitenverein')",
+                format!("Person {i}")
+            )
+            .execute(&server.sqlx_db)
+            .await
+            .unwrap();
+        }
+        let page = |page: i32| async move {
+            let r = server
+                .autoren_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::AutorenGetQueryParams {
+                        fach: None,
+                        org: None,
+                        person: None,
+                        page: Some(page),
+                        per_page: Some(10),
+                    },
+                )
+                .await
+                .unwrap();
+            match r {
+                AutorenGetResponse::Status200_Success {
+                    body,
+                    x_total_count,
+                    x_total_pages,
+                    x_page,
+                    x_per_page,
+                    link,
+                    ..
+                } => {
+                    assert_eq!(x_total_count, Some(40));
+                    assert_eq!(x_total_pages, Some(4));
+                    assert_eq!(x_page, Some(page));
+                    assert_eq!(x_per_page, Some(10));
+                    assert!(link.unwrap().contains("/api/v2/autoren?page=4&per_page=10"));
+                    body
+                }
+                other => panic!("Expected Success, got {other:?}"),
+            }
+        };
+        let mut seen = vec![];
+        for p in 1..=4 {
+            let body = page(p).await;
+            assert_eq!(body.len(), 10);
+            seen.extend(body.into_iter().map(|a| a.person.unwrap()));
+        }
+        seen.sort();
+        seen.dedup();
+        assert_eq!(seen.len(), 40);
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_gremien_enum_get_pagination() {
+        let scenario = TestSetup::new("gremien_enum_get_pagination").await;
+        let server = &scenario.server;
+        for i in 0..15 {
+            sqlx::query!(
+                "INSERT INTO gremium(parl, name, wp) VALUES ((SELECT id FROM parlament WHERE value = 'BT'), $1, 20)",
+                format!("Ausschuss {i}")
+            )
+            .execute(&server.sqlx_db)
+            .await
+            .unwrap();
+            sqlx::query!(
+                "INSERT INTO schlagwort(value) VALUES ($1)",
+                format!("seitenwort{i}")
+            )
+            .execute(&server.sqlx_db)
+            .await
+            .unwrap();
+        }
+        let mut gremien = vec![];
+        let mut schlagworte = vec![];
+        for page in 1..=3 {
+            let r = server
+                .gremien_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::GremienGetQueryParams {
+                        p: None,
+                        wp: None,
+                        gr: Some("Ausschuss ".to_string()),
+                        page: Some(page),
+                        per_page: Some(5),
+                    },
+                )
+                .await
+                .unwrap();
+            let GremienGetResponse::Status200_Success {
+                body,
+                x_total_count,
+                ..
+            } = r
+            else {
+                panic!("Expected Success, got {r:?}");
+            };
+            assert_eq!(x_total_count, Some(15));
+            assert_eq!(body.len(), 5);
+            gremien.extend(body.into_iter().map(|g| g.name));
+
+            let r = server
+                .enum_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::EnumGetPathParams {
+                        name: models::EnumerationNames::Schlagworte,
+                    },
+                    &models::EnumGetQueryParams {
+                        contains: Some("seitenwort".to_string()),
+                        page: Some(page),
+                        per_page: Some(5),
+                    },
+                )
+                .await
+                .unwrap();
+            let EnumGetResponse::Status200_Success {
+                body,
+                x_total_count,
+                ..
+            } = r
+            else {
+                panic!("Expected Success, got {r:?}");
+            };
+            assert_eq!(x_total_count, Some(15));
+            assert_eq!(body.len(), 5);
+            schlagworte.extend(body);
+        }
+        for names in [&mut gremien, &mut schlagworte] {
+            names.sort();
+            names.dedup();
+            assert_eq!(names.len(), 15);
+        }
+        scenario.teardown().await;
+    }
+
     #[tokio::test]
     async fn test_autor_get_success() {
         let scenario = TestSetup::new("autor_get_success").await;
@@ -583,4 +752,45 @@ mod test_unauthorisiert {
         }
         scenario.teardown().await;
     }
+
+    #[tokio::test]
+    async fn test_dokument_touched_by_scope() {
+        let scenario = TestSetup::new("test_dokument_touched_by_scope").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        server
+            .vorgang_id_put(
+                &Method::PUT,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &(APIScope::KeyAdder, 1),
+                &models::VorgangIdPutPathParams {
+                    vorgang_id: vorgang.api_id,
+                },
+                &vorgang,
+            )
+            .await
+            .unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let did = sqlx::query!("SELECT id FROM dokument LIMIT 1")
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        let admin = crate::db::retrieve::dokument_by_id_scoped(did, APIScope::Admin, &mut tx)
+            .await
+            .unwrap();
+        assert!(
+            admin.touched_by.as_ref().is_some_and(|t| !t.is_empty()),
+            "{:?}",
+            admin.touched_by
+        );
+        let collector =
+            crate::db::retrieve::dokument_by_id_scoped(did, APIScope::Collector, &mut tx)
+                .await
+                .unwrap();
+        assert!(collector.touched_by.is_none());
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
 }
@@ -14,7 +14,16 @@ use sqlx::Row;
 use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
-use super::RoundTimestamp;
+use super::normalize::compare_dokument;
+
+/// what has to happen before the references of a table are rewritten from old to new ids
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum ConflictResolutionQuery {
+    /// the referencing column is not part of a unique constraint, nothing can conflict
+    None,
+    /// deletes all rows that would violate a unique constraint after the rewrite
+    Query(String),
+}
 
 // this query tries to resolve all potential unique constraint conflicts
 // on tables where the enumeration entry are part of a shared unique constraint.
@@ -22,10 +31,14 @@ use super::RoundTimestamp;
 // this would mean, if there is a n:m relation table for dokument to autor and values x and y for field autor
 // which are to be merged (x is to be made y) this would violate a unique constraint in the table
 // thus this query tries to find these and delete entries that are to be the same after the whole transaction
-macro_rules! conflict_resolve_query(
-    ($table_name:expr, $shorthand:expr, $ident_col:expr, $element_col:expr) => {
-        concat!(
-            "WITH lookup(new,old) AS (SELECT * FROM UNNEST($1::int4[], $2::int4[]) AS iv(new, old)) -- this is the vector of all authors to be replaced
+fn build_conflict_resolve_query(
+    table: &str,
+    shorthand: &str,
+    ident_col: &str,
+    element_col: &str,
+) -> String {
+    format!(
+        "WITH lookup(new,old) AS (SELECT * FROM UNNEST($1::int4[], $2::int4[]) AS iv(new, old)) -- this is the vector of all authors to be replaced
 -- assumes
 -- (1) no circular replacements (to be detected in server code)
 -- (2) uniqueness of entries
@@ -33,16 +46,16 @@ macro_rules! conflict_resolve_query(
 potential_conflicts AS (
 -- select from rda rows together with their target aut_id value (either already new or new where aut_id=old) that 
 SELECT 
-	",$ident_col," as identifier, 
-	",$element_col," as original_id, 
+	{ident_col} as identifier, 
+	{element_col} as original_id, 
 	lu.old as old_id,
 	lu.new as target_id 
-FROM ",$table_name, " ", $shorthand,"
+FROM {table} {shorthand}
 INNER JOIN lookup lu ON 
 -- (a) are to be replaced (contain an entry aut_id = old)
-lu.old = ",$shorthand,".",$element_col," OR
+lu.old = {shorthand}.{element_col} OR
 -- (b) are already a new value (contain an entry aut_id=new)
-lu.new = ",$shorthand,".",$element_col,"
+lu.new = {shorthand}.{element_col}
 ),
 
 actual_conflicts AS (
@@ -67,11 +80,96 @@ deletion_select AS(-- select all but one from each class denoted by the same ide
 	GROUP BY (identifier, target_id))
 )
 
-DELETE FROM ",$table_name," ",$shorthand," WHERE 
-EXISTS (SELECT FROM deletion_select ds WHERE ds.identifier = ",$shorthand,".",$ident_col," AND ds.original_id = ",$shorthand,".",$element_col,")"
-        ) // concat
-    } // match arm of macro
-); // macro_rules
+DELETE FROM {table} {shorthand} WHERE 
+EXISTS (SELECT FROM deletion_select ds WHERE ds.identifier = {shorthand}.{ident_col} AND ds.original_id = {shorthand}.{element_col})"
+    )
+}
+
+/// a table whose unique constraint contains a replaced reference, see `build_conflict_resolve_query`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ConflictSite {
+    table: &'static str,
+    shorthand: &'static str,
+    /// the other column of the unique constraint
+    ident_col: &'static str,
+    /// the column referencing the replaced entries
+    element_col: &'static str,
+}
+
+impl ConflictSite {
+    fn resolution(&self) -> ConflictResolutionQuery {
+        ConflictResolutionQuery::Query(build_conflict_resolve_query(
+            self.table,
+            self.shorthand,
+            self.ident_col,
+            self.element_col,
+        ))
+    }
+}
+
+const REL_DOK_AUTOR: ConflictSite = ConflictSite {
+    table: "rel_dok_autor",
+    shorthand: "rda",
+    ident_col: "dok_id",
+    element_col: "aut_id",
+};
+const REL_VORGANG_INIT: ConflictSite = ConflictSite {
+    table: "rel_vorgang_init",
+    shorthand: "rvi",
+    ident_col: "vg_id",
+    element_col: "in_id",
+};
+const REL_SITZUNG_EXPERTEN: ConflictSite = ConflictSite {
+    table: "rel_sitzung_experten",
+    shorthand: "rse",
+    ident_col: "sid",
+    element_col: "eid",
+};
+const LOBBYREGISTEREINTRAG: ConflictSite = ConflictSite {
+    table: "lobbyregistereintrag",
+    shorthand: "lre",
+    ident_col: "vg_id",
+    element_col: "organisation",
+};
+const REL_VORGANG_IDENT: ConflictSite = ConflictSite {
+    table: "rel_vorgang_ident",
+    shorthand: "rvi",
+    ident_col: "vg_id",
+    element_col: "typ",
+};
+const REL_DOK_SCHLAGWORT: ConflictSite = ConflictSite {
+    table: "rel_dok_schlagwort",
+    shorthand: "rds",
+    ident_col: "dok_id",
+    element_col: "sw_id",
+};
+const REL_STATION_SCHLAGWORT: ConflictSite = ConflictSite {
+    table: "rel_station_schlagwort",
+    shorthand: "rss",
+    ident_col: "stat_id",
+    element_col: "sw_id",
+};
+
+/// every site the conflict resolution runs at, all of them are covered by the tests
+#[cfg(test)]
+const CONFLICT_SITES: [ConflictSite; 7] = [
+    REL_DOK_AUTOR,
+    REL_VORGANG_INIT,
+    REL_SITZUNG_EXPERTEN,
+    LOBBYREGISTEREINTRAG,
+    REL_VORGANG_IDENT,
+    REL_DOK_SCHLAGWORT,
+    REL_STATION_SCHLAGWORT,
+];
+
+/// true if the target of a (new, old) replacement is itself replaced in the same request,
+/// which would swap entries instead of merging them
+fn is_circular_replacement(replacement_tuples: &[(i32, i32)]) -> bool {
+    let replaced: BTreeSet<i32> = replacement_tuples.iter().map(|(_, old)| *old).collect();
+    replacement_tuples
+        .iter()
+        .any(|(new, _)| replaced.contains(new))
+}
 
 #[async_trait]
 impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
@@ -296,11 +394,13 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         // check if none of the replacing authors are in the database
         // if both: NotModified
         let (mut person, mut organisation, mut fach, mut lobby) = (vec![], vec![], vec![], vec![]);
+        let mut fraktion = vec![];
         for a in body.objects.iter() {
             person.push(a.person.clone());
             organisation.push(a.organisation.clone());
             fach.push(a.fachgebiet.clone());
             lobby.push(a.lobbyregister.clone());
+            fraktion.push(crate::db::insert::submitted_fraktion(a));
         }
 
         if count_existing_authors(&mut tx, &body.objects).await? == body.objects.len() {
@@ -332,18 +432,26 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
 
         debug!("Request was valid");
         // insert all authors, fetch their IDs
+        sqlx::query!(
+            "INSERT INTO fraktion(value) SELECT DISTINCT f FROM UNNEST($1::text[]) AS f
+            WHERE f IS NOT NULL ON CONFLICT DO NOTHING",
+            &fraktion[..] as &[Option<String>]
+        )
+        .execute(&mut *tx)
+        .await?;
         let new_ids = sqlx::query!("
-        INSERT INTO autor(person, organisation, fachgebiet, lobbyregister) 
+        INSERT INTO autor(person, organisation, fachgebiet, lobbyregister, fraktion) 
 
-        SELECT ps, og, fc, lb FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[]) AS iv(ps, og, fc, lb)
+        SELECT ps, og, fc, lb, (SELECT id FROM fraktion WHERE value = fr) FROM UNNEST($1::text[], $2::text[], $3::text[], $4::text[], $5::text[]) AS iv(ps, og, fc, lb, fr)
 
         ON CONFLICT ON CONSTRAINT unq_data 
         DO UPDATE SET 
         fachgebiet = EXCLUDED.fachgebiet,
-        lobbyregister = EXCLUDED.lobbyregister
+        lobbyregister = EXCLUDED.lobbyregister,
+        fraktion = COALESCE(EXCLUDED.fraktion, autor.fraktion)
 
         RETURNING autor.id
-        ", &person[..] as &[Option<String>], &organisation[..], &fach[..] as &[Option<String>], &lobby[..] as &[Option<String>])
+        ", &person[..] as &[Option<String>], &organisation[..], &fach[..] as &[Option<String>], &lobby[..] as &[Option<String>], &fraktion[..] as &[Option<String>])
         .map(|r| r.id)
         .fetch_all(&mut *tx).await?;
 
@@ -383,57 +491,60 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
             .await?;
             replacement_tuples.extend(value_ids);
         }
+        if is_circular_replacement(&replacement_tuples) {
+            warn!("CIRCULAR_REPLACEMENT: a replacing author is itself replaced");
+            return Ok(AutorenPutResponse::Status400_BadRequest {
+                x_rate_limit_limit: None,
+                x_rate_limit_remaining: None,
+                x_rate_limit_reset: None,
+            });
+        }
+        // concurrent replacements of overlapping authors have to lock in the same order
+        replacement_tuples.sort_by_key(|(new, old)| (*old, *new));
+        replacement_tuples.dedup();
+        let mut locked: Vec<i32> = replacement_tuples
+            .iter()
+            .flat_map(|(new, old)| [*old, *new])
+            .collect();
+        locked.sort_unstable();
+        locked.dedup();
+        sqlx::query!(
+            "SELECT id FROM autor WHERE id = ANY($1::int4[]) ORDER BY id FOR UPDATE",
+            &locked[..]
+        )
+        .fetch_all(&mut *tx)
+        .await?;
         let rep_new: Vec<_> = replacement_tuples.iter().map(|x| x.0).collect();
         let rep_old: Vec<_> = replacement_tuples.iter().map(|x| x.1).collect();
 
         // tables referencing authors:
-        // table in question, column that references the author, query to delete conflicts _if_ the author is part of a unique identifier. can be empty if not applicable
+        // table in question, column that references the author, query to delete conflicts _if_ the author is part of a unique identifier
         let tables = vec![
             (
-                "rel_dok_autor",
-                "aut_id",
-                Some(conflict_resolve_query!(
-                    "rel_dok_autor",
-                    "rda",
-                    "dok_id",
-                    "aut_id"
-                )),
+                REL_DOK_AUTOR.table,
+                REL_DOK_AUTOR.element_col,
+                REL_DOK_AUTOR.resolution(),
             ),
             (
-                "rel_vorgang_init",
-                "in_id",
-                Some(conflict_resolve_query!(
-                    "rel_vorgang_init",
-                    "rvi",
-                    "vg_id",
-                    "in_id"
-                )),
+                REL_VORGANG_INIT.table,
+                REL_VORGANG_INIT.element_col,
+                REL_VORGANG_INIT.resolution(),
             ),
             (
-                "rel_sitzung_experten",
-                "eid",
-                Some(conflict_resolve_query!(
-                    "rel_sitzung_experten",
-                    "rse",
-                    "sid",
-                    "eid"
-                )),
+                REL_SITZUNG_EXPERTEN.table,
+                REL_SITZUNG_EXPERTEN.element_col,
+                REL_SITZUNG_EXPERTEN.resolution(),
             ),
             (
-                "lobbyregistereintrag",
-                "organisation",
-                Some(conflict_resolve_query!(
-                    "lobbyregistereintrag",
-                    "lre",
-                    "vg_id",
-                    "organisation"
-                )),
+                LOBBYREGISTEREINTRAG.table,
+                LOBBYREGISTEREINTRAG.element_col,
+                LOBBYREGISTEREINTRAG.resolution(),
             ),
         ];
         for (table, column, conflict_res_query) in tables {
             // first, delete potentially conflicting entries
-            if let Some(conflict_res_query) = conflict_res_query {
-                sqlx::query(conflict_res_query)
+            if let ConflictResolutionQuery::Query(conflict_res_query) = conflict_res_query {
+                sqlx::query(&conflict_res_query)
                     .bind(&rep_new[..])
                     .bind(&rep_old[..])
                     .execute(&mut *tx)
@@ -490,11 +601,19 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
                 x_rate_limit_reset: None,
             });
         }
-        // check if replacing contain an index larger than the object list
-        // if so: Bad Request
+        // if replacing contains an index larger than the object list: Bad Request
+        // if a replaced gremium is identifiable with an object in the object list: Bad Request
         if let Some(replc) = &body.replacing {
             for rpl in replc.iter() {
-                if rpl.replaced_by as usize >= body.objects.len() {
+                if rpl.replaced_by as usize >= body.objects.len()
+                    || rpl.values.iter().any(|v| {
+                        body.objects.iter().any(|o| {
+                            o.name == v.name
+                                && o.parlament == v.parlament
+                                && o.wahlperiode == v.wahlperiode
+                        })
+                    })
+                {
                     info!(
                         "Semantically bad request: Either a circular replacement was detected or 
                         there were more replacement rules than new entries. 
@@ -601,17 +720,28 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
             .await?;
             replacement_tuples.extend(value_ids);
         }
+        if is_circular_replacement(&replacement_tuples) {
+            warn!("CIRCULAR_REPLACEMENT: a replacing gremium is itself replaced");
+            return Ok(GremienPutResponse::Status400_BadRequest {
+                x_rate_limit_limit: None,
+                x_rate_limit_remaining: None,
+                x_rate_limit_reset: None,
+            });
+        }
         let rep_new: Vec<_> = replacement_tuples.iter().map(|x| x.0).collect();
         let rep_old: Vec<_> = replacement_tuples.iter().map(|x| x.1).collect();
         // tables that reference a gremium:
         // - station(gr_id)
         // - sitzung(gr_id)
-        let tables = vec![("station", "gr_id", None), ("sitzung", "gr_id", None)];
+        let tables = vec![
+            ("station", "gr_id", ConflictResolutionQuery::None),
+            ("sitzung", "gr_id", ConflictResolutionQuery::None),
+        ];
         for (table, column, conflict_resolution_query) in tables {
             // first, delete potentially conflicting entries
             // currently not used because both tables are not identifying
-            if let Some(crq) = conflict_resolution_query {
-                sqlx::query(crq)
+            if let ConflictResolutionQuery::Query(crq) = conflict_resolution_query {
+                sqlx::query(&crq)
                     .bind(&rep_new[..])
                     .bind(&rep_old[..])
                     .execute(&mut *tx)
@@ -668,11 +798,17 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
                 x_rate_limit_reset: None,
             });
         }
-        // check if replacing contain an index larger than the object list
-        // if so: Bad Request
+        // if replacing contains an index larger than the object list: Bad Request
+        // if a replaced value is also in the object list: Bad Request
         if let Some(replc) = &body.replacing {
             for rpl in replc.iter() {
-                if rpl.replaced_by as usize >= body.objects.len() {
+                if rpl.replaced_by as usize >= body.objects.len()
+                    || rpl.values.iter().any(|v| body.objects.contains(v))
+                {
+                    warn!(
+                        "Semantically bad request: Either a value is both inserted and replaced or
+                        there were more replacement rules than new entries."
+                    );
                     return Ok(EnumPutResponse::Status400_BadRequest {
                         x_rate_limit_limit: None,
                         x_rate_limit_remaining: None,
@@ -681,6 +817,31 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
                 }
             }
         }
+        // not among the generated fields, see `utils::spec_ext`
+        let parent = match crate::utils::spec_ext::submitted("parent", |_| true) {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(parent)) => Some(parent),
+            Some(other) => {
+                warn!("Parent must be a string, got {other}");
+                return Ok(EnumPutResponse::Status400_BadRequest {
+                    x_rate_limit_limit: None,
+                    x_rate_limit_remaining: None,
+                    x_rate_limit_reset: None,
+                });
+            }
+        };
+        // only Schlagworte form a hierarchy
+        if parent.is_some() && path_params.name != models::EnumerationNames::Schlagworte {
+            warn!(
+                "Parent supplied for {:?}, which is not hierarchical",
+                path_params.name
+            );
+            return Ok(EnumPutResponse::Status400_BadRequest {
+                x_rate_limit_limit: None,
+                x_rate_limit_remaining: None,
+                x_rate_limit_reset: None,
+            });
+        }
         let mut tx = self.sqlx_db.begin().await?;
         // check if all gremien are existent in the database
         // check if none of the replacing gremien are in the database or replacing is None
@@ -704,7 +865,7 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         .map(|r| r.get::<i64, _>(0) as usize)
         .fetch_one(&mut *tx).await?;
 
-        if present == body.objects.len() {
+        if present == body.objects.len() && parent.is_none() {
             // flatten the replacement objects and check for existence
             if let Some(repl) = &body.replacing {
                 let flattened: Vec<String> =
@@ -745,6 +906,20 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         .fetch_all(&mut *tx)
         .await?;
 
+        if let Some(parent) = &parent {
+            if !super::schlagworte::set_schlagwort_parent(&new_ids, parent, &mut tx).await? {
+                warn!(
+                    "Setting `{parent}` as parent of {:?} would create a cycle",
+                    body.objects
+                );
+                return Ok(EnumPutResponse::Status400_BadRequest {
+                    x_rate_limit_limit: None,
+                    x_rate_limit_remaining: None,
+                    x_rate_limit_reset: None,
+                });
+            }
+        }
+
         if body.replacing.is_none() {
             tx.commit().await?;
             // if there is nothing to replace, we are done here
@@ -781,6 +956,14 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
             .await?;
             replacement_tuples.extend(value_ids);
         }
+        if is_circular_replacement(&replacement_tuples) {
+            warn!("CIRCULAR_REPLACEMENT: a replacing enumeration entry is itself replaced");
+            return Ok(EnumPutResponse::Status400_BadRequest {
+                x_rate_limit_limit: None,
+                x_rate_limit_remaining: None,
+                x_rate_limit_reset: None,
+            });
+        }
         let rep_new: Vec<_> = replacement_tuples.iter().map(|x| x.0).collect();
         let rep_old: Vec<_> = replacement_tuples.iter().map(|x| x.1).collect();
         // referencing tables:
@@ -789,41 +972,44 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         // stationstyp: station(typ)
         // vg_ident_typ: rel_vorgang_ident(typ)
         // vorgangstyp: vorgang(typ)
-        // schlagwort: rel_station_schlagwort(sw_id) / rel_dok_schlagwort(sw_id)
+        // schlagwort: rel_station_schlagwort(sw_id) / rel_dok_schlagwort(sw_id) / schlagwort(parent_id)
         let enum_table_refs = BTreeMap::from_iter(
             vec![
                 (
                     models::EnumerationNames::Parlamente,
                     // not a key component
-                    BTreeSet::from_iter(vec![("gremium", "parl", None)].drain(..)),
+                    BTreeSet::from_iter(
+                        vec![("gremium", "parl", ConflictResolutionQuery::None)].drain(..),
+                    ),
                 ),
                 (
                     models::EnumerationNames::Dokumententypen,
-                    BTreeSet::from_iter(vec![("dokument", "typ", None)].drain(..)), // not a key component
+                    BTreeSet::from_iter(
+                        vec![("dokument", "typ", ConflictResolutionQuery::None)].drain(..),
+                    ), // not a key component
                 ),
                 (
                     models::EnumerationNames::Stationstypen,
-                    BTreeSet::from_iter(vec![("station", "typ", None)].drain(..)), // not a key component
+                    BTreeSet::from_iter(
+                        vec![("station", "typ", ConflictResolutionQuery::None)].drain(..),
+                    ), // not a key component
                 ),
                 (
                     models::EnumerationNames::Vgidtypen,
                     BTreeSet::from_iter(
                         vec![(
-                            "rel_vorgang_ident",
-                            "typ",
-                            Some(conflict_resolve_query!(
-                                "rel_vorgang_ident",
-                                "rvi",
-                                "vg_id",
-                                "typ"
-                            )),
+                            REL_VORGANG_IDENT.table,
+                            REL_VORGANG_IDENT.element_col,
+                            REL_VORGANG_IDENT.resolution(),
                         )]
                         .drain(..),
                     ), // a key component
                 ),
                 (
                     models::EnumerationNames::Vorgangstypen,
-                    BTreeSet::from_iter(vec![("vorgang", "typ", Some(""))].drain(..)), // not a key component
+                    BTreeSet::from_iter(
+                        vec![("vorgang", "typ", ConflictResolutionQuery::None)].drain(..),
+                    ), // not a key component
                 ),
                 (
                     models::EnumerationNames::Schlagworte,
@@ -831,25 +1017,17 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
                     BTreeSet::from_iter(
                         vec![
                             (
-                                "rel_dok_schlagwort",
-                                "sw_id",
-                                Some(conflict_resolve_query!(
-                                    "rel_dok_schlagwort",
-                                    "rds",
-                                    "dok_id",
-                                    "sw_id"
-                                )),
+                                REL_DOK_SCHLAGWORT.table,
+                                REL_DOK_SCHLAGWORT.element_col,
+                                REL_DOK_SCHLAGWORT.resolution(),
                             ),
                             (
-                                "rel_station_schlagwort",
-                                "sw_id",
-                                Some(conflict_resolve_query!(
-                                    "rel_station_schlagwort",
-                                    "rss",
-                                    "stat_id",
-                                    "sw_id"
-                                )),
+                                REL_STATION_SCHLAGWORT.table,
+                                REL_STATION_SCHLAGWORT.element_col,
+                                REL_STATION_SCHLAGWORT.resolution(),
                             ),
+                            // children of replaced Schlagworte move to the replacement
+                            ("schlagwort", "parent_id", ConflictResolutionQuery::None),
                         ]
                         .drain(..),
                     ),
@@ -859,7 +1037,7 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         );
         for (table, column, conflict_resolution_query) in enum_table_refs[&path_params.name].iter()
         {
-            if let Some(crq) = conflict_resolution_query {
+            if let ConflictResolutionQuery::Query(crq) = conflict_resolution_query {
                 sqlx::query(crq)
                     .bind(&rep_new[..])
                     .bind(&rep_old[..])
@@ -957,7 +1135,7 @@ impl DataAdministrationMiscellaneous<LTZFError> for LTZFServer {
         .await?;
         if let Some(did) = did {
             let dok = crate::db::retrieve::dokument_by_id(did, &mut tx).await?;
-            if dok.with_round_timestamps() == body.with_round_timestamps() {
+            if compare_dokument(&dok, body) {
                 info!("Dokument was not modified");
                 return Ok(DokumentPutIdResponse::Status304_NotModified {
                     x_rate_limit_limit: None,
@@ -1531,6 +1709,30 @@ mod test_authorisiert {
         let gremien_new = fetch_all_gremien(&scenario.server).await;
         assert_eq!(gremien.len(), gremien_new.len());
 
+        // circular reference that only differs in the non-identifying link
+        let response = gp_with(
+            &scenario.server,
+            &models::GremienPutRequest {
+                objects: vec![repl_grm.clone()],
+                replacing: Some(vec![models::GremienPutRequestReplacingInner {
+                    replaced_by: 0,
+                    values: vec![models::Gremium {
+                        link: Some("https://example.com/gremium".to_string()),
+                        ..repl_grm.clone()
+                    }],
+                }]),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            response,
+            GremienPutResponse::Status400_BadRequest { .. }
+        ));
+        let gremien_new = fetch_all_gremien(&scenario.server).await;
+        assert_eq!(gremien.len(), gremien_new.len());
+        assert!(gremien_new.contains(&repl_grm));
+
         scenario.teardown().await;
     }
 
@@ -1549,6 +1751,51 @@ mod test_authorisiert {
             .await
     }
     #[tokio::test]
+    async fn test_autor_put_concurrent_overlap() {
+        let scenario = TestSetup::new("test_autor_put_concurrent_overlap").await;
+        insert_default_vorgang(&scenario.server).await;
+        let (person, institution) = (
+            generate::default_autor_person(),
+            generate::default_autor_institution(),
+        );
+        let replacement =
+            |organisation: &str, values: Vec<models::Autor>| models::AutorenPutRequest {
+                objects: vec![models::Autor {
+                    fachgebiet: None,
+                    lobbyregister: None,
+                    person: None,
+                    organisation: organisation.to_string(),
+                }],
+                replacing: Some(vec![models::AutorenPutRequestReplacingInner {
+                    replaced_by: 0,
+                    values,
+                }]),
+            };
+        // both replace the same authors, listed in opposite order
+        let first = replacement(
+            "Zusammenschluss Nord",
+            vec![person.clone(), institution.clone()],
+        );
+        let second = replacement("Zusammenschluss Süd", vec![institution, person]);
+        let (a, b) = tokio::time::timeout(std::time::Duration::from_secs(30), async {
+            tokio::join!(
+                ap_with(&scenario.server, &first),
+                ap_with(&scenario.server, &second)
+            )
+        })
+        .await
+        .expect("concurrent autoren_put calls did not finish");
+        assert!(
+            matches!(a, Ok(AutorenPutResponse::Status201_Created { .. })),
+            "{a:?}"
+        );
+        assert!(
+            matches!(b, Ok(AutorenPutResponse::Status201_Created { .. })),
+            "{b:?}"
+        );
+        scenario.teardown().await;
+    }
+    #[tokio::test]
     async fn test_autor_put() {
         let scenario = TestSetup::new("test_autor_put").await;
         insert_default_vorgang(&scenario.server).await;
@@ -1683,6 +1930,29 @@ mod test_authorisiert {
         let gremien_new = fetch_all_authors(&scenario.server).await;
         assert_eq!(autoren.len(), gremien_new.len());
 
+        // circular reference that only differs in the non-identifying fachgebiet
+        let response = ap_with(
+            &scenario.server,
+            &models::AutorenPutRequest {
+                objects: vec![repl_grm.clone()],
+                replacing: Some(vec![models::AutorenPutRequestReplacingInner {
+                    replaced_by: 0,
+                    values: vec![models::Autor {
+                        fachgebiet: Some("Blattzerlegung".to_string()),
+                        ..repl_grm.clone()
+                    }],
+                }]),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            response,
+            AutorenPutResponse::Status400_BadRequest { .. }
+        ));
+        let gremien_new = fetch_all_authors(&scenario.server).await;
+        assert_eq!(autoren.len(), gremien_new.len());
+
         // test case of merging two foreign keys: currently disabled !!THIS IS A TODO!!
         let mod_autor = models::Autor {
             person: Some("Heribert Schnakenwurst IV".to_string()),
@@ -1887,8 +2157,293 @@ mod test_authorisiert {
             ));
             let entries_new = fetch_all_enumvars(&scenario.server, *tp).await;
             assert_eq!(entries.len(), entries_new.len());
+
+            // circular reference
+            let response = ep_with(
+                &scenario.server,
+                *tp,
+                &models::EnumPutRequest {
+                    objects: vec![other_new_entry.clone()],
+                    replacing: Some(vec![models::EnumPutRequestReplacingInner {
+                        replaced_by: 0,
+                        values: vec![other_new_entry.clone()],
+                    }]),
+                },
+            )
+            .await
+            .unwrap();
+            assert!(matches!(
+                response,
+                EnumPutResponse::Status400_BadRequest { .. }
+            ));
+            let entries_new = fetch_all_enumvars(&scenario.server, *tp).await;
+            assert_eq!(entries.len(), entries_new.len());
+            assert!(entries_new.contains(other_new_entry));
         }
 
         scenario.teardown().await;
     }
+
+    #[tokio::test]
+    async fn test_enum_put_parent() {
+        let scenario = TestSetup::new("test_enum_put_parent").await;
+        // the parent is read from the submitted body, see `utils::spec_ext`
+        let put_with_parent = |tp: EnumerationNames, parent: serde_json::Value| {
+            let body = serde_json::json!({ "objects": ["windkraft"], "parent": parent });
+            let request = axum::extract::Request::builder()
+                .method("PUT")
+                .uri(format!("/api/v2/enumeration/{tp}"))
+                .body(axum::body::Body::from(body.to_string()))
+                .unwrap();
+            let server = &scenario.server;
+            async move {
+                let body: models::EnumPutRequest = serde_json::from_value(body).unwrap();
+                crate::utils::spec_ext::with_request(request, ep_with(server, tp, &body))
+                    .await
+                    .0
+                    .unwrap()
+            }
+        };
+        // only Schlagworte form a hierarchy
+        let response = put_with_parent(
+            EnumerationNames::Vorgangstypen,
+            serde_json::json!("energie"),
+        )
+        .await;
+        assert!(matches!(
+            response,
+            EnumPutResponse::Status400_BadRequest { .. }
+        ));
+        let response = put_with_parent(EnumerationNames::Schlagworte, serde_json::json!(42)).await;
+        assert!(matches!(
+            response,
+            EnumPutResponse::Status400_BadRequest { .. }
+        ));
+        let response =
+            put_with_parent(EnumerationNames::Schlagworte, serde_json::json!("Energie")).await;
+        assert!(matches!(
+            response,
+            EnumPutResponse::Status201_Created { .. }
+        ));
+        let parent = sqlx::query!(
+            "SELECT p.value FROM schlagwort s INNER JOIN schlagwort p ON p.id = s.parent_id
+            WHERE s.value = 'windkraft'"
+        )
+        .map(|r| r.value)
+        .fetch_one(&scenario.server.sqlx_db)
+        .await
+        .unwrap();
+        assert_eq!(parent, "energie");
+
+        scenario.teardown().await;
+    }
+}
+
+#[cfg(test)]
+mod test_conflict_resolution {
+    use sqlx::Row;
+    use uuid::Uuid;
+
+    use super::{
+        CONFLICT_SITES, ConflictResolutionQuery, ConflictSite, LOBBYREGISTEREINTRAG, REL_DOK_AUTOR,
+        REL_DOK_SCHLAGWORT, REL_SITZUNG_EXPERTEN, REL_STATION_SCHLAGWORT, REL_VORGANG_IDENT,
+        REL_VORGANG_INIT, is_circular_replacement,
+    };
+    use crate::db::merge::execute::run_integration;
+    use crate::db::merge::sitzung::run_sitzung_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_is_circular_replacement() {
+        assert!(!is_circular_replacement(&[]));
+        assert!(!is_circular_replacement(&[(1, 2), (1, 3), (4, 5)]));
+        // 1 replaces 2 and is itself replaced by 4: a swap
+        assert!(is_circular_replacement(&[(1, 2), (4, 1)]));
+        assert!(is_circular_replacement(&[(1, 2), (2, 1)]));
+        assert!(is_circular_replacement(&[(3, 3)]));
+    }
+
+    const NEW_AUTOR: &str = "INSERT INTO autor(organisation) VALUES ($1) RETURNING id";
+    const NEW_SCHLAGWORT: &str = "INSERT INTO schlagwort(value) VALUES ($1) RETURNING id";
+
+    /// a call site of `build_conflict_resolve_query` together with the
+    /// statements needed to provoke a conflict in its table
+    struct Site {
+        site: ConflictSite,
+        /// the table referenced by `ident_col`
+        parent: &'static str,
+        /// inserts a referenced element named $1, returning its id
+        new_element: &'static str,
+        /// inserts a row with identifier $1 and element $2
+        insert_row: &'static str,
+    }
+
+    const SITES: [Site; 7] = [
+        Site {
+            site: REL_DOK_AUTOR,
+            parent: "dokument",
+            new_element: NEW_AUTOR,
+            insert_row: "INSERT INTO rel_dok_autor(dok_id, aut_id) VALUES ($1, $2)",
+        },
+        Site {
+            site: REL_VORGANG_INIT,
+            parent: "vorgang",
+            new_element: NEW_AUTOR,
+            insert_row: "INSERT INTO rel_vorgang_init(vg_id, in_id) VALUES ($1, $2)",
+        },
+        Site {
+            site: REL_SITZUNG_EXPERTEN,
+            parent: "sitzung",
+            new_element: NEW_AUTOR,
+            insert_row: "INSERT INTO rel_sitzung_experten(sid, eid) VALUES ($1, $2)",
+        },
+        Site {
+            site: LOBBYREGISTEREINTRAG,
+            parent: "vorgang",
+            new_element: NEW_AUTOR,
+            insert_row:
+                "INSERT INTO lobbyregistereintrag(vg_id, organisation, interne_id, intention, link)
+            VALUES ($1, $2, 'konflikt', 'Konflikt', 'https://example.com/konflikt')",
+        },
+        Site {
+            site: REL_VORGANG_IDENT,
+            parent: "vorgang",
+            new_element: "INSERT INTO vg_ident_typ(value) VALUES ($1) RETURNING id",
+            insert_row: "INSERT INTO rel_vorgang_ident(vg_id, typ, identifikator) VALUES ($1, $2, 'konflikt')",
+        },
+        Site {
+            site: REL_DOK_SCHLAGWORT,
+            parent: "dokument",
+            new_element: NEW_SCHLAGWORT,
+            insert_row: "INSERT INTO rel_dok_schlagwort(dok_id, sw_id) VALUES ($1, $2)",
+        },
+        Site {
+            site: REL_STATION_SCHLAGWORT,
+            parent: "station",
+            new_element: NEW_SCHLAGWORT,
+            insert_row: "INSERT INTO rel_station_schlagwort(stat_id, sw_id) VALUES ($1, $2)",
+        },
+    ];
+
+    #[test]
+    fn test_all_sites_covered() {
+        for site in CONFLICT_SITES {
+            assert!(
+                SITES.iter().any(|s| s.site == site),
+                "no test for {}",
+                site.table
+            );
+        }
+    }
+
+    async fn rows_of(
+        site: &ConflictSite,
+        elements: &[i32],
+        tx: &mut sqlx::PgTransaction<'_>,
+    ) -> Vec<(i32, i32)> {
+        sqlx::query(&format!(
+            "SELECT {ident}, {element} FROM {table} WHERE {element} = ANY($1::int4[]) ORDER BY {ident}, {element}",
+            ident = site.ident_col,
+            element = site.element_col,
+            table = site.table
+        ))
+        .bind(elements)
+        .map(|r: sqlx::postgres::PgRow| (r.get::<i32, _>(0), r.get::<i32, _>(1)))
+        .fetch_all(&mut **tx)
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_conflict_resolve_query_sites() {
+        let scenario = TestSetup::new("test_conflict_resolve_query_sites").await;
+        let server = &scenario.server;
+        // parents: two of each vorgang, dokument, station and sitzung
+        for vg in [generate::default_vorgang(), generate::random::vorgang(42)] {
+            run_integration(&vg, Uuid::nil(), 1, server).await.unwrap();
+        }
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        for sz in [generate::default_sitzung(), generate::random::sitzung(42)] {
+            run_sitzung_integration(&sz, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap();
+        }
+
+        for Site {
+            site,
+            parent,
+            new_element,
+            insert_row,
+        } in SITES.iter()
+        {
+            let parents: Vec<i32> =
+                sqlx::query(&format!("SELECT id FROM {} ORDER BY id LIMIT 2", parent))
+                    .map(|r: sqlx::postgres::PgRow| r.get::<i32, _>(0))
+                    .fetch_all(&mut *tx)
+                    .await
+                    .unwrap();
+            assert_eq!(parents.len(), 2, "two parents for {}", site.table);
+            let (conflicted, untouched) = (parents[0], parents[1]);
+
+            let element = |name: String| {
+                sqlx::query(new_element)
+                    .bind(name)
+                    .map(|r: sqlx::postgres::PgRow| r.get::<i32, _>(0))
+            };
+            let old = element(format!("alt-{}", site.table))
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+            let new = element(format!("neu-{}", site.table))
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+
+            // `conflicted` references both old and new, replacing old by new would
+            // violate uniqueness; `untouched` only references old.
+            for (ident, elem) in [(conflicted, old), (conflicted, new), (untouched, old)] {
+                sqlx::query(insert_row)
+                    .bind(ident)
+                    .bind(elem)
+                    .execute(&mut *tx)
+                    .await
+                    .unwrap();
+            }
+
+            let ConflictResolutionQuery::Query(query) = site.resolution() else {
+                panic!("{} has no conflict resolution", site.table);
+            };
+            sqlx::query(&query)
+                .bind(&[new][..])
+                .bind(&[old][..])
+                .execute(&mut *tx)
+                .await
+                .unwrap_or_else(|e| panic!("conflict resolution for {} failed: {e}", site.table));
+            assert_eq!(
+                rows_of(site, &[old, new], &mut tx).await,
+                // of the two rows that would collide, the one with the lower element id is kept
+                vec![(conflicted, old), (untouched, old)],
+                "exactly the conflicting row of {} is deleted",
+                site.table
+            );
+
+            // the replacement itself now succeeds
+            sqlx::query(&format!(
+                "UPDATE {table} SET {element} = $1 WHERE {element} = $2",
+                table = site.table,
+                element = site.element_col
+            ))
+            .bind(new)
+            .bind(old)
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+            assert_eq!(
+                rows_of(site, &[old, new], &mut tx).await,
+                vec![(conflicted, new), (untouched, new)]
+            );
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
 }
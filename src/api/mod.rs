@@ -1,3 +1,8 @@
+//! The handlers of the generated OpenAPI server and the side routers.
+//!
+//! Endpoints that are not part of the generated OpenAPI server are plain axum handlers
+//! in their own modules, merged into the router in main, see `api::import` for an example.
+
 use chrono::DurationRound;
 use std::cmp::Ordering;
 use std::fmt::Debug;
@@ -5,6 +10,7 @@ use std::fmt::Display;
 use std::sync::Arc;
 
 use async_trait::async_trait;
+use axum::response::IntoResponse;
 use axum_extra::extract::Host;
 use openapi::models;
 use tracing::debug;
@@ -17,11 +23,35 @@ use crate::utils::notify;
 use crate::utils::tracing::Logging;
 use openapi::apis::unauthorisiert::*;
 
+pub(crate) mod abstimmung;
+pub(crate) mod aenderungen;
 pub(crate) mod auth;
+pub(crate) mod batch;
+pub(crate) mod db_audit;
+pub(crate) mod docs;
+pub(crate) mod enumeration;
+pub(crate) mod export;
+pub(crate) mod health;
+pub(crate) mod import;
+pub(crate) mod kalender;
+pub(crate) mod keys;
+pub(crate) mod migrations;
 pub(crate) mod misc;
 pub(crate) mod misc_auth;
+pub(crate) mod normalize;
+pub(crate) mod preview;
+pub(crate) mod projection;
+pub(crate) mod quarantine;
+pub(crate) mod review_queue;
+pub(crate) mod schlagworte;
 pub(crate) mod sitzung;
+pub(crate) mod staleness;
+pub(crate) mod station_links;
+pub(crate) mod stats;
+pub(crate) mod stellungnahmen;
+pub(crate) mod top;
 pub(crate) mod vorgang;
+pub(crate) mod wahlperiode;
 
 pub type Claims = (auth::APIScope, i32);
 
@@ -47,6 +77,12 @@ impl LTZFServer {
             logging,
         }
     }
+    /// scheme and authority under which clients reach the server, prepended to the links in `Link` headers.
+    /// Without `LTZF_PUBLIC_BASE_URL` the links stay relative, since the bind address is no address of the server
+    /// a client could use and the scheme in front of a reverse proxy is unknown
+    pub fn public_base_url(&self) -> String {
+        self.config.public_base_url.clone().unwrap_or_default()
+    }
 }
 
 #[async_trait]
@@ -59,11 +95,98 @@ impl openapi::apis::ErrorHandler<LTZFError> for LTZFServer {
         _cookies: &axum_extra::extract::CookieJar,
         error: LTZFError,
     ) -> std::result::Result<axum::response::Response, axum::http::StatusCode> {
+        if let LTZFError::DatabaseConstraint {
+            code,
+            constraint,
+            table,
+            detail,
+        } = &error
+        {
+            tracing::warn!("Request violated a database constraint during {method}: {error}");
+            return Ok((
+                constraint_status(code),
+                axum::Json(serde_json::json!({
+                    "code": code,
+                    "constraint": constraint,
+                    "table": table,
+                    "detail": detail
+                })),
+            )
+                .into_response());
+        }
+        if error.is_bad_request() {
+            tracing::warn!("Bad request during {method}: {error}");
+            return Ok((
+                axum::http::StatusCode::BAD_REQUEST,
+                axum::Json(serde_json::json!({ "message": error.to_string() })),
+            )
+                .into_response());
+        }
+        if error.is_conflict() {
+            tracing::warn!("Request conflicted with existing data during {method}: {error}");
+            return Ok((
+                axum::http::StatusCode::CONFLICT,
+                axum::Json(serde_json::json!({ "message": error.to_string() })),
+            )
+                .into_response());
+        }
+        if error.is_unprocessable() {
+            tracing::warn!("Submitted content could not be processed during {method}: {error}");
+            return Ok((
+                axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+                axum::Json(serde_json::json!({ "message": error.to_string() })),
+            )
+                .into_response());
+        }
+        if let LTZFError::Conflict { .. } = &error {
+            tracing::warn!("Request conflicted with concurrent writes during {method}: {error}");
+            return Err(axum::http::StatusCode::CONFLICT);
+        }
         tracing::error!("An error occurred during {method} that was not expected: {error}\n");
         return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
     }
 }
 
+/// result type of handlers that are not part of the generated server
+pub(crate) type HandlerResult =
+    std::result::Result<axum::response::Response, axum::http::StatusCode>;
+
+/// unique violations conflict with existing data, foreign key and check violations
+/// mean the submitted data itself is unusable
+fn constraint_status(code: &str) -> axum::http::StatusCode {
+    match code {
+        "23505" => axum::http::StatusCode::CONFLICT,
+        _ => axum::http::StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+/// counterpart of `handle_error` for handlers that are not part of the generated server
+pub(crate) fn internal_error<E: Into<LTZFError>>(error: E) -> axum::http::StatusCode {
+    let error: LTZFError = error.into();
+    if let LTZFError::DatabaseConstraint { code, .. } = &error {
+        tracing::warn!("Request violated a database constraint: {error}");
+        return constraint_status(code);
+    }
+    if error.is_bad_request() {
+        tracing::warn!("Bad request: {error}");
+        return axum::http::StatusCode::BAD_REQUEST;
+    }
+    if error.is_conflict() {
+        tracing::warn!("Request conflicted with existing data: {error}");
+        return axum::http::StatusCode::CONFLICT;
+    }
+    if error.is_unprocessable() {
+        tracing::warn!("Submitted content could not be processed: {error}");
+        return axum::http::StatusCode::UNPROCESSABLE_ENTITY;
+    }
+    if let LTZFError::Conflict { .. } = &error {
+        tracing::warn!("Request conflicted with concurrent writes: {error}");
+        return axum::http::StatusCode::CONFLICT;
+    }
+    tracing::error!("An error occurred that was not expected: {error}\n");
+    axum::http::StatusCode::INTERNAL_SERVER_ERROR
+}
+
 #[async_trait]
 impl Unauthorisiert<LTZFError> for LTZFServer {
     #[instrument(skip_all, fields(t=?query_params.t))]
@@ -112,9 +235,12 @@ impl PaginationResponsePart {
     pub const MAX_PER_PAGE: i32 = 256;
     pub fn new(x_total_count: i32, x_page: Option<i32>, x_per_page: Option<i32>) -> Self {
         let x_per_page = x_per_page
-            .map(|x| x.clamp(0, Self::MAX_PER_PAGE))
+            .map(|x| x.clamp(1, Self::MAX_PER_PAGE))
             .unwrap_or(Self::DEFAULT_PER_PAGE);
-        let x_total_pages = ((x_total_count as f32) / x_per_page as f32).ceil().max(1.) as i32;
+        // integer division, f32 cannot represent large counts exactly.
+        // i64 keeps the sum from overflowing, `div_ceil` is not stable for signed integers
+        let (total, per_page) = (x_total_count.max(0) as i64, x_per_page as i64);
+        let x_total_pages = ((total + per_page - 1) / per_page).max(1) as i32;
         let x_page = x_page.map(|x| x.clamp(1, x_total_pages)).unwrap_or(1);
 
         Self {
@@ -138,124 +264,317 @@ impl PaginationResponsePart {
             .min(self.x_total_count as i64)
             .max(0) as usize
     }
-    pub fn generate_link_header(&self, link_first_part: &str) -> String {
-        let mut link_string = String::new();
+    /// RFC 8288 links to the next, previous, first and last page, `base_url` is prepended to `path`.
+    /// The links keep the filter parameters in `query`, see `filter_query`
+    pub fn generate_link_header<K: AsRef<str>>(
+        &self,
+        base_url: &str,
+        path: &str,
+        query: &[(K, String)],
+    ) -> String {
+        let base_url = base_url.trim_end_matches('/');
+        let link = |page: i32, rel: &str| {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(query.iter().map(|(k, v)| (k.as_ref(), v)))
+                .append_pair("page", &page.to_string())
+                .append_pair("per_page", &self.x_per_page.to_string())
+                .finish();
+            format!("<{base_url}{path}?{query}>; rel=\"{rel}\"")
+        };
+        let mut links = vec![];
         if self.x_page < self.x_total_pages {
-            link_string = format!(
-                "<\"{}?page={}&per_page={}\">; rel=\"next\", ",
-                link_first_part,
-                self.x_page + 1,
-                self.x_per_page
-            );
+            links.push(link(self.x_page + 1, "next"));
         }
         if self.x_page > 1 {
-            link_string = format!(
-                "{}<\"{}?page={}&per_page={}\">; rel=\"previous\", ",
-                link_string,
-                link_first_part,
-                self.x_page - 1,
-                self.x_per_page
-            );
+            links.push(link(self.x_page - 1, "previous"));
         }
-        link_string = format!(
-            "{}<\"{}?page={}&per_page={}\">; rel=\"first\", ",
-            link_string, link_first_part, 1, self.x_per_page
-        );
-        link_string = format!(
-            "{}<\"{}?page={}&per_page={}\">; rel=\"last\"",
-            link_string,
-            link_first_part,
-            self.x_total_pages.max(1),
-            self.x_per_page
-        );
-        link_string
+        links.push(link(1, "first"));
+        links.push(link(self.x_total_pages.max(1), "last"));
+        links.join(", ")
     }
 }
 
+/// the filter parameters of a request for `generate_link_header`, i.e. the set query parameters
+/// except the pagination. Lists become one pair per element, like clients send them
+pub fn filter_query<T: serde::Serialize>(query_params: &T) -> Vec<(String, String)> {
+    let Ok(serde_json::Value::Object(params)) = serde_json::to_value(query_params) else {
+        return vec![];
+    };
+    let as_string = |value: serde_json::Value| match value {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Null => None,
+        other => Some(other.to_string()),
+    };
+    let mut pairs = vec![];
+    for (name, value) in params {
+        if name == "page" || name == "per_page" {
+            continue;
+        }
+        match value {
+            serde_json::Value::Array(values) => pairs.extend(
+                values
+                    .into_iter()
+                    .filter_map(as_string)
+                    .map(|v| (name.clone(), v)),
+            ),
+            value => pairs.extend(as_string(value).map(|v| (name, v))),
+        }
+    }
+    pairs
+}
+
+/// query parameters of `GET /api/v2/vorgang/{vorgang_id}` and `GET /api/v2/sitzung/{sid}`
+/// that are not in the generated server, see `utils::spec_ext`
+#[derive(Debug, Default, serde::Deserialize)]
+pub(crate) struct ExpandQueryParams {
+    /// replaces the references to Dokumente by the Dokumente
+    pub expand_dokumente: Option<bool>,
+}
+
+/// the Parlamente of a repeated or comma separated `p` query parameter, None if it is not given
+pub(crate) fn parlament_list(values: &[String]) -> Result<Option<Vec<models::Parlament>>> {
+    use std::str::FromStr;
+    let parlamente = values
+        .iter()
+        .flat_map(|v| v.split(','))
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            models::Parlament::from_str(p).map_err(|e| {
+                crate::error::DataValidationError::InvalidQueryParameter {
+                    parameter: "p".to_string(),
+                    message: e.to_string(),
+                }
+                .into()
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(crate::utils::as_option(parlamente))
+}
+
 #[cfg(test)]
 mod prp_test {
     use crate::api::PaginationResponsePart;
+
+    const BASE: &str = "https://api.ltzf.example.com/";
+    const NO_QUERY: &[(&str, String)] = &[];
     #[test]
     fn test_link_header() {
         let prp = PaginationResponsePart::new(0, None, Some(16));
-        let lh = prp.generate_link_header("/");
+        let lh = prp.generate_link_header(BASE, "/", NO_QUERY);
         let link_hdr_parts: Vec<_> = lh.split(", ").collect();
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=1&per_page=16\">; rel=\"first\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=1&per_page=16>; rel=\"first\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=1&per_page=16\">; rel=\"last\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=1&per_page=16>; rel=\"last\""),
             "{:?}",
             link_hdr_parts
         );
         assert_eq!(link_hdr_parts.len(), 2);
 
         let prp = PaginationResponsePart::new(100, Some(1), Some(16));
-        let lh = prp.generate_link_header("/");
+        let lh = prp.generate_link_header(BASE, "/", NO_QUERY);
         let link_hdr_parts: Vec<_> = lh.split(", ").collect();
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=2&per_page=16\">; rel=\"next\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=2&per_page=16>; rel=\"next\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=1&per_page=16\">; rel=\"first\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=1&per_page=16>; rel=\"first\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=7&per_page=16\">; rel=\"last\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=7&per_page=16>; rel=\"last\""),
             "{:?}",
             link_hdr_parts
         );
         assert_eq!(link_hdr_parts.len(), 3);
 
         let prp = PaginationResponsePart::new(100, Some(2), Some(16));
-        let lh = prp.generate_link_header("/");
+        let lh = prp.generate_link_header(BASE, "/", NO_QUERY);
         let link_hdr_parts: Vec<_> = lh.split(", ").collect();
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=3&per_page=16\">; rel=\"next\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=3&per_page=16>; rel=\"next\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=1&per_page=16\">; rel=\"previous\""),
+                .any(|x| *x
+                    == "<https://api.ltzf.example.com/?page=1&per_page=16>; rel=\"previous\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=1&per_page=16\">; rel=\"first\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=1&per_page=16>; rel=\"first\""),
             "{:?}",
             link_hdr_parts
         );
         assert!(
             link_hdr_parts
                 .iter()
-                .any(|x| *x == "<\"/?page=7&per_page=16\">; rel=\"last\""),
+                .any(|x| *x == "<https://api.ltzf.example.com/?page=7&per_page=16>; rel=\"last\""),
             "{:?}",
             link_hdr_parts
         );
         assert_eq!(link_hdr_parts.len(), 4);
     }
 
+    #[test]
+    fn test_link_header_parseable() {
+        let prp = PaginationResponsePart::new(100, Some(2), Some(16));
+        let lh = prp.generate_link_header(BASE, "/api/v2/vorgang", NO_QUERY);
+        let links = parse_link_header::parse_with_rel(&lh).unwrap();
+        assert_eq!(links.len(), 4);
+        let next = &links["next"];
+        assert_eq!(
+            next.raw_uri,
+            "https://api.ltzf.example.com/api/v2/vorgang?page=3&per_page=16"
+        );
+        assert_eq!(next.uri.scheme_str(), Some("https"));
+        assert_eq!(next.queries["page"], "3");
+        assert_eq!(links["last"].queries["page"], "7");
+    }
+
+    #[test]
+    fn test_link_header_keeps_query() {
+        let prp = PaginationResponsePart::new(100, Some(2), Some(16));
+        let lh =
+            prp.generate_link_header(BASE, "/api/v2/top", &[("vorgang_id", "a b&c".to_string())]);
+        let links = parse_link_header::parse_with_rel(&lh).unwrap();
+        assert_eq!(links.len(), 4);
+        for link in links.values() {
+            assert!(
+                link.raw_uri.contains("?vorgang_id=a+b%26c&page="),
+                "{}",
+                link.raw_uri
+            );
+        }
+        assert_eq!(
+            links["next"].raw_uri,
+            "https://api.ltzf.example.com/api/v2/top?vorgang_id=a+b%26c&page=3&per_page=16"
+        );
+    }
+
+    #[test]
+    fn test_filter_query() {
+        #[derive(serde::Serialize)]
+        struct Params {
+            page: Option<i32>,
+            per_page: Option<i32>,
+            wp: Option<i32>,
+            vgtyp: Option<String>,
+            p: Option<Vec<String>>,
+        }
+        let mut query = super::filter_query(&Params {
+            page: Some(2),
+            per_page: Some(16),
+            wp: Some(20),
+            vgtyp: None,
+            p: Some(vec!["BT".to_string(), "BY".to_string()]),
+        });
+        query.sort();
+        assert_eq!(
+            query,
+            vec![
+                ("p".to_string(), "BT".to_string()),
+                ("p".to_string(), "BY".to_string()),
+                ("wp".to_string(), "20".to_string()),
+            ]
+        );
+
+        // without a configured base url the links stay relative
+        let prp = PaginationResponsePart::new(100, Some(2), Some(16));
+        let lh = prp.generate_link_header("", "/api/v2/vorgang", &query);
+        assert!(
+            lh.starts_with("</api/v2/vorgang?p=BT&p=BY&wp=20&page=3&per_page=16>"),
+            "{lh}"
+        );
+    }
+
+    #[test]
+    fn test_parlament_list() {
+        use openapi::models::Parlament;
+        let list = |values: &[&str]| {
+            super::parlament_list(&values.iter().map(|v| v.to_string()).collect::<Vec<_>>())
+        };
+        assert_eq!(list(&[]).unwrap(), None);
+        assert_eq!(
+            list(&["BT", "BR,BY"]).unwrap(),
+            Some(vec![Parlament::Bt, Parlament::Br, Parlament::By])
+        );
+        assert!(list(&["BT,Mond"]).unwrap_err().is_bad_request());
+    }
+
+    /// the rel values of a link header, sorted
+    fn rels(prp: &PaginationResponsePart) -> Vec<String> {
+        let lh = prp.generate_link_header(BASE, "/api/v2/vorgang", NO_QUERY);
+        let mut rels: Vec<_> = parse_link_header::parse_with_rel(&lh)
+            .unwrap()
+            .into_keys()
+            .collect();
+        rels.sort();
+        rels
+    }
+
+    #[test]
+    fn test_link_header_page_edges() {
+        // exactly one full page
+        let prp = PaginationResponsePart::new(16, Some(1), Some(16));
+        assert_eq!(prp.x_total_pages, 1);
+        assert_eq!(rels(&prp), vec!["first", "last"]);
+
+        // one more than a page
+        let prp = PaginationResponsePart::new(17, Some(1), Some(16));
+        assert_eq!(prp.x_total_pages, 2);
+        assert_eq!(rels(&prp), vec!["first", "last", "next"]);
+        let prp = PaginationResponsePart::new(17, Some(2), Some(16));
+        assert_eq!(rels(&prp), vec!["first", "last", "previous"]);
+        assert_eq!((prp.start(), prp.end()), (16, 17));
+
+        // last of several maximal pages
+        let max = PaginationResponsePart::MAX_PER_PAGE;
+        let prp = PaginationResponsePart::new(3 * max, Some(3), Some(max));
+        assert_eq!(rels(&prp), vec!["first", "last", "previous"]);
+        assert_eq!(prp.end() - prp.start(), max as usize);
+    }
+
+    #[test]
+    fn test_link_header_valid_header_value() {
+        for (count, page, per_page) in [
+            (0, None, None),
+            (17, Some(2), Some(16)),
+            (1000, Some(5), Some(3)),
+        ] {
+            let prp = PaginationResponsePart::new(count, page, per_page);
+            let lh = prp.generate_link_header(BASE, "/api/v2/enumeration/schlagworte", NO_QUERY);
+            assert!(
+                axum::http::HeaderValue::from_str(&lh).is_ok(),
+                "not a valid header value: {lh}"
+            );
+        }
+    }
+
     #[test]
     fn test_start_and_end() {
         let prp = PaginationResponsePart::new(0, None, None);
@@ -266,6 +585,50 @@ mod prp_test {
         assert_eq!(prp.start(), 0);
         assert_eq!(prp.end(), 1);
     }
+
+    #[test]
+    fn test_per_page_zero() {
+        let prp = PaginationResponsePart::new(10, Some(3), Some(0));
+        assert_eq!(prp.x_per_page, 1);
+        assert_eq!(prp.x_total_pages, 10);
+        assert_eq!((prp.start(), prp.end()), (2, 3));
+
+        let prp = PaginationResponsePart::new(0, None, Some(0));
+        assert_eq!(prp.x_per_page, 1);
+        assert_eq!(prp.x_total_pages, 1);
+        assert_eq!((prp.start(), prp.end()), (0, 0));
+
+        let prp = PaginationResponsePart::new(5, None, Some(-4));
+        assert_eq!(prp.x_per_page, 1);
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_start_end_within_bounds(
+            x_total_count in 0..=i32::MAX,
+            x_page in proptest::option::of(proptest::num::i32::ANY),
+            x_per_page in proptest::option::of(proptest::num::i32::ANY),
+        ) {
+            let prp = PaginationResponsePart::new(x_total_count, x_page, x_per_page);
+            proptest::prop_assert!(prp.start() <= prp.end(), "{:?}", prp);
+            proptest::prop_assert!(prp.end() <= x_total_count as usize, "{:?}", prp);
+            proptest::prop_assert!(prp.end() - prp.start() <= prp.x_per_page as usize, "{:?}", prp);
+        }
+
+        #[test]
+        fn prop_last_page_is_full_or_remainder(
+            x_total_count in 1..=100_000i32,
+            x_per_page in 1..=PaginationResponsePart::MAX_PER_PAGE,
+        ) {
+            let prp = PaginationResponsePart::new(x_total_count, Some(i32::MAX), Some(x_per_page));
+            let expected = match x_total_count % x_per_page {
+                0 => x_per_page,
+                rest => rest,
+            };
+            proptest::prop_assert_eq!(prp.end() - prp.start(), expected as usize);
+            proptest::prop_assert_eq!(prp.end(), x_total_count as usize);
+        }
+    }
 }
 
 pub struct DateRange {
@@ -273,6 +636,31 @@ pub struct DateRange {
     pub until: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+impl DateRange {
+    /// None if both bounds are set and `since` does not precede `until`
+    pub fn validate(&self) -> Option<()> {
+        if let (Some(since), Some(until)) = (self.since, self.until) {
+            if since >= until {
+                return None;
+            }
+        }
+        Some(())
+    }
+    /// German representation for notifications, e.g. `von 01.01.2024 00:00:00 bis jetzt`
+    pub fn fmt_de(&self) -> String {
+        const FORMAT: &str = "%d.%m.%Y %H:%M:%S";
+        format!(
+            "von {} bis {}",
+            self.since
+                .map(|x| x.format(FORMAT).to_string())
+                .unwrap_or("Anfang an".to_string()),
+            self.until
+                .map(|x| x.format(FORMAT).to_string())
+                .unwrap_or("jetzt".to_string())
+        )
+    }
+}
+
 impl Debug for DateRange {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         Display::fmt(self, f)
@@ -329,7 +717,9 @@ pub fn find_applicable_date_range(
             } else {
                 Some((
                     chrono::NaiveDate::from_ymd_opt(y as i32, m, 1).unwrap(),
-                    chrono::NaiveDate::from_ymd_opt(y as i32, m + 1, 1)
+                    chrono::NaiveDate::from_ymd_opt(y as i32, m, 1)
+                        .unwrap()
+                        .checked_add_months(chrono::Months::new(1))
                         .unwrap()
                         .checked_sub_days(chrono::Days::new(1))
                         .unwrap(),
@@ -389,13 +779,12 @@ pub fn find_applicable_date_range(
         if since_min.is_some() && since_min.unwrap() > yu
             || until_min.is_some() && until_min.unwrap() < ys
         {
-            None
-        } else {
-            Some((since_min, until_min).into())
+            return None;
         }
-    } else {
-        Some((since_min, until_min).into())
     }
+    let range: DateRange = (since_min, until_min).into();
+    range.validate()?;
+    Some(range)
 }
 
 #[cfg(test)]
@@ -430,6 +819,23 @@ mod test_applicable_date_range {
         )
     }
     #[test]
+    fn test_date_range_since_until_order() {
+        let until = DateTime::parse_from_rfc3339("1960-01-02T00:00:00+00:00")
+            .unwrap()
+            .to_utc();
+        let result = find_applicable_date_range(None, None, None, Some(until), Some(until), None);
+        assert!(result.is_none(), "since == until should yield None");
+
+        let since = until - chrono::Duration::seconds(1);
+        let result = find_applicable_date_range(None, None, None, Some(since), Some(until), None);
+        assert!(
+            result.is_some()
+                && result.as_ref().unwrap().since == Some(since)
+                && result.unwrap().until == Some(until),
+            "since slightly before until should yield (since, until)"
+        );
+    }
+    #[test]
     fn test_date_range_ymd() {
         let y = 2012u32;
         let m = 5u32;
@@ -491,6 +897,32 @@ mod test_applicable_date_range {
         );
     }
 
+    #[test]
+    fn test_date_range_ym_month_ends() {
+        // (year, month, last day of the month)
+        for (y, m, last) in [
+            (2023u32, 12u32, 31u32),
+            (2024, 2, 29),
+            (2023, 2, 28),
+            (2023, 1, 31),
+        ] {
+            let result = find_applicable_date_range(Some(y), Some(m), None, None, None, None)
+                .expect("year and month should always yield a date range");
+            let expected_since = chrono::NaiveDate::from_ymd_opt(y as i32, m, 1)
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc();
+            let expected_until = chrono::NaiveDate::from_ymd_opt(y as i32, m, last)
+                .unwrap()
+                .and_hms_opt(23, 59, 59)
+                .unwrap()
+                .and_utc();
+            assert_eq!(result.since, Some(expected_since), "{y}-{m}");
+            assert_eq!(result.until, Some(expected_until), "{y}-{m}");
+        }
+    }
+
     #[test]
     fn test_minmax() {
         let y = 2012u32;
@@ -526,8 +958,59 @@ mod test_applicable_date_range {
     }
 }
 
-/// this is here to implement a PartialEq, Eq, Ord, ...
-/// for hashing, since we need t
+#[cfg(test)]
+mod test_date_range_format {
+    use super::DateRange;
+
+    fn ranges() -> [DateRange; 4] {
+        let since = chrono::NaiveDate::from_ymd_opt(2024, 1, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        let until = chrono::NaiveDate::from_ymd_opt(2024, 12, 31)
+            .unwrap()
+            .and_hms_opt(23, 59, 59)
+            .unwrap()
+            .and_utc();
+        [
+            (Some(since), Some(until)).into(),
+            (Some(since), None).into(),
+            (None, Some(until)).into(),
+            (None, None).into(),
+        ]
+    }
+
+    #[test]
+    fn test_date_range_display() {
+        let expected = [
+            "[2024-01-01 00:00:00 UTC, 2024-12-31 23:59:59 UTC]",
+            "[2024-01-01 00:00:00 UTC, ∞]",
+            "[-∞, 2024-12-31 23:59:59 UTC]",
+            "[-∞, ∞]",
+        ];
+        for (range, expected) in ranges().iter().zip(expected) {
+            assert_eq!(range.to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_date_range_fmt_de() {
+        let expected = [
+            "von 01.01.2024 00:00:00 bis 31.12.2024 23:59:59",
+            "von 01.01.2024 00:00:00 bis jetzt",
+            "von Anfang an bis 31.12.2024 23:59:59",
+            "von Anfang an bis jetzt",
+        ];
+        for (range, expected) in ranges().iter().zip(expected) {
+            assert_eq!(range.fmt_de(), expected);
+        }
+    }
+}
+
+/// Wrapper that compares Autoren by their identifying fields `(organisation, person)` only,
+/// so it can be put into a set. `fachgebiet` and `lobbyregister` are non-identifying,
+/// two Autoren that only differ there are the same Autor.
 #[derive(Debug, Clone)]
 pub(crate) struct WrappedAutor<'wrapped> {
     pub autor: &'wrapped models::Autor,
@@ -552,169 +1035,6 @@ impl<'wrapped> Ord for WrappedAutor<'wrapped> {
             .then(self.autor.person.cmp(&other.autor.person))
     }
 }
-/// This trait enables sorting all arrays contained in an object
-/// to be able to compare them afterwards without caring for ordering
-#[cfg(test)]
-pub(crate) trait SortArrays: Clone {
-    fn sort_arrays(&mut self);
-}
-
-#[cfg(test)]
-impl SortArrays for models::Dokument {
-    fn sort_arrays(&mut self) {
-        let nil = uuid::Uuid::nil();
-        let emp = "".to_owned();
-        if let Some(x) = self.schlagworte.as_mut() {
-            x.sort();
-        }
-        if let Some(x) = self.touched_by.as_mut() {
-            x.sort_by(|a, b| {
-                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
-                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
-            })
-        }
-        self.autoren
-            .sort_by(|a, b| a.organisation.cmp(&b.organisation));
-    }
-}
-#[cfg(test)]
-impl SortArrays for models::Station {
-    fn sort_arrays(&mut self) {
-        let nil = uuid::Uuid::nil();
-        let emp = "".to_owned();
-        if let Some(x) = self.schlagworte.as_mut() {
-            x.sort();
-        }
-        if let Some(x) = self.touched_by.as_mut() {
-            x.sort_by(|a, b| {
-                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
-                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
-            })
-        }
-        if let Some(x) = self.additional_links.as_mut() {
-            x.sort();
-        }
-        self.dokumente.sort_by(|a, b| match (a, b) {
-            (
-                models::StationDokumenteInner::String(x),
-                models::StationDokumenteInner::String(y),
-            ) => x.cmp(y),
-            (
-                models::StationDokumenteInner::String(x),
-                models::StationDokumenteInner::Dokument(y),
-            ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
-            (
-                models::StationDokumenteInner::Dokument(x),
-                models::StationDokumenteInner::String(y),
-            ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
-            (
-                models::StationDokumenteInner::Dokument(x),
-                models::StationDokumenteInner::Dokument(y),
-            ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
-        });
-        self.dokumente.iter_mut().for_each(|x| {
-            if let models::StationDokumenteInner::Dokument(x) = x {
-                x.sort_arrays();
-            }
-        });
-        if let Some(x) = self.stellungnahmen.as_mut() {
-            x.sort_by(|a, b| match (a, b) {
-                (
-                    models::StationDokumenteInner::String(x),
-                    models::StationDokumenteInner::String(y),
-                ) => x.cmp(y),
-                (
-                    models::StationDokumenteInner::String(x),
-                    models::StationDokumenteInner::Dokument(y),
-                ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
-                (
-                    models::StationDokumenteInner::Dokument(x),
-                    models::StationDokumenteInner::String(y),
-                ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
-                (
-                    models::StationDokumenteInner::Dokument(x),
-                    models::StationDokumenteInner::Dokument(y),
-                ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
-            });
-            x.iter_mut().for_each(|x| {
-                if let models::StationDokumenteInner::Dokument(x) = x {
-                    x.sort_arrays();
-                }
-            });
-        }
-    }
-}
-#[cfg(test)]
-impl SortArrays for models::Vorgang {
-    fn sort_arrays(&mut self) {
-        let nil = uuid::Uuid::nil();
-        let emp = "".to_owned();
-        if let Some(x) = self.touched_by.as_mut() {
-            x.sort_by(|a, b| {
-                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
-                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
-            })
-        }
-        if let Some(x) = self.lobbyregister.as_mut() {
-            x.sort_by(|a, b| a.link.cmp(&b.link));
-        }
-        self.stationen
-            .sort_by(|a, b| (a.zp_start, a.api_id).cmp(&(b.zp_start, b.api_id)));
-        self.stationen.iter_mut().for_each(|a| a.sort_arrays());
-        self.initiatoren
-            .sort_by(|a, b| a.organisation.cmp(&b.organisation));
-        if let Some(x) = self.ids.as_mut() {
-            x.sort_by(|a, b| (a.typ, &a.id).cmp(&(b.typ, &b.id)));
-        }
-        if let Some(x) = self.links.as_mut() {
-            x.sort();
-        }
-    }
-}
-#[cfg(test)]
-impl SortArrays for models::Sitzung {
-    fn sort_arrays(&mut self) {
-        let nil = uuid::Uuid::nil();
-        let emp = "".to_owned();
-        if let Some(x) = self.touched_by.as_mut() {
-            x.sort_by(|a, b| {
-                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
-                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
-            })
-        }
-        if let Some(x) = self.experten.as_mut() {
-            x.sort_by(|a, b| a.organisation.cmp(&b.organisation));
-        }
-
-        if let Some(x) = self.dokumente.as_mut() {
-            x.sort_by(|a, b| match (a, b) {
-                (
-                    models::StationDokumenteInner::String(x),
-                    models::StationDokumenteInner::String(y),
-                ) => x.cmp(y),
-                (
-                    models::StationDokumenteInner::String(x),
-                    models::StationDokumenteInner::Dokument(y),
-                ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
-                (
-                    models::StationDokumenteInner::Dokument(x),
-                    models::StationDokumenteInner::String(y),
-                ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
-                (
-                    models::StationDokumenteInner::Dokument(x),
-                    models::StationDokumenteInner::Dokument(y),
-                ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
-            });
-
-            x.iter_mut().for_each(|x| {
-                if let models::StationDokumenteInner::Dokument(x) = x {
-                    x.sort_arrays();
-                }
-            });
-        }
-        self.tops.sort_by(|a, b| a.nummer.cmp(&b.nummer));
-    }
-}
 /// Helper Trait that allows me to compare objects (vorgang, dokument, ...)
 /// that are stored and re-fetched with whatever precision where only the
 /// very very margins differ by a few nanosecs.
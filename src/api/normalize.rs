@@ -0,0 +1,293 @@
+//! Normalisation of API objects so that they can be compared independent of
+//! the order of their arrays and of sub-second timestamp differences.
+
+use openapi::models;
+
+use super::RoundTimestamp;
+
+/// This trait enables sorting all arrays contained in an object
+/// to be able to compare them afterwards without caring for ordering
+pub(crate) trait SortArrays: Clone {
+    fn sort_arrays(&mut self);
+}
+
+impl SortArrays for models::Dokument {
+    fn sort_arrays(&mut self) {
+        let nil = uuid::Uuid::nil();
+        let emp = "".to_owned();
+        if let Some(x) = self.schlagworte.as_mut() {
+            x.sort();
+        }
+        if let Some(x) = self.touched_by.as_mut() {
+            x.sort_by(|a, b| {
+                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
+                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
+            })
+        }
+        self.autoren
+            .sort_by(|a, b| a.organisation.cmp(&b.organisation));
+    }
+}
+impl SortArrays for models::Station {
+    fn sort_arrays(&mut self) {
+        let nil = uuid::Uuid::nil();
+        let emp = "".to_owned();
+        if let Some(x) = self.schlagworte.as_mut() {
+            x.sort();
+        }
+        if let Some(x) = self.touched_by.as_mut() {
+            x.sort_by(|a, b| {
+                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
+                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
+            })
+        }
+        if let Some(x) = self.additional_links.as_mut() {
+            x.sort();
+        }
+        self.dokumente.sort_by(|a, b| match (a, b) {
+            (
+                models::StationDokumenteInner::String(x),
+                models::StationDokumenteInner::String(y),
+            ) => x.cmp(y),
+            (
+                models::StationDokumenteInner::String(x),
+                models::StationDokumenteInner::Dokument(y),
+            ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
+            (
+                models::StationDokumenteInner::Dokument(x),
+                models::StationDokumenteInner::String(y),
+            ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
+            (
+                models::StationDokumenteInner::Dokument(x),
+                models::StationDokumenteInner::Dokument(y),
+            ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
+        });
+        self.dokumente.iter_mut().for_each(|x| {
+            if let models::StationDokumenteInner::Dokument(x) = x {
+                x.sort_arrays();
+            }
+        });
+        if let Some(x) = self.stellungnahmen.as_mut() {
+            x.sort_by(|a, b| match (a, b) {
+                (
+                    models::StationDokumenteInner::String(x),
+                    models::StationDokumenteInner::String(y),
+                ) => x.cmp(y),
+                (
+                    models::StationDokumenteInner::String(x),
+                    models::StationDokumenteInner::Dokument(y),
+                ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
+                (
+                    models::StationDokumenteInner::Dokument(x),
+                    models::StationDokumenteInner::String(y),
+                ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
+                (
+                    models::StationDokumenteInner::Dokument(x),
+                    models::StationDokumenteInner::Dokument(y),
+                ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
+            });
+            x.iter_mut().for_each(|x| {
+                if let models::StationDokumenteInner::Dokument(x) = x {
+                    x.sort_arrays();
+                }
+            });
+        }
+    }
+}
+impl SortArrays for models::Vorgang {
+    fn sort_arrays(&mut self) {
+        let nil = uuid::Uuid::nil();
+        let emp = "".to_owned();
+        if let Some(x) = self.touched_by.as_mut() {
+            x.sort_by(|a, b| {
+                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
+                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
+            })
+        }
+        if let Some(x) = self.lobbyregister.as_mut() {
+            x.sort_by(|a, b| a.link.cmp(&b.link));
+        }
+        self.stationen
+            .sort_by(|a, b| (a.zp_start, a.api_id).cmp(&(b.zp_start, b.api_id)));
+        self.stationen.iter_mut().for_each(|a| a.sort_arrays());
+        self.initiatoren
+            .sort_by(|a, b| a.organisation.cmp(&b.organisation));
+        if let Some(x) = self.ids.as_mut() {
+            x.sort_by(|a, b| (a.typ, &a.id).cmp(&(b.typ, &b.id)));
+        }
+        if let Some(x) = self.links.as_mut() {
+            x.sort();
+        }
+    }
+}
+impl SortArrays for models::Sitzung {
+    fn sort_arrays(&mut self) {
+        let nil = uuid::Uuid::nil();
+        let emp = "".to_owned();
+        if let Some(x) = self.touched_by.as_mut() {
+            x.sort_by(|a, b| {
+                (a.key.as_ref().unwrap_or(&emp), a.scraper_id.unwrap_or(nil))
+                    .cmp(&(b.key.as_ref().unwrap_or(&emp), b.scraper_id.unwrap_or(nil)))
+            })
+        }
+        if let Some(x) = self.experten.as_mut() {
+            x.sort_by(|a, b| a.organisation.cmp(&b.organisation));
+        }
+
+        if let Some(x) = self.dokumente.as_mut() {
+            x.sort_by(|a, b| match (a, b) {
+                (
+                    models::StationDokumenteInner::String(x),
+                    models::StationDokumenteInner::String(y),
+                ) => x.cmp(y),
+                (
+                    models::StationDokumenteInner::String(x),
+                    models::StationDokumenteInner::Dokument(y),
+                ) => (**x).cmp(&y.api_id.unwrap_or(nil).to_string()),
+                (
+                    models::StationDokumenteInner::Dokument(x),
+                    models::StationDokumenteInner::String(y),
+                ) => (**y).cmp(&x.api_id.unwrap_or(nil).to_string()),
+                (
+                    models::StationDokumenteInner::Dokument(x),
+                    models::StationDokumenteInner::Dokument(y),
+                ) => x.api_id.unwrap_or(nil).cmp(&y.api_id.unwrap_or(nil)),
+            });
+
+            x.iter_mut().for_each(|x| {
+                if let models::StationDokumenteInner::Dokument(x) = x {
+                    x.sort_arrays();
+                }
+            });
+        }
+        self.tops.sort_by(|a, b| a.nummer.cmp(&b.nummer));
+    }
+}
+
+/// sorted arrays and rounded timestamps, the form in which two objects are compared
+pub(crate) trait Normalize: SortArrays + RoundTimestamp {
+    fn normalized(&self) -> Self {
+        let mut x = self.with_round_timestamps();
+        x.sort_arrays();
+        x
+    }
+}
+impl<T: SortArrays + RoundTimestamp> Normalize for T {}
+
+pub(crate) fn compare_vorgang(a: &models::Vorgang, b: &models::Vorgang) -> bool {
+    a.normalized() == b.normalized()
+}
+pub(crate) fn compare_sitzung(a: &models::Sitzung, b: &models::Sitzung) -> bool {
+    a.normalized() == b.normalized()
+}
+pub(crate) fn compare_dokument(a: &models::Dokument, b: &models::Dokument) -> bool {
+    a.normalized() == b.normalized()
+}
+
+#[cfg(test)]
+mod test_sort_arrays {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::SortArrays;
+    use crate::utils::testing::generate;
+
+    fn dokument(api_id: u128, schlagworte: &[&str]) -> models::Dokument {
+        models::Dokument {
+            api_id: Some(Uuid::from_u128(api_id)),
+            schlagworte: Some(schlagworte.iter().map(|s| s.to_string()).collect()),
+            ..generate::default_dokument()
+        }
+    }
+    fn station(api_id: u128, dokumente: Vec<models::Dokument>) -> models::Station {
+        models::Station {
+            api_id: Some(Uuid::from_u128(api_id)),
+            schlagworte: Some(vec!["zwei".to_string(), "eins".to_string()]),
+            dokumente: dokumente
+                .into_iter()
+                .map(models::StationDokumenteInner::Dokument)
+                .collect(),
+            ..generate::default_station()
+        }
+    }
+
+    #[test]
+    fn test_nested_vorgang_sorting() {
+        let mut scrambled = models::Vorgang {
+            stationen: vec![
+                station(2, vec![dokument(4, &["z", "a"]), dokument(3, &["c", "b"])]),
+                station(1, vec![dokument(2, &["y", "x", "w"]), dokument(1, &[])]),
+            ],
+            ..generate::default_vorgang()
+        };
+        let sorted = |api_id: u128, dokumente: Vec<models::Dokument>| models::Station {
+            schlagworte: Some(vec!["eins".to_string(), "zwei".to_string()]),
+            ..station(api_id, dokumente)
+        };
+        let expected = models::Vorgang {
+            stationen: vec![
+                sorted(1, vec![dokument(1, &[]), dokument(2, &["w", "x", "y"])]),
+                sorted(2, vec![dokument(3, &["b", "c"]), dokument(4, &["a", "z"])]),
+            ],
+            ..scrambled.clone()
+        };
+        scrambled.sort_arrays();
+        assert_eq!(scrambled, expected);
+    }
+
+    #[test]
+    fn test_nested_sitzung_sorting() {
+        let mut scrambled = models::Sitzung {
+            dokumente: Some(vec![
+                models::StationDokumenteInner::Dokument(dokument(2, &["b", "a"])),
+                models::StationDokumenteInner::Dokument(dokument(1, &["d", "c"])),
+            ]),
+            tops: vec![
+                models::Top {
+                    nummer: 2,
+                    ..generate::default_top()
+                },
+                models::Top {
+                    nummer: 1,
+                    ..generate::default_top()
+                },
+            ],
+            ..generate::default_sitzung()
+        };
+        let expected = models::Sitzung {
+            dokumente: Some(vec![
+                models::StationDokumenteInner::Dokument(dokument(1, &["c", "d"])),
+                models::StationDokumenteInner::Dokument(dokument(2, &["a", "b"])),
+            ]),
+            tops: vec![
+                models::Top {
+                    nummer: 1,
+                    ..generate::default_top()
+                },
+                models::Top {
+                    nummer: 2,
+                    ..generate::default_top()
+                },
+            ],
+            ..scrambled.clone()
+        };
+        scrambled.sort_arrays();
+        assert_eq!(scrambled, expected);
+    }
+
+    #[test]
+    fn test_sorting_is_idempotent() {
+        let mut once = generate::random::vorgang(7);
+        once.stationen.reverse();
+        once.sort_arrays();
+        let mut twice = once.clone();
+        twice.sort_arrays();
+        assert_eq!(once, twice);
+
+        let mut once = generate::random::sitzung(7);
+        once.sort_arrays();
+        let mut twice = once.clone();
+        twice.sort_arrays();
+        assert_eq!(once, twice);
+    }
+}
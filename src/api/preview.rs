@@ -0,0 +1,122 @@
+//! Preview of the merge outcome for a Vorgang, so that scrapers can detect
+//! conflicts before submitting.
+//!
+//! - `POST /api/v2/vorgang/preview_merge` reports whether the Vorgang would be inserted or merged
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::post;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::db::merge::MatchState;
+use crate::db::merge::candidates::vorgang_merge_candidates;
+use crate::{LTZFArc, LTZFServer, Result};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeAction {
+    Insert,
+    Merge,
+    Ambiguous,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MergePreview {
+    pub action: MergeAction,
+    /// the candidate the Vorgang would be merged into, or the most similar one if ambiguous
+    pub candidate_api_id: Option<Uuid>,
+    /// title similarity between the submitted Vorgang and the candidate
+    pub similarity: Option<f32>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/vorgang/preview_merge", post(preview_merge))
+        .with_state(server)
+}
+
+/// PreviewMerge - POST /api/v2/vorgang/preview_merge
+#[instrument(skip_all, fields(vg=%body.api_id))]
+async fn preview_merge(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Json(body): Json<models::Vorgang>,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Collector, APIScope::Admin, APIScope::KeyAdder],
+    )
+    .await?;
+    let preview = merge_preview(&body, &server)
+        .await
+        .map_err(internal_error)?;
+    info!("Merge preview: {:?}", preview);
+    Ok(Json(preview).into_response())
+}
+
+/// determines the merge candidates of `model` in a transaction that is rolled back afterwards
+pub async fn merge_preview(model: &models::Vorgang, srv: &LTZFServer) -> Result<MergePreview> {
+    let mut tx = srv.sqlx_db.begin().await?;
+    let (action, candidates) = match vorgang_merge_candidates(model, &mut *tx, srv).await? {
+        MatchState::NoMatch => (MergeAction::Insert, vec![]),
+        MatchState::ExactlyOne(one) => (MergeAction::Merge, vec![one]),
+        MatchState::Ambiguous(many) => (MergeAction::Ambiguous, many),
+    };
+    let best = sqlx::query!(
+        "SELECT api_id, ltzf_similarity(titel, $2, $3) as sim FROM vorgang
+        WHERE id = ANY($1::int4[])
+        ORDER BY sim DESC NULLS LAST
+        LIMIT 1",
+        &candidates[..],
+        model.titel,
+        srv.config.similarity_function.as_sql()
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    tx.rollback().await?;
+    Ok(MergePreview {
+        action,
+        candidate_api_id: best.as_ref().map(|b| b.api_id),
+        similarity: best.and_then(|b| b.sim),
+    })
+}
+
+#[cfg(test)]
+mod test_preview {
+    use uuid::Uuid;
+
+    use super::{MergeAction, merge_preview};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_merge_preview() {
+        let scenario = TestSetup::new("test_merge_preview").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let preview = merge_preview(&vorgang, server).await.unwrap();
+        assert_eq!(preview.action, MergeAction::Insert);
+        assert_eq!(preview.candidate_api_id, None);
+
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let resubmission = openapi::models::Vorgang {
+            api_id: Uuid::now_v7(),
+            ..vorgang.clone()
+        };
+        let preview = merge_preview(&resubmission, server).await.unwrap();
+        assert_eq!(preview.action, MergeAction::Merge);
+        assert_eq!(preview.candidate_api_id, Some(vorgang.api_id));
+        assert_eq!(preview.similarity, Some(1.));
+        scenario.teardown().await;
+    }
+}
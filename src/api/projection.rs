@@ -0,0 +1,246 @@
+//! Experimental: field projection for Vorgang responses, which can become very large
+//! because of the Dokument volltext.
+//!
+//! - `GET /api/v2/vorgang/{vorgang_id}/projection?fields=api_id,titel,stationen.typ` returns only the listed fields
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use openapi::models;
+use serde::Deserialize;
+use serde::de::{DeserializeOwned, Deserializer, Visitor};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::LTZFArc;
+use crate::api::{HandlerResult, internal_error};
+use crate::db::retrieve;
+
+/// a field of a model as it appears in the serialised json, together with the fields of the nested model
+#[derive(Debug)]
+pub struct FieldSchema {
+    pub name: &'static str,
+    pub nested: Vec<FieldSchema>,
+}
+
+/// the serialised field names of `T`, taken from its `Deserialize` implementation, which passes
+/// them to `deserialize_struct`. Fields in `nested` are described by the given nested schema.
+fn schema_of<T: DeserializeOwned>(mut nested: Vec<(&str, Vec<FieldSchema>)>) -> Vec<FieldSchema> {
+    let mut names = FieldNames(&[]);
+    let _ = T::deserialize(&mut names);
+    let schema: Vec<_> = names
+        .0
+        .iter()
+        .map(|&name| FieldSchema {
+            name,
+            nested: nested
+                .iter()
+                .position(|(n, _)| *n == name)
+                .map(|i| nested.swap_remove(i).1)
+                .unwrap_or_default(),
+        })
+        .collect();
+    debug_assert!(nested.is_empty(), "unknown nested fields {nested:?}");
+    schema
+}
+
+/// a deserializer that only records the field names of the struct it is asked for
+struct FieldNames(&'static [&'static str]);
+impl<'de> Deserializer<'de> for &mut FieldNames {
+    type Error = serde::de::value::Error;
+    fn deserialize_any<V: Visitor<'de>>(self, _: V) -> std::result::Result<V::Value, Self::Error> {
+        Err(serde::de::Error::custom("only structs are supported"))
+    }
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        _: V,
+    ) -> std::result::Result<V::Value, Self::Error> {
+        self.0 = fields;
+        Err(serde::de::Error::custom("field names recorded"))
+    }
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+        option unit unit_struct newtype_struct seq tuple tuple_struct map enum identifier ignored_any
+    }
+}
+
+/// the structure of `models::Vorgang`, derived from the model
+pub static VORGANG: LazyLock<Vec<FieldSchema>> = LazyLock::new(|| {
+    let autor = schema_of::<models::Autor>(vec![]);
+    let gremium = schema_of::<models::Gremium>(vec![]);
+    let station = schema_of::<models::Station>(vec![("gremium", gremium)]);
+    let mut vorgang =
+        schema_of::<models::Vorgang>(vec![("initiatoren", autor), ("stationen", station)]);
+    // not in the generated model, see `utils::spec_ext`
+    vorgang.push(FieldSchema {
+        name: "aendert",
+        nested: vec![],
+    });
+    vorgang
+});
+
+/// a set of dotted field paths. A field without children is kept as a whole.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProjectionSet {
+    fields: BTreeMap<String, ProjectionSet>,
+}
+
+impl ProjectionSet {
+    /// parses a comma separated list of dotted paths and validates them against `schema`.
+    /// Returns the offending path on error.
+    pub fn parse(fields: &str, schema: &[FieldSchema]) -> std::result::Result<Self, String> {
+        let mut set = ProjectionSet::default();
+        for path in fields.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut node = &mut set;
+            let mut level = schema;
+            for segment in path.split('.') {
+                let Some(field) = level.iter().find(|f| f.name == segment) else {
+                    return Err(path.to_string());
+                };
+                level = &field.nested;
+                node = node.fields.entry(segment.to_string()).or_default();
+            }
+        }
+        if set.fields.is_empty() {
+            return Err(fields.to_string());
+        }
+        Ok(set)
+    }
+
+    /// removes every field from `value` that is not part of this set. Arrays are projected element-wise.
+    pub fn apply(&self, value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Array(elements) => {
+                elements.iter_mut().for_each(|e| self.apply(e));
+            }
+            serde_json::Value::Object(map) => {
+                map.retain(|k, _| self.fields.contains_key(k));
+                for (key, child) in map.iter_mut() {
+                    let projection = &self.fields[key];
+                    if !projection.fields.is_empty() {
+                        projection.apply(child);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectionQuery {
+    pub fields: String,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/vorgang/{vorgang_id}/projection",
+            get(vorgang_projection),
+        )
+        .with_state(server)
+}
+
+/// VorgangProjection - GET /api/v2/vorgang/{vorgang_id}/projection
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn vorgang_projection(
+    State(server): State<LTZFArc>,
+    Path(vorgang_id): Path<Uuid>,
+    Query(query): Query<ProjectionQuery>,
+) -> HandlerResult {
+    let projection = match ProjectionSet::parse(&query.fields, &VORGANG) {
+        Ok(projection) => projection,
+        Err(path) => {
+            warn!("Unknown field path in projection: `{path}`");
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    };
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let vorgang = retrieve::vorgang_by_id(id, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    let mut value =
+        serde_json::to_value(&vorgang).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    crate::utils::spec_ext::apply_recorded(&mut value);
+    projection.apply(&mut value);
+    info!("Successful projected retrieval");
+    Ok(Json(value).into_response())
+}
+
+#[cfg(test)]
+mod test_projection {
+    use super::{ProjectionSet, VORGANG};
+    use crate::utils::testing::generate;
+
+    #[test]
+    fn test_projection_parse() {
+        assert!(ProjectionSet::parse("api_id, titel,stationen.typ", &VORGANG).is_ok());
+        assert!(ProjectionSet::parse("stationen.gremium.name", &VORGANG).is_ok());
+        assert_eq!(
+            ProjectionSet::parse("api_id,stationen.volltext", &VORGANG),
+            Err("stationen.volltext".to_string())
+        );
+        assert!(ProjectionSet::parse("unbekannt", &VORGANG).is_err());
+        assert!(ProjectionSet::parse(" , ", &VORGANG).is_err());
+    }
+
+    #[test]
+    fn test_schema_matches_model() {
+        // every field of a serialised Vorgang can be projected
+        let value = serde_json::to_value(generate::default_vorgang()).unwrap();
+        for key in value.as_object().unwrap().keys() {
+            assert!(ProjectionSet::parse(key, &VORGANG).is_ok(), "{key}");
+        }
+        for key in value["stationen"][0].as_object().unwrap().keys() {
+            let path = format!("stationen.{key}");
+            assert!(ProjectionSet::parse(&path, &VORGANG).is_ok(), "{path}");
+        }
+        assert!(
+            VORGANG
+                .iter()
+                .any(|f| f.name == "initiatoren" && !f.nested.is_empty())
+        );
+    }
+
+    #[test]
+    fn test_projection_apply() {
+        let vorgang = generate::default_vorgang();
+        let mut value = serde_json::to_value(&vorgang).unwrap();
+        ProjectionSet::parse("api_id,stationen.api_id,stationen.typ", &VORGANG)
+            .unwrap()
+            .apply(&mut value);
+        let object = value.as_object().unwrap();
+        assert_eq!(object.len(), 2);
+        assert_eq!(object["api_id"], serde_json::json!(vorgang.api_id));
+        let station = object["stationen"][0].as_object().unwrap();
+        assert_eq!(station.len(), 2);
+        assert!(station.contains_key("typ"));
+
+        // a field without children keeps its whole subtree
+        let mut value = serde_json::to_value(&vorgang).unwrap();
+        ProjectionSet::parse("stationen", &VORGANG)
+            .unwrap()
+            .apply(&mut value);
+        assert_eq!(
+            value["stationen"],
+            serde_json::to_value(&vorgang.stationen).unwrap()
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! Review of enumeration values that were added automatically because a scraper submitted
+//! them while `LTZF_QUARANTINE_UNKNOWN_ENUMS` was set, see `db::insert::ensure_enum_value`.
+//!
+//! - `GET /api/v2/admin/quarantine/enums` lists the quarantined values
+//! - `DELETE /api/v2/admin/quarantine/enums/{name}/{value}` approves the value, it stays in the enumeration.
+//!   With `?remove=true` the value is removed from the enumeration as well, as long as nothing refers to it.
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{delete, get};
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::enumeration::enum_table;
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct QuarantinedValue {
+    pub table_name: String,
+    pub value: String,
+    pub first_seen: chrono::DateTime<chrono::Utc>,
+    pub seen_count: i32,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReleaseQuery {
+    #[serde(default)]
+    pub remove: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReleaseOutcome {
+    Approved,
+    Removed,
+    NotFound,
+    InUse,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/quarantine/enums", get(quarantine_list))
+        .route(
+            "/api/v2/admin/quarantine/enums/{name}/{value}",
+            delete(quarantine_release),
+        )
+        .with_state(server)
+}
+
+/// QuarantineList - GET /api/v2/admin/quarantine/enums
+#[instrument(skip_all)]
+async fn quarantine_list(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let values = quarantined_values(&mut tx).await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(Json(values).into_response())
+}
+
+/// QuarantineRelease - DELETE /api/v2/admin/quarantine/enums/{name}/{value}
+#[instrument(skip_all, fields(name=%name, value=%value))]
+async fn quarantine_release(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path((name, value)): Path<(models::EnumerationNames, String)>,
+    Query(query): Query<ReleaseQuery>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let outcome = release_value(name, &value, query.remove, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    match outcome {
+        ReleaseOutcome::Approved | ReleaseOutcome::Removed => {
+            tx.commit().await.map_err(internal_error)?;
+            info!(target: "obj", "Released quarantined {name} entry `{value}`: {outcome:?}");
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        ReleaseOutcome::NotFound => {
+            warn!("Entry is not quarantined");
+            Err(StatusCode::NOT_FOUND)
+        }
+        ReleaseOutcome::InUse => {
+            warn!("Entry is still referenced and cannot be removed");
+            Err(StatusCode::CONFLICT)
+        }
+    }
+}
+
+pub async fn quarantined_values(tx: &mut sqlx::PgTransaction<'_>) -> Result<Vec<QuarantinedValue>> {
+    Ok(sqlx::query_as!(
+        QuarantinedValue,
+        "SELECT table_name, value, first_seen, seen_count FROM quarantined_enum_values
+        ORDER BY first_seen ASC"
+    )
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// the table and column referring to entries of the enumeration table `table`
+fn referencing_column(table: &str) -> Option<(&'static str, &'static str)> {
+    match table {
+        "vorgangstyp" => Some(("vorgang", "typ")),
+        "stationstyp" => Some(("station", "typ")),
+        "dokumententyp" => Some(("dokument", "typ")),
+        "vg_ident_typ" => Some(("rel_vorgang_ident", "typ")),
+        _ => None,
+    }
+}
+
+/// drops the quarantine record of `value`. If `remove` is set, the value is deleted from
+/// the enumeration too, which is refused while objects refer to it,
+/// since the references would otherwise be deleted with it.
+pub async fn release_value(
+    name: models::EnumerationNames,
+    value: &str,
+    remove: bool,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<ReleaseOutcome> {
+    let table = enum_table(name);
+    let released = sqlx::query!(
+        "DELETE FROM quarantined_enum_values WHERE table_name = $1 AND value = $2",
+        table,
+        value
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if released == 0 {
+        return Ok(ReleaseOutcome::NotFound);
+    }
+    if !remove {
+        return Ok(ReleaseOutcome::Approved);
+    }
+    if let Some((ref_table, ref_column)) = referencing_column(table) {
+        let in_use: bool = sqlx::query_scalar(&format!(
+            "SELECT EXISTS(SELECT 1 FROM {ref_table} r INNER JOIN {table} e ON e.id = r.{ref_column} WHERE e.value = $1)"
+        ))
+        .bind(value)
+        .fetch_one(&mut **tx)
+        .await?;
+        if in_use {
+            return Ok(ReleaseOutcome::InUse);
+        }
+    }
+    sqlx::query(&format!("DELETE FROM {table} WHERE value = $1"))
+        .bind(value)
+        .execute(&mut **tx)
+        .await?;
+    Ok(ReleaseOutcome::Removed)
+}
+
+#[cfg(test)]
+mod test_quarantine {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::{ReleaseOutcome, quarantined_values, release_value};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_quarantine_unknown_enum() {
+        let mut scenario = TestSetup::new("test_quarantine_unknown_enum").await;
+        let vorgang = generate::default_vorgang();
+        let typ = vorgang.typ.to_string();
+        // the value is valid for the API but missing from the database, e.g. after a rename
+        sqlx::query!(
+            "UPDATE vorgangstyp SET value = 'umbenannt' WHERE value = $1",
+            typ
+        )
+        .execute(&scenario.server.sqlx_db)
+        .await
+        .unwrap();
+
+        assert!(
+            run_integration(&vorgang, Uuid::nil(), 1, &scenario.server)
+                .await
+                .is_err()
+        );
+
+        scenario.server.config.quarantine_unknown_enums = true;
+        let server = &scenario.server;
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let mut second = generate::random::vorgang(7);
+        second.typ = vorgang.typ;
+        run_integration(&second, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let values = quarantined_values(&mut tx).await.unwrap();
+        assert_eq!(values.len(), 1);
+        assert_eq!(values[0].table_name, "vorgangstyp");
+        assert_eq!(values[0].value, typ);
+        assert_eq!(values[0].seen_count, 2);
+
+        let name = models::EnumerationNames::Vorgangstypen;
+        assert_eq!(
+            release_value(name, &typ, true, &mut tx).await.unwrap(),
+            ReleaseOutcome::InUse
+        );
+        tx.rollback().await.unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        assert_eq!(
+            release_value(name, &typ, false, &mut tx).await.unwrap(),
+            ReleaseOutcome::Approved
+        );
+        assert_eq!(
+            release_value(name, &typ, false, &mut tx).await.unwrap(),
+            ReleaseOutcome::NotFound
+        );
+        assert!(quarantined_values(&mut tx).await.unwrap().is_empty());
+        tx.commit().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,326 @@
+//! Review queue for Vorgänge that matched several existing Vorgänge when they were submitted.
+//! Instead of rejecting them, `run_integration` parks them here until an administrator decides.
+//!
+//! - `GET /api/v2/admin/review_queue` lists the pending items
+//! - `POST /api/v2/admin/review_queue/{id}/resolve` merges the item into a Vorgang (`{"action": "merge_into", "target_api_id": ...}`)
+//!   or inserts it as a new one (`{"action": "insert_new"}`)
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use chrono::{DateTime, Utc};
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{Claims, HandlerResult, internal_error};
+use crate::db::{insert, merge};
+use crate::error::LTZFError;
+use crate::{LTZFArc, LTZFServer, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ReviewItem {
+    pub id: Uuid,
+    pub incoming: models::Vorgang,
+    /// api_ids of the Vorgänge the incoming one matched
+    pub candidates: Vec<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Resolution {
+    MergeInto { target_api_id: Uuid },
+    InsertNew,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResolveOutcome {
+    Resolved,
+    /// no pending item with this id
+    NotFound,
+    /// the merge target does not exist
+    UnknownTarget,
+    /// a Vorgang with the api_id of the item exists by now, so it cannot be inserted as new
+    AlreadyExists,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/review_queue", get(review_queue_list))
+        .route(
+            "/api/v2/admin/review_queue/{id}/resolve",
+            post(review_queue_resolve),
+        )
+        .with_state(server)
+}
+
+/// ReviewQueueList - GET /api/v2/admin/review_queue
+#[instrument(skip_all)]
+async fn review_queue_list(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let items = pending_reviews(&server).await.map_err(internal_error)?;
+    info!("Listed {} pending reviews", items.len());
+    Ok(Json(items).into_response())
+}
+
+/// ReviewQueueResolve - POST /api/v2/admin/review_queue/{id}/resolve
+#[instrument(skip_all, fields(review=%id))]
+async fn review_queue_resolve(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(id): Path<Uuid>,
+    Json(resolution): Json<Resolution>,
+) -> HandlerResult {
+    let claims =
+        authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    match resolve_review(id, &resolution, claims, &server)
+        .await
+        .map_err(internal_error)?
+    {
+        ResolveOutcome::Resolved => {
+            info!("Resolved review with {resolution:?}");
+            Ok(StatusCode::NO_CONTENT.into_response())
+        }
+        ResolveOutcome::NotFound => {
+            warn!("No pending review with this id");
+            Err(StatusCode::NOT_FOUND)
+        }
+        ResolveOutcome::UnknownTarget => {
+            warn!("Merge target of {resolution:?} does not exist");
+            Err(StatusCode::UNPROCESSABLE_ENTITY)
+        }
+        ResolveOutcome::AlreadyExists => {
+            warn!("The Vorgang of this review exists by now, it can only be merged");
+            Err(StatusCode::CONFLICT)
+        }
+    }
+}
+
+pub async fn pending_reviews(server: &LTZFServer) -> Result<Vec<ReviewItem>> {
+    let rows = sqlx::query!(
+        "SELECT id, incoming_payload::text as \"payload!\", candidates::text as \"candidates!\", created_at
+        FROM review_queue WHERE status = 'pending'
+        ORDER BY created_at ASC"
+    )
+    .fetch_all(&server.sqlx_db)
+    .await?;
+    let mut items = Vec::with_capacity(rows.len());
+    for row in rows {
+        items.push(ReviewItem {
+            id: row.id,
+            incoming: parse_json(&row.payload)?,
+            candidates: parse_json(&row.candidates)?,
+            created_at: row.created_at,
+        });
+    }
+    Ok(items)
+}
+
+fn parse_json<T: serde::de::DeserializeOwned>(json: &str) -> Result<T> {
+    serde_json::from_str(json)
+        .map_err(|e| LTZFError::other(format!("Malformed review queue entry: {e}")))
+}
+
+/// applies `resolution` to the pending review `id` on behalf of `claims`
+pub async fn resolve_review(
+    id: Uuid,
+    resolution: &Resolution,
+    claims: Claims,
+    server: &LTZFServer,
+) -> Result<ResolveOutcome> {
+    let mut tx = server.sqlx_db.begin().await?;
+    let Some(item) = sqlx::query!(
+        "SELECT incoming_payload::text as \"payload!\", scraper_id, collector_key
+        FROM review_queue WHERE id = $1 AND status = 'pending'
+        FOR UPDATE",
+        id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(ResolveOutcome::NotFound);
+    };
+    let incoming: models::Vorgang = parse_json(&item.payload)?;
+    let status = match resolution {
+        Resolution::MergeInto { target_api_id } => {
+            let Some(target) =
+                sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", target_api_id)
+                    .map(|r| r.id)
+                    .fetch_optional(&mut *tx)
+                    .await?
+            else {
+                return Ok(ResolveOutcome::UnknownTarget);
+            };
+            info!(target: "obj", "Merge(review) Vorgang {} into Vorgang {}", incoming.api_id, target_api_id);
+            merge::execute::execute_merge_vorgang(
+                &incoming,
+                target,
+                item.scraper_id,
+                item.collector_key,
+                &mut tx,
+                server,
+            )
+            .await?;
+            "merged"
+        }
+        Resolution::InsertNew => {
+            info!(target: "obj", "Merge(review, Insert New) Vorgang {}", incoming.api_id);
+            match insert::insert_vorgang(
+                &incoming,
+                item.scraper_id,
+                item.collector_key,
+                &mut tx,
+                server,
+            )
+            .await
+            {
+                Ok(_) => {}
+                // the Vorgang was submitted again and inserted while the item was pending
+                Err(LTZFError::DatabaseConstraint { code, .. }) if code == "23505" => {
+                    return Ok(ResolveOutcome::AlreadyExists);
+                }
+                Err(e) => return Err(e),
+            }
+            "inserted"
+        }
+    };
+    sqlx::query!(
+        "UPDATE review_queue SET status = $2, resolved_at = NOW(), resolved_by = $3 WHERE id = $1",
+        id,
+        status,
+        claims.1
+    )
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(ResolveOutcome::Resolved)
+}
+
+#[cfg(test)]
+mod test_review_queue {
+    use uuid::Uuid;
+
+    use super::{Resolution, ResolveOutcome, pending_reviews, resolve_review};
+    use crate::api::auth::APIScope;
+    use crate::db::merge::execute::{IntegrationOutcome, run_integration};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_resolution_format() {
+        assert_eq!(
+            serde_json::from_str::<Resolution>(r#"{"action": "insert_new"}"#).unwrap(),
+            Resolution::InsertNew
+        );
+        let target = Uuid::now_v7();
+        assert_eq!(
+            serde_json::from_str::<Resolution>(&format!(
+                r#"{{"action": "merge_into", "target_api_id": "{target}"}}"#
+            ))
+            .unwrap(),
+            Resolution::MergeInto {
+                target_api_id: target
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_review_queue() {
+        let scenario = TestSetup::new("test_review_queue").await;
+        let server = &scenario.server;
+        // two copies of the same Vorgang under different api_ids make the third one ambiguous
+        let vg1 = generate::default_vorgang();
+        let (mut vg2, mut vg3) = (vg1.clone(), vg1.clone());
+        vg2.api_id = Uuid::now_v7();
+        vg3.api_id = Uuid::now_v7();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        for vg in [&vg1, &vg2] {
+            crate::db::insert::insert_vorgang(vg, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap();
+        }
+        tx.commit().await.unwrap();
+
+        let outcome = run_integration(&vg3, Uuid::nil(), 1, server).await.unwrap();
+        let IntegrationOutcome::Queued(review_id) = outcome else {
+            panic!("Expected the Vorgang to be queued, got {outcome:?}");
+        };
+        let pending = pending_reviews(server).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, review_id);
+        assert_eq!(pending[0].incoming.api_id, vg3.api_id);
+        assert!(pending[0].candidates.contains(&vg1.api_id));
+        assert!(pending[0].candidates.contains(&vg2.api_id));
+
+        // submitting it again replaces the pending item instead of queueing a second one
+        let mut vg3_again = vg3.clone();
+        vg3_again.kurztitel = Some("Erneut eingereicht".to_string());
+        let outcome = run_integration(&vg3_again, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        assert_eq!(outcome, IntegrationOutcome::Queued(review_id));
+        let pending = pending_reviews(server).await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].incoming.kurztitel, vg3_again.kurztitel);
+
+        let claims = (APIScope::Admin, 1);
+        assert_eq!(
+            resolve_review(
+                review_id,
+                &Resolution::MergeInto {
+                    target_api_id: Uuid::nil()
+                },
+                claims,
+                server
+            )
+            .await
+            .unwrap(),
+            ResolveOutcome::UnknownTarget
+        );
+        assert_eq!(
+            resolve_review(
+                review_id,
+                &Resolution::MergeInto {
+                    target_api_id: vg1.api_id
+                },
+                claims,
+                server
+            )
+            .await
+            .unwrap(),
+            ResolveOutcome::Resolved
+        );
+        assert!(pending_reviews(server).await.unwrap().is_empty());
+        assert_eq!(
+            resolve_review(review_id, &Resolution::InsertNew, claims, server)
+                .await
+                .unwrap(),
+            ResolveOutcome::NotFound
+        );
+
+        // a Vorgang inserted while its review is pending cannot be inserted again
+        let mut vg4 = vg1.clone();
+        vg4.api_id = Uuid::now_v7();
+        let IntegrationOutcome::Queued(review_id) =
+            run_integration(&vg4, Uuid::nil(), 1, server).await.unwrap()
+        else {
+            panic!("Expected the Vorgang to be queued");
+        };
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        crate::db::insert::insert_vorgang(&vg4, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        assert_eq!(
+            resolve_review(review_id, &Resolution::InsertNew, claims, server)
+                .await
+                .unwrap(),
+            ResolveOutcome::AlreadyExists
+        );
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,329 @@
+//! Bulk maintenance of Schlagworte, e.g. to retroactively tag entities after a new
+//! Schlagwort was introduced, and their hierarchy. Parents are set via `enum_put` on the Schlagworte.
+//!
+//! - `POST /api/v2/admin/schlagworte/assign` attaches Schlagworte to a batch of Stationen and Dokumente
+//! - `GET /api/v2/schlagworte/tree` returns all Schlagworte nested below their parents
+
+use std::collections::HashMap;
+
+use axum::Json;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::IntoResponse;
+use axum::routing::{get, post};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::db::insert::{insert_dok_sw, insert_station_sw};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchlagwortAssignment {
+    pub schlagworte: Vec<String>,
+    #[serde(default)]
+    pub station_ids: Vec<Uuid>,
+    #[serde(default)]
+    pub dok_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Default)]
+pub struct AssignmentResult {
+    pub assigned_to_stations: usize,
+    pub assigned_to_dokumente: usize,
+    /// api_ids that matched neither a Station nor a Dokument
+    pub unknown_ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchlagwortNode {
+    pub value: String,
+    pub children: Vec<SchlagwortNode>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/admin/schlagworte/assign", post(schlagworte_assign))
+        .route("/api/v2/schlagworte/tree", get(schlagworte_tree))
+        .with_state(server)
+}
+
+/// SchlagworteTree - GET /api/v2/schlagworte/tree
+#[instrument(skip_all)]
+async fn schlagworte_tree(State(server): State<LTZFArc>) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let tree = schlagwort_tree(&mut tx).await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Retrieved {} root Schlagworte", tree.len());
+    Ok(Json(tree).into_response())
+}
+
+/// all Schlagworte without a parent, each with its descendants, ordered by value
+pub async fn schlagwort_tree(tx: &mut sqlx::PgTransaction<'_>) -> Result<Vec<SchlagwortNode>> {
+    let rows = sqlx::query!("SELECT id, value, parent_id FROM schlagwort ORDER BY value ASC")
+        .fetch_all(&mut **tx)
+        .await?;
+    let mut children: HashMap<Option<i32>, Vec<(i32, String)>> = HashMap::new();
+    for r in rows {
+        children
+            .entry(r.parent_id)
+            .or_default()
+            .push((r.id, r.value));
+    }
+    fn build(
+        parent: Option<i32>,
+        children: &HashMap<Option<i32>, Vec<(i32, String)>>,
+    ) -> Vec<SchlagwortNode> {
+        children
+            .get(&parent)
+            .map(|nodes| {
+                nodes
+                    .iter()
+                    .map(|(id, value)| SchlagwortNode {
+                        value: value.clone(),
+                        children: build(Some(*id), children),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    Ok(build(None, &children))
+}
+
+/// makes `parent` (inserted if unknown) the parent of the Schlagworte `sw_ids`.
+/// Returns false without changing anything if that would create a cycle.
+pub async fn set_schlagwort_parent(
+    sw_ids: &[i32],
+    parent: &str,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<bool> {
+    let parent_id = sqlx::query!(
+        "INSERT INTO schlagwort(value) VALUES ($1)
+        ON CONFLICT(value) DO UPDATE SET value = EXCLUDED.value RETURNING id",
+        parent.trim().to_lowercase()
+    )
+    .map(|r| r.id)
+    .fetch_one(&mut **tx)
+    .await?;
+    let cycle = sqlx::query!(
+        "WITH RECURSIVE ancestors(id) AS (
+            SELECT $1::int4
+            UNION
+            SELECT s.parent_id FROM schlagwort s INNER JOIN ancestors a ON a.id = s.id
+            WHERE s.parent_id IS NOT NULL
+        )
+        SELECT EXISTS(SELECT 1 FROM ancestors WHERE id = ANY($2::int4[])) as \"cycle!\"",
+        parent_id,
+        sw_ids
+    )
+    .map(|r| r.cycle)
+    .fetch_one(&mut **tx)
+    .await?;
+    if cycle {
+        return Ok(false);
+    }
+    sqlx::query!(
+        "UPDATE schlagwort SET parent_id = $1 WHERE id = ANY($2::int4[])",
+        parent_id,
+        sw_ids
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(true)
+}
+
+/// SchlagworteAssign - POST /api/v2/admin/schlagworte/assign
+#[instrument(skip_all, fields(sw=?body.schlagworte))]
+async fn schlagworte_assign(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Json(body): Json<SchlagwortAssignment>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let result = assign_schlagworte(&body, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(
+        target: "obj",
+        "Assigned Schlagworte to {} Stationen and {} Dokumente, {} unknown ids",
+        result.assigned_to_stations,
+        result.assigned_to_dokumente,
+        result.unknown_ids.len()
+    );
+    Ok(Json(result).into_response())
+}
+
+/// attaches all Schlagworte of `assignment` to the referenced Stationen and Dokumente
+pub async fn assign_schlagworte(
+    assignment: &SchlagwortAssignment,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<AssignmentResult> {
+    let mut result = AssignmentResult::default();
+    for api_id in &assignment.station_ids {
+        let id = sqlx::query!("SELECT id FROM station WHERE api_id = $1", api_id)
+            .map(|r| r.id)
+            .fetch_optional(&mut **tx)
+            .await?;
+        if let Some(id) = id {
+            insert_station_sw(id, assignment.schlagworte.clone(), tx).await?;
+            result.assigned_to_stations += 1;
+        } else {
+            result.unknown_ids.push(*api_id);
+        }
+    }
+    for api_id in &assignment.dok_ids {
+        let id = sqlx::query!("SELECT id FROM dokument WHERE api_id = $1", api_id)
+            .map(|r| r.id)
+            .fetch_optional(&mut **tx)
+            .await?;
+        if let Some(id) = id {
+            insert_dok_sw(id, assignment.schlagworte.clone(), tx).await?;
+            result.assigned_to_dokumente += 1;
+        } else {
+            result.unknown_ids.push(*api_id);
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test_schlagworte {
+    use uuid::Uuid;
+
+    use super::{
+        SchlagwortAssignment, SchlagwortNode, assign_schlagworte, schlagwort_tree,
+        set_schlagwort_parent,
+    };
+    use crate::db::merge::execute::run_integration;
+    use crate::db::retrieve::{VGGetParameters, vorgang_by_parameter};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_assign_schlagworte() {
+        let scenario = TestSetup::new("test_assign_schlagworte").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let station_id = generate::default_station().api_id.unwrap();
+        let dok_id = generate::default_dokument().api_id.unwrap();
+        let unknown = Uuid::nil();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let result = assign_schlagworte(
+            &SchlagwortAssignment {
+                schlagworte: vec![" Energie".to_string(), "klima".to_string()],
+                station_ids: vec![station_id, unknown],
+                dok_ids: vec![dok_id],
+            },
+            &mut tx,
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.assigned_to_stations, 1);
+        assert_eq!(result.assigned_to_dokumente, 1);
+        assert_eq!(result.unknown_ids, vec![unknown]);
+
+        let station_sw = sqlx::query!(
+            "SELECT sw.value FROM rel_station_schlagwort r
+            INNER JOIN schlagwort sw ON sw.id = r.sw_id
+            INNER JOIN station s ON s.id = r.stat_id
+            WHERE s.api_id = $1",
+            station_id
+        )
+        .map(|r| r.value)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+        assert!(station_sw.contains(&"energie".to_string()));
+        assert!(station_sw.contains(&"klima".to_string()));
+        assert!(station_sw.contains(&"stationär".to_string()));
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_schlagwort_hierarchy() {
+        let scenario = TestSetup::new("test_schlagwort_hierarchy").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let child = sqlx::query!("SELECT id FROM schlagwort WHERE value = 'stationär'")
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        assert!(
+            set_schlagwort_parent(&[child], "Energie", &mut tx)
+                .await
+                .unwrap()
+        );
+        let parent = sqlx::query!("SELECT id FROM schlagwort WHERE value = 'energie'")
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        // the child must not become an ancestor of its parent
+        assert!(
+            !set_schlagwort_parent(&[parent], "stationär", &mut tx)
+                .await
+                .unwrap()
+        );
+
+        let tree = schlagwort_tree(&mut tx).await.unwrap();
+        let energie = tree.iter().find(|n| n.value == "energie").unwrap();
+        assert_eq!(
+            energie.children,
+            vec![SchlagwortNode {
+                value: "stationär".to_string(),
+                children: vec![]
+            }]
+        );
+        assert!(!tree.iter().any(|n| n.value == "stationär"));
+
+        for (schlagwort, schlagwort_any) in [
+            (Some(vec!["energie".to_string()]), None),
+            (None, Some(vec!["energie".to_string()])),
+        ] {
+            let params = VGGetParameters {
+                lower_date: None,
+                upper_date: None,
+                parlament: None,
+                wp: None,
+                inipsn: None,
+                iniorg: None,
+                inifch: None,
+                vgtyp: None,
+                schlagwort,
+                schlagwort_any,
+                min_meinung: None,
+                max_meinung: None,
+                trojanergefahr_min: None,
+                modified_since: None,
+                dok_schlagwort: None,
+                initiator_fraktion: None,
+                created_since: None,
+                created_until: None,
+                sort: vec![],
+            };
+            let (_, result) = vorgang_by_parameter(params, None, None, &mut tx)
+                .await
+                .unwrap();
+            assert_eq!(
+                result.iter().map(|v| v.api_id).collect::<Vec<_>>(),
+                vec![vorgang.api_id]
+            );
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -1,6 +1,6 @@
-use super::RoundTimestamp;
+use super::normalize::{Normalize, compare_sitzung};
 use crate::db::retrieve::{SitzungFilterParameters, sitzung_by_param};
-use crate::db::{delete, insert, retrieve};
+use crate::db::{delete, insert, merge, retrieve};
 use crate::error::LTZFError;
 use crate::utils::as_option;
 use crate::{LTZFServer, Result};
@@ -16,7 +16,7 @@ use tracing::{debug, error, info, instrument, warn};
 use uuid::Uuid;
 
 use super::auth::{self, APIScope};
-use super::find_applicable_date_range;
+use super::{ExpandQueryParams, find_applicable_date_range};
 
 // helper that converts the documents in a sitzung into just their uuids instead of full objects
 fn st_to_uuiddoks(st: &models::Sitzung) -> models::Sitzung {
@@ -64,16 +64,19 @@ impl DataAdministrationSitzung<LTZFError> for LTZFServer {
             });
         }
         let r = delete::delete_sitzung_by_api_id(path_params.sid, self).await?;
-        info!(target: "obj", "Deleted Sitzung {}", path_params.sid);
-        info!("Success");
+        if let SitzungDeleteResponse::Status404_NotFound { .. } = r {
+            warn!("Sitzung was not found");
+        } else {
+            info!(target: "obj", "Deleted Sitzung {}", path_params.sid);
+            info!("Success");
+        }
         Ok(r)
     }
 
     /// PUTs a models::Sitzung into the database with checks on whether
     /// the objects modifies internal state.
     /// NOTE: Documents that are referenced by UUID (within body.dokumente)
-    /// and point to a document that is not in the database are silently
-    /// filtered out.
+    /// and point to a document that is not in the database are rejected.
     #[doc = "SidPut - PUT /api/v2/sitzung/{sid}"]
     #[instrument(skip_all, fields(claim=%claims.0, sid=%path_params.sid))]
     async fn sid_put(
@@ -93,20 +96,35 @@ impl DataAdministrationSitzung<LTZFError> for LTZFServer {
                 x_rate_limit_reset: None,
             });
         }
-        let mut tx = self.sqlx_db.begin().await?;
         let api_id = path_params.sid;
-        let db_id = sqlx::query!("SELECT id FROM sitzung WHERE api_id = $1", api_id)
-            .map(|x| x.id)
-            .fetch_optional(&mut *tx)
-            .await?;
-        if let Some(db_id) = db_id {
-            let db_cmpvg = retrieve::sitzung_by_id(db_id, &mut tx).await?;
-            debug!(
-                "odb: {}\nonew: {}",
-                serde_json::to_string(&db_cmpvg.with_round_timestamps()).unwrap(),
-                serde_json::to_string(&st_to_uuiddoks(body).with_round_timestamps()).unwrap()
-            );
-            if db_cmpvg.with_round_timestamps() == st_to_uuiddoks(body).with_round_timestamps() {
+        // the delete and the insert happen in the same transaction, which is retried as a whole
+        let modified = crate::db::retry_on_conflict(|| async move {
+            let mut tx = crate::db::begin_merge(self).await?;
+            let db_id = sqlx::query!("SELECT id FROM sitzung WHERE api_id = $1", api_id)
+                .map(|x| x.id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            if let Some(db_id) = db_id {
+                let db_cmpvg = retrieve::sitzung_by_id(db_id, &mut tx).await?;
+                debug!(
+                    "odb: {}\nonew: {}",
+                    serde_json::to_string(&db_cmpvg.normalized()).unwrap(),
+                    serde_json::to_string(&st_to_uuiddoks(body).normalized()).unwrap()
+                );
+                if compare_sitzung(&db_cmpvg, &st_to_uuiddoks(body)) {
+                    return Ok(false);
+                }
+                sqlx::query!("DELETE FROM sitzung WHERE id = $1", db_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            insert::insert_sitzung(body, Uuid::nil(), claims.1, &mut tx, self).await?;
+            tx.commit().await?;
+            Ok::<_, LTZFError>(true)
+        })
+        .await;
+        match modified {
+            Ok(false) => {
                 info!("Sitzung has the same state as the input object");
                 return Ok(SidPutResponse::Status304_NotModified {
                     x_rate_limit_limit: None,
@@ -114,19 +132,10 @@ impl DataAdministrationSitzung<LTZFError> for LTZFServer {
                     x_rate_limit_reset: None,
                 });
             }
-            match delete::delete_sitzung_by_api_id(api_id, self).await? {
-                SitzungDeleteResponse::Status204_NoContent { .. } => {
-                    insert::insert_sitzung(body, Uuid::nil(), claims.1, &mut tx, self).await?;
-                }
-                _ => {
-                    error!("Delete was unsuccessful despite session being in the database");
-                    unreachable!("If this is reached, some assumptions did not hold")
-                }
+            modified => {
+                modified?;
             }
-        } else {
-            insert::insert_sitzung(body, Uuid::nil(), claims.1, &mut tx, self).await?;
         }
-        tx.commit().await?;
         info!(target: "obj", "PUT Sitzung {}", api_id);
         info!("Successfully PUT session into database");
         Ok(SidPutResponse::Status201_Created {
@@ -196,39 +205,33 @@ impl CollectorSchnittstellenSitzung<LTZFError> for LTZFServer {
             });
         }
 
-        let mut tx = self.sqlx_db.begin().await?;
-
-        let dt_begin = path_params
-            .datum
-            .and_time(chrono::NaiveTime::from_hms_micro_opt(0, 0, 0, 0).unwrap())
-            .and_utc();
-        let dt_end = path_params
-            .datum
-            .checked_add_days(chrono::Days::new(1))
-            .unwrap()
-            .and_time(chrono::NaiveTime::from_hms_micro_opt(0, 0, 0, 0).unwrap())
-            .and_utc();
-        // delete all entries that fit the description
-        debug!("Deleting entries from {dt_begin} until {dt_end}");
-        sqlx::query!(
-            "DELETE FROM sitzung WHERE sitzung.id = ANY(SELECT s.id FROM sitzung s 
-        INNER JOIN gremium g ON g.id=s.gr_id 
-        INNER JOIN parlament p ON p.id=g.parl 
-        WHERE p.value = $1 AND s.termin BETWEEN $2 AND $3)",
-            path_params.parlament.to_string(),
-            dt_begin,
-            dt_end
-        )
-        .execute(&mut *tx)
+        // the submission replaces the day: entries are merged into existing ones,
+        // sitzungen of that day only this scraper submitted and that are missing now are deleted
+        let day = (
+            path_params.datum.and_time(chrono::NaiveTime::MIN).and_utc(),
+            (path_params.datum + chrono::Days::new(1))
+                .and_time(chrono::NaiveTime::MIN)
+                .and_utc(),
+        );
+        let body = &body;
+        crate::db::retry_on_conflict(|| async move {
+            let mut tx = crate::db::begin_merge(self).await?;
+            merge::sitzung::replace_sitzungen_in_range(
+                body,
+                path_params.parlament,
+                day,
+                header_params.x_scraper_id,
+                claims.1,
+                &mut tx,
+                self,
+            )
+            .await?;
+            tx.commit().await?;
+            Ok::<_, LTZFError>(())
+        })
         .await?;
-
-        // insert all entries
-        for s in &body {
-            insert::insert_sitzung(s, header_params.x_scraper_id, claims.1, &mut tx, self).await?;
-        }
-        tx.commit().await?;
-        info!(target: "obj", "Inserted sitzungen into db: {:?}", body);
-        info!("Inserted {} sessions into the database", body.len());
+        info!(target: "obj", "Merged sitzungen into db: {:?}", body);
+        info!("Merged {} sessions into the database", body.len());
         Ok(KalDatePutResponse::Status201_Created {
             x_rate_limit_limit: None,
             x_rate_limit_remaining: None,
@@ -273,7 +276,7 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         let dt_end = dr.until;
         let result = sitzung_by_param(
             &SitzungFilterParameters {
-                parlament: Some(path_params.parlament),
+                parlament: Some(vec![path_params.parlament]),
                 gremium_like: None,
                 since: dt_begin,
                 until: dt_end,
@@ -304,10 +307,14 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
             x_rate_limit_limit: None,
             x_rate_limit_remaining: None,
             x_rate_limit_reset: None,
-            link: Some(prp.generate_link_header(&format!(
-                "/api/v2/kalender/{}/{}",
-                path_params.parlament, path_params.datum
-            ))),
+            link: Some(prp.generate_link_header(
+                &self.public_base_url(),
+                &format!(
+                    "/api/v2/kalender/{}/{}",
+                    path_params.parlament, path_params.datum
+                ),
+                &crate::api::filter_query(query_params),
+            )),
             x_page: Some(prp.x_page),
             x_per_page: Some(prp.x_per_page),
             x_total_count: Some(prp.x_total_count),
@@ -327,6 +334,7 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         header_params: &models::KalGetHeaderParams,
         query_params: &models::KalGetQueryParams,
     ) -> Result<KalGetResponse> {
+        let ext_params: KalGetExtParams = crate::utils::spec_ext::query()?;
         let qparams = query_params;
         let hparams = header_params;
         let mut tx = self.sqlx_db.begin().await?;
@@ -352,8 +360,8 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
 
         let params = retrieve::SitzungFilterParameters {
             gremium_like: qparams.gr.clone(),
-            parlament: qparams.p,
-            vgid: None,
+            parlament: qparams.p.map(|p| vec![p]),
+            vgid: ext_params.vgid,
             wp: qparams.wp.map(|x| x as u32),
             since: result.as_ref().unwrap().since,
             until: result.unwrap().until,
@@ -363,16 +371,17 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         let result =
             retrieve::sitzung_by_param(&params, query_params.page, query_params.per_page, &mut tx)
                 .await?;
-        if result.1.is_empty() && header_params.if_modified_since.is_none() {
-            info!("No Sitzungen found");
-            Ok(KalGetResponse::Status204_NoContent {
+        // nothing changed since If-Modified-Since takes precedence over nothing being found
+        if header_params.if_modified_since.is_some() && result.1.is_empty() {
+            info!("All results remain unchanged");
+            Ok(KalGetResponse::Status304_NotModified {
                 x_rate_limit_limit: None,
                 x_rate_limit_remaining: None,
                 x_rate_limit_reset: None,
             })
-        } else if result.1.is_empty() && header_params.if_modified_since.is_some() {
-            info!("All results remain unchanged");
-            Ok(KalGetResponse::Status304_NotModified {
+        } else if result.1.is_empty() {
+            info!("No Sitzungen found");
+            Ok(KalGetResponse::Status204_NoContent {
                 x_rate_limit_limit: None,
                 x_rate_limit_remaining: None,
                 x_rate_limit_reset: None,
@@ -380,6 +389,8 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         } else {
             tx.commit().await?;
             let prp = &result.0;
+            let mut filter = crate::api::filter_query(query_params);
+            filter.extend(crate::api::filter_query(&ext_params));
             info!("{} Sitzungen retrieved", result.1.len());
             Ok(KalGetResponse::Status200_SuccessfulResponse {
                 body: result.1,
@@ -390,7 +401,11 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
                 x_total_pages: Some(prp.x_total_pages),
                 x_page: Some(prp.x_page),
                 x_per_page: Some(prp.x_per_page),
-                link: Some(prp.generate_link_header("/api/v2/kalender")),
+                link: Some(prp.generate_link_header(
+                    &self.public_base_url(),
+                    "/api/v2/kalender",
+                    &filter,
+                )),
             })
         }
     }
@@ -405,11 +420,14 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         header_params: &models::SGetByIdHeaderParams,
         path_params: &models::SGetByIdPathParams,
     ) -> Result<SGetByIdResponse> {
-        // TODO: find a better way to handle admin-only info for every object
-        // in the past this was done by optional authentication, but that
-        // turned out not to neatly fit into the oapi spec.
-        // for now this is just a disabled feature
-        let claims = (APIScope::Collector, 0);
+        // admin-only info, the key of the request is checked by the `touched_by` middleware
+        let privileged = crate::utils::touched_by::is_privileged();
+        let scope = if privileged {
+            APIScope::Admin
+        } else {
+            APIScope::Collector
+        };
+        let query_params: ExpandQueryParams = crate::utils::spec_ext::query()?;
         let mut tx = self.sqlx_db.begin().await?;
         let api_id = path_params.sid;
         let id_exists = sqlx::query!("SELECT 1 as x FROM sitzung WHERE api_id = $1", api_id)
@@ -435,21 +453,34 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         .await?;
         if let Some(id) = id {
             let mut result = retrieve::sitzung_by_id(id, &mut tx).await?;
-            if claims.0 == APIScope::KeyAdder || claims.0 == APIScope::Admin {
-                result.touched_by = as_option(
-                    sqlx::query!(
-                        "SELECT * FROM scraper_touched_sitzung sts
+            if privileged {
+                let touched = sqlx::query!(
+                    "SELECT api_keys.key_hash, sts.scraper FROM scraper_touched_sitzung sts
                     INNER JOIN api_keys ON api_keys.id = sts.collector_key
-                    WHERE sid = $1",
-                        id
-                    )
-                    .map(|r| models::TouchedByInner {
-                        key: Some(r.key_hash),
-                        scraper_id: Some(r.scraper),
-                    })
-                    .fetch_all(&mut *tx)
-                    .await?,
+                    WHERE sid = $1 ORDER BY sts.time_stamp DESC",
+                    id
+                )
+                .map(|r| models::TouchedByInner {
+                    key: Some(r.key_hash),
+                    scraper_id: Some(r.scraper),
+                })
+                .fetch_all(&mut *tx)
+                .await?;
+                crate::utils::touched_by::record_touched_by(
+                    touched.iter().filter_map(|t| t.scraper_id).collect(),
                 );
+                result.touched_by = as_option(touched);
+                // not in the generated model, see `utils::spec_ext`
+                let created_at = sqlx::query!("SELECT created_at FROM sitzung WHERE id = $1", id)
+                    .map(|r| r.created_at)
+                    .fetch_one(&mut *tx)
+                    .await?;
+                if let Some(api_id) = result.api_id {
+                    crate::utils::spec_ext::extend_object(api_id, "created_at", created_at);
+                }
+            }
+            if query_params.expand_dokumente == Some(true) {
+                retrieve::expand_sitzung_dokumente(&mut result, scope, &mut tx).await?;
             }
             tx.commit().await?;
             info!("Success");
@@ -483,6 +514,7 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         header_params: &models::SGetHeaderParams,
         query_params: &models::SGetQueryParams,
     ) -> Result<SGetResponse> {
+        let ext_params: SGetExtParams = crate::utils::spec_ext::query()?;
         let range = find_applicable_date_range(
             None,
             None,
@@ -504,7 +536,7 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
         }
         let params = retrieve::SitzungFilterParameters {
             gremium_like: query_params.gr.clone(),
-            parlament: query_params.p,
+            parlament: super::parlament_list(&ext_params.p)?.or(query_params.p.map(|p| vec![p])),
             wp: query_params.wp.map(|x| x as u32),
             since: range.as_ref().unwrap().since,
             until: range.unwrap().until,
@@ -535,6 +567,8 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
             })
         } else {
             info!("Successfully retrieved {} Sitzungen", result.1.len());
+            let mut filter = crate::api::filter_query(query_params);
+            filter.extend(crate::api::filter_query(&ext_params));
             Ok(SGetResponse::Status200_SuccessfulResponse {
                 body: result.1,
                 x_rate_limit_limit: None,
@@ -544,12 +578,33 @@ impl SitzungUnauthorisiert<LTZFError> for LTZFServer {
                 x_total_pages: Some(prp.x_total_pages),
                 x_page: Some(prp.x_page),
                 x_per_page: Some(prp.x_per_page),
-                link: Some(prp.generate_link_header("/api/v2/sitzung")),
+                link: Some(prp.generate_link_header(
+                    &self.public_base_url(),
+                    "/api/v2/sitzung",
+                    &filter,
+                )),
             })
         }
     }
 }
 
+/// query parameters of `GET /api/v2/sitzung` that are not in the generated server,
+/// see `utils::spec_ext`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct SGetExtParams {
+    /// Parlamente, repeated or comma separated, one of them has to match
+    #[serde(default)]
+    pub p: Vec<String>,
+}
+
+/// query parameters of `GET /api/v2/kalender` that are not in the generated server,
+/// see `utils::spec_ext`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct KalGetExtParams {
+    /// a Vorgang one of the TOPs refers to, like `vgid` of `GET /api/v2/sitzung`
+    pub vgid: Option<Uuid>,
+}
+
 #[cfg(test)]
 mod sitzung_test {
     use axum::http::Method;
@@ -1037,6 +1092,38 @@ mod sitzung_test {
             "{:?}",
             response
         );
+        // the same query without If-Modified-Since finds the sitzung
+        let response = server
+            .kal_get(
+                &Method::GET,
+                &host,
+                &cookies,
+                &models::KalGetHeaderParams {
+                    if_modified_since: None,
+                },
+                &models::KalGetQueryParams {
+                    page: None,
+                    per_page: None,
+                    y: None,
+                    m: None,
+                    dom: None,
+                    gr: None,
+                    p: Some(parlament),
+                    since: None,
+                    until: None,
+                    wp: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert!(
+            matches!(
+                response,
+                KalGetResponse::Status200_SuccessfulResponse { .. }
+            ),
+            "{:?}",
+            response
+        );
         let response = server
             .kal_get(
                 &Method::GET,
@@ -1071,6 +1158,67 @@ mod sitzung_test {
         setup.teardown().await;
     }
 
+    #[tokio::test]
+    async fn test_s_get_by_vorgang() {
+        let scenario = TestSetup::new("test_s_get_by_vorgang").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let mut session = generate::default_sitzung();
+        session.tops[0].vorgang_id = Some(vec![vorgang.api_id]);
+        server
+            .sid_put(
+                &Method::PUT,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &(auth::APIScope::Admin, 1),
+                &models::SidPutPathParams {
+                    sid: session.api_id.unwrap(),
+                },
+                &session,
+            )
+            .await
+            .unwrap();
+        for (vgid, found) in [(vorgang.api_id, true), (Uuid::nil(), false)] {
+            let response = server
+                .s_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::SGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &models::SGetQueryParams {
+                        page: None,
+                        per_page: None,
+                        gr: None,
+                        p: None,
+                        since: None,
+                        until: None,
+                        wp: None,
+                        vgid: Some(vgid),
+                        vgtyp: None,
+                    },
+                )
+                .await
+                .unwrap();
+            match response {
+                SGetResponse::Status200_SuccessfulResponse { body, .. } => {
+                    assert!(found, "Expected no Sitzung for {vgid}");
+                    assert_eq!(body.len(), 1);
+                    assert_eq!(body[0].api_id, session.api_id);
+                }
+                SGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a Sitzung for {vgid}")
+                }
+                rsp => panic!("Unexpected response {rsp:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
     #[tokio::test]
     pub(crate) async fn test_session_get_endpoints() {
         // Setup test server and database
@@ -0,0 +1,143 @@
+//! Coordination between scrapers, which can check whether a Vorgang was scraped recently
+//! before scraping it again.
+//!
+//! - `GET /api/v2/vorgang/{vorgang_id}/staleness` lists per scraper when it last submitted the Vorgang
+//!   and whether that is longer ago than [`STALE_AFTER_DAYS`]
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+/// a scrape older than this is stale
+pub const STALE_AFTER_DAYS: i32 = 7;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ScraperStaleness {
+    pub scraper_id: Uuid,
+    pub last_scraped_at: DateTime<Utc>,
+    pub is_stale: bool,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/vorgang/{vorgang_id}/staleness",
+            get(vorgang_staleness),
+        )
+        .with_state(server)
+}
+
+/// VorgangStaleness - GET /api/v2/vorgang/{vorgang_id}/staleness
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn vorgang_staleness(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(vorgang_id): Path<Uuid>,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Admin, APIScope::KeyAdder, APIScope::Collector],
+    )
+    .await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let staleness = scraper_staleness(vorgang_id, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    let Some(staleness) = staleness else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    info!("Vorgang was scraped by {} scrapers", staleness.len());
+    Ok(Json(staleness).into_response())
+}
+
+/// when each scraper in the log of the Vorgang last submitted it, most recent first.
+/// The log entry of a scraper is renewed on every submission.
+/// None if there is no Vorgang with this api_id.
+pub async fn scraper_staleness(
+    vorgang_id: Uuid,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Option<Vec<ScraperStaleness>>> {
+    let Some(vg_id) = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut **tx)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let staleness = sqlx::query!(
+        "SELECT scraper, time_stamp,
+        time_stamp < NOW() - make_interval(days => $2) as \"is_stale!\"
+        FROM scraper_touched_vorgang WHERE vg_id = $1
+        ORDER BY time_stamp DESC",
+        vg_id,
+        STALE_AFTER_DAYS
+    )
+    .map(|r| ScraperStaleness {
+        scraper_id: r.scraper,
+        last_scraped_at: r.time_stamp,
+        is_stale: r.is_stale,
+    })
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(Some(staleness))
+}
+
+#[cfg(test)]
+mod test_staleness {
+    use uuid::Uuid;
+
+    use super::scraper_staleness;
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_scraper_staleness() {
+        let scenario = TestSetup::new("test_scraper_staleness").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let (fresh, old) = (Uuid::now_v7(), Uuid::now_v7());
+        run_integration(&vorgang, old, 1, server).await.unwrap();
+        run_integration(&vorgang, fresh, 1, server).await.unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        sqlx::query!(
+            "UPDATE scraper_touched_vorgang SET time_stamp = NOW() - '8 days'::interval
+            WHERE scraper = $1",
+            old
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        let staleness = scraper_staleness(vorgang.api_id, &mut tx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(staleness.len(), 2);
+        assert_eq!(staleness[0].scraper_id, fresh);
+        assert!(!staleness[0].is_stale);
+        assert_eq!(staleness[1].scraper_id, old);
+        assert!(staleness[1].is_stale);
+
+        assert!(
+            scraper_staleness(Uuid::now_v7(), &mut tx)
+                .await
+                .unwrap()
+                .is_none()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,348 @@
+//! Maintenance of `Station.additional_links` without resubmitting the whole Vorgang,
+//! which would run the full merge pipeline for a purely additive change.
+//!
+//! - `GET /api/v2/station/{station_id}/links` lists the links of a Station
+//! - `POST /api/v2/station/{station_id}/links` adds the links of a json array and answers with the ones that were new,
+//!   known links are ignored
+//! - `DELETE /api/v2/station/{station_id}/links?link=...` removes a single link
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use serde::Deserialize;
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LinkQuery {
+    pub link: String,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/station/{station_id}/links",
+            get(station_links_get)
+                .post(station_links_post)
+                .delete(station_links_delete),
+        )
+        .with_state(server)
+}
+
+/// StationLinksGet - GET /api/v2/station/{station_id}/links
+#[instrument(skip_all, fields(stat=%station_id))]
+async fn station_links_get(
+    State(server): State<LTZFArc>,
+    Path(station_id): Path<Uuid>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = station_db_id(station_id, &mut tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Station was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let links = station_links(id, &mut tx).await.map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(Json(links).into_response())
+}
+
+/// StationLinksPost - POST /api/v2/station/{station_id}/links
+#[instrument(skip_all, fields(stat=%station_id))]
+async fn station_links_post(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(station_id): Path<Uuid>,
+    Json(links): Json<Vec<String>>,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Admin, APIScope::KeyAdder, APIScope::Collector],
+    )
+    .await?;
+    let links: Vec<String> = links
+        .iter()
+        .map(|l| l.trim().to_string())
+        .filter(|l| !l.is_empty())
+        .collect();
+    if links.is_empty() {
+        warn!("No links were supplied");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = station_db_id(station_id, &mut tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Station was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let added = add_station_links(id, &links, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!(target: "obj", "Added {} links to Station {station_id}", added.len());
+    let status = if added.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::CREATED
+    };
+    Ok((status, Json(added)).into_response())
+}
+
+/// StationLinksDelete - DELETE /api/v2/station/{station_id}/links
+#[instrument(skip_all, fields(stat=%station_id))]
+async fn station_links_delete(
+    State(server): State<LTZFArc>,
+    headers: HeaderMap,
+    Path(station_id): Path<Uuid>,
+    Query(query): Query<LinkQuery>,
+) -> HandlerResult {
+    authorize_scopes(
+        &server,
+        &headers,
+        &[APIScope::Admin, APIScope::KeyAdder, APIScope::Collector],
+    )
+    .await?;
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = station_db_id(station_id, &mut tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Station was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let removed = remove_station_link(id, &query.link, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    if !removed {
+        warn!("Station does not have the link `{}`", query.link);
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!(target: "obj", "Removed link {} from Station {station_id}", query.link);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn station_db_id(api_id: Uuid, tx: &mut sqlx::PgTransaction<'_>) -> Result<Option<i32>> {
+    Ok(
+        sqlx::query!("SELECT id FROM station WHERE api_id = $1", api_id)
+            .map(|r| r.id)
+            .fetch_optional(&mut **tx)
+            .await?,
+    )
+}
+
+pub async fn station_links(id: i32, tx: &mut sqlx::PgTransaction<'_>) -> Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "SELECT link FROM rel_station_link WHERE stat_id = $1 ORDER BY link ASC",
+        id
+    )
+    .map(|r| r.link)
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// returns the links that were not yet known
+pub async fn add_station_links(
+    id: i32,
+    links: &[String],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<String>> {
+    Ok(sqlx::query!(
+        "INSERT INTO rel_station_link(stat_id, link)
+        SELECT $1, blub FROM UNNEST($2::text[]) as blub
+        ON CONFLICT DO NOTHING
+        RETURNING link",
+        id,
+        links
+    )
+    .map(|r| r.link)
+    .fetch_all(&mut **tx)
+    .await?)
+}
+
+/// returns whether the link was attached to the Station
+pub async fn remove_station_link(
+    id: i32,
+    link: &str,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<bool> {
+    Ok(sqlx::query!(
+        "DELETE FROM rel_station_link WHERE stat_id = $1 AND link = $2",
+        id,
+        link
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected()
+        > 0)
+}
+
+#[cfg(test)]
+mod test_station_links {
+    use std::sync::Arc;
+
+    use axum::Json;
+    use axum::extract::{Path, Query, State};
+    use axum::http::{HeaderMap, HeaderValue, StatusCode};
+    use uuid::Uuid;
+
+    use super::{
+        LinkQuery, add_station_links, remove_station_link, station_db_id, station_links,
+        station_links_delete, station_links_get, station_links_post,
+    };
+    use crate::api::auth::APIScope;
+    use crate::api::keys::create_key;
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    async fn body_links(response: axum::response::Response) -> Vec<String> {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_station_links_endpoints() {
+        let scenario = TestSetup::new("test_station_links_endpoints").await;
+        let server = Arc::new(scenario.server.clone());
+        run_integration(&generate::default_vorgang(), Uuid::nil(), 1, &server)
+            .await
+            .unwrap();
+        let station = generate::default_station();
+        let station_id = station.api_id.unwrap();
+        let existing = station.additional_links.clone().unwrap();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let key = create_key(
+            APIScope::Collector,
+            chrono::Utc::now() + chrono::Duration::days(1),
+            1,
+            &mut tx,
+        )
+        .await
+        .unwrap()
+        .key;
+        tx.commit().await.unwrap();
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_str(&key).unwrap());
+
+        let response = station_links_get(State(server.clone()), Path(station_id))
+            .await
+            .unwrap();
+        assert_eq!(body_links(response).await, existing);
+        assert_eq!(
+            station_links_get(State(server.clone()), Path(Uuid::nil()))
+                .await
+                .unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+
+        // writing requires a key
+        let unauthorized = station_links_post(
+            State(server.clone()),
+            HeaderMap::new(),
+            Path(station_id),
+            Json(vec!["https://example.com/neu".to_string()]),
+        )
+        .await;
+        assert_eq!(unauthorized.unwrap_err(), StatusCode::UNAUTHORIZED);
+
+        let new_link = "https://example.com/neu".to_string();
+        let response = station_links_post(
+            State(server.clone()),
+            headers.clone(),
+            Path(station_id),
+            Json(vec![new_link.clone(), existing[0].clone()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(body_links(response).await, vec![new_link.clone()]);
+        // nothing new
+        let response = station_links_post(
+            State(server.clone()),
+            headers.clone(),
+            Path(station_id),
+            Json(vec![new_link.clone()]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body_links(response).await.is_empty());
+        assert_eq!(
+            station_links_post(
+                State(server.clone()),
+                headers.clone(),
+                Path(station_id),
+                Json(vec![" ".to_string()]),
+            )
+            .await
+            .unwrap_err(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let delete = |link: &str| {
+            station_links_delete(
+                State(server.clone()),
+                headers.clone(),
+                Path(station_id),
+                Query(LinkQuery {
+                    link: link.to_string(),
+                }),
+            )
+        };
+        assert_eq!(
+            delete(&new_link).await.unwrap().status(),
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(delete(&new_link).await.unwrap_err(), StatusCode::NOT_FOUND);
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_station_links() {
+        let scenario = TestSetup::new("test_station_links").await;
+        let server = &scenario.server;
+        run_integration(&generate::default_vorgang(), Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let station = generate::default_station();
+        let existing = station.additional_links.clone().unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        assert_eq!(station_db_id(Uuid::nil(), &mut tx).await.unwrap(), None);
+        let id = station_db_id(station.api_id.unwrap(), &mut tx)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(station_links(id, &mut tx).await.unwrap(), existing);
+
+        let new_link = "https://example.com/neu".to_string();
+        let added = add_station_links(id, &[new_link.clone(), existing[0].clone()], &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(added, vec![new_link.clone()]);
+        assert!(
+            station_links(id, &mut tx)
+                .await
+                .unwrap()
+                .contains(&new_link)
+        );
+
+        assert!(remove_station_link(id, &new_link, &mut tx).await.unwrap());
+        assert!(!remove_station_link(id, &new_link, &mut tx).await.unwrap());
+        assert_eq!(station_links(id, &mut tx).await.unwrap(), existing);
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,164 @@
+//! Aggregated counts for dashboards. The underlying queries scan all Vorgänge, repeated
+//! requests are answered by the response cache (`utils::response_cache`), which drops them
+//! whenever a Vorgang changes.
+//!
+//! - `GET /api/v2/stats/vorgaenge?group_by=parlament,wahlperiode&since=` counts the Vorgänge
+//!   per parliament of their Stationen and per Wahlperiode, optionally only those updated since `since`
+
+use std::str::FromStr;
+
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::{DateTime, Utc};
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+
+use crate::api::{HandlerResult, internal_error};
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Default, Deserialize, PartialEq, Eq, Hash)]
+pub struct StatsQuery {
+    /// comma separated list of `parlament` and `wahlperiode`, defaults to both
+    pub group_by: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct VorgangCount {
+    pub parlament: Option<models::Parlament>,
+    pub wahlperiode: Option<u32>,
+    pub count: i64,
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/stats/vorgaenge", get(stats_vorgaenge))
+        .with_state(server)
+}
+
+/// StatsVorgaenge - GET /api/v2/stats/vorgaenge
+#[instrument(skip_all, fields(group_by=?query.group_by, since=?query.since))]
+async fn stats_vorgaenge(
+    State(server): State<LTZFArc>,
+    Query(query): Query<StatsQuery>,
+) -> HandlerResult {
+    let Some((by_parlament, by_wahlperiode)) = parse_group_by(query.group_by.as_deref()) else {
+        warn!("Unknown grouping requested: {:?}", query.group_by);
+        return Err(StatusCode::BAD_REQUEST);
+    };
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let counts = count_vorgaenge(by_parlament, by_wahlperiode, query.since, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    Ok(Json(counts).into_response())
+}
+
+/// returns whether to group by (parlament, wahlperiode), or None for unknown dimensions
+fn parse_group_by(group_by: Option<&str>) -> Option<(bool, bool)> {
+    let Some(group_by) = group_by else {
+        return Some((true, true));
+    };
+    let (mut by_parlament, mut by_wahlperiode) = (false, false);
+    for dimension in group_by.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        match dimension {
+            "parlament" => by_parlament = true,
+            "wahlperiode" => by_wahlperiode = true,
+            _ => return None,
+        }
+    }
+    Some((by_parlament, by_wahlperiode))
+}
+
+/// counts the Vorgänge per parliament of their Stationen and per Wahlperiode.
+/// A Vorgang with Stationen in several parliaments is counted once for each of them.
+pub async fn count_vorgaenge(
+    by_parlament: bool,
+    by_wahlperiode: bool,
+    since: Option<DateTime<Utc>>,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<VorgangCount>> {
+    let rows = sqlx::query!(
+        "SELECT CASE WHEN $1 THEN p.value END as parlament,
+        CASE WHEN $2 THEN v.wahlperiode END as wahlperiode,
+        COUNT(DISTINCT v.id) as \"count!\", MAX(v.last_update) as last_update
+        FROM vorgang v
+        INNER JOIN station s ON s.vg_id = v.id
+        INNER JOIN gremium g ON g.id = s.gr_id
+        INNER JOIN parlament p ON p.id = g.parl
+        WHERE ($3::timestamptz IS NULL OR v.last_update > $3)
+        GROUP BY 1, 2
+        ORDER BY 1, 2",
+        by_parlament,
+        by_wahlperiode,
+        since
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|r| VorgangCount {
+            parlament: r
+                .parlament
+                .and_then(|p| models::Parlament::from_str(&p).ok()),
+            wahlperiode: r.wahlperiode.map(|wp| wp as u32),
+            count: r.count,
+            last_update: r.last_update,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test_stats {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::{count_vorgaenge, parse_group_by};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_parse_group_by() {
+        assert_eq!(parse_group_by(None), Some((true, true)));
+        assert_eq!(parse_group_by(Some("wahlperiode")), Some((false, true)));
+        assert_eq!(
+            parse_group_by(Some("parlament, wahlperiode")),
+            Some((true, true))
+        );
+        assert_eq!(parse_group_by(Some("fraktion")), None);
+    }
+
+    #[tokio::test]
+    async fn test_count_vorgaenge() {
+        let scenario = TestSetup::new("test_count_vorgaenge").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let parlament = vorgang.stationen[0].gremium.parlament;
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let counts = count_vorgaenge(true, true, None, &mut tx).await.unwrap();
+        assert_eq!(counts.len(), 1);
+        assert_eq!(counts[0].parlament, Some(parlament));
+        assert_eq!(counts[0].wahlperiode, Some(vorgang.wahlperiode));
+        assert_eq!(counts[0].count, 1);
+
+        let counts = count_vorgaenge(false, true, None, &mut tx).await.unwrap();
+        assert_eq!(counts[0].parlament, None::<models::Parlament>);
+
+        let future = chrono::Utc::now() + chrono::Duration::days(1);
+        let counts = count_vorgaenge(true, true, Some(future), &mut tx)
+            .await
+            .unwrap();
+        assert!(counts.is_empty());
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,201 @@
+//! Flat access to the Stellungnahmen of a Vorgang, for analyses over the `meinung` of the
+//! submitting organisations without walking through every Station.
+//!
+//! - `GET /api/v2/vorgang/{vorgang_id}/stellungnahmen?min_meinung=&max_meinung=&autor_org=`
+//!   lists the Stellungnahmen of all Stationen together with their Station, sorted by `meinung` descending
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::routing::get;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::{HandlerResult, internal_error};
+use crate::db::retrieve;
+use crate::error::DataValidationError;
+use crate::{LTZFArc, Result};
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StellungnahmeQuery {
+    pub min_meinung: Option<u8>,
+    pub max_meinung: Option<u8>,
+    /// substring of the organisation of one of the Autoren, case insensitive
+    pub autor_org: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StellungnahmeEntry {
+    pub station_api_id: Uuid,
+    pub station_typ: models::Stationstyp,
+    pub dokument: models::Dokument,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route(
+            "/api/v2/vorgang/{vorgang_id}/stellungnahmen",
+            get(vorgang_stellungnahmen),
+        )
+        .with_state(server)
+}
+
+/// VorgangStellungnahmen - GET /api/v2/vorgang/{vorgang_id}/stellungnahmen
+#[instrument(skip_all, fields(vg=%vorgang_id))]
+async fn vorgang_stellungnahmen(
+    State(server): State<LTZFArc>,
+    Path(vorgang_id): Path<Uuid>,
+    Query(query): Query<StellungnahmeQuery>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(id) = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Vorgang was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let entries = stellungnahmen(id, &query, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("{} Stellungnahmen matched", entries.len());
+    Ok(Json(entries).into_response())
+}
+
+/// `%`, `_` and the escape character itself match literally in the ILIKE pattern
+fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// the Stellungnahmen of all Stationen of the Vorgang `vg_id` matching `query`.
+/// Unrated Stellungnahmen come last and are excluded as soon as one of the bounds is set.
+pub async fn stellungnahmen(
+    vg_id: i32,
+    query: &StellungnahmeQuery,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<StellungnahmeEntry>> {
+    if let (Some(min), Some(max)) = (query.min_meinung, query.max_meinung) {
+        if min > max {
+            warn!("min_meinung {min} is larger than max_meinung {max}");
+            return Err(DataValidationError::InvalidQueryParameter {
+                parameter: "min_meinung".to_string(),
+                message: format!("{min} is greater than max_meinung {max}"),
+            }
+            .into());
+        }
+    }
+    let rows = sqlx::query!(
+        "SELECT s.api_id as station_api_id, st.value as station_typ, d.id as dok_id
+        FROM station s
+        INNER JOIN stationstyp st ON st.id = s.typ
+        INNER JOIN rel_station_stln rsl ON rsl.stat_id = s.id
+        INNER JOIN dokument d ON d.id = rsl.dok_id
+        WHERE s.vg_id = $1
+        AND ($2::int4 IS NULL OR d.meinung >= $2)
+        AND ($3::int4 IS NULL OR d.meinung <= $3)
+        AND ($4::text IS NULL OR EXISTS(SELECT 1 FROM rel_dok_autor rda INNER JOIN autor a ON a.id = rda.aut_id
+            WHERE rda.dok_id = d.id AND a.organisation ILIKE CONCAT('%', $4::text, '%') ESCAPE '\\'))
+        ORDER BY d.meinung DESC NULLS LAST, d.api_id ASC",
+        vg_id,
+        query.min_meinung.map(|m| m as i32),
+        query.max_meinung.map(|m| m as i32),
+        query.autor_org.as_deref().map(escape_like)
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut entries = Vec::with_capacity(rows.len());
+    for row in rows {
+        entries.push(StellungnahmeEntry {
+            station_api_id: row.station_api_id,
+            station_typ: row
+                .station_typ
+                .parse()
+                .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
+            dokument: retrieve::dokument_by_id(row.dok_id, tx).await?,
+        });
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod test_stellungnahmen {
+    use uuid::Uuid;
+
+    use super::{StellungnahmeQuery, stellungnahmen};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_stellungnahmen_filter() {
+        // the default Stellungnahme is written by the "Ministerium der Magie"
+        let scenario = TestSetup::new("test_stellungnahmen_filter").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let station = &vorgang.stationen[0];
+        let stln = generate::default_stellungnahme();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let vg_id = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vorgang.api_id)
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        let all = stellungnahmen(vg_id, &StellungnahmeQuery::default(), &mut tx)
+            .await
+            .unwrap();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].station_api_id, station.api_id.unwrap());
+        assert_eq!(all[0].station_typ, station.typ);
+        assert_eq!(all[0].dokument.meinung, stln.meinung);
+
+        // the default Stellungnahme has a meinung of 3
+        let cases = [
+            (Some(3), Some(3), None, 1),
+            (Some(4), None, None, 0),
+            (None, Some(2), None, 0),
+            (None, None, Some("MINISTERIUM der".to_string()), 1),
+            (None, None, Some("gibtsnicht".to_string()), 0),
+            // wildcards in the search term match literally
+            (None, None, Some("%".to_string()), 0),
+            (None, None, Some("Minis_erium".to_string()), 0),
+        ];
+        for (min_meinung, max_meinung, autor_org, expected) in cases {
+            let query = StellungnahmeQuery {
+                min_meinung,
+                max_meinung,
+                autor_org,
+            };
+            let found = stellungnahmen(vg_id, &query, &mut tx).await.unwrap();
+            assert_eq!(found.len(), expected, "{query:?}");
+        }
+        let inverted = StellungnahmeQuery {
+            min_meinung: Some(4),
+            max_meinung: Some(2),
+            autor_org: None,
+        };
+        assert!(
+            stellungnahmen(vg_id, &inverted, &mut tx)
+                .await
+                .unwrap_err()
+                .is_bad_request()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
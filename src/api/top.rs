@@ -0,0 +1,433 @@
+//! Standalone access to Tagesordnungspunkte, which are otherwise only part of a Sitzung.
+//!
+//! - `GET /api/v2/top?vorgang_id=` lists all TOPs linked to a Vorgang across all Sitzungen
+//! - `GET /api/v2/top/{nummer}?sitzung_id=` retrieves a single TOP of a Sitzung
+//! - `PUT /api/v2/top/{sitzung_id}/{nummer}` creates or replaces a single TOP of a Sitzung
+//! - `DELETE /api/v2/top/{sitzung_id}/{nummer}` removes a single TOP from a Sitzung
+
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use openapi::models;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, instrument, warn};
+use uuid::Uuid;
+
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::normalize::compare_dokument;
+use crate::api::{HandlerResult, PaginationResponsePart, internal_error};
+use crate::db::merge::MatchState;
+use crate::db::merge::candidates::dokument_merge_candidates;
+use crate::db::{KeyIndex, insert, retrieve};
+use crate::{LTZFArc, LTZFServer, Result};
+
+/// a TOP together with the Sitzung it belongs to
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TopEntry {
+    pub sitzung_id: Uuid,
+    #[serde(flatten)]
+    pub top: models::Top,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopGetQuery {
+    pub sitzung_id: Uuid,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TopListQuery {
+    pub vorgang_id: Uuid,
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/top", get(top_list))
+        .route("/api/v2/top/{nummer}", get(top_get))
+        .route(
+            "/api/v2/top/{sitzung_id}/{nummer}",
+            axum::routing::put(top_put).delete(top_delete),
+        )
+        .with_state(server)
+}
+
+/// TopList - GET /api/v2/top
+#[instrument(skip_all, fields(vg=%query.vorgang_id))]
+async fn top_list(
+    State(server): State<LTZFArc>,
+    Query(query): Query<TopListQuery>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let (prp, tops) = tops_by_vorgang(query.vorgang_id, query.page, query.per_page, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Successful retrieval");
+    let mut headers = HeaderMap::new();
+    for (name, value) in [
+        ("x-total-count", prp.x_total_count.to_string()),
+        ("x-total-pages", prp.x_total_pages.to_string()),
+        ("x-page", prp.x_page.to_string()),
+        ("x-per-page", prp.x_per_page.to_string()),
+        (
+            "link",
+            prp.generate_link_header(
+                &server.public_base_url(),
+                "/api/v2/top",
+                &[("vorgang_id", query.vorgang_id.to_string())],
+            ),
+        ),
+    ] {
+        headers.insert(
+            name,
+            HeaderValue::from_str(&value).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?,
+        );
+    }
+    Ok((headers, Json(tops)).into_response())
+}
+
+/// TopGet - GET /api/v2/top/{nummer}
+#[instrument(skip_all, fields(sid=%query.sitzung_id, nummer=%nummer))]
+async fn top_get(
+    State(server): State<LTZFArc>,
+    Path(nummer): Path<u32>,
+    Query(query): Query<TopGetQuery>,
+) -> HandlerResult {
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(tid) = top_id(query.sitzung_id, nummer, &mut tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        info!("Not Found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let top = retrieve::top_by_id(tid, &mut tx)
+        .await
+        .map_err(internal_error)?;
+    tx.commit().await.map_err(internal_error)?;
+    info!("Successful retrieval");
+    Ok(Json(top).into_response())
+}
+
+/// TopPut - PUT /api/v2/top/{sitzung_id}/{nummer}
+#[instrument(skip_all, fields(sid=%sitzung_id, nummer=%nummer))]
+async fn top_put(
+    State(server): State<LTZFArc>,
+    Path((sitzung_id, nummer)): Path<(Uuid, u32)>,
+    headers: HeaderMap,
+    Json(body): Json<models::Top>,
+) -> HandlerResult {
+    let claims = authorize_scopes(&server, &headers, &[APIScope::Admin]).await?;
+    if body.nummer != nummer {
+        info!(
+            "Semantically bad request: nummer of the body ({}) differs from the path",
+            body.nummer
+        );
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let mut tx = server.sqlx_db.begin().await.map_err(internal_error)?;
+    let Some(sid) = sqlx::query!("SELECT id FROM sitzung WHERE api_id = $1", sitzung_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut *tx)
+        .await
+        .map_err(internal_error)?
+    else {
+        warn!("Sitzung was not found");
+        return Err(StatusCode::NOT_FOUND);
+    };
+    let modified = upsert_top(sid, &body, Uuid::nil(), claims.1, &mut tx, &server)
+        .await
+        .map_err(internal_error)?;
+    if !modified {
+        info!("TOP was not modified");
+        return Ok(StatusCode::NOT_MODIFIED.into_response());
+    }
+    tx.commit().await.map_err(internal_error)?;
+    info!(target: "obj", "Set TOP {} of Sitzung {}", nummer, sitzung_id);
+    Ok(StatusCode::CREATED.into_response())
+}
+
+/// TopDelete - DELETE /api/v2/top/{sitzung_id}/{nummer}
+#[instrument(skip_all, fields(sid=%sitzung_id, nummer=%nummer))]
+async fn top_delete(
+    State(server): State<LTZFArc>,
+    Path((sitzung_id, nummer)): Path<(Uuid, u32)>,
+    headers: HeaderMap,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin]).await?;
+    let n_deleted = sqlx::query!(
+        "DELETE FROM top t USING sitzung s
+        WHERE s.id = t.sid AND s.api_id = $1 AND t.nummer = $2",
+        sitzung_id,
+        nummer as i32
+    )
+    .execute(&server.sqlx_db)
+    .await
+    .map_err(internal_error)?
+    .rows_affected();
+    if n_deleted == 0 {
+        info!("Not Found");
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!(target: "obj", "Deleted TOP {} of Sitzung {}", nummer, sitzung_id);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+async fn top_id(
+    sitzung_id: Uuid,
+    nummer: u32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Option<i32>> {
+    Ok(sqlx::query!(
+        "SELECT t.id FROM top t
+        INNER JOIN sitzung s ON s.id = t.sid
+        WHERE s.api_id = $1 AND t.nummer = $2",
+        sitzung_id,
+        nummer as i32
+    )
+    .map(|r| r.id)
+    .fetch_optional(&mut **tx)
+    .await?)
+}
+
+/// replaces the TOP with the same nummer in the sitzung `sid` or inserts it if there is none.
+/// Returns false if the stored TOP is equal to `top`, in which case nothing is changed.
+pub async fn upsert_top(
+    sid: i32,
+    top: &models::Top,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<bool> {
+    let existing = sqlx::query!(
+        "SELECT id FROM top WHERE sid = $1 AND nummer = $2",
+        sid,
+        top.nummer as i32
+    )
+    .map(|r| r.id)
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(tid) = existing {
+        if top_unchanged(tid, top, tx, srv).await? {
+            return Ok(false);
+        }
+        sqlx::query!("DELETE FROM top WHERE id = $1", tid)
+            .execute(&mut **tx)
+            .await?;
+    }
+    insert::insert_top(sid, top, scraper_id, collector_key, tx, srv).await?;
+    Ok(true)
+}
+
+/// compares the stored TOP `tid` with `top` in the form in which it would be inserted:
+/// document references are resolved to the stored documents and only the explicitly linked
+/// Vorgänge that exist count, not the ones `retrieve::top_by_id` derives from the documents.
+async fn top_unchanged(
+    tid: i32,
+    top: &models::Top,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<bool> {
+    let titel = sqlx::query!("SELECT titel FROM top WHERE id = $1", tid)
+        .map(|r| r.titel)
+        .fetch_one(&mut **tx)
+        .await?;
+    if titel != top.titel {
+        return Ok(false);
+    }
+    let mut stored_doks = sqlx::query!("SELECT dok_id FROM tops_doks WHERE top_id = $1", tid)
+        .map(|r| r.dok_id)
+        .fetch_all(&mut **tx)
+        .await?;
+    let mut doks = vec![];
+    for d in top.dokumente.as_deref().unwrap_or(&[]) {
+        match d {
+            models::StationDokumenteInner::String(api_id) => {
+                doks.push(insert::dokument_by_reference(api_id, tx).await?)
+            }
+            models::StationDokumenteInner::Dokument(dok) => {
+                let MatchState::ExactlyOne(did) =
+                    dokument_merge_candidates(dok, &mut **tx, srv).await?
+                else {
+                    return Ok(false);
+                };
+                let stored = retrieve::dokument_by_id(did, tx).await?;
+                if !compare_dokument(&stored, dok) {
+                    return Ok(false);
+                }
+                doks.push(did);
+            }
+        }
+    }
+    stored_doks.sort_unstable();
+    stored_doks.dedup();
+    doks.sort_unstable();
+    doks.dedup();
+    if stored_doks != doks {
+        return Ok(false);
+    }
+    let vg_differ = sqlx::query!(
+        "SELECT EXISTS(
+            (SELECT vg_id FROM rel_top_vorgang WHERE top_id = $1
+            EXCEPT SELECT id FROM vorgang WHERE api_id = ANY($2::uuid[]))
+            UNION ALL
+            (SELECT id FROM vorgang WHERE api_id = ANY($2::uuid[])
+            EXCEPT SELECT vg_id FROM rel_top_vorgang WHERE top_id = $1)
+        ) AS \"differ!\"",
+        tid,
+        top.vorgang_id.as_deref().unwrap_or(&[])
+    )
+    .map(|r| r.differ)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(!vg_differ)
+}
+
+/// all TOPs that are linked to the vorgang, ordered by the date of their sitzung
+pub async fn tops_by_vorgang(
+    vorgang_id: Uuid,
+    page: Option<i32>,
+    per_page: Option<i32>,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<(PaginationResponsePart, Vec<TopEntry>)> {
+    let mut ids = sqlx::query!(
+        "SELECT t.id, s.api_id FROM top t
+        INNER JOIN sitzung s ON s.id = t.sid
+        WHERE EXISTS (
+            SELECT 1 FROM rel_top_vorgang rtv
+            INNER JOIN vorgang v ON v.id = rtv.vg_id
+            WHERE rtv.top_id = t.id AND v.api_id = $1
+        ) OR EXISTS (
+            SELECT 1 FROM tops_doks td
+            LEFT JOIN rel_station_dokument rsd ON rsd.dok_id = td.dok_id
+            LEFT JOIN rel_station_stln rss ON rss.dok_id = td.dok_id
+            INNER JOIN station st ON st.id = rsd.stat_id OR st.id = rss.stat_id
+            INNER JOIN vorgang v ON v.id = st.vg_id
+            WHERE td.top_id = t.id AND v.api_id = $1
+        )
+        ORDER BY s.termin ASC, t.nummer ASC",
+        vorgang_id
+    )
+    .map(|r| (r.id, r.api_id))
+    .fetch_all(&mut **tx)
+    .await?;
+    let prp = PaginationResponsePart::new(ids.len() as i32, page, per_page);
+    debug!("Found {} TOPs for Vorgang {}", ids.len(), vorgang_id);
+    if ids.is_empty() {
+        return Ok((prp, vec![]));
+    }
+    let ids = ids.drain(prp.start()..prp.end());
+    let mut result = Vec::with_capacity(ids.len());
+    for (tid, sitzung_id) in ids {
+        result.push(TopEntry {
+            sitzung_id,
+            top: retrieve::top_by_id(tid, tx).await?,
+        });
+    }
+    Ok((prp, result))
+}
+
+#[cfg(test)]
+mod test_top {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::{top_id, tops_by_vorgang, upsert_top};
+    use crate::db::merge::execute::run_integration;
+    use crate::db::merge::sitzung::run_sitzung_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_top_upsert() {
+        let scenario = TestSetup::new("test_top_upsert").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let sitzung = generate::default_sitzung();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        run_sitzung_integration(&sitzung, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let sid = sqlx::query!("SELECT id FROM sitzung")
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+
+        let top = models::Top {
+            nummer: 42,
+            titel: "Änderung der Schuppenfärbeverordnung".to_string(),
+            dokumente: None,
+            vorgang_id: Some(vec![vorgang.api_id]),
+        };
+        assert!(
+            upsert_top(sid, &top, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap()
+        );
+        // unchanged
+        assert!(
+            !upsert_top(sid, &top, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap()
+        );
+        let changed = models::Top {
+            titel: "Änderung der Schuppenfärbeverordnung (neu)".to_string(),
+            ..top.clone()
+        };
+        assert!(
+            upsert_top(sid, &changed, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap()
+        );
+        let sitzung_id = sitzung.api_id.unwrap();
+        let tid = top_id(sitzung_id, 42, &mut tx).await.unwrap();
+        assert!(tid.is_some());
+
+        // the stored default TOP, once with the full document and once referencing it
+        let default_top = generate::default_top();
+        let default_tid = top_id(sitzung_id, default_top.nummer, &mut tx)
+            .await
+            .unwrap();
+        assert!(
+            !upsert_top(sid, &default_top, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap()
+        );
+        let referencing = models::Top {
+            dokumente: Some(vec![models::StationDokumenteInner::String(
+                generate::default_dokument().api_id.unwrap().to_string(),
+            )]),
+            ..default_top.clone()
+        };
+        assert!(
+            !upsert_top(sid, &referencing, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            top_id(sitzung_id, default_top.nummer, &mut tx)
+                .await
+                .unwrap(),
+            default_tid
+        );
+        assert_eq!(top_id(sitzung_id, 42, &mut tx).await.unwrap(), tid);
+
+        let (prp, tops) = tops_by_vorgang(vorgang.api_id, None, None, &mut tx)
+            .await
+            .unwrap();
+        // the default top may be linked to the vorgang by a shared document as well
+        assert_eq!(prp.x_total_count as usize, tops.len());
+        let entry = tops.iter().find(|t| t.top.nummer == 42).unwrap();
+        assert_eq!(entry.sitzung_id, sitzung_id);
+        assert_eq!(entry.top, changed);
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -1,4 +1,6 @@
-use crate::db::{delete, insert, merge, retrieve};
+use std::collections::HashSet;
+
+use crate::db::{insert, merge, retrieve};
 use crate::error::{DataValidationError, LTZFError};
 use crate::utils::as_option;
 use crate::{LTZFServer, Result};
@@ -9,12 +11,13 @@ use openapi::apis::{
     collector_schnittstellen_vorgang::*, data_administration_vorgang::*, unauthorisiert_vorgang::*,
 };
 use openapi::models;
-use tracing::{debug, error, info, instrument, warn};
+use tracing::{debug, info, instrument, warn};
 use uuid::Uuid;
 
+use super::abstimmung;
 use super::auth::{self, APIScope};
-use super::find_applicable_date_range;
-use crate::api::RoundTimestamp;
+use super::{ExpandQueryParams, find_applicable_date_range};
+use crate::api::normalize::compare_vorgang;
 use crate::db;
 
 #[async_trait]
@@ -63,6 +66,13 @@ impl DataAdministrationVorgang<LTZFError> for LTZFServer {
             });
         }
         let mut tx = self.sqlx_db.begin().await?;
+        for warning in crate::db::validate::validate_vorgang(body, &mut tx).await? {
+            warn!(
+                "Plausibility warning for Vorgang {}: {}",
+                body.api_id, warning
+            );
+            crate::utils::warnings::push_warning(warning);
+        }
         let api_id = path_params.vorgang_id;
         let db_id = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", api_id)
             .map(|x| x.id)
@@ -73,21 +83,37 @@ impl DataAdministrationVorgang<LTZFError> for LTZFServer {
                 debug!("Match found: {db_id}");
                 let db_cmpvg = retrieve::vorgang_by_id(db_id, &mut tx).await?;
 
-                if body.with_round_timestamps() == db_cmpvg.with_round_timestamps() {
+                if compare_vorgang(body, &db_cmpvg) {
                     return Ok(VorgangIdPutResponse::Status304_NotModified {
                         x_rate_limit_limit: None,
                         x_rate_limit_remaining: None,
                         x_rate_limit_reset: None,
                     });
                 }
-                match delete::delete_vorgang_by_api_id(api_id, self).await? {
-                    VorgangDeleteResponse::Status204_NoContent { .. } => {
-                        insert::insert_vorgang(body, Uuid::nil(), claims.1, &mut tx, self).await?;
-                    }
-                    _ => {
-                        error!("After successful delete an insert cannot fail");
-                        unreachable!("If this is reached, some assumptions did not hold")
+                // Abstimmungen are deleted with their Stationen, they are attached to the
+                // Stationen of the replacement again. Stationen with Abstimmungen have to stay.
+                let abstimmungen = abstimmung::abstimmungen_by_station(db_id, &mut tx).await?;
+                let kept: HashSet<Uuid> = body.stationen.iter().filter_map(|s| s.api_id).collect();
+                let mut orphaned: Vec<Uuid> = abstimmungen
+                    .iter()
+                    .map(|a| a.station)
+                    .filter(|s| !kept.contains(s))
+                    .collect();
+                if orphaned.is_empty() {
+                    sqlx::query!("DELETE FROM vorgang WHERE id = $1", db_id)
+                        .execute(&mut *tx)
+                        .await?;
+                    insert::insert_vorgang(body, Uuid::nil(), claims.1, &mut tx, self).await?;
+                    orphaned = abstimmung::restore_abstimmungen(&abstimmungen, &mut tx).await?;
+                }
+                if !orphaned.is_empty() {
+                    orphaned.sort();
+                    orphaned.dedup();
+                    warn!("Replacement would delete the Abstimmungen of Stationen {orphaned:?}");
+                    return Err(DataValidationError::AbstimmungenOrphaned {
+                        stationen: orphaned,
                     }
+                    .into());
                 }
             }
             None => {
@@ -133,9 +159,31 @@ impl CollectorSchnittstellenVorgang<LTZFError> for LTZFServer {
                 x_rate_limit_reset: None,
             });
         }
-        let rval =
-            merge::execute::run_integration(body, header_params.x_scraper_id, claims.1, self).await;
+        // not among the generated header parameters, see `utils::spec_ext`
+        let merge_hint = crate::utils::spec_ext::header("x-merge-candidate-id").and_then(|h| {
+            Uuid::parse_str(&h)
+                .inspect_err(|e| warn!("Ignoring the invalid X-Merge-Candidate-Id `{h}`: {e}"))
+                .ok()
+        });
+        let rval = merge::execute::run_integration_with_hint(
+            body,
+            merge_hint,
+            header_params.x_scraper_id,
+            claims.1,
+            self,
+        )
+        .await;
         match rval {
+            Ok(merge::execute::IntegrationOutcome::Queued(review_id)) => {
+                info!("Ambiguous merge candidates, queued for review as {review_id}");
+                // 202 is not among the generated responses, see `utils::spec_ext`
+                crate::utils::spec_ext::set_status(axum::http::StatusCode::ACCEPTED);
+                Ok(VorgangPutResponse::Status201_Created {
+                    x_rate_limit_limit: None,
+                    x_rate_limit_remaining: None,
+                    x_rate_limit_reset: None,
+                })
+            }
             Ok(_) => {
                 info!("Integration Successful");
                 Ok(VorgangPutResponse::Status201_Created {
@@ -148,7 +196,8 @@ impl CollectorSchnittstellenVorgang<LTZFError> for LTZFServer {
                 warn!("Unsuccessful Integration Attempt: {e}");
                 match &e {
                     LTZFError::Validation { source } => match **source {
-                        DataValidationError::AmbiguousMatch { .. } => {
+                        DataValidationError::AmbiguousMatch { .. }
+                        | DataValidationError::DuplicateApiId { .. } => {
                             Ok(VorgangPutResponse::Status409_Conflict {
                                 x_rate_limit_limit: None,
                                 x_rate_limit_remaining: None,
@@ -157,6 +206,11 @@ impl CollectorSchnittstellenVorgang<LTZFError> for LTZFServer {
                         }
                         _ => Err(e),
                     },
+                    LTZFError::Conflict { .. } => Ok(VorgangPutResponse::Status409_Conflict {
+                        x_rate_limit_limit: None,
+                        x_rate_limit_remaining: None,
+                        x_rate_limit_reset: None,
+                    }),
                     _ => Err(e),
                 }
             }
@@ -176,11 +230,14 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
         header_params: &models::VorgangGetByIdHeaderParams,
         path_params: &models::VorgangGetByIdPathParams,
     ) -> Result<VorgangGetByIdResponse> {
-        // TODO: find a better way to handle admin-only info for every object
-        // in the past this was done by optional authentication, but that
-        // turned out not to neatly fit into the oapi spec.
-        // for now this is just a disabled feature
-        let claims = (APIScope::Collector, 0);
+        // admin-only info, the key of the request is checked by the `touched_by` middleware
+        let privileged = crate::utils::touched_by::is_privileged();
+        let scope = if privileged {
+            APIScope::Admin
+        } else {
+            APIScope::Collector
+        };
+        let query_params: ExpandQueryParams = crate::utils::spec_ext::query()?;
 
         let mut tx = self.sqlx_db.begin().await?;
         let exists = sqlx::query!(
@@ -210,22 +267,31 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
         .await?;
         if let Some(dbid) = dbid {
             let mut result = retrieve::vorgang_by_id(dbid, &mut tx).await?;
-            if claims.0 == APIScope::Admin || claims.0 == APIScope::KeyAdder {
-                result.touched_by = as_option(
-                    sqlx::query!(
-                        "SELECT * FROM scraper_touched_vorgang sts
+            if privileged {
+                let touched = sqlx::query!(
+                    "SELECT api_keys.key_hash, sts.scraper FROM scraper_touched_vorgang sts
                 INNER JOIN api_keys ON api_keys.id = sts.collector_key
-                WHERE vg_id = $1",
-                        dbid
-                    )
-                    .map(|r| models::TouchedByInner {
-                        key: Some(r.key_hash),
-                        scraper_id: Some(r.scraper),
-                    })
-                    .fetch_all(&mut *tx)
-                    .await?,
+                WHERE vg_id = $1 ORDER BY sts.time_stamp DESC",
+                    dbid
+                )
+                .map(|r| models::TouchedByInner {
+                    key: Some(r.key_hash),
+                    scraper_id: Some(r.scraper),
+                })
+                .fetch_all(&mut *tx)
+                .await?;
+                crate::utils::touched_by::record_touched_by(
+                    touched.iter().filter_map(|t| t.scraper_id).collect(),
                 );
+                result.touched_by = as_option(touched);
+            }
+            if query_params.expand_dokumente == Some(true) {
+                retrieve::expand_vorgang_dokumente(&mut result, scope, &mut tx).await?;
             }
+            if privileged {
+                fill_created_at(std::slice::from_ref(&result), &mut tx).await?;
+            }
+            fill_metadata(std::slice::from_ref(&result), &mut tx).await?;
             tx.commit().await?;
             info!("Successful retrieval");
             Ok(VorgangGetByIdResponse::Status200_Success {
@@ -257,6 +323,35 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
         header_params: &models::VorgangGetHeaderParams,
         query_params: &models::VorgangGetQueryParams,
     ) -> Result<VorgangGetResponse> {
+        let ext_params: VorgangGetExtParams = crate::utils::spec_ext::query()?;
+        let sort = match ext_params.sort.as_deref().map(retrieve::parse_sort) {
+            Some(Ok(sort)) => sort,
+            None => vec![],
+            Some(Err(e)) => {
+                warn!("Sort parameter could not be parsed: {e}");
+                return Err(e);
+            }
+        };
+        if let (Some(since), Some(until)) = (ext_params.created_since, ext_params.created_until) {
+            if since > until {
+                warn!("Creation range is empty: {since} > {until}");
+                return Ok(VorgangGetResponse::Status416_RequestRangeNotSatisfiable {
+                    x_rate_limit_limit: None,
+                    x_rate_limit_remaining: None,
+                    x_rate_limit_reset: None,
+                });
+            }
+        }
+        if let (Some(min), Some(max)) = (ext_params.min_meinung, ext_params.max_meinung) {
+            if min > max {
+                warn!("Meinung range is empty: {min} > {max}");
+                return Err(DataValidationError::InvalidQueryParameter {
+                    parameter: "min_meinung".to_string(),
+                    message: format!("{min} is greater than max_meinung {max}"),
+                }
+                .into());
+            }
+        }
         let mut tx = self.sqlx_db.begin().await?;
         if let Some(range) = find_applicable_date_range(
             None,
@@ -268,15 +363,34 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
         ) {
             let parameters = retrieve::VGGetParameters {
                 lower_date: range.since,
-                parlament: query_params.p,
+                parlament: super::parlament_list(&ext_params.p)?
+                    .or(query_params.p.map(|p| vec![p])),
                 upper_date: range.until,
                 vgtyp: query_params.vgtyp,
                 wp: query_params.wp,
                 inifch: query_params.fach.clone(),
                 iniorg: query_params.org.clone(),
                 inipsn: query_params.person.clone(),
+                schlagwort: schlagwort_list(&ext_params.schlagwort),
+                schlagwort_any: schlagwort_list(&ext_params.schlagwort_any),
+                min_meinung: ext_params.min_meinung.map(|m| m as i32),
+                max_meinung: ext_params.max_meinung.map(|m| m as i32),
+                trojanergefahr_min: ext_params.trojanergefahr_min.map(|t| t as i32),
+                modified_since: ext_params.modified_since,
+                dok_schlagwort: ext_params
+                    .dok_schlagwort
+                    .as_ref()
+                    .map(|s| s.trim().to_lowercase())
+                    .filter(|s| !s.is_empty()),
+                initiator_fraktion: ext_params
+                    .initiator_fraktion
+                    .as_ref()
+                    .map(|f| f.trim().to_string()),
+                created_since: ext_params.created_since,
+                created_until: ext_params.created_until,
+                sort,
             };
-            let result = retrieve::vorgang_by_parameter(
+            let mut result = retrieve::vorgang_by_parameter(
                 parameters,
                 query_params.page,
                 query_params.per_page,
@@ -286,8 +400,8 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
             if result.1.is_empty() && header_params.if_modified_since.is_none() {
                 tx.rollback().await?;
                 info!(
-                    "Parameters did not yield any content: {:?}, ims=None",
-                    query_params
+                    "Parameters did not yield any content: {:?}, {:?}, ims=None",
+                    query_params, ext_params
                 );
                 Ok(VorgangGetResponse::Status204_NoContent {
                     x_rate_limit_limit: None,
@@ -306,8 +420,20 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
                     x_rate_limit_reset: None,
                 })
             } else {
+                fill_metadata(&result.1, &mut tx).await?;
+                if crate::utils::touched_by::is_privileged() {
+                    fill_touched_by(
+                        &mut result.1,
+                        self.config.per_object_scraper_log_size,
+                        &mut tx,
+                    )
+                    .await?;
+                    fill_created_at(&result.1, &mut tx).await?;
+                }
                 tx.commit().await?;
                 let prp = &result.0;
+                let mut filter = crate::api::filter_query(query_params);
+                filter.extend(crate::api::filter_query(&ext_params));
                 info!("{} Objects matched query Parameters", result.1.len());
                 Ok(VorgangGetResponse::Status200_Successful {
                     body: result.1,
@@ -315,7 +441,11 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
                     x_total_pages: Some(prp.x_total_pages),
                     x_page: Some(prp.x_page),
                     x_per_page: Some(prp.x_per_page),
-                    link: Some(prp.generate_link_header("/api/v2/vorgang")),
+                    link: Some(prp.generate_link_header(
+                        &self.public_base_url(),
+                        "/api/v2/vorgang",
+                        &filter,
+                    )),
                     x_rate_limit_limit: None,
                     x_rate_limit_remaining: None,
                     x_rate_limit_reset: None,
@@ -324,8 +454,8 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
         } else {
             tx.rollback().await?;
             warn!(
-                "Parameters were chosen such that the request is unsatisfiable: {:?}, ims={:?}",
-                query_params, header_params.if_modified_since
+                "Parameters were chosen such that the request is unsatisfiable: {:?}, {:?}, ims={:?}",
+                query_params, ext_params, header_params.if_modified_since
             );
             Ok(VorgangGetResponse::Status416_RequestRangeNotSatisfiable {
                 x_rate_limit_limit: None,
@@ -336,9 +466,158 @@ impl UnauthorisiertVorgang<LTZFError> for LTZFServer {
     }
 }
 
+/// query parameters of `GET /api/v2/vorgang` that are not in the generated server,
+/// see `utils::spec_ext`
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub(crate) struct VorgangGetExtParams {
+    /// Parlamente, repeated or comma separated, one of them has to match
+    #[serde(default)]
+    pub p: Vec<String>,
+    /// comma separated Schlagworte of the Stationen, all of them have to match
+    pub schlagwort: Option<String>,
+    /// comma separated Schlagworte of the Stationen, one of them has to match
+    pub schlagwort_any: Option<String>,
+    /// comma separated `field:direction` pairs, see `retrieve::parse_sort`
+    pub sort: Option<String>,
+    /// lowest Meinung of the Stellungnahmen, from 1 to 5
+    pub min_meinung: Option<u8>,
+    /// highest Meinung of the Stellungnahmen, from 1 to 5
+    pub max_meinung: Option<u8>,
+    /// some Station has at least this `trojanergefahr`
+    pub trojanergefahr_min: Option<u8>,
+    /// the Vorgang or one of its parts changed after this time
+    pub modified_since: Option<chrono::DateTime<chrono::Utc>>,
+    /// a Schlagwort of one of the Dokumente
+    pub dok_schlagwort: Option<String>,
+    /// the Fraktion of one of the Initiatoren
+    pub initiator_fraktion: Option<String>,
+    /// the Vorgang was stored at or after this time
+    pub created_since: Option<chrono::DateTime<chrono::Utc>>,
+    /// the Vorgang was stored at or before this time
+    pub created_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// splits a comma separated list of Schlagworte from the query into the normalised form
+/// they are stored in. `schlagwort` requires all of them, `schlagwort_any` at least one.
+fn schlagwort_list(param: &Option<String>) -> Option<Vec<String>> {
+    as_option(
+        param
+            .iter()
+            .flat_map(|p| p.split(','))
+            .map(|s| s.trim().to_lowercase())
+            .filter(|s| !s.is_empty())
+            .collect(),
+    )
+}
+
+/// fills in the derived fields that are only part of GET responses:
+/// the highest `trojanergefahr` of all Stationen and the time of the last change.
+/// Neither is in the generated model, see `utils::spec_ext`
+async fn fill_metadata(
+    vorgaenge: &[models::Vorgang],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    let api_ids: Vec<Uuid> = vorgaenge.iter().map(|vg| vg.api_id).collect();
+    let last_updates = sqlx::query!(
+        "SELECT api_id, last_update FROM vorgang WHERE api_id = ANY($1::uuid[])",
+        &api_ids[..]
+    )
+    .map(|r| (r.api_id, r.last_update))
+    .fetch_all(&mut **tx)
+    .await?;
+    for vg in vorgaenge {
+        if let Some(max) = vg.stationen.iter().filter_map(|s| s.trojanergefahr).max() {
+            crate::utils::spec_ext::extend_object(vg.api_id, "trojanergefahr_max", max);
+        }
+    }
+    for (api_id, last_update) in last_updates {
+        crate::utils::spec_ext::extend_object(api_id, "last_update", last_update);
+    }
+    Ok(())
+}
+
+/// sets `touched_by` of all Vorgänge with one query, at most `limit` scrapers each, most recent first
+async fn fill_touched_by(
+    vorgaenge: &mut [models::Vorgang],
+    limit: u32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    let api_ids: Vec<Uuid> = vorgaenge.iter().map(|vg| vg.api_id).collect();
+    let touches = sqlx::query!(
+        "SELECT v.api_id,
+        ARRAY_AGG(t.key_hash ORDER BY t.time_stamp DESC) as \"keys!\",
+        ARRAY_AGG(t.scraper ORDER BY t.time_stamp DESC) as \"scrapers!\"
+        FROM vorgang v, LATERAL (
+            SELECT sts.scraper, sts.time_stamp, ak.key_hash FROM scraper_touched_vorgang sts
+            INNER JOIN api_keys ak ON ak.id = sts.collector_key
+            WHERE sts.vg_id = v.id
+            ORDER BY sts.time_stamp DESC LIMIT $2
+        ) t
+        WHERE v.api_id = ANY($1::uuid[])
+        GROUP BY v.api_id",
+        &api_ids[..],
+        limit as i64
+    )
+    .map(|r| (r.api_id, r.keys, r.scrapers))
+    .fetch_all(&mut **tx)
+    .await?;
+    for vg in vorgaenge.iter_mut() {
+        vg.touched_by = touches
+            .iter()
+            .find(|(api_id, _, _)| *api_id == vg.api_id)
+            .map(|(_, keys, scrapers)| {
+                keys.iter()
+                    .zip(scrapers)
+                    .map(|(key, scraper)| models::TouchedByInner {
+                        key: Some(key.clone()),
+                        scraper_id: Some(*scraper),
+                    })
+                    .collect()
+            });
+    }
+    Ok(())
+}
+
+/// `created_at` of the Vorgänge, their Stationen and the Dokumente included in full, which is
+/// not in the generated models, see `utils::spec_ext`.
+/// api_ids are unique across the tables, so one lookup serves all of them
+async fn fill_created_at(
+    vorgaenge: &[models::Vorgang],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    let api_ids: Vec<Uuid> = vorgaenge.iter().map(|vg| vg.api_id).collect();
+    let created = sqlx::query!(
+        "SELECT v.api_id as \"api_id!\", v.created_at as \"created_at!\" FROM vorgang v
+        WHERE v.api_id = ANY($1::uuid[])
+        UNION ALL
+        SELECT s.api_id, s.created_at FROM station s
+        INNER JOIN vorgang v ON v.id = s.vg_id
+        WHERE v.api_id = ANY($1::uuid[])
+        UNION ALL
+        SELECT d.api_id, d.created_at FROM dokument d
+        WHERE EXISTS (
+            SELECT 1 FROM station s
+            INNER JOIN vorgang v ON v.id = s.vg_id
+            LEFT JOIN rel_station_dokument rsd ON rsd.stat_id = s.id
+            LEFT JOIN rel_station_stln rss ON rss.stat_id = s.id
+            WHERE v.api_id = ANY($1::uuid[]) AND (rsd.dok_id = d.id OR rss.dok_id = d.id)
+        )",
+        &api_ids[..]
+    )
+    .map(|r| (r.api_id, r.created_at))
+    .fetch_all(&mut **tx)
+    .await?;
+    for (api_id, created_at) in created {
+        crate::utils::spec_ext::extend_object(api_id, "created_at", created_at);
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod test_endpoints {
 
+    use axum::body::Body;
+    use axum::extract::Request;
     use axum::http::Method;
     use axum_extra::extract::{CookieJar, Host};
     use chrono::Utc;
@@ -353,8 +632,53 @@ mod test_endpoints {
 
     use crate::api::auth;
     use crate::api::auth::APIScope;
+    use crate::utils::spec_ext::with_request;
     use crate::utils::testing::{TestSetup, generate};
 
+    /// query parameters without any filter, to be completed with the ones under test
+    fn query_params() -> models::VorgangGetQueryParams {
+        models::VorgangGetQueryParams {
+            page: None,
+            per_page: None,
+            p: None,
+            since: None,
+            until: None,
+            vgtyp: None,
+            wp: None,
+            fach: None,
+            org: None,
+            person: None,
+        }
+    }
+
+    /// a `GET /api/v2/vorgang` request with query parameters that are not in the generated
+    /// server, see `utils::spec_ext`
+    fn listing<V: serde::Serialize>(query: &[(&str, V)]) -> Request {
+        Request::builder()
+            .uri(format!(
+                "/api/v2/vorgang?{}",
+                serde_html_form::to_string(query).unwrap()
+            ))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// a `GET /api/v2/vorgang/{vorgang_id}` request, see `utils::spec_ext`
+    fn by_id(api_id: Uuid) -> Request {
+        Request::builder()
+            .uri(format!("/api/v2/vorgang/{api_id}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// a request for the Vorgang with expanded Dokumente, see `utils::spec_ext`
+    fn expanding(api_id: Uuid) -> Request {
+        Request::builder()
+            .uri(format!("/api/v2/vorgang/{api_id}?expand_dokumente=true"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
     // Procedure (Vorgang) tests
     #[tokio::test]
     async fn test_vorgang_get_by_id_endpoints() {
@@ -411,6 +735,77 @@ mod test_endpoints {
             }
         }
 
+        // 1b. Get existing procedure with expanded dokumente
+        {
+            let (response, _) = with_request(
+                expanding(test_vorgang.api_id),
+                server.vorgang_get_by_id(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetByIdHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &models::VorgangGetByIdPathParams {
+                        vorgang_id: test_vorgang.api_id,
+                    },
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetByIdResponse::Status200_Success { body, .. } => {
+                    assert!(body.stationen.iter().all(|s| {
+                        s.dokumente
+                            .iter()
+                            .chain(s.stellungnahmen.iter().flatten())
+                            .all(|d| matches!(d, models::StationDokumenteInner::Dokument(_)))
+                    }));
+                }
+                _ => panic!("Expected successful operation response"),
+            }
+        }
+
+        // 1c. admin-only fields are filled in for privileged keys only
+        for privileged in [false, true] {
+            let (response, recorded) = with_request(
+                expanding(test_vorgang.api_id),
+                crate::utils::touched_by::PRIVILEGED.scope(
+                    privileged,
+                    server.vorgang_get_by_id(
+                        &Method::GET,
+                        &Host("localhost".to_string()),
+                        &CookieJar::new(),
+                        &models::VorgangGetByIdHeaderParams {
+                            if_modified_since: None,
+                        },
+                        &models::VorgangGetByIdPathParams {
+                            vorgang_id: test_vorgang.api_id,
+                        },
+                    ),
+                ),
+            )
+            .await;
+            let VorgangGetByIdResponse::Status200_Success { body, .. } = response.unwrap() else {
+                panic!("Expected successful operation response");
+            };
+            assert_eq!(body.touched_by.is_some(), privileged);
+            // created_at is added by the middleware, see `utils::spec_ext`
+            let mut body = serde_json::to_value(body).unwrap();
+            recorded.apply(&mut body);
+            assert_eq!(body.get("created_at").is_some(), privileged);
+            let mut nested = 0;
+            for station in body["stationen"].as_array().unwrap() {
+                assert_eq!(station.get("created_at").is_some(), privileged);
+                let dokumente = station["dokumente"].as_array().unwrap();
+                for dok in dokumente.iter().filter(|d| d.is_object()) {
+                    assert_eq!(dok.get("created_at").is_some(), privileged);
+                    assert_eq!(dok.get("touched_by").is_some(), privileged);
+                    nested += 1;
+                }
+            }
+            assert!(nested > 0, "Expected expanded Dokumente");
+        }
+
         // 2. Get non-existent procedure
         {
             let non_existent_id = Uuid::now_v7();
@@ -532,16 +927,9 @@ mod test_endpoints {
                         if_modified_since: None,
                     },
                     &models::VorgangGetQueryParams {
-                        page: None,
-                        per_page: None,
-                        p: None,
                         since: Some(Utc::now()),
                         until: Some(Utc::now() - chrono::Duration::days(365)), // invalid: until is before since
-                        vgtyp: None,
-                        wp: None,
-                        fach: None,
-                        org: None,
-                        person: None,
+                        ..query_params()
                     },
                 )
                 .await
@@ -563,16 +951,9 @@ mod test_endpoints {
                         if_modified_since: None,
                     },
                     &models::VorgangGetQueryParams {
-                        page: None,
-                        per_page: None,
-                        p: None,
                         since: Some(Utc::now() + chrono::Duration::days(365)),
                         until: Some(Utc::now() + chrono::Duration::days(366)),
-                        vgtyp: None,
-                        wp: None,
-                        fach: None,
-                        org: None,
-                        person: None,
+                        ..query_params()
                     },
                 )
                 .await
@@ -633,13 +1014,9 @@ mod test_endpoints {
                         page: Some(0),
                         per_page: Some(32),
                         p: Some(models::Parlament::Bb),
-                        since: None,
-                        until: None,
                         vgtyp: Some(test_vorgang.typ),
                         wp: Some(test_vorgang.wahlperiode as i32),
-                        fach: None,
-                        org: None,
-                        person: None,
+                        ..query_params()
                     },
                 )
                 .await
@@ -656,6 +1033,544 @@ mod test_endpoints {
         scenario.teardown().await;
     }
 
+    #[tokio::test]
+    async fn test_vorgang_get_invalid_sort() {
+        let scenario = TestSetup::new("test_vorgang_get_invalid_sort").await;
+        let server = &scenario.server;
+        let (response, _) = with_request(
+            listing(&[("sort", "titel:seitwaerts")]),
+            server.vorgang_get(
+                &Method::GET,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &models::VorgangGetHeaderParams {
+                    if_modified_since: None,
+                },
+                &query_params(),
+            ),
+        )
+        .await;
+        // answered with 400 by the error handler
+        assert!(response.is_err_and(|e| e.is_bad_request()));
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_initiator_filter() {
+        let scenario = TestSetup::new("test_vorgang_get_initiator_filter").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        // substring matches are case insensitive
+        let cases = [
+            (Some("ministerium der magie"), None, true),
+            (None, Some("töpfer"), true),
+            (Some("MAGIE"), Some("Harald"), true),
+            (Some("Bundesrat"), None, false),
+            (None, Some("Mueller"), false),
+        ];
+        for (org, person, found) in cases {
+            let response = server
+                .vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &models::VorgangGetQueryParams {
+                        org: org.map(str::to_string),
+                        person: person.map(str::to_string),
+                        ..query_params()
+                    },
+                )
+                .await
+                .unwrap();
+            match response {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(found, "Expected no match for {org:?}/{person:?}");
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a match for {org:?}/{person:?}")
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_schlagwort_filter() {
+        let scenario = TestSetup::new("test_vorgang_get_schlagwort_filter").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        // the default station is tagged with "stationär" only
+        let cases = [
+            (Some("Stationär"), None, true),
+            (Some("stationär,energie"), None, false),
+            (None, Some("energie, stationär"), true),
+            (None, Some("energie,klima"), false),
+        ];
+        for (schlagwort, schlagwort_any, found) in cases {
+            let query: Vec<_> = [
+                ("schlagwort", schlagwort),
+                ("schlagwort_any", schlagwort_any),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect();
+            let (response, _) = with_request(
+                listing(&query),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(
+                        found,
+                        "Expected no match for {schlagwort:?}/{schlagwort_any:?}"
+                    );
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(
+                        !found,
+                        "Expected a match for {schlagwort:?}/{schlagwort_any:?}"
+                    )
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_dok_schlagwort_filter() {
+        let scenario = TestSetup::new("test_vorgang_get_dok_schlagwort_filter").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        // "stationär" is only attached to the Station, not to its Dokumente
+        let cases = [(" Drache", true), ("schuppen", true), ("stationär", false)];
+        for (dok_schlagwort, found) in cases {
+            let (response, _) = with_request(
+                listing(&[("dok_schlagwort", dok_schlagwort)]),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(found, "Expected no match for {dok_schlagwort:?}");
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a match for {dok_schlagwort:?}")
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_touched_by() {
+        let mut scenario = TestSetup::new("test_vorgang_get_touched_by").await;
+        let test_vorgang = generate::default_vorgang();
+        let scrapers = [Uuid::now_v7(), Uuid::now_v7(), Uuid::now_v7()];
+        for scraper in scrapers {
+            crate::db::merge::execute::run_integration(&test_vorgang, scraper, 1, &scenario.server)
+                .await
+                .unwrap();
+        }
+        scenario.server.config.per_object_scraper_log_size = 2;
+        let server = &scenario.server;
+        let query = models::VorgangGetQueryParams { ..query_params() };
+        let headers = models::VorgangGetHeaderParams {
+            if_modified_since: None,
+        };
+        for privileged in [true, false] {
+            let response = crate::utils::touched_by::PRIVILEGED
+                .scope(
+                    privileged,
+                    server.vorgang_get(
+                        &Method::GET,
+                        &Host("localhost".to_string()),
+                        &CookieJar::new(),
+                        &headers,
+                        &query,
+                    ),
+                )
+                .await
+                .unwrap();
+            let VorgangGetResponse::Status200_Successful { body, .. } = response else {
+                panic!("Unexpected response {response:?}");
+            };
+            if privileged {
+                let touched_by: Vec<_> = body[0]
+                    .touched_by
+                    .as_ref()
+                    .unwrap()
+                    .iter()
+                    .map(|t| t.scraper_id.unwrap())
+                    .collect();
+                assert_eq!(touched_by, vec![scrapers[2], scrapers[1]]);
+            } else {
+                assert!(body[0].touched_by.is_none());
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_initiator_fraktion() {
+        let scenario = TestSetup::new("test_vorgang_get_initiator_fraktion").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        // the Fraktion is only part of the submitted body, see `utils::spec_ext`
+        let mut body = serde_json::to_value(&test_vorgang).unwrap();
+        body["initiatoren"][0]["fraktion"] = serde_json::json!(" SPD ");
+        let request = Request::builder()
+            .method("PUT")
+            .uri("/api/v2/vorgang")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let (result, _) = with_request(
+            request,
+            crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server),
+        )
+        .await;
+        result.unwrap();
+        for (fraktion, found) in [("SPD", true), ("CDU", false)] {
+            let (response, _) = with_request(
+                listing(&[("initiator_fraktion", fraktion)]),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(found, "Expected no match for {fraktion}");
+                    assert_eq!(body[0].initiatoren, test_vorgang.initiatoren);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a match for {fraktion}")
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_created() {
+        let scenario = TestSetup::new("test_vorgang_get_created").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let hour = chrono::Duration::hours(1);
+        let now = chrono::Utc::now();
+        let cases = [
+            (Some(now - hour), None, true),
+            (Some(now + hour), None, false),
+            (None, Some(now - hour), false),
+            (Some(now - hour), Some(now + hour), true),
+        ];
+        for (created_since, created_until, found) in cases {
+            let query: Vec<_> = [
+                ("created_since", created_since),
+                ("created_until", created_until),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?.to_rfc3339())))
+            .collect();
+            let (response, _) = with_request(
+                listing(&query),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(
+                        found,
+                        "Expected no match for {created_since:?}/{created_until:?}"
+                    );
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(
+                        !found,
+                        "Expected a match for {created_since:?}/{created_until:?}"
+                    )
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_meinung_filter() {
+        let scenario = TestSetup::new("test_vorgang_get_meinung_filter").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        // the only Stellungnahme of the default vorgang has meinung 3
+        let cases = [
+            (Some(1u8), Some(3u8), true),
+            (Some(3), None, true),
+            (None, Some(2), false),
+            (Some(4), Some(10), false),
+        ];
+        for (min_meinung, max_meinung, found) in cases {
+            let query: Vec<_> = [("min_meinung", min_meinung), ("max_meinung", max_meinung)]
+                .into_iter()
+                .filter_map(|(name, value)| Some((name, value?)))
+                .collect();
+            let (response, _) = with_request(
+                listing(&query),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(
+                        found,
+                        "Expected no match for {min_meinung:?}..{max_meinung:?}"
+                    );
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(
+                        !found,
+                        "Expected a match for {min_meinung:?}..{max_meinung:?}"
+                    )
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        // an empty range is a bad request
+        let (response, _) = with_request(
+            listing(&[("min_meinung", 5), ("max_meinung", 2)]),
+            server.vorgang_get(
+                &Method::GET,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &models::VorgangGetHeaderParams {
+                    if_modified_since: None,
+                },
+                &query_params(),
+            ),
+        )
+        .await;
+        assert!(response.is_err_and(|e| e.is_bad_request()));
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_trojanergefahr() {
+        let scenario = TestSetup::new("test_vorgang_get_trojanergefahr").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        // the default station has trojanergefahr 2
+        for (trojanergefahr_min, found) in [(None, true), (Some(2u8), true), (Some(3), false)] {
+            let query: Vec<_> = trojanergefahr_min
+                .map(|t| ("trojanergefahr_min", t))
+                .into_iter()
+                .collect();
+            let (response, recorded) = with_request(
+                listing(&query),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(found, "Expected no match for {trojanergefahr_min:?}");
+                    assert_eq!(body[0].api_id, test_vorgang.api_id);
+                    let mut body = serde_json::to_value(body).unwrap();
+                    recorded.apply(&mut body);
+                    assert_eq!(body[0]["trojanergefahr_max"], 2);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a match for {trojanergefahr_min:?}")
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+
+        let (response, recorded) = with_request(
+            by_id(test_vorgang.api_id),
+            server.vorgang_get_by_id(
+                &Method::GET,
+                &Host("localhost".to_string()),
+                &CookieJar::new(),
+                &models::VorgangGetByIdHeaderParams {
+                    if_modified_since: None,
+                },
+                &models::VorgangGetByIdPathParams {
+                    vorgang_id: test_vorgang.api_id,
+                },
+            ),
+        )
+        .await;
+        match response.unwrap() {
+            VorgangGetByIdResponse::Status200_Success { body, .. } => {
+                let mut body = serde_json::to_value(body).unwrap();
+                recorded.apply(&mut body);
+                assert_eq!(body["trojanergefahr_max"], 2);
+            }
+            response => panic!("Unexpected response {response:?}"),
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_get_modified_since() {
+        let scenario = TestSetup::new("test_vorgang_get_modified_since").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let now = chrono::Utc::now();
+        for (modified_since, found) in [
+            (now - chrono::Duration::hours(1), true),
+            (now + chrono::Duration::hours(1), false),
+        ] {
+            let (response, recorded) = with_request(
+                listing(&[("modified_since", modified_since.to_rfc3339())]),
+                server.vorgang_get(
+                    &Method::GET,
+                    &Host("localhost".to_string()),
+                    &CookieJar::new(),
+                    &models::VorgangGetHeaderParams {
+                        if_modified_since: None,
+                    },
+                    &query_params(),
+                ),
+            )
+            .await;
+            match response.unwrap() {
+                VorgangGetResponse::Status200_Successful { body, .. } => {
+                    assert!(found, "Expected no match for {modified_since}");
+                    let mut body = serde_json::to_value(body).unwrap();
+                    recorded.apply(&mut body);
+                    let last_update: chrono::DateTime<chrono::Utc> =
+                        serde_json::from_value(body[0]["last_update"].clone()).unwrap();
+                    assert!(last_update > modified_since);
+                }
+                VorgangGetResponse::Status204_NoContent { .. } => {
+                    assert!(!found, "Expected a match for {modified_since}")
+                }
+                response => panic!("Unexpected response {response:?}"),
+            }
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_last_update_only_on_change() {
+        let scenario = TestSetup::new("test_vorgang_last_update_only_on_change").await;
+        let server = &scenario.server;
+        let test_vorgang = generate::default_vorgang();
+        let last_update = async || {
+            sqlx::query!(
+                "SELECT last_update FROM vorgang WHERE api_id = $1",
+                test_vorgang.api_id
+            )
+            .map(|r| r.last_update)
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap()
+        };
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let first = last_update().await;
+
+        // merging the same data again is no change
+        crate::db::merge::execute::run_integration(&test_vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        assert_eq!(last_update().await, first);
+
+        // a changed dokument of one of the stationen is
+        sqlx::query!(
+            "UPDATE dokument SET titel = 'Geändert'
+            WHERE id = (SELECT rsd.dok_id FROM rel_station_dokument rsd
+                JOIN station s ON s.id = rsd.stat_id
+                JOIN vorgang v ON v.id = s.vg_id
+                WHERE v.api_id = $1 LIMIT 1)",
+            test_vorgang.api_id
+        )
+        .execute(&server.sqlx_db)
+        .await
+        .unwrap();
+        assert!(last_update().await > first);
+        scenario.teardown().await;
+    }
+
     #[tokio::test]
     async fn test_vorgang_put_endpoint() {
         // Setup test server and database
@@ -744,7 +1659,7 @@ mod test_endpoints {
             );
         }
 
-        // 2. Handle ambiguous matches (conflict)
+        // 2. Handle ambiguous matches (queued for review)
         {
             let vg1 = generate::default_vorgang();
             let mut vg2 = vg1.clone();
@@ -796,8 +1711,14 @@ mod test_endpoints {
                 }
             );
 
-            let conflict_resp = server
-                .vorgang_put(
+            let request = Request::builder()
+                .method("PUT")
+                .uri("/api/v2/vorgang")
+                .body(Body::empty())
+                .unwrap();
+            let (conflict_resp, recorded) = with_request(
+                request,
+                server.vorgang_put(
                     &Method::PUT,
                     &host,
                     &cookies,
@@ -806,17 +1727,27 @@ mod test_endpoints {
                         x_scraper_id: Uuid::nil(),
                     },
                     &vg3,
-                )
-                .await
-                .unwrap();
+                ),
+            )
+            .await;
             assert_eq!(
-                conflict_resp,
-                VorgangPutResponse::Status409_Conflict {
+                conflict_resp.unwrap(),
+                VorgangPutResponse::Status201_Created {
                     x_rate_limit_limit: None,
                     x_rate_limit_remaining: None,
                     x_rate_limit_reset: None
                 }
             );
+            // answered with 202 by the middleware
+            assert_eq!(recorded.status(), Some(axum::http::StatusCode::ACCEPTED));
+            let pending = sqlx::query!(
+                "SELECT COUNT(1) as \"cnt!\" FROM review_queue WHERE status = 'pending'"
+            )
+            .map(|r| r.cnt)
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap();
+            assert_eq!(pending, 1);
         }
 
         // Cleanup
@@ -960,53 +1891,111 @@ mod test_failed_irl_scenarios {
     use tokio::io::{AsyncBufReadExt, BufReader};
     use uuid::Uuid;
 
-    async fn read_jsonl(path: &Path) -> Vec<models::Vorgang> {
-        let file = tokio::fs::File::open(path).await.unwrap();
+    const SCENARIO_DIR: &str = "tests/scenarios";
+
+    async fn read_jsonl(path: &Path) -> Result<Vec<models::Vorgang>, String> {
+        let file = tokio::fs::File::open(path)
+            .await
+            .map_err(|e| e.to_string())?;
         let buf_reader = BufReader::new(file);
         let mut records = vec![];
         let mut lines = buf_reader.lines();
-        while let Ok(Some(line)) = lines.next_line().await {
-            records.push(serde_json::from_str(&line).unwrap())
-        }
-        return records;
-    }
-
-    macro_rules! irl_scenario {
-        ($path:expr, $name:ident) => {
-            #[tokio::test]
-            async fn $name() {
-                let path = PathBuf::from($path);
-                let test_setup =
-                    TestSetup::new(&concat!("scenario_test_", stringify!($name))).await;
-                let host = Host("localhost".to_string());
-                let cookies = CookieJar::new();
-                let objects = read_jsonl(&path).await;
-                for obj in objects.iter() {
-                    let response = test_setup
-                        .server
-                        .vorgang_put(
-                            &Method::PUT,
-                            &host,
-                            &cookies,
-                            &(APIScope::KeyAdder, 1),
-                            &models::VorgangPutHeaderParams {
-                                x_scraper_id: Uuid::nil(),
-                            },
-                            obj,
-                        )
-                        .await
-                        .unwrap();
-                    assert!(matches!(
-                        response,
-                        VorgangPutResponse::Status201_Created { .. }
-                    ));
-                }
-                test_setup.teardown().await;
+        while let Some(line) = lines.next_line().await.map_err(|e| e.to_string())? {
+            let record = serde_json::from_str(&line)
+                .map_err(|e| format!("line {}: {e}", records.len() + 1))?;
+            records.push(record);
+        }
+        Ok(records)
+    }
+
+    /// reads every `*.jsonl` file in `dir`. Files that cannot be read are skipped with a warning,
+    /// only if none could be read all failures are returned as (file, error)
+    async fn load_dir(
+        dir: &Path,
+    ) -> Result<Vec<(PathBuf, Vec<models::Vorgang>)>, Vec<(String, String)>> {
+        let mut entries = tokio::fs::read_dir(dir)
+            .await
+            .map_err(|e| vec![(dir.display().to_string(), e.to_string())])?;
+        let mut paths = vec![];
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            let path = entry.path();
+            if path.extension().is_some_and(|e| e == "jsonl") {
+                paths.push(path);
             }
-        };
+        }
+        paths.sort();
+        let (mut scenarios, mut failures) = (vec![], vec![]);
+        for path in paths {
+            match read_jsonl(&path).await {
+                Ok(objects) => scenarios.push((path, objects)),
+                Err(e) => failures.push((path.display().to_string(), e)),
+            }
+        }
+        if scenarios.is_empty() && !failures.is_empty() {
+            return Err(failures);
+        }
+        for (file, error) in &failures {
+            tracing::warn!("Skipping scenario {file}: {error}");
+        }
+        Ok(scenarios)
+    }
+
+    #[tokio::test]
+    async fn test_load_dir() {
+        let dir = std::env::temp_dir().join(format!("ltzf-scenarios-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vorgang =
+            serde_json::to_string(&crate::utils::testing::generate::default_vorgang()).unwrap();
+        std::fs::write(dir.join("gut.jsonl"), format!("{vorgang}\n{vorgang}\n")).unwrap();
+        std::fs::write(dir.join("kaputt.jsonl"), "{\n").unwrap();
+        std::fs::write(dir.join("ignoriert.json"), "{\n").unwrap();
+
+        let scenarios = load_dir(&dir).await.unwrap();
+        assert_eq!(scenarios.len(), 1);
+        assert_eq!(scenarios[0].0, dir.join("gut.jsonl"));
+        assert_eq!(scenarios[0].1.len(), 2);
+
+        std::fs::remove_file(dir.join("gut.jsonl")).unwrap();
+        let failures = load_dir(&dir).await.unwrap_err();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].0.ends_with("kaputt.jsonl"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_irl_scenarios() {
+        let scenarios = load_dir(Path::new(SCENARIO_DIR))
+            .await
+            .unwrap_or_else(|failures| panic!("No scenario could be loaded: {failures:?}"));
+        assert!(!scenarios.is_empty(), "No scenarios in {SCENARIO_DIR}");
+        let host = Host("localhost".to_string());
+        let cookies = CookieJar::new();
+        for (path, objects) in scenarios {
+            let name = path.file_stem().unwrap().to_string_lossy();
+            // the setup keeps its name for the lifetime of the test run
+            let test_setup = TestSetup::new(format!("scenario_test_{name}").leak()).await;
+            for obj in objects.iter() {
+                let response = test_setup
+                    .server
+                    .vorgang_put(
+                        &Method::PUT,
+                        &host,
+                        &cookies,
+                        &(APIScope::KeyAdder, 1),
+                        &models::VorgangPutHeaderParams {
+                            x_scraper_id: Uuid::nil(),
+                        },
+                        obj,
+                    )
+                    .await
+                    .unwrap();
+                assert!(
+                    matches!(response, VorgangPutResponse::Status201_Created { .. }),
+                    "{}: {response:?}",
+                    path.display()
+                );
+            }
+            test_setup.teardown().await;
+        }
     }
-    irl_scenario!(
-        "tests/scenarios/on_onflict_upd_nodouble.jsonl",
-        test_scenario_on_onflict_upd_nodouble
-    );
 }
@@ -0,0 +1,125 @@
+//! Administration of the real-world boundaries of Wahlperioden,
+//! which are used to check incoming data for plausibility (see `db::validate`).
+//!
+//! - `GET /api/v2/wahlperioden` lists all known boundaries
+//! - `PUT /api/v2/wahlperioden/{parlament}/{wp}` creates or replaces the boundaries of a Wahlperiode
+//! - `DELETE /api/v2/wahlperioden/{parlament}/{wp}` removes the boundaries of a Wahlperiode
+
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
+use axum::routing::get;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use tracing::{info, instrument, warn};
+
+use crate::LTZFArc;
+use crate::api::auth::{APIScope, authorize_scopes};
+use crate::api::{HandlerResult, internal_error};
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WahlperiodeBoundary {
+    pub parlament: String,
+    pub wahlperiode: i32,
+    pub beginn: NaiveDate,
+    pub ende: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WahlperiodeBoundaryPut {
+    pub beginn: NaiveDate,
+    pub ende: Option<NaiveDate>,
+}
+
+pub(crate) fn router(server: LTZFArc) -> axum::Router {
+    axum::Router::new()
+        .route("/api/v2/wahlperioden", get(wahlperioden_get))
+        .route(
+            "/api/v2/wahlperioden/{parlament}/{wp}",
+            axum::routing::put(wahlperiode_put).delete(wahlperiode_delete),
+        )
+        .with_state(server)
+}
+
+/// WahlperiodenGet - GET /api/v2/wahlperioden
+#[instrument(skip_all)]
+async fn wahlperioden_get(State(server): State<LTZFArc>, headers: HeaderMap) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let result = sqlx::query!(
+        "SELECT p.value as parlament, wb.wp, wb.beginn, wb.ende FROM wahlperiode_boundaries wb
+        INNER JOIN parlament p ON p.id = wb.parl
+        ORDER BY p.value ASC, wb.wp ASC"
+    )
+    .map(|r| WahlperiodeBoundary {
+        parlament: r.parlament,
+        wahlperiode: r.wp,
+        beginn: r.beginn,
+        ende: r.ende,
+    })
+    .fetch_all(&server.sqlx_db)
+    .await
+    .map_err(internal_error)?;
+    info!("Successful retrieval");
+    Ok(Json(result).into_response())
+}
+
+/// WahlperiodePut - PUT /api/v2/wahlperioden/{parlament}/{wp}
+#[instrument(skip_all, fields(parlament=%parlament, wp=%wp))]
+async fn wahlperiode_put(
+    State(server): State<LTZFArc>,
+    Path((parlament, wp)): Path<(String, i32)>,
+    headers: HeaderMap,
+    Json(body): Json<WahlperiodeBoundaryPut>,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    if body.ende.is_some_and(|ende| ende < body.beginn) {
+        info!("Semantically bad request: ende lies before beginn");
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let n_upserted = sqlx::query!(
+        "INSERT INTO wahlperiode_boundaries(parl, wp, beginn, ende)
+        SELECT p.id, $2, $3, $4 FROM parlament p WHERE p.value = $1
+        ON CONFLICT(parl, wp) DO UPDATE SET beginn = EXCLUDED.beginn, ende = EXCLUDED.ende",
+        parlament,
+        wp,
+        body.beginn,
+        body.ende
+    )
+    .execute(&server.sqlx_db)
+    .await
+    .map_err(internal_error)?
+    .rows_affected();
+    if n_upserted == 0 {
+        warn!("Parlament `{}` is unknown", parlament);
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!(target: "obj", "Set Wahlperiode {} of {} to {:?}", wp, parlament, body);
+    Ok(StatusCode::CREATED.into_response())
+}
+
+/// WahlperiodeDelete - DELETE /api/v2/wahlperioden/{parlament}/{wp}
+#[instrument(skip_all, fields(parlament=%parlament, wp=%wp))]
+async fn wahlperiode_delete(
+    State(server): State<LTZFArc>,
+    Path((parlament, wp)): Path<(String, i32)>,
+    headers: HeaderMap,
+) -> HandlerResult {
+    authorize_scopes(&server, &headers, &[APIScope::Admin, APIScope::KeyAdder]).await?;
+    let n_deleted = sqlx::query!(
+        "DELETE FROM wahlperiode_boundaries wb USING parlament p
+        WHERE p.id = wb.parl AND p.value = $1 AND wb.wp = $2",
+        parlament,
+        wp
+    )
+    .execute(&server.sqlx_db)
+    .await
+    .map_err(internal_error)?
+    .rows_affected();
+    if n_deleted == 0 {
+        info!("Not Found");
+        return Err(StatusCode::NOT_FOUND);
+    }
+    info!(target: "obj", "Deleted Wahlperiode {} of {}", wp, parlament);
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
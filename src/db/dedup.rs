@@ -0,0 +1,286 @@
+//! Background job merging Dokumente that were inserted several times, e.g. because different
+//! scrapers submitted them in the context of different Stationen via PUT, which does not
+//! look for merge candidates. Dokumente with the same drucksnr and hash are duplicates,
+//! they are merged into the one referenced most often (the oldest one on a tie)
+//! and all references are moved to it.
+//!
+//! Every instance runs the job, an advisory lock makes sure only one of them deduplicates at a time.
+
+use tracing::{error, info, warn};
+
+use crate::db::merge::execute::execute_merge_dokument;
+use crate::db::retrieve;
+use crate::{LTZFArc, LTZFServer, Result};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DedupReport {
+    /// number of groups of duplicates found
+    pub groups: i32,
+    /// number of Dokumente merged into another one and deleted
+    pub merged: i32,
+}
+
+/// runs `dedup_dokumente` every `LTZF_DEDUP_INTERVAL_HOURS` hours, 0 disables the job
+pub fn spawn_dedup_job(server: LTZFArc) {
+    let hours = server.config.dedup_interval_hours;
+    if hours == 0 {
+        info!("Dokument deduplication is disabled");
+        return;
+    }
+    tokio::spawn(async move {
+        let mut tick_interval =
+            tokio::time::interval(std::time::Duration::from_secs(hours as u64 * 60 * 60));
+        loop {
+            tick_interval.tick().await;
+            match dedup_dokumente(&server).await {
+                Ok(Some(report)) => info!(
+                    "Dokument deduplication merged {} Dokumente in {} groups",
+                    report.merged, report.groups
+                ),
+                Ok(None) => info!("Dokument deduplication is already running on another instance"),
+                Err(e) => error!("Dokument deduplication failed: {e}"),
+            }
+        }
+    });
+}
+
+/// merges all groups of Dokumente with the same drucksnr and hash into their most referenced member
+/// and records the counts in `dedup_log`. Each group is merged in its own transaction.
+/// None if another run holds the lock.
+pub async fn dedup_dokumente(server: &LTZFServer) -> Result<Option<DedupReport>> {
+    // released with the transaction, also if the run fails
+    let mut lock = server.sqlx_db.begin().await?;
+    let locked = sqlx::query!(
+        "SELECT pg_try_advisory_xact_lock(hashtextextended('dedup:dokument', 0)) as \"locked!\""
+    )
+    .map(|r| r.locked)
+    .fetch_one(&mut *lock)
+    .await?;
+    if !locked {
+        return Ok(None);
+    }
+    // the member with the most references first, so UUID references of scrapers keep working for most of them
+    let groups = sqlx::query!(
+        "SELECT d.drucksnr, d.hash, ARRAY_AGG(d.id ORDER BY (
+            (SELECT COUNT(*) FROM rel_station_dokument r WHERE r.dok_id = d.id)
+            + (SELECT COUNT(*) FROM rel_station_stln r WHERE r.dok_id = d.id)
+            + (SELECT COUNT(*) FROM rel_sitzung_doks r WHERE r.did = d.id)
+            + (SELECT COUNT(*) FROM tops_doks r WHERE r.dok_id = d.id)
+        ) DESC, d.id ASC) as \"ids!\"
+        FROM dokument d WHERE d.drucksnr IS NOT NULL
+        GROUP BY d.drucksnr, d.hash HAVING COUNT(*) > 1"
+    )
+    .fetch_all(&server.sqlx_db)
+    .await?;
+    let mut report = DedupReport::default();
+    for group in &groups {
+        let ids = &group.ids;
+        let merged = crate::db::retry_on_conflict(|| async move {
+            let mut tx = crate::db::begin_merge(server).await?;
+            let mut merged = 0;
+            for duplicate in &ids[1..] {
+                if merge_duplicate(*duplicate, ids[0], &mut tx, server).await? {
+                    merged += 1;
+                }
+            }
+            tx.commit().await?;
+            Ok(merged)
+        })
+        .await?;
+        if merged == 0 {
+            continue;
+        }
+        info!(
+            "Merged {merged} duplicates of Dokument {:?} ({}) into {}",
+            group.drucksnr, group.hash, ids[0]
+        );
+        report.groups += 1;
+        report.merged += merged;
+    }
+    sqlx::query!(
+        "INSERT INTO dedup_log(groups, merged) VALUES ($1, $2)",
+        report.groups,
+        report.merged
+    )
+    .execute(&mut *lock)
+    .await?;
+    lock.commit().await?;
+    Ok(Some(report))
+}
+
+/// merges the Dokument `duplicate` into `keep`, moves all references and deletes `duplicate`.
+/// Returns false without any change if neither was ever touched by a scraper, since the merge needs one.
+async fn merge_duplicate(
+    duplicate: i32,
+    keep: i32,
+    tx: &mut sqlx::PgTransaction<'_>,
+    server: &LTZFServer,
+) -> Result<bool> {
+    let touch = sqlx::query!(
+        "SELECT scraper, collector_key FROM scraper_touched_dokument
+        WHERE dok_id = $1 OR dok_id = $2 ORDER BY time_stamp DESC LIMIT 1",
+        keep,
+        duplicate
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(touch) = touch else {
+        warn!(
+            "Neither Dokument {keep} nor {duplicate} was ever touched by a scraper, not merging them"
+        );
+        return Ok(false);
+    };
+    let model = retrieve::dokument_by_id(duplicate, tx).await?;
+    // references are copied, the originals are removed by the cascading delete below
+    sqlx::query!(
+        "INSERT INTO rel_station_dokument(stat_id, dok_id)
+        SELECT stat_id, $2 FROM rel_station_dokument WHERE dok_id = $1 ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO rel_station_stln(stat_id, dok_id)
+        SELECT stat_id, $2 FROM rel_station_stln WHERE dok_id = $1 ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO rel_sitzung_doks(sid, did)
+        SELECT sid, $2 FROM rel_sitzung_doks WHERE did = $1 ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO tops_doks(top_id, dok_id)
+        SELECT top_id, $2 FROM tops_doks WHERE dok_id = $1 ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO dokument_versions(time_stamp, dok_id, previous_id)
+        SELECT time_stamp,
+        CASE WHEN dok_id = $1 THEN $2 ELSE dok_id END,
+        CASE WHEN previous_id = $1 THEN $2 ELSE previous_id END
+        FROM dokument_versions
+        WHERE (dok_id = $1 AND previous_id <> $2) OR (previous_id = $1 AND dok_id <> $2)
+        ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "INSERT INTO dokument_raw_content(dok_id, mime, content, time_stamp)
+        SELECT $2, mime, content, time_stamp FROM dokument_raw_content WHERE dok_id = $1
+        ON CONFLICT DO NOTHING",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    // the scrapers that touched the duplicate touched the kept Dokument
+    sqlx::query!(
+        "INSERT INTO scraper_touched_dokument(time_stamp, dok_id, scraper, collector_key)
+        SELECT time_stamp, $2, scraper, collector_key FROM scraper_touched_dokument
+        WHERE dok_id = $1
+        ON CONFLICT(dok_id, scraper) DO UPDATE SET time_stamp = GREATEST(
+            scraper_touched_dokument.time_stamp, EXCLUDED.time_stamp)",
+        duplicate,
+        keep
+    )
+    .execute(&mut **tx)
+    .await?;
+    execute_merge_dokument(&model, keep, touch.scraper, touch.collector_key, tx, server).await?;
+    sqlx::query!("DELETE FROM dokument WHERE id = $1", duplicate)
+        .execute(&mut **tx)
+        .await?;
+    info!(target: "obj", "Merge(dedup) Dokument {:?} into Dokument {}", model.api_id, keep);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test_dedup {
+    use uuid::Uuid;
+
+    use super::dedup_dokumente;
+    use crate::db::insert::insert_dokument;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_dedup_dokumente() {
+        let scenario = TestSetup::new("test_dedup_dokumente").await;
+        let server = &scenario.server;
+        let original = generate::default_dokument();
+        let mut duplicate = original.clone();
+        duplicate.api_id = Some(Uuid::now_v7());
+        duplicate.titel = "Neuer Titel".to_string();
+        duplicate.hash = "anderer hash".to_string();
+        duplicate.drucksnr = Some("20/442".to_string());
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let keep = insert_dokument(original, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let duplicate = insert_dokument(duplicate, Uuid::now_v7(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        // as if submitted by a path that does not look for merge candidates
+        sqlx::query!(
+            "UPDATE dokument SET (hash, drucksnr) = (SELECT hash, drucksnr FROM dokument WHERE id = $1)
+            WHERE id = $2",
+            keep,
+            duplicate
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        // another instance holding the lock skips the run
+        let mut other = server.sqlx_db.begin().await.unwrap();
+        sqlx::query!("SELECT pg_advisory_xact_lock(hashtextextended('dedup:dokument', 0))")
+            .execute(&mut *other)
+            .await
+            .unwrap();
+        assert_eq!(dedup_dokumente(server).await.unwrap(), None);
+        other.rollback().await.unwrap();
+
+        let report = dedup_dokumente(server).await.unwrap().unwrap();
+        assert_eq!(report.groups, 1);
+        assert_eq!(report.merged, 1);
+        let remaining = sqlx::query!("SELECT id, titel FROM dokument")
+            .fetch_all(&server.sqlx_db)
+            .await
+            .unwrap();
+        assert_eq!(remaining.len(), 1);
+        assert_eq!(remaining[0].id, keep);
+        assert_eq!(remaining[0].titel, "Neuer Titel");
+        let touches = sqlx::query!(
+            "SELECT COUNT(*) as \"c!\" FROM scraper_touched_dokument WHERE dok_id = $1",
+            keep
+        )
+        .fetch_one(&server.sqlx_db)
+        .await
+        .unwrap()
+        .c;
+        assert_eq!(touches, 2);
+
+        let report = dedup_dokumente(server).await.unwrap().unwrap();
+        assert_eq!(report.groups, 0);
+        let runs = sqlx::query!("SELECT COUNT(*) as \"c!\" FROM dedup_log")
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap()
+            .c;
+        assert_eq!(runs, 2);
+        scenario.teardown().await;
+    }
+}
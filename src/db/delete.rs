@@ -31,19 +31,17 @@ pub async fn delete_sitzung_by_api_id(
     api_id: Uuid,
     server: &LTZFServer,
 ) -> Result<SitzungDeleteResponse> {
-    let thing = sqlx::query!("SELECT 1 as x FROM sitzung WHERE api_id = $1", api_id)
+    // a single statement, so a concurrent delete of the same Sitzung cannot both report success
+    let deleted = sqlx::query!("DELETE FROM sitzung WHERE api_id = $1 RETURNING id", api_id)
         .fetch_optional(&server.sqlx_db)
         .await?;
-    if thing.is_none() {
+    if deleted.is_none() {
         return Ok(SitzungDeleteResponse::Status404_NotFound {
             x_rate_limit_limit: None,
             x_rate_limit_remaining: None,
             x_rate_limit_reset: None,
         });
     }
-    sqlx::query!("DELETE FROM sitzung WHERE api_id = $1", api_id)
-        .execute(&server.sqlx_db)
-        .await?;
     Ok(SitzungDeleteResponse::Status204_NoContent {
         x_rate_limit_limit: None,
         x_rate_limit_remaining: None,
@@ -10,6 +10,85 @@ use openapi::models;
 use sqlx::PgTransaction;
 use uuid::Uuid;
 
+/// makes sure `value` is an entry of the enumeration table `table`.
+/// Unknown values are rejected, unless `LTZF_QUARANTINE_UNKNOWN_ENUMS` is set. Then the value is
+/// added and recorded in `quarantined_enum_values` until an admin approves or removes it.
+pub async fn ensure_enum_value<T: std::fmt::Debug + std::fmt::Display>(
+    value: &T,
+    table: &'static str,
+    tx: &mut PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<()> {
+    let value_str = value.to_string();
+    let quarantined = sqlx::query!(
+        "UPDATE quarantined_enum_values SET seen_count = seen_count + 1
+        WHERE table_name = $1 AND value = $2",
+        table,
+        value_str
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if quarantined {
+        return Ok(());
+    }
+    let known: bool = sqlx::query_scalar(&format!(
+        "SELECT EXISTS(SELECT 1 FROM {table} WHERE value = $1)"
+    ))
+    .bind(&value_str)
+    .fetch_one(&mut **tx)
+    .await?;
+    if known {
+        return Ok(());
+    }
+    if !srv.config.quarantine_unknown_enums {
+        return Err(crate::error::DataValidationError::InvalidEnumValue {
+            msg: format!("`{value_str}` is not a known entry of {table}"),
+        }
+        .into());
+    }
+    sqlx::query(&format!("INSERT INTO {table}(value) VALUES ($1)"))
+        .bind(&value_str)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO quarantined_enum_values(table_name, value) VALUES ($1, $2)",
+        table,
+        value_str
+    )
+    .execute(&mut **tx)
+    .await?;
+    tracing::warn!("Quarantined unknown entry `{value_str}` of {table}");
+    notify_new_enum_entry(value, vec![], srv)?;
+    Ok(())
+}
+
+/// api_ids are unique across Vorgang, Sitzung and Dokument (see `global_api_ids`).
+/// Returns `DuplicateApiId` if `api_id` already belongs to an object of another type.
+pub async fn ensure_api_id_free(
+    api_id: Uuid,
+    entity_type: &str,
+    tx: &mut PgTransaction<'_>,
+) -> Result<()> {
+    let existing = sqlx::query!(
+        "SELECT entity_type FROM global_api_ids WHERE api_id = $1 AND entity_type <> $2",
+        api_id,
+        entity_type
+    )
+    .map(|r| r.entity_type)
+    .fetch_optional(&mut **tx)
+    .await?;
+    if let Some(existing_type) = existing {
+        return Err(crate::error::DataValidationError::DuplicateApiId {
+            existing_type,
+            api_id,
+        }
+        .into());
+    }
+    Ok(())
+}
+
 /// Inserts a new Vorgang into the database.
 pub async fn insert_vorgang(
     vg: &models::Vorgang,
@@ -20,6 +99,11 @@ pub async fn insert_vorgang(
 ) -> Result<i32> {
     tracing::info!("Inserting Complete Vorgang into the database");
     let obj = "vorgang";
+    ensure_api_id_free(vg.api_id, "vorgang", tx).await?;
+    ensure_enum_value(&vg.typ, "vorgangstyp", tx, server).await?;
+    for ident in vg.ids.iter().flatten() {
+        ensure_enum_value(&ident.typ, "vg_ident_typ", tx, server).await?;
+    }
     // master insert
     let vg_id = sqlx::query!(
         "
@@ -39,10 +123,11 @@ pub async fn insert_vorgang(
     .await?;
 
     // insert links
+    let links = utils::links::normalise_links(vg.links.as_deref().unwrap_or_default());
     sqlx::query!(
         "INSERT INTO rel_vorgang_links(link, vg_id) 
     SELECT val, $2 FROM UNNEST($1::text[]) as val",
-        vg.links.as_ref().map(|x| &x[..]),
+        &links[..],
         vg_id
     )
     .execute(&mut **tx)
@@ -67,11 +152,15 @@ pub async fn insert_vorgang(
         .as_ref()
         .map(|x| x.iter().map(|el| el.id.clone()).collect::<Vec<_>>());
 
-    let identt_list = vg.ids.as_ref().map(|x| {
-        x.iter()
-            .map(|el| server.guard_ts(el.typ, vg.api_id, obj).unwrap())
-            .collect::<Vec<_>>()
-    });
+    let identt_list = vg
+        .ids
+        .as_ref()
+        .map(|x| {
+            x.iter()
+                .map(|el| server.guard_ts(el.typ, vg.api_id, obj))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
 
     sqlx::query!(
         "INSERT INTO rel_vorgang_ident (vg_id, typ, identifikator) 
@@ -85,6 +174,10 @@ pub async fn insert_vorgang(
     .execute(&mut **tx)
     .await?;
 
+    // insert amendment relations
+    let aendert = crate::api::aenderungen::submitted(vg.api_id)?;
+    insert_vorgang_aendert(vg_id, aendert.as_deref(), tx).await?;
+
     // insert stations
     let mut stat_ids = vec![];
     for stat in &vg.stationen {
@@ -199,6 +292,7 @@ pub async fn insert_station(
         return Ok(id.id);
     }
     let gr_id = insert_or_retrieve_gremium(&stat.gremium, tx, srv).await?;
+    ensure_enum_value(&stat.typ, "stationstyp", tx, srv).await?;
     let stat_id = sqlx::query!(
         "INSERT INTO station 
         (api_id, gr_id, link, titel, trojanergefahr, typ, 
@@ -275,6 +369,12 @@ pub async fn insert_dokument(
     tx: &mut sqlx::PgTransaction<'_>,
     srv: &LTZFServer,
 ) -> Result<i32> {
+    let mut dok = dok;
+    let inline_content =
+        utils::content::submitted_inline_content(&dok, srv.config.max_inline_content_mb).await?;
+    if let Some(content) = &inline_content {
+        dok.volltext = content.text.clone();
+    }
     let dapi = dok.api_id.unwrap_or(uuid::Uuid::now_v7());
     match dokument_merge_candidates(&dok, &mut **tx, srv).await? {
         super::merge::MatchState::ExactlyOne(id) => return Ok(id),
@@ -286,11 +386,22 @@ pub async fn insert_dokument(
             .map(|r| r.api_id)
             .fetch_all(&mut **tx)
             .await?;
-            utils::notify::notify_ambiguous_match(api_ids, &dok, "insert_dokument", srv)?;
+            utils::notify::notify_ambiguous_match(
+                api_ids,
+                &[],
+                "",
+                &dok,
+                "insert_dokument",
+                tx,
+                srv,
+            )
+            .await?;
         }
         super::merge::MatchState::NoMatch => {}
     }
     let obj = "Dokument";
+    ensure_api_id_free(dapi, "dokument", tx).await?;
+    ensure_enum_value(&dok.typ, "dokumententyp", tx, srv).await?;
     let did = sqlx::query!(
         "INSERT INTO dokument(api_id, drucksnr, typ, titel, kurztitel, vorwort, 
         volltext, zusammenfassung, zp_lastmod, link, hash, zp_referenz, zp_created, meinung)
@@ -316,6 +427,9 @@ pub async fn insert_dokument(
     .map(|r| r.id)
     .fetch_one(&mut **tx)
     .await?;
+    if let Some(content) = &inline_content {
+        insert_dok_raw_content(did, content, tx).await?;
+    }
     // Schlagworte
     insert_dok_sw(did, dok.schlagworte.unwrap_or_default(), tx).await?;
 
@@ -372,14 +486,16 @@ pub async fn insert_sitzung(
     srv: &LTZFServer,
 ) -> Result<i32> {
     let api_id = ass.api_id.unwrap_or(uuid::Uuid::now_v7());
+    ensure_api_id_free(api_id, "sitzung", tx).await?;
 
     // gremium insert or fetch
     let gr_id = insert_or_retrieve_gremium(&ass.gremium, tx, srv).await?;
     // master insert
     let id = sqlx::query!(
         "INSERT INTO sitzung 
-        (api_id, termin, public, gr_id, link, nummer, titel)
-    VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id",
+        (api_id, termin, public, gr_id, link, nummer, titel, p_id)
+    VALUES ($1, $2, $3, $4, $5, $6, $7, (SELECT parl FROM gremium WHERE id = $4))
+    ON CONFLICT ON CONSTRAINT sitzung_gremium_termin_nummer DO NOTHING RETURNING id",
         api_id,
         ass.termin,
         ass.public,
@@ -389,8 +505,46 @@ pub async fn insert_sitzung(
         ass.titel
     )
     .map(|r| r.id)
-    .fetch_one(&mut **tx)
+    .fetch_optional(&mut **tx)
     .await?;
+    let Some(id) = id else {
+        // the same gremium, termin and nummer: this sitzung is already known, possibly under another api_id
+        let existing = sqlx::query!(
+            "SELECT id, api_id FROM sitzung WHERE gr_id = $1 AND termin = $2 AND nummer = $3",
+            gr_id,
+            ass.termin,
+            ass.nummer as i32
+        )
+        .fetch_one(&mut **tx)
+        .await?;
+        if existing.api_id != api_id {
+            tracing::warn!(
+                "Sitzung {} is already known as {} (same gremium, termin and nummer), merging",
+                api_id,
+                existing.api_id
+            );
+            utils::notify::notify_ambiguous_match(
+                vec![existing.api_id],
+                &[],
+                "",
+                ass,
+                "insert_sitzung",
+                tx,
+                srv,
+            )
+            .await?;
+        }
+        crate::db::merge::sitzung::execute_merge_sitzung(
+            ass,
+            existing.id,
+            scraper_id,
+            collector_key,
+            tx,
+            srv,
+        )
+        .await?;
+        return Ok(existing.id);
+    };
     // insert tops
     for top in &ass.tops {
         insert_top(id, top, scraper_id, collector_key, tx, srv).await?;
@@ -442,14 +596,20 @@ pub async fn insert_sitzung(
     if let Some(docs) = &ass.dokumente {
         let mut dok_ids = vec![];
         for d in docs {
-            if let models::StationDokumenteInner::Dokument(d) = d {
-                let id = insert_dokument(d.clone(), scraper_id, collector_key, tx, srv).await?;
-                dok_ids.push(id);
-            }
+            let id = match d {
+                models::StationDokumenteInner::Dokument(d) => {
+                    insert_dokument(d.clone(), scraper_id, collector_key, tx, srv).await?
+                }
+                models::StationDokumenteInner::String(api_id) => {
+                    dokument_by_reference(api_id, tx).await?
+                }
+            };
+            dok_ids.push(id);
         }
         sqlx::query!(
             "INSERT INTO rel_sitzung_doks(sid, did)
-                SELECT $1, dokid from UNNEST($2::int4[]) as dokid",
+                SELECT $1, dokid from UNNEST($2::int4[]) as dokid
+                ON CONFLICT DO NOTHING",
             id,
             &dok_ids[..]
         )
@@ -497,6 +657,17 @@ pub async fn insert_top(
     .execute(&mut **tx)
     .await?;
 
+    // vorgänge, unknown ones are skipped
+    sqlx::query!(
+        "INSERT INTO rel_top_vorgang(top_id, vg_id)
+    SELECT $1, v.id FROM vorgang v WHERE v.api_id = ANY($2::uuid[])
+    ON CONFLICT DO NOTHING",
+        tid,
+        top.vorgang_id.as_ref().map(|v| &v[..]).unwrap_or(&[])
+    )
+    .execute(&mut **tx)
+    .await?;
+
     Ok(tid)
 }
 
@@ -522,12 +693,13 @@ pub async fn insert_or_retrieve_gremium(
     }
 
     let similarity = sqlx::query!(
-        "SELECT g.wp,g.name, SIMILARITY(name, $1) as sim, g.link
+        "SELECT g.wp,g.name, ltzf_similarity(name, $1, $3) as sim, g.link
     FROM gremium g, parlament p
-    WHERE SIMILARITY(name, $1) > 0.66 AND 
+    WHERE ltzf_similarity(name, $1, $3) > 0.66 AND 
     g.parl = p.id AND p.value = $2",
         gr.name,
-        gr.parlament.to_string()
+        gr.parlament.to_string(),
+        srv.config.similarity_function.as_sql()
     )
     .map(|r| {
         (
@@ -575,7 +747,20 @@ pub async fn insert_or_retrieve_autor(
     .map(|r| r.id)
     .fetch_optional(&mut **tx)
     .await?;
+    let fraktion = match submitted_fraktion(at) {
+        Some(f) => Some(insert_or_retrieve_fraktion(&f, tx).await?),
+        None => None,
+    };
     if let Some(eid) = eid {
+        if fraktion.is_some() {
+            sqlx::query!(
+                "UPDATE autor SET fraktion = $2 WHERE id = $1",
+                eid,
+                fraktion
+            )
+            .execute(&mut **tx)
+            .await?;
+        }
         return Ok(eid);
     }
 
@@ -583,9 +768,9 @@ pub async fn insert_or_retrieve_autor(
         "
         WITH similarities AS (
             SELECT id, 
-            SIMILARITY(person, $1) as p, 
-            SIMILARITY(organisation, $2) as o, 
-            SIMILARITY(fachgebiet, $3) as f
+            ltzf_similarity(person, $1, $4) as p, 
+            ltzf_similarity(organisation, $2, $4) as o, 
+            ltzf_similarity(fachgebiet, $3, $4) as f
             FROM autor a
         )
         SELECT a.*, 
@@ -602,7 +787,8 @@ pub async fn insert_or_retrieve_autor(
         (($3 IS NULL AND a.fachgebiet IS NULL) OR s.f > 0.66)",
         at.person,
         at.organisation,
-        at.fachgebiet
+        at.fachgebiet,
+        srv.config.similarity_function.as_sql()
     )
     .map(|r| {
         (
@@ -619,12 +805,13 @@ pub async fn insert_or_retrieve_autor(
     .await?;
     notify_new_enum_entry(at, similarity, srv)?;
     let id = sqlx::query!(
-        "INSERT INTO autor(person, organisation, lobbyregister, fachgebiet) 
-        VALUES ($1, $2, $3, $4) RETURNING autor.id",
+        "INSERT INTO autor(person, organisation, lobbyregister, fachgebiet, fraktion) 
+        VALUES ($1, $2, $3, $4, $5) RETURNING autor.id",
         at.person,
         at.organisation,
         at.lobbyregister,
         at.fachgebiet,
+        fraktion,
     )
     .map(|r| r.id)
     .fetch_one(&mut **tx)
@@ -632,6 +819,35 @@ pub async fn insert_or_retrieve_autor(
     Ok(id)
 }
 
+/// the Fraktion submitted with the Autor. It is not in the generated model,
+/// so it is read from the submitted body, see `utils::spec_ext`
+pub fn submitted_fraktion(at: &models::Autor) -> Option<String> {
+    let person = at.person.as_deref();
+    crate::utils::spec_ext::submitted("fraktion", |a| {
+        a.get("organisation").and_then(|o| o.as_str()) == Some(at.organisation.as_str())
+            && a.get("person").and_then(|p| p.as_str()) == person
+    })
+    .and_then(|f| f.as_str().map(|f| f.trim().to_string()))
+    .filter(|f| !f.is_empty())
+}
+
+/// Fraktionen are not known in advance, new ones are added silently
+pub async fn insert_or_retrieve_fraktion(
+    fraktion: &str,
+    tx: &mut PgTransaction<'_>,
+) -> Result<i32> {
+    Ok(sqlx::query!(
+        "WITH ins AS (
+            INSERT INTO fraktion(value) VALUES ($1) ON CONFLICT DO NOTHING RETURNING id
+        )
+        SELECT id as \"id!\" FROM ins UNION ALL SELECT id FROM fraktion WHERE value = $1",
+        fraktion.trim()
+    )
+    .map(|r| r.id)
+    .fetch_one(&mut **tx)
+    .await?)
+}
+
 pub async fn insert_or_retrieve_dok(
     dr: &models::StationDokumenteInner,
     scraper_id: Uuid,
@@ -643,16 +859,58 @@ pub async fn insert_or_retrieve_dok(
         models::StationDokumenteInner::Dokument(dok) => {
             Ok(insert_dokument(dok.clone(), scraper_id, collector_key, tx, srv).await?)
         }
-        models::StationDokumenteInner::String(dapi_id) => {
-            let api_id = uuid::Uuid::from_str(dapi_id.as_str())?;
-            Ok(
-                sqlx::query!("SELECT id FROM dokument WHERE api_id = $1", api_id)
-                    .map(|r| r.id)
-                    .fetch_one(&mut **tx)
-                    .await?,
-            )
+        models::StationDokumenteInner::String(dapi_id) => dokument_by_reference(dapi_id, tx).await,
+    }
+}
+
+/// resolves a dokument referenced by its api_id instead of a full object
+pub async fn dokument_by_reference(api_id: &str, tx: &mut PgTransaction<'_>) -> Result<i32> {
+    let api_id = uuid::Uuid::from_str(api_id)?;
+    let id = sqlx::query!("SELECT id FROM dokument WHERE api_id = $1", api_id)
+        .map(|r| r.id)
+        .fetch_optional(&mut **tx)
+        .await?;
+    id.ok_or_else(|| {
+        crate::error::DataValidationError::IncompleteDataSupplied {
+            input: format!(
+                "Supplied uuid `{api_id}` as document id without a body, but no such ID is in the database."
+            ),
         }
+        .into()
+    })
+}
+/// links the Vorgang `vg_id` to the Vorgänge it amends. References to unknown Vorgänge are skipped,
+/// known relations keep their article reference and date unless new ones are supplied.
+pub async fn insert_vorgang_aendert(
+    vg_id: i32,
+    aendert: Option<&[crate::api::aenderungen::Aenderung]>,
+    tx: &mut PgTransaction<'_>,
+) -> Result<()> {
+    let Some(aendert) = aendert else {
+        return Ok(());
+    };
+    let (mut api_ids, mut article_refs, mut dates) = (vec![], vec![], vec![]);
+    for a in aendert {
+        api_ids.push(a.api_id);
+        article_refs.push(a.article_ref.clone());
+        dates.push(a.in_force_since);
     }
+    sqlx::query!(
+        "INSERT INTO rel_vorgang_aendert(amending_vg_id, amended_vg_id, article_ref, in_force_since)
+        SELECT $1, v.id, ae.article_ref, ae.in_force_since
+        FROM UNNEST($2::uuid[], $3::text[], $4::date[]) AS ae(api_id, article_ref, in_force_since)
+        INNER JOIN vorgang v ON v.api_id = ae.api_id
+        ON CONFLICT (amending_vg_id, amended_vg_id) DO UPDATE SET
+        article_ref = COALESCE(EXCLUDED.article_ref, rel_vorgang_aendert.article_ref),
+        in_force_since = COALESCE(EXCLUDED.in_force_since, rel_vorgang_aendert.in_force_since)",
+        vg_id,
+        &api_ids[..],
+        &article_refs[..] as &[Option<String>],
+        &dates[..] as &[Option<chrono::NaiveDate>]
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
 }
 pub async fn insert_station_sw(
     sid: i32,
@@ -710,3 +968,24 @@ pub async fn insert_dok_sw(did: i32, sw: Vec<String>, tx: &mut PgTransaction<'_>
     .await?;
     Ok(())
 }
+
+/// archives the raw bytes of inline submitted document content.
+/// Newer content replaces the archived content of the same document.
+pub async fn insert_dok_raw_content(
+    did: i32,
+    content: &utils::content::InlineContent,
+    tx: &mut PgTransaction<'_>,
+) -> Result<()> {
+    sqlx::query!(
+        "INSERT INTO dokument_raw_content(dok_id, mime, content)
+        VALUES ($1, $2, $3)
+        ON CONFLICT(dok_id) DO UPDATE SET 
+        mime = EXCLUDED.mime, content = EXCLUDED.content, time_stamp = NOW()",
+        did,
+        content.mime,
+        &content.raw[..]
+    )
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
@@ -4,6 +4,28 @@ use crate::db::merge::MatchState;
 use openapi::models;
 use uuid::Uuid;
 
+/// why a Vorgang was considered a merge candidate, see `vorgang_merge_candidates`
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub struct CandidateReason {
+    pub api_id: Uuid,
+    pub api_id_match: bool,
+    pub wp_type_ident_match: bool,
+    /// title similarity according to the configured similarity function
+    pub similarity_score: f32,
+}
+
+/// identifiers and their types of the model, as used for matching
+fn vorgang_idents(model: &models::Vorgang, srv: &LTZFServer) -> Result<(Vec<String>, Vec<String>)> {
+    let obj = "merged Vorgang";
+    let ids = model.ids.as_deref().unwrap_or_default();
+    Ok((
+        ids.iter().map(|x| x.id.clone()).collect(),
+        ids.iter()
+            .map(|x| srv.guard_ts(x.typ, model.api_id, obj))
+            .collect::<Result<_>>()?,
+    ))
+}
+
 /// this function determines what means "matching enough".
 /// 1. wenn api_id matcht
 /// 2. wenn wp, typ und mindestens ein identifikator matchen
@@ -13,20 +35,7 @@ pub async fn vorgang_merge_candidates(
     srv: &LTZFServer,
 ) -> Result<MatchState<i32>> {
     let obj = "merged Vorgang";
-    let ident_t: Vec<_> = model
-        .ids
-        .as_ref()
-        .unwrap_or(&vec![])
-        .iter()
-        .map(|x| x.id.clone())
-        .collect();
-    let identt_t: Vec<_> = model
-        .ids
-        .as_ref()
-        .unwrap_or(&vec![])
-        .iter()
-        .map(|x| srv.guard_ts(x.typ, model.api_id, obj).unwrap())
-        .collect();
+    let (ident_t, identt_t) = vorgang_idents(model, srv)?;
 
     let result = sqlx::query!(
         "WITH db_id_table AS (
@@ -79,6 +88,108 @@ SELECT DISTINCT(vorgang.id), vorgang.api_id FROM vorgang -- gib vorgänge, bei d
     })
 }
 
+/// maximum length of the diff in ambiguous match notifications
+pub const CANDIDATE_DIFF_LIMIT: usize = 5000;
+
+/// the fields an administrator compares to decide between merge candidates, one per line
+fn vorgang_key_fields(vorgang: &models::Vorgang) -> String {
+    let mut fields = format!("titel: {}\n", vorgang.titel);
+    for (i, station) in vorgang.stationen.iter().enumerate() {
+        fields.push_str(&format!("stationen[{i}].typ: {}\n", station.typ));
+    }
+    for (i, ident) in vorgang.ids.iter().flatten().enumerate() {
+        fields.push_str(&format!("ids[{i}].id: {}\n", ident.id));
+    }
+    fields
+}
+
+/// unified diff of the key fields of each stored candidate against the incoming Vorgang,
+/// cut off after `CANDIDATE_DIFF_LIMIT` characters
+pub fn candidate_diff(incoming: &models::Vorgang, candidates: &[models::Vorgang]) -> String {
+    let new = vorgang_key_fields(incoming);
+    let mut diff = String::new();
+    for candidate in candidates {
+        let old = vorgang_key_fields(candidate);
+        let stored = candidate.api_id.to_string();
+        let submitted = format!("eingehend {}", incoming.api_id);
+        diff.push_str(
+            &similar::TextDiff::from_lines(&old, &new)
+                .unified_diff()
+                .header(&stored, &submitted)
+                .to_string(),
+        );
+    }
+    if diff.chars().count() > CANDIDATE_DIFF_LIMIT {
+        diff = diff.chars().take(CANDIDATE_DIFF_LIMIT).collect();
+        diff.push_str("\n[...]");
+    }
+    diff
+}
+
+/// `candidate_diff` against the stored versions of the candidates
+pub async fn vorgang_candidate_diff(
+    model: &models::Vorgang,
+    candidates: &[i32],
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<String> {
+    let mut stored = Vec::with_capacity(candidates.len());
+    for id in candidates {
+        stored.push(crate::db::retrieve::vorgang_by_id(*id, tx).await?);
+    }
+    Ok(candidate_diff(model, &stored))
+}
+
+/// annotates the candidates found by `vorgang_merge_candidates` with the rule that matched them
+pub async fn vorgang_candidate_reasons(
+    model: &models::Vorgang,
+    candidates: &[i32],
+    executor: impl sqlx::PgExecutor<'_>,
+    srv: &LTZFServer,
+) -> Result<Vec<CandidateReason>> {
+    let obj = "merged Vorgang";
+    let (ident_t, identt_t) = vorgang_idents(model, srv)?;
+    let reasons = sqlx::query!(
+        "WITH candidates AS (
+            SELECT v.id, v.api_id, v.titel, v.wahlperiode, vt.value as typ FROM vorgang v
+            INNER JOIN vorgangstyp vt ON vt.id = v.typ
+            WHERE v.id = ANY($1::int4[])),
+        api_id_matches AS (
+            SELECT c.id, 'api_id' as match_reason FROM candidates c WHERE c.api_id = $2),
+        ident_matches AS (
+            SELECT c.id, 'wp_type_ident' as match_reason FROM candidates c
+            WHERE c.wahlperiode = $5 AND c.typ = $6 AND EXISTS (
+                SELECT 1 FROM UNNEST($3::text[], $4::text[]) as eingabe(ident, typ)
+                INNER JOIN rel_vorgang_ident rvi ON rvi.identifikator = eingabe.ident
+                INNER JOIN vg_ident_typ vit ON vit.id = rvi.typ
+                WHERE rvi.vg_id = c.id AND vit.value = eingabe.typ)),
+        reasons AS (
+            SELECT * FROM api_id_matches UNION ALL SELECT * FROM ident_matches)
+        SELECT c.api_id,
+            EXISTS(SELECT 1 FROM reasons r WHERE r.id = c.id AND r.match_reason = 'api_id') as \"api_id_match!\",
+            EXISTS(SELECT 1 FROM reasons r WHERE r.id = c.id AND r.match_reason = 'wp_type_ident') as \"wp_type_ident_match!\",
+            ltzf_similarity(c.titel, $7, $8) as sim
+        FROM candidates c
+        ORDER BY c.api_id",
+        candidates,
+        model.api_id,
+        &ident_t[..],
+        &identt_t[..],
+        model.wahlperiode as i32,
+        srv.guard_ts(model.typ, model.api_id, obj)?,
+        model.titel,
+        srv.config.similarity_function.as_sql()
+    )
+    .map(|r| CandidateReason {
+        api_id: r.api_id,
+        api_id_match: r.api_id_match,
+        wp_type_ident_match: r.wp_type_ident_match,
+        similarity_score: r.sim.unwrap_or(0.),
+    })
+    .fetch_all(executor)
+    .await?;
+    Ok(reasons)
+}
+
 /// bei gleichem Vorgang => Vorraussetzung
 /// 1. wenn die api_id matcht
 /// 2. wenn vorgang, typ und gremium matchen und mindestens ein Dokument gleich ist
@@ -372,3 +483,28 @@ mod candid_test {
         setup.teardown().await;
     }
 }
+
+#[cfg(test)]
+mod test_candidate_diff {
+    use super::{CANDIDATE_DIFF_LIMIT, candidate_diff};
+    use crate::utils::testing::generate;
+
+    #[test]
+    fn test_candidate_diff() {
+        let stored = generate::default_vorgang();
+        let mut incoming = stored.clone();
+        incoming.titel = "Neuer Titel".to_string();
+        let diff = candidate_diff(&incoming, std::slice::from_ref(&stored));
+        assert!(diff.contains(&format!("--- {}", stored.api_id)));
+        assert!(diff.contains(&format!("-titel: {}", stored.titel)));
+        assert!(diff.contains("+titel: Neuer Titel"));
+        assert!(!diff.contains("-stationen[0].typ"));
+
+        assert!(candidate_diff(&stored, std::slice::from_ref(&stored)).is_empty());
+
+        incoming.titel = "x".repeat(2 * CANDIDATE_DIFF_LIMIT);
+        let diff = candidate_diff(&incoming, &[stored]);
+        assert!(diff.ends_with("[...]"));
+        assert!(diff.chars().count() <= CANDIDATE_DIFF_LIMIT + 6);
+    }
+}
@@ -1,7 +1,9 @@
 use super::MatchState;
 use crate::db::KeyIndex;
 use crate::db::insert::{self, insert_or_retrieve_autor};
-use crate::error::DataValidationError;
+use crate::db::validate::validate_vorgang;
+use crate::error::{DataValidationError, LTZFError};
+use crate::utils::content::submitted_inline_content;
 use crate::utils::notify::notify_ambiguous_match;
 /// Handles merging of two datasets.
 /// vorgang, station and dokument are mergeable, meaning their data is not atomic.
@@ -12,7 +14,7 @@ use crate::utils::notify::notify_ambiguous_match;
 ///     - if it is mergeable and one merge candidate found, merge
 ///     - if it is not mergeable and has a match in the existing set, the existing element takes precedence and is not replaced
 ///     - if it is not mergeable and has no match it is added to the set.
-use crate::{LTZFServer, Result};
+use crate::{LTZFServer, MergeMode, Result};
 use openapi::models;
 use tracing::{debug, info, warn};
 use uuid::Uuid;
@@ -31,12 +33,19 @@ pub async fn execute_merge_dokument(
     srv: &LTZFServer,
 ) -> Result<()> {
     let db_id = candidate;
-    // master update
+    let inline_content = submitted_inline_content(model, srv.config.max_inline_content_mb).await?;
+    let volltext = inline_content
+        .as_ref()
+        .map(|c| c.text.clone())
+        .unwrap_or(model.volltext.clone());
+    // master update, in aggressive mode missing optional fields clear the stored ones
     sqlx::query!(
         "UPDATE dokument SET
         drucksnr = $2, titel =$3,
-        kurztitel = COALESCE($4, kurztitel), vorwort=COALESCE($5, vorwort),
-        volltext=COALESCE($6, volltext), zusammenfassung=COALESCE($7, zusammenfassung),
+        kurztitel = CASE WHEN $12 THEN $4 ELSE COALESCE($4, kurztitel) END,
+        vorwort = CASE WHEN $12 THEN $5 ELSE COALESCE($5, vorwort) END,
+        volltext = CASE WHEN $12 THEN $6 ELSE COALESCE($6, volltext) END,
+        zusammenfassung = CASE WHEN $12 THEN $7 ELSE COALESCE($7, zusammenfassung) END,
         zp_lastmod=$8, link=$9, hash=$10, meinung=$11
         WHERE dokument.id = $1
         ",
@@ -45,15 +54,19 @@ pub async fn execute_merge_dokument(
         model.titel,
         model.kurztitel,
         model.vorwort,
-        model.volltext,
+        volltext,
         model.zusammenfassung,
         model.zp_modifiziert,
         model.link,
         model.hash,
-        model.meinung.map(|x| x as i32)
+        model.meinung.map(|x| x as i32),
+        srv.config.merge_mode == MergeMode::Aggressive
     )
     .execute(&mut **tx)
     .await?;
+    if let Some(content) = &inline_content {
+        insert::insert_dok_raw_content(db_id, content, tx).await?;
+    }
     // schlagworte::UNION
     insert::insert_dok_sw(db_id, model.schlagworte.clone().unwrap_or_default(), tx).await?;
     // autoren::UNION
@@ -154,7 +167,16 @@ pub async fn insert_or_merge_dok(
                     .map(|r| r.api_id)
                     .fetch_all(&mut **tx)
                     .await?;
-                    notify_ambiguous_match(api_ids, &dok, "execute merge station.dokumente", srv)?;
+                    notify_ambiguous_match(
+                        api_ids,
+                        &[],
+                        "",
+                        &dok,
+                        "execute merge station.dokumente",
+                        tx,
+                        srv,
+                    )
+                    .await?;
                     Err(DataValidationError::AmbiguousMatch {
                         message: "Ambiguous document match(station), see notification".to_string(),
                     }
@@ -174,20 +196,64 @@ pub async fn execute_merge_station(
     srv: &LTZFServer,
 ) -> Result<()> {
     let db_id = candidate;
-    let obj = "merge station";
+    // fetched before any modification, so every error below can name the station
     let sapi = sqlx::query!("SELECT api_id FROM station WHERE id = $1", db_id)
         .map(|x| x.api_id)
-        .fetch_one(&mut **tx)
+        .fetch_optional(&mut **tx)
         .await?;
+    let Some(sapi) = sapi else {
+        return Err(DataValidationError::IncompleteDataSupplied {
+            input: format!("station with id {db_id}"),
+        }
+        .into());
+    };
+    merge_station_data(model, db_id, sapi, scraper_id, collector_key, tx, srv)
+        .await
+        .map_err(|e| with_api_id(e, "station", sapi))
+}
+
+/// attaches the api_id of the merged object to constraint violations,
+/// other errors are kept as they are (e.g. for the retry on serialization failures) and logged with it
+fn with_api_id(error: LTZFError, object: &str, api_id: Uuid) -> LTZFError {
+    match error {
+        LTZFError::DatabaseConstraint {
+            code,
+            constraint,
+            table,
+            detail,
+        } => LTZFError::DatabaseConstraint {
+            code,
+            constraint,
+            table,
+            detail: Some(format!("{object} {api_id}: {}", detail.unwrap_or_default())),
+        },
+        e => {
+            warn!("Merging {object} {api_id} failed: {e}");
+            e
+        }
+    }
+}
+
+async fn merge_station_data(
+    model: &models::Station,
+    db_id: i32,
+    sapi: Uuid,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<()> {
+    let obj = "merge station";
     // pre-master updates
     let gr_id = insert::insert_or_retrieve_gremium(&model.gremium, tx, srv).await?;
-    // master update
+    insert::ensure_enum_value(&model.typ, "stationstyp", tx, srv).await?;
+    // master update, zp_modifiziert is moved by a trigger if the scraper does not supply it
     sqlx::query!(
         "UPDATE station SET 
         gr_id = COALESCE($2, gr_id),
         typ = (SELECT id FROM stationstyp WHERE value = $3),
         titel = COALESCE($4, titel),
-        zp_start = $5, zp_modifiziert = COALESCE($6, NOW()),
+        zp_start = $5, zp_modifiziert = COALESCE($6, zp_modifiziert),
         trojanergefahr = COALESCE($7, trojanergefahr),
         link = COALESCE($8, link),
         gremium_isff = $9
@@ -229,15 +295,17 @@ pub async fn execute_merge_station(
         if let Some(id) = insert_or_merge_dok(dok, scraper_id, collector_key, tx, srv).await? {
             insert_ids.push(id);
         }
-        sqlx::query!(
-            "INSERT INTO rel_station_dokument(stat_id, dok_id) 
-        SELECT $1, did FROM UNNEST($2::int4[]) as did",
-            db_id,
-            &insert_ids[..]
-        )
-        .execute(&mut **tx)
-        .await?;
     }
+    // uuid references may point to documents that are already associated
+    sqlx::query!(
+        "INSERT INTO rel_station_dokument(stat_id, dok_id) 
+        SELECT $1, did FROM UNNEST($2::int4[]) as did
+        ON CONFLICT DO NOTHING",
+        db_id,
+        &insert_ids[..]
+    )
+    .execute(&mut **tx)
+    .await?;
 
     // stellungnahmen
     let mut insert_ids = vec![];
@@ -245,15 +313,16 @@ pub async fn execute_merge_station(
         if let Some(id) = insert_or_merge_dok(stln, scraper_id, collector_key, tx, srv).await? {
             insert_ids.push(id);
         }
-        sqlx::query!(
-            "INSERT INTO rel_station_stln(stat_id, dok_id) 
-            SELECT $1, did FROM UNNEST($2::int4[]) as did",
-            db_id,
-            &insert_ids[..]
-        )
-        .execute(&mut **tx)
-        .await?;
     }
+    sqlx::query!(
+        "INSERT INTO rel_station_stln(stat_id, dok_id) 
+        SELECT $1, did FROM UNNEST($2::int4[]) as did
+        ON CONFLICT DO NOTHING",
+        db_id,
+        &insert_ids[..]
+    )
+    .execute(&mut **tx)
+    .await?;
     sqlx::query!(
         "INSERT INTO scraper_touched_station(stat_id, collector_key, scraper) 
         VALUES ($1, $2, $3) ON CONFLICT(stat_id, scraper) DO UPDATE SET time_stamp=NOW()",
@@ -296,6 +365,10 @@ pub async fn execute_merge_vorgang(
     let db_id = candidate;
     let obj = "Vorgang";
     let vapi = model.api_id;
+    insert::ensure_enum_value(&model.typ, "vorgangstyp", tx, srv).await?;
+    for ident in model.ids.iter().flatten() {
+        insert::ensure_enum_value(&ident.typ, "vg_ident_typ", tx, srv).await?;
+    }
     // master insert
     sqlx::query!(
         "UPDATE vorgang SET
@@ -327,7 +400,7 @@ pub async fn execute_merge_vorgang(
     .execute(&mut **tx)
     .await?;
     // links
-    let links = model.links.clone().unwrap_or_default();
+    let links = crate::utils::links::normalise_links(model.links.as_deref().unwrap_or_default());
     sqlx::query!(
         "INSERT INTO rel_vorgang_links (vg_id, link)
         SELECT $1, blub FROM UNNEST($2::text[]) as blub
@@ -343,11 +416,15 @@ pub async fn execute_merge_vorgang(
         .as_ref()
         .map(|x| x.iter().map(|el| el.id.clone()).collect::<Vec<_>>());
 
-    let identt_list = model.ids.as_ref().map(|x| {
-        x.iter()
-            .map(|el| srv.guard_ts(el.typ, model.api_id, obj).unwrap())
-            .collect::<Vec<_>>()
-    });
+    let identt_list = model
+        .ids
+        .as_ref()
+        .map(|x| {
+            x.iter()
+                .map(|el| srv.guard_ts(el.typ, model.api_id, obj))
+                .collect::<Result<Vec<_>>>()
+        })
+        .transpose()?;
 
     sqlx::query!(
         "INSERT INTO rel_vorgang_ident (vg_id, typ, identifikator)
@@ -363,6 +440,10 @@ pub async fn execute_merge_vorgang(
     .execute(&mut **tx)
     .await?;
 
+    // aendert::UNION
+    let aendert = crate::api::aenderungen::submitted(model.api_id)?;
+    insert::insert_vorgang_aendert(db_id, aendert.as_deref(), tx).await?;
+
     for stat in &model.stationen {
         match station_merge_candidates(stat, db_id, &mut **tx, srv).await? {
             MatchState::NoMatch => {
@@ -381,7 +462,16 @@ pub async fn execute_merge_vorgang(
                 .map(|r| r.api_id)
                 .fetch_all(&mut **tx)
                 .await?;
-                notify_ambiguous_match(mids, stat, "exec_merge_vorgang: station matching", srv)?;
+                notify_ambiguous_match(
+                    mids,
+                    &[],
+                    "",
+                    stat,
+                    "exec_merge_vorgang: station matching",
+                    tx,
+                    srv,
+                )
+                .await?;
             }
         }
     }
@@ -456,19 +546,212 @@ pub async fn execute_merge_vorgang(
     Ok(())
 }
 
+/// what happened to a Vorgang handed to `run_integration`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegrationOutcome {
+    Inserted,
+    Merged,
+    /// there were several merge candidates, the Vorgang waits in the review queue under this id
+    Queued(Uuid),
+}
+
+/// inserts or merges the Vorgang in its own transaction, which is retried on serialisation failures
 pub async fn run_integration(
     model: &models::Vorgang,
     scraper_id: Uuid,
     collector_key: KeyIndex,
     server: &LTZFServer,
-) -> Result<()> {
-    let mut tx = server.sqlx_db.begin().await?;
+) -> Result<IntegrationOutcome> {
+    run_integration_with_hint(model, None, scraper_id, collector_key, server).await
+}
+
+/// like `run_integration`, but a scraper that knows the api_id of the Vorgang it updates
+/// may name it as `merge_hint` (`X-Merge-Candidate-Id`), which skips the candidate search.
+/// A hint that does not exist or belongs to another wahlperiode is ignored.
+pub async fn run_integration_with_hint(
+    model: &models::Vorgang,
+    merge_hint: Option<Uuid>,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    server: &LTZFServer,
+) -> Result<IntegrationOutcome> {
+    let (outcome, warnings) = crate::db::retry_on_conflict(|| {
+        integrate_vorgang(model, merge_hint, scraper_id, collector_key, server)
+    })
+    .await?;
+    // pushed only now, a retried attempt would report the warnings again
+    for warning in warnings {
+        warn!(
+            "Plausibility warning for Vorgang {}: {}",
+            model.api_id, warning
+        );
+        crate::utils::warnings::push_warning(warning);
+    }
+    Ok(outcome)
+}
+
+/// the database id of the hinted merge candidate, if it exists and matches the wahlperiode
+async fn hinted_candidate(
+    model: &models::Vorgang,
+    merge_hint: Uuid,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Option<i32>> {
+    let candidate = sqlx::query!(
+        "SELECT id, wahlperiode FROM vorgang WHERE api_id = $1",
+        merge_hint
+    )
+    .fetch_optional(executor)
+    .await?;
+    match candidate {
+        Some(c) if c.wahlperiode as u32 == model.wahlperiode => Ok(Some(c.id)),
+        Some(c) => {
+            warn!(
+                "Merge hint {} for Vorgang {} is in Wahlperiode {} instead of {}, searching for candidates",
+                merge_hint, model.api_id, c.wahlperiode, model.wahlperiode
+            );
+            Ok(None)
+        }
+        None => {
+            warn!(
+                "Merge hint {} for Vorgang {} does not exist, searching for candidates",
+                merge_hint, model.api_id
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// session level advisory lock held on a pooled connection while a Vorgang is merged.
+/// Unlike a transaction level lock it is taken before the merge transaction starts, so that
+/// the snapshot of a repeatable read or serializable transaction already contains the changes
+/// of the previous holder.
+struct MergeLock {
+    conn: Option<sqlx::pool::PoolConnection<sqlx::Postgres>>,
+    key: String,
+}
+
+impl MergeLock {
+    async fn acquire(key: String, server: &LTZFServer) -> Result<Self> {
+        let mut conn = server.sqlx_db.acquire().await?;
+        sqlx::query("SELECT pg_advisory_lock(hashtextextended($1, 0))")
+            .bind(&key)
+            .execute(&mut *conn)
+            .await?;
+        Ok(Self {
+            conn: Some(conn),
+            key,
+        })
+    }
+    fn conn(&mut self) -> &mut sqlx::PgConnection {
+        self.conn
+            .as_mut()
+            .expect("connection is only taken on release")
+    }
+    async fn release(mut self) -> Result<()> {
+        let mut conn = self.conn.take().expect("lock is released once");
+        let unlocked = sqlx::query("SELECT pg_advisory_unlock(hashtextextended($1, 0))")
+            .bind(&self.key)
+            .execute(&mut *conn)
+            .await;
+        if unlocked.is_err() {
+            // closing the session releases the lock
+            drop(conn.detach());
+        }
+        unlocked?;
+        Ok(())
+    }
+}
+
+impl Drop for MergeLock {
+    fn drop(&mut self) {
+        // not released, e.g. because the request was cancelled. The connection must not be
+        // returned to the pool still holding the lock, closing the session releases it
+        if let Some(conn) = self.conn.take() {
+            drop(conn.detach());
+        }
+    }
+}
+
+/// the hinted candidate or else the ones found by `vorgang_merge_candidates`
+async fn merge_candidates(
+    model: &models::Vorgang,
+    hinted: Option<i32>,
+    executor: impl sqlx::PgExecutor<'_>,
+    server: &LTZFServer,
+) -> Result<MatchState<i32>> {
     debug!(
         "Looking for Merge Candidates for Vorgang with api_id: {:?}",
         model.api_id
     );
-    let candidates = vorgang_merge_candidates(model, &mut *tx, server).await?;
+    match hinted {
+        Some(id) => Ok(MatchState::ExactlyOne(id)),
+        None => vorgang_merge_candidates(model, executor, server).await,
+    }
+}
+
+/// the key of the merge lock: the Vorgang the submission is merged into (the first one if
+/// there are several candidates) or, for a new Vorgang, its Wahlperiode, so that the same
+/// new Vorgang submitted under different api_ids is not inserted twice
+fn merge_lock_key(model: &models::Vorgang, candidates: &MatchState<i32>) -> String {
     match candidates {
+        MatchState::ExactlyOne(id) => format!("vorgang:{id}"),
+        MatchState::Ambiguous(many) => format!("vorgang:{}", many.iter().min().unwrap_or(&0)),
+        MatchState::NoMatch => format!("vorgang:new:{}", model.wahlperiode),
+    }
+}
+
+/// returns the outcome together with the plausibility warnings for the Vorgang
+async fn integrate_vorgang(
+    model: &models::Vorgang,
+    merge_hint: Option<Uuid>,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    server: &LTZFServer,
+) -> Result<(IntegrationOutcome, Vec<String>)> {
+    let hinted = match merge_hint {
+        Some(hint) => hinted_candidate(model, hint, &server.sqlx_db).await?,
+        None => None,
+    };
+    // submissions merged into the same Vorgang are merged one after another, otherwise
+    // concurrent submissions would all miss each other's uncommitted changes
+    let candidates = merge_candidates(model, hinted, &server.sqlx_db, server).await?;
+    let mut key = merge_lock_key(model, &candidates);
+    loop {
+        let mut lock = MergeLock::acquire(key, server).await?;
+        // the previous holder of the lock may have changed the candidates
+        let candidates = merge_candidates(model, hinted, lock.conn(), server).await?;
+        let locked_key = merge_lock_key(model, &candidates);
+        if locked_key != lock.key {
+            lock.release().await?;
+            key = locked_key;
+            continue;
+        }
+        let outcome = integrate_vorgang_locked(
+            model,
+            candidates,
+            scraper_id,
+            collector_key,
+            lock.conn(),
+            server,
+        )
+        .await;
+        lock.release().await?;
+        return outcome;
+    }
+}
+
+async fn integrate_vorgang_locked(
+    model: &models::Vorgang,
+    candidates: MatchState<i32>,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    conn: &mut sqlx::PgConnection,
+    server: &LTZFServer,
+) -> Result<(IntegrationOutcome, Vec<String>)> {
+    let mut tx = sqlx::Connection::begin(conn).await?;
+    crate::db::set_merge_isolation(&mut tx, server).await?;
+    let warnings = validate_vorgang(model, &mut tx).await?;
+    let outcome = match candidates {
         MatchState::NoMatch => {
             info!(
                 "No Merge Candidate found, Inserting Complete Vorgang with api_id: {:?}",
@@ -477,6 +760,7 @@ pub async fn run_integration(
             let model = model.clone();
             info!(target: "obj", "Merge(Insert New) Vorgang {}", model.api_id);
             insert::insert_vorgang(&model, scraper_id, collector_key, &mut tx, server).await?;
+            IntegrationOutcome::Inserted
         }
         MatchState::ExactlyOne(one) => {
             let api_id = sqlx::query!("SELECT api_id FROM vorgang WHERE id = $1", one)
@@ -490,13 +774,14 @@ pub async fn run_integration(
             info!(target: "obj", "Merge(merge) new Vorgang {} into Vorgang {}", model.api_id, api_id);
             let model = model.clone();
             execute_merge_vorgang(&model, one, scraper_id, collector_key, &mut tx, server).await?;
+            IntegrationOutcome::Merged
         }
         MatchState::Ambiguous(many) => {
             warn!(
                 "Ambiguous matches for Vorgang with api_id: {:?}",
                 model.api_id
             );
-            warn!("Transaction not committed, administrators notified");
+            warn!("Vorgang put into the review queue, administrators notified");
             debug!("Details:  {:?} \n\n {:?}", model, many);
             let api_ids = sqlx::query!(
                 "SELECT api_id FROM vorgang WHERE id=ANY($1::int4[])",
@@ -505,29 +790,83 @@ pub async fn run_integration(
             .map(|r| r.api_id)
             .fetch_all(&mut *tx)
             .await?;
-            notify_ambiguous_match(api_ids, model, "merging vorgang", server)?;
-            tx.rollback().await?;
-            return Err(DataValidationError::AmbiguousMatch {
-                message: format!(
-                    "Tried to merge object with id `{}`, found {} matching VGs.",
-                    model.api_id,
-                    many.len()
-                ),
-            }
-            .into());
+            let reasons = vorgang_candidate_reasons(model, &many, &mut *tx, server).await?;
+            let review_id =
+                enqueue_review(model, &api_ids, scraper_id, collector_key, &mut tx).await?;
+            info!(
+                target: "obj",
+                "Merge(queued) Vorgang {} as review {}, found {} matching VGs: {:?}",
+                model.api_id,
+                review_id,
+                many.len(),
+                reasons
+            );
+            let diff = vorgang_candidate_diff(model, &many, &mut tx).await?;
+            notify_ambiguous_match(
+                api_ids,
+                &reasons,
+                &diff,
+                model,
+                "merging vorgang",
+                &mut tx,
+                server,
+            )
+            .await?;
+            IntegrationOutcome::Queued(review_id)
         }
-    }
+    };
     tx.commit().await?;
-    Ok(())
+    Ok((outcome, warnings))
+}
+
+/// stores the Vorgang together with its merge candidates for a decision by an administrator.
+/// If the same Vorgang is already pending, its payload and candidates are replaced and the
+/// id of the existing review is returned.
+async fn enqueue_review(
+    model: &models::Vorgang,
+    candidates: &[Uuid],
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Uuid> {
+    let id = Uuid::now_v7();
+    let payload = serde_json::to_string(model)
+        .map_err(|e| crate::LTZFError::other(format!("Vorgang is not serialisable: {e}")))?;
+    let candidates = serde_json::to_string(candidates)
+        .map_err(|e| crate::LTZFError::other(format!("Candidates are not serialisable: {e}")))?;
+    let id = sqlx::query!(
+        "INSERT INTO review_queue(id, incoming_payload, candidates, scraper_id, collector_key)
+        VALUES ($1, $2::text::jsonb, $3::text::jsonb, $4, $5)
+        ON CONFLICT ((incoming_payload->>'api_id')) WHERE status = 'pending'
+        DO UPDATE SET
+            incoming_payload = EXCLUDED.incoming_payload,
+            candidates = EXCLUDED.candidates,
+            scraper_id = EXCLUDED.scraper_id,
+            collector_key = EXCLUDED.collector_key
+        RETURNING id",
+        id,
+        payload,
+        candidates,
+        scraper_id,
+        collector_key
+    )
+    .map(|r| r.id)
+    .fetch_one(&mut **tx)
+    .await?;
+    Ok(id)
 }
 
 #[cfg(test)]
 mod scenariotest {
-    use crate::api::{RoundTimestamp, SortArrays};
+    use crate::api::RoundTimestamp;
+    use crate::api::normalize::SortArrays;
+    use crate::db::SERIALIZATION_RETRIES;
     use crate::utils::testing::{TestSetup, generate};
+    use crate::utils::warnings::collect_warnings;
     use crate::{LTZFServer, Result, api::PaginationResponsePart, db::retrieve};
     use openapi::models::{self, StationDokumenteInner};
     use std::str::FromStr;
+    use std::sync::atomic::Ordering;
     use uuid::Uuid;
 
     struct Scenario {
@@ -615,6 +954,17 @@ mod scenariotest {
                 parlament: None,
                 lower_date: None,
                 upper_date: None,
+                schlagwort: None,
+                schlagwort_any: None,
+                min_meinung: None,
+                max_meinung: None,
+                trojanergefahr_min: None,
+                modified_since: None,
+                dok_schlagwort: None,
+                initiator_fraktion: None,
+                created_since: None,
+                created_until: None,
+                sort: vec![],
             };
             let mut tx = server.sqlx_db.begin().await.unwrap();
             let mut db_vorgangs = retrieve::vorgang_by_parameter(
@@ -857,6 +1207,39 @@ mod scenariotest {
         scenario.run().await.unwrap();
     }
     #[tokio::test]
+    async fn test_station_zp_modifiziert_unchanged_data() {
+        let setup = TestSetup::new("station_zp_modifiziert").await;
+        let server = &setup.server;
+        let mut vg = generate::default_vorgang();
+        vg.stationen[0].zp_modifiziert = None;
+        let station = vg.stationen[0].api_id.unwrap();
+        let zp_modifiziert = || async {
+            sqlx::query!(
+                "SELECT zp_modifiziert FROM station WHERE api_id = $1",
+                station
+            )
+            .map(|r| r.zp_modifiziert)
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap()
+        };
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let first = zp_modifiziert().await;
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        assert_eq!(zp_modifiziert().await, first);
+
+        vg.stationen[0].titel = Some("Geänderter Titel".to_string());
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        assert!(zp_modifiziert().await > first);
+        setup.teardown().await;
+    }
+    #[tokio::test]
     async fn test_dokument_merging_on_weak_property_changes() {
         let modified_dokument = models::Dokument {
             api_id: Some(Uuid::from_str("b18bee64-c0ff-ff0c-ff1c-deadbeef4732").unwrap()),
@@ -905,4 +1288,412 @@ mod scenariotest {
 
         scenario.run().await.unwrap();
     }
+
+    #[tokio::test]
+    async fn test_merge_station_with_uuid_dokument_refs() {
+        let setup = TestSetup::new("merge_station_uuid_refs").await;
+        let server = &setup.server;
+        let vg = generate::default_vorgang();
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let extra = generate::random::dokument(33);
+        let extra_id =
+            crate::db::insert::insert_dokument(extra.clone(), Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap();
+        let station = generate::default_station();
+        let stat_id = sqlx::query!(
+            "SELECT id FROM station WHERE api_id = $1",
+            station.api_id.unwrap()
+        )
+        .map(|r| r.id)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+
+        let mut referencing = station.clone();
+        referencing.dokumente.push(StationDokumenteInner::String(
+            extra.api_id.unwrap().to_string(),
+        ));
+        // the second round checks that the association survives a merge of the same station
+        for _ in 0..2 {
+            let mut tx = server.sqlx_db.begin().await.unwrap();
+            super::execute_merge_station(&referencing, stat_id, Uuid::nil(), 1, &mut tx, server)
+                .await
+                .unwrap();
+            tx.commit().await.unwrap();
+            let associated = sqlx::query!(
+                "SELECT EXISTS(SELECT 1 FROM rel_station_dokument WHERE stat_id = $1 AND dok_id = $2) as ex",
+                stat_id,
+                extra_id
+            )
+            .map(|r| r.ex.unwrap_or(false))
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap();
+            assert!(associated);
+        }
+
+        let unknown = Uuid::now_v7();
+        let mut dangling = station.clone();
+        dangling
+            .dokumente
+            .push(StationDokumenteInner::String(unknown.to_string()));
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let result =
+            super::execute_merge_station(&dangling, stat_id, Uuid::nil(), 1, &mut tx, server).await;
+        tx.rollback().await.unwrap();
+        match result {
+            Err(crate::LTZFError::Validation { source }) => match *source {
+                crate::error::DataValidationError::IncompleteDataSupplied { input } => {
+                    assert!(input.contains(&unknown.to_string()))
+                }
+                other => panic!("Expected IncompleteDataSupplied, got {other:?}"),
+            },
+            other => panic!("Expected a validation error, got {other:?}"),
+        }
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_station_error_names_station() {
+        let setup = TestSetup::new("merge_station_error_names_station").await;
+        let server = &setup.server;
+        for vg in [generate::default_vorgang(), generate::random::vorgang(42)] {
+            super::run_integration(&vg, Uuid::nil(), 1, server)
+                .await
+                .unwrap();
+        }
+        let station = generate::default_station();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let stat_id = sqlx::query!(
+            "SELECT id FROM station WHERE api_id = $1",
+            station.api_id.unwrap()
+        )
+        .map(|r| r.id)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        // another station holds the titel, so the master update violates the index
+        sqlx::query!(
+            "UPDATE station SET titel = 'belegt' WHERE id = (SELECT MIN(id) FROM station WHERE id <> $1)",
+            stat_id
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+        sqlx::query!(
+            "CREATE UNIQUE INDEX station_titel_belegt ON station(titel) WHERE titel = 'belegt'"
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+
+        let mut conflicting = station.clone();
+        conflicting.titel = Some("belegt".to_string());
+        let result =
+            super::execute_merge_station(&conflicting, stat_id, Uuid::nil(), 1, &mut tx, server)
+                .await;
+        tx.rollback().await.unwrap();
+        match result {
+            Err(crate::LTZFError::DatabaseConstraint {
+                constraint, detail, ..
+            }) => {
+                assert_eq!(constraint.as_deref(), Some("station_titel_belegt"));
+                let detail = detail.unwrap();
+                assert!(
+                    detail.contains(&station.api_id.unwrap().to_string()),
+                    "{detail}"
+                );
+            }
+            other => panic!("Expected a constraint violation, got {other:?}"),
+        }
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_parallel_integration() {
+        let setup = TestSetup::new("parallel_integration").await;
+        let server = &setup.server;
+        let vg = generate::default_vorgang();
+        let mut vg2 = vg.clone();
+        vg2.titel = "Paralleler Titel".to_string();
+        let (first, second) = tokio::join!(
+            super::run_integration(&vg, Uuid::nil(), 1, server),
+            super::run_integration(&vg2, Uuid::nil(), 1, server)
+        );
+        first.unwrap();
+        second.unwrap();
+
+        // merges of the same Vorgang are serialised by the merge lock, a concurrent write
+        // outside of a merge makes the merge fail once it commits, so the merge is retried
+        let retries_before = SERIALIZATION_RETRIES.load(Ordering::Relaxed);
+        let mut blocker = server.sqlx_db.begin().await.unwrap();
+        let blocker_pid = sqlx::query!("SELECT pg_backend_pid() AS \"pid!\"")
+            .map(|r| r.pid)
+            .fetch_one(&mut *blocker)
+            .await
+            .unwrap();
+        sqlx::query!(
+            "UPDATE vorgang SET kurztitel = 'Blockiert' WHERE api_id = $1",
+            vg.api_id
+        )
+        .execute(&mut *blocker)
+        .await
+        .unwrap();
+        let mut vg3 = vg.clone();
+        vg3.titel = "Dritter Titel".to_string();
+        // outside of Wahlperiode 20, the warning must be reported once despite the retry
+        vg3.stationen[0].gremium.parlament = models::Parlament::Bt;
+        vg3.stationen[0].zp_start = chrono::DateTime::parse_from_rfc3339("1990-01-01T12:00:00Z")
+            .unwrap()
+            .to_utc();
+        let merge = {
+            let server = server.clone();
+            tokio::spawn(async move {
+                collect_warnings(super::run_integration(&vg3, Uuid::nil(), 1, &server)).await
+            })
+        };
+        let mut blocked = false;
+        for _ in 0..500 {
+            blocked = sqlx::query!(
+                "SELECT EXISTS(SELECT 1 FROM pg_stat_activity WHERE $1 = ANY(pg_blocking_pids(pid)))
+                AS \"blocked!\"",
+                blocker_pid
+            )
+            .map(|r| r.blocked)
+            .fetch_one(&server.sqlx_db)
+            .await
+            .unwrap();
+            if blocked {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(blocked, "the merge never waited for the concurrent update");
+        blocker.commit().await.unwrap();
+        let (merged, warnings) = merge.await.unwrap();
+        merged.unwrap();
+        assert!(SERIALIZATION_RETRIES.load(Ordering::Relaxed) > retries_before);
+        let outside = warnings
+            .iter()
+            .filter(|w| w.contains("outside of Wahlperiode"));
+        assert_eq!(outside.count(), 1, "{warnings:?}");
+        let count = sqlx::query!(
+            "SELECT COUNT(1) as cnt FROM vorgang WHERE api_id = $1",
+            vg.api_id
+        )
+        .map(|r| r.cnt.unwrap_or(0))
+        .fetch_one(&server.sqlx_db)
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_parallel_integration_other_api_id() {
+        let setup = TestSetup::new("parallel_integration_other_api_id").await;
+        let server = &setup.server;
+        let vg = generate::default_vorgang();
+        let mut vg2 = vg.clone();
+        vg2.api_id = Uuid::now_v7();
+        // the lock is keyed on the candidate, not on the submitted api_id
+        let (first, second) = tokio::join!(
+            super::run_integration(&vg, Uuid::nil(), 1, server),
+            super::run_integration(&vg2, Uuid::nil(), 1, server)
+        );
+        first.unwrap();
+        second.unwrap();
+        let count = sqlx::query!(
+            "SELECT COUNT(1) as cnt FROM vorgang WHERE api_id = ANY($1::uuid[])",
+            &[vg.api_id, vg2.api_id][..]
+        )
+        .map(|r| r.cnt.unwrap_or(0))
+        .fetch_one(&server.sqlx_db)
+        .await
+        .unwrap();
+        assert_eq!(count, 1);
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_parallel_station_submissions() {
+        let setup = TestSetup::new("parallel_station_submissions").await;
+        let server = &setup.server;
+        let vg = generate::default_vorgang();
+        let mut tasks = Vec::new();
+        let mut expected: Vec<Uuid> = vg.stationen.iter().filter_map(|s| s.api_id).collect();
+        for seed in 0..10 {
+            let mut submission = vg.clone();
+            let station = generate::random::station(seed);
+            expected.push(station.api_id.unwrap());
+            submission.stationen.push(station);
+            let server = server.clone();
+            tasks.push(tokio::spawn(async move {
+                super::run_integration(&submission, Uuid::nil(), 1, &server).await
+            }));
+        }
+        for task in tasks {
+            task.await.unwrap().unwrap();
+        }
+
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let id = sqlx::query!("SELECT id FROM vorgang WHERE api_id = $1", vg.api_id)
+            .map(|r| r.id)
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap();
+        let merged = retrieve::vorgang_by_id(id, &mut tx).await.unwrap();
+        tx.rollback().await.unwrap();
+        let mut stationen: Vec<Uuid> = merged.stationen.iter().map(|s| s.api_id.unwrap()).collect();
+        stationen.sort();
+        expected.sort();
+        assert_eq!(stationen, expected);
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_dokument_merge_mode() {
+        let mut setup = TestSetup::new("dokument_merge_mode").await;
+        let vg = generate::default_vorgang();
+        super::run_integration(&vg, Uuid::nil(), 1, &setup.server)
+            .await
+            .unwrap();
+        let dok_id = generate::default_dokument().api_id.unwrap();
+        let mut cleared = vg.clone();
+        for d in cleared.stationen[0].dokumente.iter_mut() {
+            if let StationDokumenteInner::Dokument(d) = d {
+                if d.api_id == Some(dok_id) {
+                    d.kurztitel = None;
+                }
+            }
+        }
+        let kurztitel = |server: LTZFServer| async move {
+            sqlx::query!("SELECT kurztitel FROM dokument WHERE api_id = $1", dok_id)
+                .map(|r| r.kurztitel)
+                .fetch_one(&server.sqlx_db)
+                .await
+                .unwrap()
+        };
+
+        super::run_integration(&cleared, Uuid::nil(), 1, &setup.server)
+            .await
+            .unwrap();
+        assert_eq!(
+            kurztitel(setup.server.clone()).await,
+            generate::default_dokument().kurztitel
+        );
+
+        setup.server.config.merge_mode = crate::MergeMode::Aggressive;
+        super::run_integration(&cleared, Uuid::nil(), 1, &setup.server)
+            .await
+            .unwrap();
+        assert_eq!(kurztitel(setup.server.clone()).await, None);
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_inline_content() {
+        use base64::Engine;
+        let setup = TestSetup::new("inline_content").await;
+        let vg = generate::default_vorgang();
+        let dok_id = generate::default_dokument().api_id.unwrap();
+        let html = "<html><body><p>Inline eingereicht</p></body></html>";
+        // content_b64 is not part of the generated Dokument, it is read from the submitted body
+        let mut body = serde_json::to_value(&vg).unwrap();
+        for d in body["stationen"][0]["dokumente"].as_array_mut().unwrap() {
+            if d["api_id"] == serde_json::json!(dok_id) {
+                d["content_b64"] = base64::engine::general_purpose::STANDARD
+                    .encode(html)
+                    .into();
+            }
+        }
+        let request = axum::extract::Request::builder()
+            .method("PUT")
+            .uri("/api/v2/vorgang")
+            .body(axum::body::Body::from(body.to_string()))
+            .unwrap();
+        let (result, _) = crate::utils::spec_ext::with_request(
+            request,
+            super::run_integration(&vg, Uuid::nil(), 1, &setup.server),
+        )
+        .await;
+        result.unwrap();
+        let (volltext, mime) = sqlx::query!(
+            "SELECT d.volltext, c.mime FROM dokument d
+            INNER JOIN dokument_raw_content c ON c.dok_id = d.id
+            WHERE d.api_id = $1",
+            dok_id
+        )
+        .map(|r| (r.volltext, r.mime))
+        .fetch_one(&setup.server.sqlx_db)
+        .await
+        .unwrap();
+        assert_eq!(volltext, "Inline eingereicht");
+        assert_eq!(mime, "text/html");
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_links_normalised() {
+        let setup = TestSetup::new("vorgang_links_normalised").await;
+        let server = &setup.server;
+        let mut vg = generate::default_vorgang();
+        vg.links = Some(vec![
+            "https://example.com/vorgang/".to_string(),
+            "https://example.com/vorgang".to_string(),
+        ]);
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        vg.links = Some(vec!["HTTPS://EXAMPLE.com/vorgang//".to_string()]);
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let links = sqlx::query!("SELECT link FROM rel_vorgang_links")
+            .map(|r| r.link)
+            .fetch_all(&server.sqlx_db)
+            .await
+            .unwrap();
+        assert_eq!(links, vec!["https://example.com/vorgang".to_string()]);
+        setup.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_hint() {
+        let setup = TestSetup::new("merge_hint").await;
+        let server = &setup.server;
+        let vg = generate::default_vorgang();
+        super::run_integration(&vg, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+
+        // no identifier in common, the candidate search alone would insert it
+        let mut hinted = vg.clone();
+        hinted.api_id = Uuid::now_v7();
+        hinted.ids = None;
+        hinted.titel = "Ich Mag Moneten und deshalb ist das ein anderes Gesetz".to_string();
+        let outcome =
+            super::run_integration_with_hint(&hinted, Some(vg.api_id), Uuid::nil(), 1, server)
+                .await
+                .unwrap();
+        assert_eq!(outcome, super::IntegrationOutcome::Merged);
+
+        // a hint in another wahlperiode is ignored
+        let mut other_wp = hinted.clone();
+        other_wp.api_id = Uuid::now_v7();
+        other_wp.wahlperiode = vg.wahlperiode + 1;
+        other_wp.stationen[0].api_id = Some(Uuid::now_v7());
+        let outcome =
+            super::run_integration_with_hint(&other_wp, Some(vg.api_id), Uuid::nil(), 1, server)
+                .await
+                .unwrap();
+        assert_eq!(outcome, super::IntegrationOutcome::Inserted);
+        setup.teardown().await;
+    }
 }
@@ -1,5 +1,6 @@
 pub mod candidates;
 pub mod execute;
+pub mod sitzung;
 
 #[derive(Debug)]
 pub enum MatchState<T> {
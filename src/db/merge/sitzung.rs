@@ -0,0 +1,585 @@
+//! Merging of Sitzungen, analogous to the Vorgang merge.
+//! Basic data points are overridden by newer information, while
+//! TOPs, Dokumente and Experten are merged additively so that data
+//! submitted by other scrapers is preserved.
+
+use super::MatchState;
+use super::candidates::dokument_merge_candidates;
+use super::execute::execute_merge_dokument;
+use crate::db::KeyIndex;
+use crate::db::insert;
+use crate::error::DataValidationError;
+use crate::utils::notify::notify_ambiguous_match;
+use crate::{LTZFServer, Result};
+use chrono::{DateTime, Utc};
+use openapi::models;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// wenn gleich:
+/// api_id OR (gremium AND termin innerhalb einer Stunde)
+pub async fn sitzung_merge_candidates(
+    model: &models::Sitzung,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<MatchState<i32>> {
+    let result = sqlx::query!(
+        "SELECT s.id, s.api_id FROM sitzung s
+        INNER JOIN gremium g ON g.id = s.gr_id
+        INNER JOIN parlament p ON p.id = s.p_id
+        WHERE s.api_id = $1 OR
+        (g.name = $2 AND p.value = $3 AND g.wp = $4 AND -- gremium übereinstimmt und
+        s.termin BETWEEN ($5::timestamptz - '1 hour'::interval) AND ($5::timestamptz + '1 hour'::interval)) -- termin innerhalb einer Stunde",
+        model.api_id,
+        model.gremium.name,
+        model.gremium.parlament.to_string(),
+        model.gremium.wahlperiode as i32,
+        model.termin
+    )
+    .fetch_all(executor)
+    .await?;
+    debug!(
+        "Found {} matches for Sitzung with api_id: {:?}",
+        result.len(),
+        model.api_id
+    );
+    Ok(match result.len() {
+        0 => MatchState::NoMatch,
+        1 => MatchState::ExactlyOne(result[0].id),
+        _ => {
+            warn!(
+                "Mehrere Sitzungen gefunden, die als Kandidaten für Merge infrage kommen für die Sitzung `{:?}`:\n{:?}",
+                model.api_id,
+                result.iter().map(|r| r.api_id).collect::<Vec<_>>()
+            );
+            MatchState::Ambiguous(result.iter().map(|x| x.id).collect())
+        }
+    })
+}
+
+/// inserts or merges a dokument by its merge candidates (api_id, hash, ...)
+/// and returns the database id of the resulting dokument.
+async fn merge_or_insert_dok(
+    dok: &models::Dokument,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<i32> {
+    match dokument_merge_candidates(dok, &mut **tx, srv).await? {
+        MatchState::NoMatch => {
+            insert::insert_dokument(dok.clone(), scraper_id, collector_key, tx, srv).await
+        }
+        MatchState::ExactlyOne(did) => {
+            execute_merge_dokument(dok, did, scraper_id, collector_key, tx, srv).await?;
+            Ok(did)
+        }
+        MatchState::Ambiguous(matches) => {
+            let api_ids = sqlx::query!(
+                "SELECT api_id FROM dokument WHERE id = ANY($1::int4[])",
+                &matches[..]
+            )
+            .map(|r| r.api_id)
+            .fetch_all(&mut **tx)
+            .await?;
+            notify_ambiguous_match(
+                api_ids,
+                &[],
+                "",
+                dok,
+                "execute merge sitzung.dokumente",
+                tx,
+                srv,
+            )
+            .await?;
+            Err(DataValidationError::AmbiguousMatch {
+                message: "Ambiguous document match(sitzung), see notification".to_string(),
+            }
+            .into())
+        }
+    }
+}
+
+pub async fn execute_merge_sitzung(
+    model: &models::Sitzung,
+    candidate: i32,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<()> {
+    let db_id = candidate;
+    // master update
+    sqlx::query!(
+        "UPDATE sitzung SET
+        titel = COALESCE($2, titel), termin = $3, public = $4,
+        link = COALESCE($5, link), nummer = $6, last_update = NOW()
+        WHERE sitzung.id = $1",
+        db_id,
+        model.titel,
+        model.termin,
+        model.public,
+        model.link,
+        model.nummer as i32
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    // tops::UNION by nummer
+    for top in &model.tops {
+        let tid = sqlx::query!(
+            "SELECT id FROM top WHERE sid = $1 AND nummer = $2",
+            db_id,
+            top.nummer as i32
+        )
+        .map(|r| r.id)
+        .fetch_optional(&mut **tx)
+        .await?;
+        let Some(tid) = tid else {
+            insert::insert_top(db_id, top, scraper_id, collector_key, tx, srv).await?;
+            continue;
+        };
+        sqlx::query!("UPDATE top SET titel = $2 WHERE id = $1", tid, top.titel)
+            .execute(&mut **tx)
+            .await?;
+        // vorgänge::UNION, unknown ones are skipped like on insert
+        sqlx::query!(
+            "INSERT INTO rel_top_vorgang(top_id, vg_id)
+            SELECT $1, v.id FROM vorgang v WHERE v.api_id = ANY($2::uuid[])
+            ON CONFLICT DO NOTHING",
+            tid,
+            top.vorgang_id.as_ref().map(|v| &v[..]).unwrap_or(&[])
+        )
+        .execute(&mut **tx)
+        .await?;
+        let mut dids = vec![];
+        for d in top.dokumente.as_ref().unwrap_or(&vec![]) {
+            dids.push(insert::insert_or_retrieve_dok(d, scraper_id, collector_key, tx, srv).await?);
+        }
+        sqlx::query!(
+            "INSERT INTO tops_doks(top_id, dok_id)
+            SELECT $1, did FROM UNNEST($2::int4[]) as did
+            ON CONFLICT DO NOTHING",
+            tid,
+            &dids[..]
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    // dokumente::UNION by hash
+    let mut dids = vec![];
+    for d in model.dokumente.as_ref().unwrap_or(&vec![]) {
+        dids.push(match d {
+            models::StationDokumenteInner::Dokument(d) => {
+                merge_or_insert_dok(d, scraper_id, collector_key, tx, srv).await?
+            }
+            models::StationDokumenteInner::String(api_id) => {
+                insert::dokument_by_reference(api_id, tx).await?
+            }
+        });
+    }
+    sqlx::query!(
+        "INSERT INTO rel_sitzung_doks(sid, did)
+        SELECT $1, did FROM UNNEST($2::int4[]) as did
+        ON CONFLICT DO NOTHING",
+        db_id,
+        &dids[..]
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    // experten::UNION
+    let mut eids = vec![];
+    for e in model.experten.as_ref().unwrap_or(&vec![]) {
+        eids.push(insert::insert_or_retrieve_autor(e, tx, srv).await?);
+    }
+    sqlx::query!(
+        "INSERT INTO rel_sitzung_experten(sid, eid)
+        SELECT $1, eid FROM UNNEST($2::int4[]) as eid
+        ON CONFLICT DO NOTHING",
+        db_id,
+        &eids[..]
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "INSERT INTO scraper_touched_sitzung (sid, collector_key, scraper) VALUES ($1, $2, $3)
+        ON CONFLICT(sid, scraper) DO UPDATE SET time_stamp=NOW()",
+        db_id,
+        collector_key,
+        scraper_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!(
+        "WITH ranked_objects AS (
+        SELECT sid, scraper,
+        ROW_NUMBER() OVER (
+            PARTITION BY sid
+            ORDER BY time_stamp DESC
+        ) AS rn
+        FROM scraper_touched_sitzung
+        )
+        DELETE FROM scraper_touched_sitzung st
+        USING ranked_objects ro
+        WHERE st.sid=ro.sid AND
+        st.scraper=ro.scraper AND
+        ro.rn > $1",
+        srv.config.per_object_scraper_log_size as i64
+    )
+    .execute(&mut **tx)
+    .await?;
+    info!("Merging Sitzung into Database successful");
+    Ok(())
+}
+
+/// inserts the sitzung if there is no merge candidate or merges it into the one candidate found
+/// and returns the database id of the resulting sitzung.
+/// Ambiguous matches are reported to the administrators and abort the operation.
+pub async fn run_sitzung_integration(
+    model: &models::Sitzung,
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<i32> {
+    match sitzung_merge_candidates(model, &mut **tx).await? {
+        MatchState::NoMatch => {
+            info!(target: "obj", "Merge(Insert New) Sitzung {:?}", model.api_id);
+            insert::insert_sitzung(model, scraper_id, collector_key, tx, srv).await
+        }
+        MatchState::ExactlyOne(one) => {
+            let api_id = sqlx::query!("SELECT api_id FROM sitzung WHERE id = $1", one)
+                .map(|r| r.api_id)
+                .fetch_one(&mut **tx)
+                .await?;
+            info!(target: "obj", "Merge(merge) new Sitzung {:?} into Sitzung {}", model.api_id, api_id);
+            execute_merge_sitzung(model, one, scraper_id, collector_key, tx, srv).await?;
+            Ok(one)
+        }
+        MatchState::Ambiguous(many) => {
+            let api_ids = sqlx::query!(
+                "SELECT api_id FROM sitzung WHERE id=ANY($1::int4[])",
+                &many[..]
+            )
+            .map(|r| r.api_id)
+            .fetch_all(&mut **tx)
+            .await?;
+            notify_ambiguous_match(api_ids, &[], "", model, "merging sitzung", tx, srv).await?;
+            Err(DataValidationError::AmbiguousMatch {
+                message: format!(
+                    "Tried to merge Sitzung with id `{:?}`, found {} matching Sitzungen.",
+                    model.api_id,
+                    many.len()
+                ),
+            }
+            .into())
+        }
+    }
+}
+
+/// merges all `sitzungen` and deletes the other Sitzungen of `parlament` taking place
+/// in [`from`, `until`) that only `scraper_id` ever submitted, so the scraper can retract
+/// cancelled Sitzungen while those of other scrapers covering the same parliament are kept.
+/// Returns the number of deleted Sitzungen.
+pub async fn replace_sitzungen_in_range(
+    sitzungen: &[models::Sitzung],
+    parlament: models::Parlament,
+    (from, until): (DateTime<Utc>, DateTime<Utc>),
+    scraper_id: Uuid,
+    collector_key: KeyIndex,
+    tx: &mut sqlx::PgTransaction<'_>,
+    srv: &LTZFServer,
+) -> Result<u64> {
+    let mut kept = vec![];
+    for s in sitzungen {
+        kept.push(run_sitzung_integration(s, scraper_id, collector_key, tx, srv).await?);
+    }
+    let deleted = sqlx::query!(
+        "DELETE FROM sitzung s USING parlament p
+        WHERE p.id = s.p_id AND p.value = $1 AND s.termin >= $2 AND s.termin < $3
+        AND s.id <> ALL($4::int4[])
+        AND EXISTS (SELECT 1 FROM scraper_touched_sitzung t WHERE t.sid = s.id AND t.scraper = $5)
+        AND NOT EXISTS (SELECT 1 FROM scraper_touched_sitzung t WHERE t.sid = s.id AND t.scraper <> $5)",
+        parlament.to_string(),
+        from,
+        until,
+        &kept[..],
+        scraper_id
+    )
+    .execute(&mut **tx)
+    .await?
+    .rows_affected();
+    if deleted > 0 {
+        info!(target: "obj", "Deleted {deleted} Sitzungen of {parlament} between {from} and {until} missing from the submission of {scraper_id}");
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod test_sitzung_merge {
+    use openapi::models;
+    use uuid::Uuid;
+
+    use super::{
+        MatchState, replace_sitzungen_in_range, run_sitzung_integration, sitzung_merge_candidates,
+    };
+    use crate::db::{insert, retrieve};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_sitzung_merge() {
+        let scenario = TestSetup::new("test_sitzung_merge").await;
+        let server = &scenario.server;
+        let original = generate::default_sitzung();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        run_sitzung_integration(&original, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+
+        // same gremium, 30 minutes later, other scraper with a different api_id
+        let overlapping = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            termin: original.termin + chrono::Duration::minutes(30),
+            tops: vec![models::Top {
+                nummer: 2,
+                titel: "Zusätzlicher Tagesordnungspunkt".to_string(),
+                dokumente: None,
+                vorgang_id: None,
+            }],
+            experten: Some(vec![generate::default_autor_person()]),
+            dokumente: None,
+            ..original.clone()
+        };
+        let candidates = sitzung_merge_candidates(&overlapping, &mut *tx)
+            .await
+            .unwrap();
+        assert!(matches!(candidates, MatchState::ExactlyOne(_)));
+        run_sitzung_integration(&overlapping, Uuid::now_v7(), 1, &mut tx, server)
+            .await
+            .unwrap();
+
+        let ids = sqlx::query!("SELECT id FROM sitzung")
+            .map(|r| r.id)
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(ids.len(), 1);
+        let merged = retrieve::sitzung_by_id(ids[0], &mut tx).await.unwrap();
+        assert_eq!(merged.api_id, original.api_id);
+        assert_eq!(merged.termin, overlapping.termin);
+        assert_eq!(merged.tops.len(), 2);
+        assert_eq!(merged.dokumente.map(|d| d.len()), Some(1));
+        assert_eq!(merged.experten.map(|e| e.len()), Some(2));
+
+        // more than an hour apart: a separate sitzung
+        let distinct = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            termin: original.termin + chrono::Duration::hours(3),
+            ..original.clone()
+        };
+        let candidates = sitzung_merge_candidates(&distinct, &mut *tx).await.unwrap();
+        assert!(matches!(candidates, MatchState::NoMatch));
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_sitzung_unique_per_termin() {
+        let scenario = TestSetup::new("test_sitzung_unique_per_termin").await;
+        let server = &scenario.server;
+        let original = generate::default_sitzung();
+        let duplicate = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            titel: Some("Anderer Titel".to_string()),
+            ..original.clone()
+        };
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let first = insert::insert_sitzung(&original, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        // bypasses the merge candidates, so only the unique constraint prevents a second row
+        let second = insert::insert_sitzung(&duplicate, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        assert_eq!(first, second);
+
+        let merged = retrieve::sitzung_by_id(first, &mut tx).await.unwrap();
+        assert_eq!(merged.api_id, original.api_id);
+        assert_eq!(merged.titel, duplicate.titel);
+        let count = sqlx::query!("SELECT COUNT(*) as \"c!\" FROM sitzung")
+            .fetch_one(&mut *tx)
+            .await
+            .unwrap()
+            .c;
+        assert_eq!(count, 1);
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_sitzung_dokument_reference() {
+        let scenario = TestSetup::new("test_sitzung_dokument_reference").await;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let dokument = generate::random::dokument(7);
+        let did = insert::insert_dokument(dokument.clone(), Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let reference = models::StationDokumenteInner::String(dokument.api_id.unwrap().to_string());
+        let sitzung = models::Sitzung {
+            dokumente: Some(vec![reference.clone()]),
+            ..generate::default_sitzung()
+        };
+
+        // insert path
+        let sid = insert::insert_sitzung(&sitzung, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let associated = sqlx::query!("SELECT did FROM rel_sitzung_doks WHERE sid = $1", sid)
+            .map(|r| r.did)
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(associated, vec![did]);
+
+        // merge path, the reference is already associated
+        run_sitzung_integration(&sitzung, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let merged = retrieve::sitzung_by_id(sid, &mut tx).await.unwrap();
+        assert_eq!(merged.dokumente, Some(vec![reference]));
+
+        // unknown references are rejected on both paths
+        let unknown = Uuid::now_v7();
+        let dangling = models::Sitzung {
+            dokumente: Some(vec![models::StationDokumenteInner::String(
+                unknown.to_string(),
+            )]),
+            ..sitzung.clone()
+        };
+        let fresh = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            termin: sitzung.termin + chrono::Duration::days(3),
+            ..dangling.clone()
+        };
+        for result in [
+            run_sitzung_integration(&dangling, Uuid::nil(), 1, &mut tx, server).await,
+            run_sitzung_integration(&fresh, Uuid::nil(), 1, &mut tx, server).await,
+        ] {
+            match result {
+                Err(crate::LTZFError::Validation { source }) => match *source {
+                    crate::error::DataValidationError::IncompleteDataSupplied { input } => {
+                        assert!(input.contains(&unknown.to_string()))
+                    }
+                    other => panic!("Expected IncompleteDataSupplied, got {other:?}"),
+                },
+                other => panic!("Expected a validation error, got {other:?}"),
+            }
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_replace_sitzungen_in_range() {
+        let scenario = TestSetup::new("test_replace_sitzungen_in_range").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        crate::db::merge::execute::run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let original = generate::default_sitzung();
+        let cancelled = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            termin: original.termin - chrono::Duration::hours(2),
+            ..original.clone()
+        };
+        // submitted by another scraper covering the same parliament
+        let foreign = models::Sitzung {
+            api_id: Some(Uuid::now_v7()),
+            termin: original.termin - chrono::Duration::hours(4),
+            ..original.clone()
+        };
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let kept = run_sitzung_integration(&original, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        run_sitzung_integration(&cancelled, Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let foreign_id = run_sitzung_integration(&foreign, Uuid::now_v7(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let dokument = generate::random::dokument(11);
+        insert::insert_dokument(dokument.clone(), Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+
+        // the resubmitted day only contains the original, its first TOP now
+        // references a Vorgang and a known Dokument by its api_id
+        let mut resubmitted = original.clone();
+        resubmitted.tops[0].vorgang_id = Some(vec![vorgang.api_id]);
+        resubmitted.tops[0].dokumente = Some(vec![models::StationDokumenteInner::String(
+            dokument.api_id.unwrap().to_string(),
+        )]);
+        let date = original.termin.date_naive();
+        let day = (
+            date.and_time(chrono::NaiveTime::MIN).and_utc(),
+            (date + chrono::Days::new(1))
+                .and_time(chrono::NaiveTime::MIN)
+                .and_utc(),
+        );
+        let parlament = original.gremium.parlament;
+        let deleted = replace_sitzungen_in_range(
+            &[resubmitted],
+            parlament,
+            day,
+            Uuid::nil(),
+            1,
+            &mut tx,
+            server,
+        )
+        .await
+        .unwrap();
+        assert_eq!(deleted, 1);
+        let ids = sqlx::query!("SELECT id FROM sitzung ORDER BY id")
+            .map(|r| r.id)
+            .fetch_all(&mut *tx)
+            .await
+            .unwrap();
+        assert_eq!(ids, vec![kept, foreign_id]);
+        let top = sqlx::query!(
+            "SELECT t.id FROM top t WHERE t.sid = $1 AND t.nummer = $2",
+            kept,
+            original.tops[0].nummer as i32
+        )
+        .map(|r| r.id)
+        .fetch_one(&mut *tx)
+        .await
+        .unwrap();
+        let linked = sqlx::query!(
+            "SELECT v.api_id FROM rel_top_vorgang r INNER JOIN vorgang v ON v.id = r.vg_id
+            WHERE r.top_id = $1",
+            top
+        )
+        .map(|r| r.api_id)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+        assert_eq!(linked, vec![vorgang.api_id]);
+        let referenced = sqlx::query!(
+            "SELECT d.api_id FROM tops_doks td INNER JOIN dokument d ON d.id = td.dok_id
+            WHERE td.top_id = $1",
+            top
+        )
+        .map(|r| r.api_id)
+        .fetch_all(&mut *tx)
+        .await
+        .unwrap();
+        assert!(referenced.contains(&dokument.api_id.unwrap()));
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -1,6 +1,148 @@
+pub mod dedup;
 pub mod delete;
 pub mod insert;
 pub mod merge;
 pub mod retrieve;
+pub mod validate;
 
 pub(crate) type KeyIndex = i32;
+
+/// how often a transaction is attempted before a serialisation failure is reported as a conflict
+pub const SERIALIZATION_ATTEMPTS: u32 = 3;
+/// number of retries after serialisation failures since startup
+pub static SERIALIZATION_RETRIES: std::sync::atomic::AtomicU64 =
+    std::sync::atomic::AtomicU64::new(0);
+
+/// begins a transaction with the isolation level configured for merges (`LTZF_MERGE_ISOLATION`)
+pub async fn begin_merge(
+    server: &crate::LTZFServer,
+) -> crate::Result<sqlx::PgTransaction<'static>> {
+    let mut tx = server.sqlx_db.begin().await?;
+    set_merge_isolation(&mut tx, server).await?;
+    Ok(tx)
+}
+
+/// sets the isolation level configured for merges, has to be the first statement in `tx`
+pub async fn set_merge_isolation(
+    tx: &mut sqlx::PgTransaction<'_>,
+    server: &crate::LTZFServer,
+) -> crate::Result<()> {
+    let level = server.config.merge_isolation.as_sql();
+    sqlx::query(&format!("SET TRANSACTION ISOLATION LEVEL {level}"))
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// runs `operation` until it does not fail with a serialisation failure (SQLSTATE 40001),
+/// waiting 50-200ms between attempts. Every call of `operation` has to open its own transaction
+/// and must not have effects outside of it, e.g. warnings for the response are returned
+/// and reported by the caller once the operation succeeded.
+/// Returns `LTZFError::Conflict` if all attempts failed.
+pub async fn retry_on_conflict<T, F, Fut>(mut operation: F) -> crate::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = crate::Result<T>>,
+{
+    use rand::Rng;
+    for attempt in 1..=SERIALIZATION_ATTEMPTS {
+        match operation().await {
+            Err(e) if e.is_serialization_failure() => {
+                tracing::warn!(
+                    "Serialisation failure on attempt {attempt}/{SERIALIZATION_ATTEMPTS}: {e}"
+                );
+                if attempt < SERIALIZATION_ATTEMPTS {
+                    SERIALIZATION_RETRIES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let backoff = rand::rng().random_range(50..=200);
+                    tokio::time::sleep(std::time::Duration::from_millis(backoff)).await;
+                }
+            }
+            result => return result,
+        }
+    }
+    Err(crate::LTZFError::Conflict {
+        attempts: SERIALIZATION_ATTEMPTS,
+    })
+}
+
+#[cfg(test)]
+mod test_retry {
+    use super::{begin_merge, retry_on_conflict};
+    use crate::utils::testing::TestSetup;
+    use crate::{IsolationLevel, LTZFError};
+
+    async fn fail_serialization(server: &crate::LTZFServer) -> crate::Result<()> {
+        sqlx::query(
+            "DO $$ BEGIN RAISE EXCEPTION 'conflict' USING ERRCODE = 'serialization_failure'; END $$",
+        )
+        .execute(&server.sqlx_db)
+        .await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_retry_on_conflict() {
+        let scenario = TestSetup::new("test_retry_on_conflict").await;
+        let server = &scenario.server;
+        let mut calls = 0;
+        let result = retry_on_conflict(|| {
+            calls += 1;
+            let fail = calls < 3;
+            async move {
+                if fail {
+                    fail_serialization(server).await
+                } else {
+                    Ok(())
+                }
+            }
+        })
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(calls, 3);
+
+        let result = retry_on_conflict(|| fail_serialization(server)).await;
+        assert!(matches!(result, Err(LTZFError::Conflict { attempts: 3 })));
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_merge_isolation() {
+        let mut scenario = TestSetup::new("test_merge_isolation").await;
+        for (level, expected) in [
+            (IsolationLevel::ReadCommitted, "read committed"),
+            (IsolationLevel::RepeatableRead, "repeatable read"),
+            (IsolationLevel::Serializable, "serializable"),
+        ] {
+            scenario.server.config.merge_isolation = level;
+            let mut tx = begin_merge(&scenario.server).await.unwrap();
+            let actual: String = sqlx::query_scalar("SHOW transaction_isolation")
+                .fetch_one(&mut *tx)
+                .await
+                .unwrap();
+            assert_eq!(actual, expected);
+            tx.rollback().await.unwrap();
+        }
+        scenario.teardown().await;
+    }
+}
+
+#[cfg(test)]
+mod test_similarity {
+    use crate::utils::testing::TestSetup;
+
+    #[tokio::test]
+    async fn test_levenshtein_long_strings() {
+        let scenario = TestSetup::new("test_levenshtein_long_strings").await;
+        let long = "Gesetz zur Änderung ".repeat(20);
+        for (a, b) in [("abcd", "abce"), (long.as_str(), long.as_str())] {
+            let sim: f32 = sqlx::query_scalar("SELECT ltzf_similarity($1, $2, 'levenshtein')")
+                .bind(a)
+                .bind(b)
+                .fetch_one(&scenario.server.sqlx_db)
+                .await
+                .unwrap();
+            assert!(sim > 0.7, "{a} / {b}: {sim}");
+        }
+        scenario.teardown().await;
+    }
+}
@@ -1,6 +1,9 @@
+use std::collections::HashMap;
 use std::str::FromStr;
 
 use crate::api::PaginationResponsePart;
+use crate::api::aenderungen::Aenderung;
+use crate::api::auth::APIScope;
 use crate::error::*;
 use crate::utils::as_option;
 use openapi::models;
@@ -10,218 +13,319 @@ pub async fn vorgang_by_id(
     id: i32,
     executor: &mut sqlx::PgTransaction<'_>,
 ) -> Result<models::Vorgang> {
-    let pre_vg = sqlx::query!(
+    let mut found = vorgaenge_by_ids(&[id], executor).await?;
+    Ok(found.remove(&id).ok_or(sqlx::Error::RowNotFound)?)
+}
+
+/// the Vorgänge with the given ids, fetched with a fixed number of queries independent of their number.
+/// Ids without a Vorgang are missing from the result
+pub async fn vorgaenge_by_ids(
+    ids: &[i32],
+    executor: &mut sqlx::PgTransaction<'_>,
+) -> Result<HashMap<i32, models::Vorgang>> {
+    let pre_vgs = sqlx::query!(
         "SELECT v.*, vt.value FROM vorgang v
         INNER JOIN vorgangstyp vt ON vt.id = v.typ
-        WHERE v.id = $1",
-        id
+        WHERE v.id = ANY($1::int4[])",
+        ids
     )
-    .fetch_one(&mut **executor)
+    .fetch_all(&mut **executor)
     .await?;
 
-    let links = sqlx::query!(
-        "SELECT link FROM rel_vorgang_links WHERE vg_id = $1 ORDER BY link ASC",
-        id
+    let mut links: HashMap<i32, Vec<String>> = HashMap::new();
+    sqlx::query!(
+        "SELECT vg_id, link FROM rel_vorgang_links WHERE vg_id = ANY($1::int4[]) ORDER BY link ASC",
+        ids
     )
-    .map(|row| row.link)
     .fetch_all(&mut **executor)
-    .await?;
+    .await?
+    .into_iter()
+    .for_each(|r| links.entry(r.vg_id).or_default().push(r.link));
 
-    let init_inst = sqlx::query!(
-        "SELECT a.* FROM rel_vorgang_init 
-        INNER JOIN autor a ON a.id = in_id
-        WHERE vg_id = $1 ORDER BY organisation ASC",
-        id
+    let mut init_inst: HashMap<i32, Vec<models::Autor>> = HashMap::new();
+    sqlx::query!(
+        "SELECT r.vg_id, a.* FROM rel_vorgang_init r
+        INNER JOIN autor a ON a.id = r.in_id
+        WHERE r.vg_id = ANY($1::int4[]) ORDER BY a.organisation ASC",
+        ids
     )
-    .map(|row| models::Autor {
-        fachgebiet: row.fachgebiet,
-        lobbyregister: row.lobbyregister,
-        organisation: row.organisation,
-        person: row.person,
-    })
     .fetch_all(&mut **executor)
-    .await?;
+    .await?
+    .into_iter()
+    .for_each(|row| {
+        init_inst.entry(row.vg_id).or_default().push(models::Autor {
+            fachgebiet: row.fachgebiet,
+            lobbyregister: row.lobbyregister,
+            organisation: row.organisation,
+            person: row.person,
+        })
+    });
 
-    let ids = sqlx::query!(
+    let mut vg_ids: HashMap<i32, Vec<models::VgIdent>> = HashMap::new();
+    sqlx::query!(
         "
-    SELECT value as typ, identifikator as ident 
+    SELECT r.vg_id, value as typ, identifikator as ident 
     FROM rel_vorgang_ident r
     INNER JOIN vg_ident_typ t ON t.id = r.typ
-    WHERE r.vg_id = $1
+    WHERE r.vg_id = ANY($1::int4[])
     ORDER BY ident ASC",
-        id
+        ids
     )
-    .map(|row| models::VgIdent {
-        typ: models::VgIdentTyp::from_str(row.typ.as_str()).unwrap_or_else(|_| {
-            panic!(
-                "Could not convert database value `{}`into VgIdentTyp Variant",
-                row.typ
-            )
-        }),
-        id: row.ident,
-    })
     .fetch_all(&mut **executor)
-    .await?;
+    .await?
+    .into_iter()
+    .for_each(|row| {
+        vg_ids.entry(row.vg_id).or_default().push(models::VgIdent {
+            typ: models::VgIdentTyp::from_str(row.typ.as_str()).unwrap_or_else(|_| {
+                panic!(
+                    "Could not convert database value `{}`into VgIdentTyp Variant",
+                    row.typ
+                )
+            }),
+            id: row.ident,
+        })
+    });
 
-    let station_ids = sqlx::query!("SELECT id FROM station WHERE vg_id = $1", id)
-        .map(|row| row.id)
-        .fetch_all(&mut **executor)
-        .await?;
+    let mut aendert: HashMap<i32, Vec<Aenderung>> = HashMap::new();
+    sqlx::query!(
+        "SELECT r.amending_vg_id, v.api_id, r.article_ref, r.in_force_since FROM rel_vorgang_aendert r
+        INNER JOIN vorgang v ON v.id = r.amended_vg_id
+        WHERE r.amending_vg_id = ANY($1::int4[])
+        ORDER BY v.api_id ASC",
+        ids
+    )
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| {
+        aendert
+            .entry(r.amending_vg_id)
+            .or_default()
+            .push(Aenderung {
+                api_id: r.api_id,
+                article_ref: r.article_ref,
+                in_force_since: r.in_force_since,
+            })
+    });
 
-    let mut stationen = vec![];
-    for sid in station_ids {
-        stationen.push(station_by_id(sid, executor).await?);
-    }
-    stationen.sort_by(|a, b| a.zp_start.cmp(&b.zp_start));
+    let mut stationen = stationen_by_vg_ids(ids, executor).await?;
 
     // lobbyregistereinträge
-    let mut lobbyreg_records =
-        sqlx::query!("SELECT * FROM lobbyregistereintrag WHERE vg_id = $1", id)
-            .map(|r| {
-                (
-                    r.id,
-                    models::Lobbyregeintrag {
-                        intention: r.intention,
-                        organisation: models::Autor {
-                            fachgebiet: None,
-                            lobbyregister: None,
-                            organisation: "".to_string(),
-                            person: None,
-                        },
-                        link: r.link,
-                        interne_id: r.interne_id,
-                        betroffene_drucksachen: vec![],
-                    },
-                    r.organisation,
-                )
-            })
-            .fetch_all(&mut **executor)
-            .await?;
-    let mut lobbyregs = vec![];
-    for (id, object, org_id) in lobbyreg_records.drain(..) {
-        let drucks = sqlx::query!(
-            "SELECT drucksnr FROM rel_lobbyreg_drucksnr WHERE lob_id = $1",
-            id
-        )
-        .map(|r| r.drucksnr)
-        .fetch_all(&mut **executor)
-        .await?;
-        lobbyregs.push(models::Lobbyregeintrag {
-            organisation: sqlx::query!("SELECT * FROM autor WHERE id = $1", org_id)
-                .map(|r| models::Autor {
+    let lobbyreg_records = sqlx::query!(
+        "SELECT l.id, l.vg_id, l.intention, l.link, l.interne_id,
+        a.fachgebiet, a.lobbyregister, a.organisation, a.person
+        FROM lobbyregistereintrag l
+        INNER JOIN autor a ON a.id = l.organisation
+        WHERE l.vg_id = ANY($1::int4[]) ORDER BY l.id ASC",
+        ids
+    )
+    .fetch_all(&mut **executor)
+    .await?;
+    let lob_ids: Vec<i32> = lobbyreg_records.iter().map(|r| r.id).collect();
+    let mut drucks: HashMap<i32, Vec<String>> = HashMap::new();
+    sqlx::query!(
+        "SELECT lob_id, drucksnr FROM rel_lobbyreg_drucksnr WHERE lob_id = ANY($1::int4[])",
+        &lob_ids[..]
+    )
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| drucks.entry(r.lob_id).or_default().push(r.drucksnr));
+    let mut lobbyregs: HashMap<i32, Vec<models::Lobbyregeintrag>> = HashMap::new();
+    for r in lobbyreg_records {
+        lobbyregs
+            .entry(r.vg_id)
+            .or_default()
+            .push(models::Lobbyregeintrag {
+                intention: r.intention,
+                organisation: models::Autor {
                     fachgebiet: r.fachgebiet,
                     lobbyregister: r.lobbyregister,
                     organisation: r.organisation,
                     person: r.person,
-                })
-                .fetch_one(&mut **executor)
-                .await?,
-            betroffene_drucksachen: drucks,
-            ..object
-        });
+                },
+                link: r.link,
+                interne_id: r.interne_id,
+                betroffene_drucksachen: drucks.remove(&r.id).unwrap_or_default(),
+            });
     }
 
-    Ok(models::Vorgang {
-        touched_by: None,
-        lobbyregister: as_option(lobbyregs),
-        api_id: pre_vg.api_id,
-        titel: pre_vg.titel,
-        kurztitel: pre_vg.kurztitel,
-        wahlperiode: pre_vg.wahlperiode as u32,
-        verfassungsaendernd: pre_vg.verfaend,
-        typ: models::Vorgangstyp::from_str(pre_vg.value.as_str())
-            .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
-        initiatoren: init_inst,
-        ids: as_option(ids),
-        links: Some(links),
-        stationen,
-    })
+    let mut vorgaenge = HashMap::with_capacity(pre_vgs.len());
+    for pre_vg in pre_vgs {
+        let id = pre_vg.id;
+        // not in the generated model, see `utils::spec_ext`
+        if let Some(aendert) = aendert.remove(&id) {
+            crate::utils::spec_ext::extend_object(pre_vg.api_id, "aendert", aendert);
+        }
+        vorgaenge.insert(
+            id,
+            models::Vorgang {
+                touched_by: None,
+                lobbyregister: as_option(lobbyregs.remove(&id).unwrap_or_default()),
+                api_id: pre_vg.api_id,
+                titel: pre_vg.titel,
+                kurztitel: pre_vg.kurztitel,
+                wahlperiode: pre_vg.wahlperiode as u32,
+                verfassungsaendernd: pre_vg.verfaend,
+                typ: models::Vorgangstyp::from_str(pre_vg.value.as_str())
+                    .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
+                initiatoren: init_inst.remove(&id).unwrap_or_default(),
+                ids: as_option(vg_ids.remove(&id).unwrap_or_default()),
+                links: Some(links.remove(&id).unwrap_or_default()),
+                stationen: stationen.remove(&id).unwrap_or_default(),
+            },
+        );
+    }
+    Ok(vorgaenge)
 }
 
-pub async fn station_by_id(
+/// the Vorgänge amended by the Vorgang `id`
+pub async fn vorgang_aendert(
     id: i32,
     executor: &mut sqlx::PgTransaction<'_>,
-) -> Result<models::Station> {
-    let doks = sqlx::query!(
-        "SELECT d.api_id FROM rel_station_dokument rsd
-        INNER JOIN dokument d ON d.id = rsd.dok_id
-        WHERE rsd.stat_id = $1
-        ORDER BY d.link ASC",
+) -> Result<Vec<Aenderung>> {
+    Ok(sqlx::query!(
+        "SELECT v.api_id, r.article_ref, r.in_force_since FROM rel_vorgang_aendert r
+        INNER JOIN vorgang v ON v.id = r.amended_vg_id
+        WHERE r.amending_vg_id = $1
+        ORDER BY v.api_id ASC",
         id
     )
-    .map(|r| models::StationDokumenteInner::String(r.api_id.to_string()))
-    .fetch_all(&mut **executor)
-    .await?;
-    let stellungnahmen = sqlx::query!(
-        "SELECT api_id FROM rel_station_stln rss 
-        INNER JOIN dokument d ON d.id = rss.dok_id 
-        WHERE rss.stat_id = $1
-        ORDER BY d.link ASC",
-        id
-    )
-    .map(|r| models::StationDokumenteInner::String(r.api_id.to_string()))
+    .map(|r| Aenderung {
+        api_id: r.api_id,
+        article_ref: r.article_ref,
+        in_force_since: r.in_force_since,
+    })
     .fetch_all(&mut **executor)
-    .await?;
-    let sw = sqlx::query!(
-        "SELECT DISTINCT(value) FROM rel_station_schlagwort r
-        LEFT JOIN schlagwort sw ON sw.id = r.sw_id
-        WHERE r.stat_id = $1
-        ORDER BY value ASC",
+    .await?)
+}
+
+/// the Vorgänge amending the Vorgang `id`, in the same shape as `vorgang_aendert`
+pub async fn vorgang_geaendert_durch(
+    id: i32,
+    executor: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<Aenderung>> {
+    Ok(sqlx::query!(
+        "SELECT v.api_id, r.article_ref, r.in_force_since FROM rel_vorgang_aendert r
+        INNER JOIN vorgang v ON v.id = r.amending_vg_id
+        WHERE r.amended_vg_id = $1
+        ORDER BY v.api_id ASC",
         id
     )
-    .map(|sw| sw.value)
+    .map(|r| Aenderung {
+        api_id: r.api_id,
+        article_ref: r.article_ref,
+        in_force_since: r.in_force_since,
+    })
     .fetch_all(&mut **executor)
-    .await?;
+    .await?)
+}
 
-    let add_links = sqlx::query!("SELECT link FROM rel_station_link WHERE stat_id = $1", id)
-        .map(|r| r.link)
-        .fetch_all(&mut **executor)
-        .await?;
-    let temp_stat = sqlx::query!(
-        "SELECT s.*, p.value as parlv, st.value as stattyp
+/// the Stationen of the Vorgänge `vg_ids` by Vorgang, ordered by `zp_start`
+async fn stationen_by_vg_ids(
+    vg_ids: &[i32],
+    executor: &mut sqlx::PgTransaction<'_>,
+) -> Result<HashMap<i32, Vec<models::Station>>> {
+    let stations = sqlx::query!(
+        "SELECT s.*, p.value as parlv, st.value as stattyp,
+        g.name as gremium_name, g.wp as gremium_wp, g.link as gremium_link
         FROM station s
         INNER JOIN gremium g ON g.id = s.gr_id
         INNER JOIN parlament p ON p.id = g.parl
         INNER JOIN stationstyp st ON st.id = s.typ
-        WHERE s.id=$1",
-        id
+        WHERE s.vg_id = ANY($1::int4[])
+        ORDER BY s.zp_start ASC, s.id ASC",
+        vg_ids
     )
-    .fetch_one(&mut **executor)
+    .fetch_all(&mut **executor)
     .await?;
+    let ids: Vec<i32> = stations.iter().map(|s| s.id).collect();
 
-    let gremium = sqlx::query!(
-        "SELECT p.value, g.name, g.wp, g.link 
-        FROM gremium g INNER JOIN parlament p on p.id = g.parl
-        WHERE g.id = $1",
-        temp_stat.gr_id
+    let mut doks: HashMap<i32, Vec<models::StationDokumenteInner>> = HashMap::new();
+    sqlx::query!(
+        "SELECT rsd.stat_id, d.api_id FROM rel_station_dokument rsd
+        INNER JOIN dokument d ON d.id = rsd.dok_id
+        WHERE rsd.stat_id = ANY($1::int4[])
+        ORDER BY d.link ASC",
+        &ids[..]
     )
-    .map(|x| models::Gremium {
-        name: x.name,
-        wahlperiode: x.wp as u32,
-        parlament: models::Parlament::from_str(&x.value).unwrap(),
-        link: x.link,
-    })
-    .fetch_one(&mut **executor)
-    .await?;
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| {
+        doks.entry(r.stat_id)
+            .or_default()
+            .push(models::StationDokumenteInner::String(r.api_id.to_string()))
+    });
+    let mut stellungnahmen: HashMap<i32, Vec<models::StationDokumenteInner>> = HashMap::new();
+    sqlx::query!(
+        "SELECT rss.stat_id, d.api_id FROM rel_station_stln rss 
+        INNER JOIN dokument d ON d.id = rss.dok_id 
+        WHERE rss.stat_id = ANY($1::int4[])
+        ORDER BY d.link ASC",
+        &ids[..]
+    )
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| {
+        stellungnahmen
+            .entry(r.stat_id)
+            .or_default()
+            .push(models::StationDokumenteInner::String(r.api_id.to_string()))
+    });
+    let mut sw: HashMap<i32, Vec<String>> = HashMap::new();
+    sqlx::query!(
+        "SELECT DISTINCT r.stat_id, sw.value FROM rel_station_schlagwort r
+        INNER JOIN schlagwort sw ON sw.id = r.sw_id
+        WHERE r.stat_id = ANY($1::int4[])
+        ORDER BY sw.value ASC",
+        &ids[..]
+    )
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| sw.entry(r.stat_id).or_default().push(r.value));
+    let mut add_links: HashMap<i32, Vec<String>> = HashMap::new();
+    sqlx::query!(
+        "SELECT stat_id, link FROM rel_station_link WHERE stat_id = ANY($1::int4[])",
+        &ids[..]
+    )
+    .fetch_all(&mut **executor)
+    .await?
+    .into_iter()
+    .for_each(|r| add_links.entry(r.stat_id).or_default().push(r.link));
 
-    Ok(models::Station {
-        touched_by: None,
-        typ: models::Stationstyp::from_str(temp_stat.stattyp.as_str())
-            .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
-        dokumente: doks,
-        schlagworte: as_option(sw),
-        stellungnahmen: as_option(stellungnahmen),
-        zp_start: temp_stat.zp_start,
-        zp_modifiziert: Some(temp_stat.zp_modifiziert),
-
-        trojanergefahr: temp_stat.trojanergefahr.map(|x| x as u8),
-        titel: temp_stat.titel,
-        gremium,
-        api_id: Some(temp_stat.api_id),
-        link: temp_stat.link,
-        additional_links: as_option(add_links),
-        gremium_federf: temp_stat.gremium_isff,
-    })
+    let mut stationen: HashMap<i32, Vec<models::Station>> = HashMap::new();
+    for temp_stat in stations {
+        let id = temp_stat.id;
+        let station = models::Station {
+            touched_by: None,
+            typ: models::Stationstyp::from_str(temp_stat.stattyp.as_str())
+                .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
+            dokumente: doks.remove(&id).unwrap_or_default(),
+            schlagworte: as_option(sw.remove(&id).unwrap_or_default()),
+            stellungnahmen: as_option(stellungnahmen.remove(&id).unwrap_or_default()),
+            zp_start: temp_stat.zp_start,
+            zp_modifiziert: Some(temp_stat.zp_modifiziert),
+
+            trojanergefahr: temp_stat.trojanergefahr.map(|x| x as u8),
+            titel: temp_stat.titel,
+            gremium: models::Gremium {
+                name: temp_stat.gremium_name,
+                wahlperiode: temp_stat.gremium_wp as u32,
+                parlament: models::Parlament::from_str(&temp_stat.parlv).unwrap(),
+                link: temp_stat.gremium_link,
+            },
+            api_id: Some(temp_stat.api_id),
+            link: temp_stat.link,
+            additional_links: as_option(add_links.remove(&id).unwrap_or_default()),
+            gremium_federf: temp_stat.gremium_isff,
+            // admin-only, filled in by the GET handlers
+        };
+        stationen.entry(temp_stat.vg_id).or_default().push(station);
+    }
+    Ok(stationen)
 }
 
 pub async fn dokument_by_id(
@@ -285,11 +389,48 @@ pub async fn dokument_by_id(
         typ: models::Doktyp::from_str(rec.typ_value.as_str())
             .map_err(|e| DataValidationError::InvalidEnumValue { msg: e })?,
         drucksnr: rec.drucksnr,
+        // admin-only, filled in by `dokument_by_id_scoped` and the GET handlers
     })
 }
 
+/// like `dokument_by_id`, but additionally populates `touched_by` and `created_at`
+/// if the caller has a privileged scope (Admin/KeyAdder)
+pub async fn dokument_by_id_scoped(
+    id: i32,
+    scope: APIScope,
+    executor: &mut sqlx::PgTransaction<'_>,
+) -> Result<models::Dokument> {
+    let mut dok = dokument_by_id(id, executor).await?;
+    if scope == APIScope::Admin || scope == APIScope::KeyAdder {
+        dok.touched_by = as_option(
+            sqlx::query!(
+                "SELECT api_keys.key_hash, std.scraper FROM scraper_touched_dokument std
+                INNER JOIN api_keys ON api_keys.id = std.collector_key
+                WHERE dok_id = $1",
+                id
+            )
+            .map(|r| models::TouchedByInner {
+                key: Some(r.key_hash),
+                scraper_id: Some(r.scraper),
+            })
+            .fetch_all(&mut **executor)
+            .await?,
+        );
+        // not in the generated model, see `utils::spec_ext`
+        let created_at = sqlx::query!("SELECT created_at FROM dokument WHERE id = $1", id)
+            .map(|r| r.created_at)
+            .fetch_one(&mut **executor)
+            .await?;
+        if let Some(api_id) = dok.api_id {
+            crate::utils::spec_ext::extend_object(api_id, "created_at", created_at);
+        }
+    }
+    Ok(dok)
+}
+
 /// the crucial part is how to find out which vg are connected to a DRCKS
-/// if there exists a station which contains a document mentioned in the top, its vorgang is connected
+/// if there exists a station which contains a document mentioned in the top, its vorgang is connected.
+/// Vorgänge linked explicitly via `rel_top_vorgang` are connected as well
 pub async fn top_by_id(id: i32, tx: &mut sqlx::PgTransaction<'_>) -> Result<models::Top> {
     let scaffold = sqlx::query!("SELECT titel, nummer FROM top WHERE id = $1", id)
         .fetch_one(&mut **tx)
@@ -321,6 +462,9 @@ EXISTS ( 									-- mit denen mindestens ein dokument assoziiert ist, dass hier
 	SELECT 1 FROM rel_station_stln rss
 	INNER JOIN tops_doks td ON td.dok_id = rss.dok_id
 	WHERE td.top_id = $1 AND rss.stat_id = s.id
+) OR EXISTS(                                -- oder die explizit verknüpft sind
+    SELECT 1 FROM rel_top_vorgang rtv
+    WHERE rtv.top_id = $1 AND rtv.vg_id = v.id
 )
     ORDER BY api_id ASC",
         id
@@ -342,7 +486,7 @@ pub async fn sitzung_by_id(id: i32, tx: &mut sqlx::PgTransaction<'_>) -> Result<
         "SELECT a.api_id, a.public, a.termin, p.value as plm, a.link as as_link, a.titel, a.nummer,
         g.name as grname, g.wp, g.link as gr_link FROM sitzung a
         INNER JOIN gremium g ON g.id = a.gr_id
-        INNER JOIN parlament p ON p.id = g.parl 
+        INNER JOIN parlament p ON p.id = a.p_id
         WHERE a.id = $1",
         id
     )
@@ -402,13 +546,15 @@ pub async fn sitzung_by_id(id: i32, tx: &mut sqlx::PgTransaction<'_>) -> Result<
         link: scaffold.as_link,
         experten: as_option(experten),
         dokumente: as_option(dids),
+        // admin-only, filled in by the GET handlers
     })
 }
 
 pub struct SitzungFilterParameters {
     pub since: Option<chrono::DateTime<chrono::Utc>>,
     pub until: Option<chrono::DateTime<chrono::Utc>>,
-    pub parlament: Option<models::Parlament>,
+    /// matches any of the given Parlamente
+    pub parlament: Option<Vec<models::Parlament>>,
     pub wp: Option<u32>,
     pub vgid: Option<Uuid>,
     pub gremium_like: Option<String>,
@@ -420,13 +566,17 @@ pub async fn sitzung_by_param(
     per_page: Option<i32>,
     tx: &mut sqlx::PgTransaction<'_>,
 ) -> Result<(PaginationResponsePart, Vec<models::Sitzung>)> {
+    let parlamente: Option<Vec<String>> = params
+        .parlament
+        .as_ref()
+        .map(|ps| ps.iter().map(|p| p.to_string()).collect());
     let mut as_list = sqlx::query!(
         "
       WITH pre_table AS (
         SELECT a.id, MAX(a.termin) as lastmod FROM  sitzung a
 		INNER JOIN gremium g ON g.id = a.gr_id
-		INNER JOIN parlament p ON p.id = g.parl
-		WHERE p.value = COALESCE($1, p.value)
+		INNER JOIN parlament p ON p.id = a.p_id
+		WHERE ($1::text[] IS NULL OR p.value = ANY($1::text[]))
 		AND g.wp      = COALESCE($2, g.wp)
         AND ($5::text IS NULL OR g.name LIKE CONCAT('%', $5, '%'))
         GROUP BY a.id
@@ -440,6 +590,11 @@ pub async fn sitzung_by_param(
 		LEFT JOIN rel_station_stln rss ON rss.dok_id = tops_doks.dok_id
 		INNER JOIN station s ON s.id = rsd.stat_id OR s.id = rss.stat_id
 		INNER JOIN vorgang v ON s.vg_id = v.id
+		UNION
+		SELECT p.id, v.api_id FROM pre_table p
+		INNER JOIN top on top.sid = p.id
+		INNER JOIN rel_top_vorgang rtv ON rtv.top_id = top.id
+		INNER JOIN vorgang v ON v.id = rtv.vg_id
 	)
 
 SELECT * FROM pre_table WHERE
@@ -447,7 +602,7 @@ lastmod > COALESCE($3::timestamptz,'1940-01-01T20:20:20Z') AND
 lastmod < COALESCE($4, NOW()) AND
 ($6::uuid IS NULL OR EXISTS (SELECT 1 FROM vgref WHERE pre_table.id = vgref.id AND vgref.api_id = COALESCE($6, vgref.api_id)))
 ORDER BY pre_table.lastmod ASC",
-        params.parlament.map(|p| p.to_string()),
+        parlamente.as_deref(),
         params.wp.map(|x|x as i32),
         params.since,
         params.until,
@@ -470,16 +625,95 @@ ORDER BY pre_table.lastmod ASC",
     Ok((prp, vector))
 }
 
+/// the fields a Vorgang listing can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortField {
+    /// the latest start of any Station
+    ZpLastUpdate,
+    Wahlperiode,
+    Titel,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortDirection {
+    Asc,
+    Desc,
+}
+impl SortField {
+    /// the key `vorgang_by_parameter` matches in its ORDER BY clause
+    fn sort_key(&self, direction: SortDirection) -> &'static str {
+        match (self, direction) {
+            (SortField::ZpLastUpdate, SortDirection::Asc) => "zp_last_update:asc",
+            (SortField::ZpLastUpdate, SortDirection::Desc) => "zp_last_update:desc",
+            (SortField::Wahlperiode, SortDirection::Asc) => "wahlperiode:asc",
+            (SortField::Wahlperiode, SortDirection::Desc) => "wahlperiode:desc",
+            (SortField::Titel, SortDirection::Asc) => "titel:asc",
+            (SortField::Titel, SortDirection::Desc) => "titel:desc",
+        }
+    }
+}
+
+/// parses `field:direction` pairs like `zp_last_update:desc,wahlperiode:asc`.
+/// The direction defaults to ascending, unknown fields or directions are rejected.
+/// Repetitions of a field are ignored, since the first one already decides the order.
+pub fn parse_sort(sort: &str) -> Result<Vec<(SortField, SortDirection)>> {
+    let invalid = |message: String| DataValidationError::InvalidQueryParameter {
+        parameter: "sort".to_string(),
+        message,
+    };
+    let mut order = vec![];
+    for entry in sort.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (field, direction) = entry.split_once(':').unwrap_or((entry, "asc"));
+        let field = match field.trim() {
+            "zp_last_update" => SortField::ZpLastUpdate,
+            "wahlperiode" => SortField::Wahlperiode,
+            "titel" => SortField::Titel,
+            other => return Err(invalid(format!("unknown sort field `{other}`")).into()),
+        };
+        let direction = match direction.trim() {
+            "asc" => SortDirection::Asc,
+            "desc" => SortDirection::Desc,
+            other => return Err(invalid(format!("unknown sort direction `{other}`")).into()),
+        };
+        if !order.iter().any(|(f, _)| *f == field) {
+            order.push((field, direction));
+        }
+    }
+    Ok(order)
+}
+
 #[derive(Debug)]
 pub struct VGGetParameters {
     pub lower_date: Option<chrono::DateTime<chrono::Utc>>,
     pub upper_date: Option<chrono::DateTime<chrono::Utc>>,
-    pub parlament: Option<models::Parlament>,
+    /// matches any of the given Parlamente
+    pub parlament: Option<Vec<models::Parlament>>,
     pub wp: Option<i32>,
     pub inipsn: Option<String>,
     pub iniorg: Option<String>,
     pub inifch: Option<String>,
     pub vgtyp: Option<models::Vorgangstyp>,
+    /// every one of these or one of its descendants has to be attached to some Station of the Vorgang
+    pub schlagwort: Option<Vec<String>>,
+    /// at least one of these or one of their descendants has to be attached to some Station of the Vorgang
+    pub schlagwort_any: Option<Vec<String>>,
+    /// lower bound for the `meinung` of all Stellungnahmen of the Vorgang.
+    /// If one of the bounds is set, only Vorgänge with at least one rated Stellungnahme match.
+    pub min_meinung: Option<i32>,
+    /// upper bound for the `meinung` of all Stellungnahmen of the Vorgang
+    pub max_meinung: Option<i32>,
+    /// some Station of the Vorgang has at least this `trojanergefahr`
+    pub trojanergefahr_min: Option<i32>,
+    /// the Vorgang or one of its Stationen changed after this point in time
+    pub modified_since: Option<chrono::DateTime<chrono::Utc>>,
+    /// attached to some Dokument of a Station of the Vorgang, Stellungnahmen are not considered
+    pub dok_schlagwort: Option<String>,
+    /// one of the Initiatoren belongs to this Fraktion
+    pub initiator_fraktion: Option<String>,
+    /// bounds for the time the Vorgang was first stored
+    pub created_since: Option<chrono::DateTime<chrono::Utc>>,
+    pub created_until: Option<chrono::DateTime<chrono::Utc>>,
+    /// applied in order before pagination, empty means `zp_last_update` descending
+    pub sort: Vec<(SortField, SortDirection)>,
 }
 /// returns (total number of available elements, chosen elements)
 pub async fn vorgang_by_parameter(
@@ -488,9 +722,27 @@ pub async fn vorgang_by_parameter(
     per_page: Option<i32>,
     executor: &mut sqlx::PgTransaction<'_>,
 ) -> Result<(PaginationResponsePart, Vec<models::Vorgang>)> {
-    let mut vg_list = sqlx::query!(
-        "WITH pre_table AS (
-        SELECT vorgang.id, MAX(ext_stat.zp_start) as lastmod FROM vorgang
+    let parlamente: Option<Vec<String>> = params
+        .parlament
+        .as_ref()
+        .map(|ps| ps.iter().map(|p| p.to_string()).collect());
+    // sorting happens on the whitelisted fields only, the id keeps the order stable between pages
+    let sort: Vec<_> = if params.sort.is_empty() {
+        vec![SortField::ZpLastUpdate.sort_key(SortDirection::Desc)]
+    } else {
+        params.sort.iter().map(|(f, d)| f.sort_key(*d)).collect()
+    };
+    // page and per_page as requested, the page is clamped to the last one in the query
+    let requested = PaginationResponsePart::new(i32::MAX, page, per_page);
+    let vg_list = sqlx::query!(
+        "WITH RECURSIVE sw_tree(root, id) AS (
+            -- every requested Schlagwort paired with itself and all of its descendants
+            SELECT value, id FROM schlagwort WHERE value = ANY($9::text[] || $10::text[])
+            UNION
+            SELECT t.root, c.id FROM sw_tree t INNER JOIN schlagwort c ON c.parent_id = t.id
+        ),
+        pre_table AS (
+        SELECT vorgang.id, vorgang.wahlperiode, vorgang.titel, MAX(ext_stat.zp_start) as lastmod FROM vorgang
             INNER JOIN vorgangstyp vt ON vt.id = vorgang.typ
             LEFT JOIN (SELECT s.vg_id, parlament.value as parl, s.zp_start FROM station s
             INNER JOIN gremium g ON g.id = s.gr_id
@@ -498,32 +750,80 @@ pub async fn vorgang_by_parameter(
             WHERE TRUE
             AND ($1::int4 IS NULL OR $1 = vorgang.wahlperiode)
             AND ($2::text IS NULL OR $2 = vt.value)
-            AND ($3::text IS NULL OR $3 = ext_stat.parl)
-			AND ($4::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.person LIKE CONCAT('%',$4::text,'%') AND rvi.vg_id = vorgang.id))
-			AND ($5::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.organisation  LIKE CONCAT('%',$5::text,'%') AND rvi.vg_id = vorgang.id))
-			AND ($6::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.fachgebiet  LIKE CONCAT('%',$6::text,'%') AND rvi.vg_id = vorgang.id))
+            AND ($3::text[] IS NULL OR ext_stat.parl = ANY($3::text[]))
+			AND ($4::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.person ILIKE CONCAT('%',$4::text,'%') AND rvi.vg_id = vorgang.id))
+			AND ($5::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.organisation ILIKE CONCAT('%',$5::text,'%') AND rvi.vg_id = vorgang.id))
+			AND ($6::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id WHERE a.fachgebiet ILIKE CONCAT('%',$6::text,'%') AND rvi.vg_id = vorgang.id))
+			AND ($9::text[] IS NULL OR NOT EXISTS(SELECT 1 FROM UNNEST($9::text[]) AS req(value) WHERE NOT EXISTS(
+                SELECT 1 FROM station s INNER JOIN rel_station_schlagwort rss ON rss.stat_id = s.id INNER JOIN sw_tree t ON t.id = rss.sw_id
+                WHERE s.vg_id = vorgang.id AND t.root = req.value)))
+			AND ($10::text[] IS NULL OR EXISTS(
+                SELECT 1 FROM station s INNER JOIN rel_station_schlagwort rss ON rss.stat_id = s.id INNER JOIN sw_tree t ON t.id = rss.sw_id
+                WHERE s.vg_id = vorgang.id AND t.root = ANY($10::text[])))
+			AND (($11::int4 IS NULL AND $12::int4 IS NULL) OR vorgang.id IN(
+                SELECT s.vg_id FROM station s INNER JOIN rel_station_stln rsl ON rsl.stat_id = s.id INNER JOIN dokument d ON d.id = rsl.dok_id
+                WHERE d.meinung IS NOT NULL
+                GROUP BY s.vg_id
+                HAVING MIN(d.meinung) >= COALESCE($11, 1) AND MAX(d.meinung) <= COALESCE($12, 10)))
+			AND ($13::int4 IS NULL OR EXISTS(SELECT 1 FROM station s WHERE s.vg_id = vorgang.id AND s.trojanergefahr >= $13))
+			AND ($14::timestamptz IS NULL OR vorgang.last_update > $14)
+			AND ($15::text IS NULL OR EXISTS(
+                SELECT 1 FROM station s INNER JOIN rel_station_dokument rsd ON rsd.stat_id = s.id
+                INNER JOIN rel_dok_schlagwort rds ON rds.dok_id = rsd.dok_id INNER JOIN schlagwort sw ON sw.id = rds.sw_id
+                WHERE s.vg_id = vorgang.id AND sw.value = $15))
+			AND ($16::text IS NULL OR EXISTS(SELECT 1 FROM rel_vorgang_init rvi INNER JOIN autor a ON a.id = rvi.in_id
+                INNER JOIN fraktion f ON f.id = a.fraktion WHERE rvi.vg_id = vorgang.id AND f.value = $16))
+			AND ($17::timestamptz IS NULL OR vorgang.created_at >= $17)
+			AND ($18::timestamptz IS NULL OR vorgang.created_at <= $18)
         GROUP BY vorgang.id
-        ORDER BY lastmod
+        ),
+        numbered AS (
+        SELECT id, COUNT(1) OVER () AS total, ROW_NUMBER() OVER (ORDER BY
+            CASE WHEN ($19::text[])[1] = 'zp_last_update:asc' THEN lastmod END ASC,
+            CASE WHEN ($19::text[])[1] = 'zp_last_update:desc' THEN lastmod END DESC,
+            CASE WHEN ($19::text[])[1] = 'wahlperiode:asc' THEN wahlperiode END ASC,
+            CASE WHEN ($19::text[])[1] = 'wahlperiode:desc' THEN wahlperiode END DESC,
+            CASE WHEN ($19::text[])[1] = 'titel:asc' THEN titel END ASC,
+            CASE WHEN ($19::text[])[1] = 'titel:desc' THEN titel END DESC,
+            CASE WHEN ($19::text[])[2] = 'zp_last_update:asc' THEN lastmod END ASC,
+            CASE WHEN ($19::text[])[2] = 'zp_last_update:desc' THEN lastmod END DESC,
+            CASE WHEN ($19::text[])[2] = 'wahlperiode:asc' THEN wahlperiode END ASC,
+            CASE WHEN ($19::text[])[2] = 'wahlperiode:desc' THEN wahlperiode END DESC,
+            CASE WHEN ($19::text[])[2] = 'titel:asc' THEN titel END ASC,
+            CASE WHEN ($19::text[])[2] = 'titel:desc' THEN titel END DESC,
+            CASE WHEN ($19::text[])[3] = 'zp_last_update:asc' THEN lastmod END ASC,
+            CASE WHEN ($19::text[])[3] = 'zp_last_update:desc' THEN lastmod END DESC,
+            CASE WHEN ($19::text[])[3] = 'wahlperiode:asc' THEN wahlperiode END ASC,
+            CASE WHEN ($19::text[])[3] = 'wahlperiode:desc' THEN wahlperiode END DESC,
+            CASE WHEN ($19::text[])[3] = 'titel:asc' THEN titel END ASC,
+            CASE WHEN ($19::text[])[3] = 'titel:desc' THEN titel END DESC,
+            id ASC) AS rn
+        FROM pre_table WHERE
+        lastmod > COALESCE($7::timestamptz, '1940-01-01T20:20:20Z')
+        AND lastmod < COALESCE($8, NOW())
         )
-SELECT * FROM pre_table WHERE
-lastmod > COALESCE($7::timestamptz, '1940-01-01T20:20:20Z') 
-AND lastmod < COALESCE($8, NOW())
-ORDER BY pre_table.lastmod ASC
+SELECT id AS \"id!\", total AS \"total!\" FROM numbered
+WHERE rn > (LEAST($20::int8, CEIL(total::numeric / $21::int8)::int8) - 1) * $21
+ORDER BY rn ASC
+LIMIT $21
 ",params.wp, params.vgtyp.map(|x|x.to_string()),
-params.parlament.map(|p|p.to_string()),
+parlamente.as_deref(),
 params.inipsn, params.iniorg, params.inifch,
-params.lower_date, params.upper_date)
-    .map(|r|r.id)
+params.lower_date, params.upper_date,
+params.schlagwort.as_deref(), params.schlagwort_any.as_deref(),
+params.min_meinung, params.max_meinung,
+params.trojanergefahr_min, params.modified_since,
+params.dok_schlagwort, params.initiator_fraktion,
+params.created_since, params.created_until,
+&sort[..] as &[&str], requested.x_page as i64, requested.limit())
+    .map(|r|(r.id, r.total))
     .fetch_all(&mut **executor).await?;
-    let prp = PaginationResponsePart::new(vg_list.len() as i32, page, per_page);
-    if vg_list.is_empty() {
-        return Ok((prp, vec![]));
-    }
+    let total = vg_list.first().map(|r| r.1).unwrap_or(0);
+    let prp = PaginationResponsePart::new(total as i32, page, per_page);
 
-    let mut vector = Vec::with_capacity(vg_list.len());
-    for id in vg_list.drain(prp.start()..prp.end()) {
-        vector.push(super::retrieve::vorgang_by_id(id, executor).await?);
-    }
+    let ids: Vec<i32> = vg_list.iter().map(|r| r.0).collect();
+    let mut found = vorgaenge_by_ids(&ids, executor).await?;
+    let vector = ids.iter().filter_map(|id| found.remove(id)).collect();
     Ok((prp, vector))
 }
 
@@ -583,3 +883,163 @@ pub(crate) async fn count_existing_authors(
     .unwrap();
     Ok(existing_obj_cnt as usize)
 }
+
+/// replaces every `String` reference in `refs` with the referenced dokument,
+/// including the admin-only fields for privileged scopes (see `dokument_by_id_scoped`).
+/// References that cannot be resolved are left as they are and reported as not found
+/// in the response, see `utils::spec_ext::mark_unresolved`.
+pub async fn expand_dokument_refs(
+    refs: &mut [models::StationDokumenteInner],
+    scope: APIScope,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    for r in refs.iter_mut() {
+        let models::StationDokumenteInner::String(reference) = r else {
+            continue;
+        };
+        let Ok(api_id) = Uuid::from_str(reference) else {
+            tracing::warn!("Dokument reference `{}` is not a valid uuid", reference);
+            crate::utils::spec_ext::mark_unresolved(reference);
+            continue;
+        };
+        let did = sqlx::query!("SELECT id FROM dokument WHERE api_id = $1", api_id)
+            .map(|r| r.id)
+            .fetch_optional(&mut **tx)
+            .await?;
+        match did {
+            Some(did) => {
+                *r = models::StationDokumenteInner::Dokument(
+                    dokument_by_id_scoped(did, scope, tx).await?,
+                )
+            }
+            None => {
+                tracing::warn!("Referenced Dokument `{}` was not found", api_id);
+                crate::utils::spec_ext::mark_unresolved(reference);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// expands the dokumente and stellungnahmen of all stationen of the vorgang
+pub async fn expand_vorgang_dokumente(
+    vorgang: &mut models::Vorgang,
+    scope: APIScope,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    for station in vorgang.stationen.iter_mut() {
+        expand_dokument_refs(&mut station.dokumente, scope, tx).await?;
+        if let Some(stln) = station.stellungnahmen.as_mut() {
+            expand_dokument_refs(stln, scope, tx).await?;
+        }
+    }
+    Ok(())
+}
+
+/// expands the dokumente of the sitzung and of its tops
+pub async fn expand_sitzung_dokumente(
+    sitzung: &mut models::Sitzung,
+    scope: APIScope,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<()> {
+    if let Some(doks) = sitzung.dokumente.as_mut() {
+        expand_dokument_refs(doks, scope, tx).await?;
+    }
+    for top in sitzung.tops.iter_mut() {
+        if let Some(doks) = top.dokumente.as_mut() {
+            expand_dokument_refs(doks, scope, tx).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test_vorgang_sort {
+    use uuid::Uuid;
+
+    use super::{SortDirection, SortField, VGGetParameters, parse_sort, vorgang_by_parameter};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_parse_sort() {
+        assert_eq!(
+            parse_sort("zp_last_update:desc, wahlperiode:asc,titel").unwrap(),
+            vec![
+                (SortField::ZpLastUpdate, SortDirection::Desc),
+                (SortField::Wahlperiode, SortDirection::Asc),
+                (SortField::Titel, SortDirection::Asc)
+            ]
+        );
+        assert!(parse_sort("").unwrap().is_empty());
+        assert_eq!(
+            parse_sort("titel:desc,titel:asc").unwrap(),
+            vec![(SortField::Titel, SortDirection::Desc)]
+        );
+        assert!(parse_sort("id:asc").is_err());
+        assert!(parse_sort("titel:up").is_err());
+        assert!(parse_sort("titel; DROP TABLE vorgang").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_vorgang_sorting() {
+        let scenario = TestSetup::new("test_vorgang_sorting").await;
+        let server = &scenario.server;
+        // (titel, wahlperiode, year of the station)
+        let specs = [("Alpha", 20, 2001), ("Beta", 19, 2003), ("Gamma", 20, 2002)];
+        let mut api_ids = vec![];
+        for (seed, (titel, wp, year)) in specs.iter().enumerate() {
+            let mut vg = generate::random::vorgang(100 + seed as u64);
+            vg.titel = titel.to_string();
+            vg.wahlperiode = *wp;
+            for station in vg.stationen.iter_mut() {
+                station.zp_start = chrono::NaiveDate::from_ymd_opt(*year, 1, 1)
+                    .unwrap()
+                    .and_hms_opt(0, 0, 0)
+                    .unwrap()
+                    .and_utc();
+            }
+            run_integration(&vg, Uuid::nil(), 1, server).await.unwrap();
+            api_ids.push(vg.api_id);
+        }
+        let cases = [
+            ("", vec![1, 2, 0]),
+            ("zp_last_update:asc", vec![0, 2, 1]),
+            ("titel:desc", vec![2, 1, 0]),
+            ("wahlperiode:asc,titel:desc", vec![1, 2, 0]),
+            ("wahlperiode:desc,zp_last_update:asc", vec![0, 2, 1]),
+        ];
+        for (sort, expected) in cases {
+            let mut tx = server.sqlx_db.begin().await.unwrap();
+            let params = VGGetParameters {
+                lower_date: None,
+                upper_date: None,
+                parlament: None,
+                wp: None,
+                inipsn: None,
+                iniorg: None,
+                inifch: None,
+                vgtyp: None,
+                schlagwort: None,
+                schlagwort_any: None,
+                min_meinung: None,
+                max_meinung: None,
+                trojanergefahr_min: None,
+                modified_since: None,
+                dok_schlagwort: None,
+                initiator_fraktion: None,
+                created_since: None,
+                created_until: None,
+                sort: parse_sort(sort).unwrap(),
+            };
+            let (_, result) = vorgang_by_parameter(params, None, None, &mut tx)
+                .await
+                .unwrap();
+            tx.rollback().await.unwrap();
+            let order: Vec<_> = result.iter().map(|v| v.api_id).collect();
+            let expected: Vec<_> = expected.iter().map(|i| api_ids[*i]).collect();
+            assert_eq!(order, expected, "sort `{sort}`");
+        }
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,195 @@
+//! Plausibility checks of incoming data against reference tables.
+//! These produce warnings instead of errors, since scraped data may
+//! legitimately precede the reference data.
+
+use openapi::models;
+
+use crate::Result;
+
+/// checks that the start of a station lies within the boundaries of the
+/// given wahlperiode of its parliament. Parliaments or periods not present
+/// in `wahlperiode_boundaries` are not checked.
+pub async fn validate_station(
+    station: &models::Station,
+    wahlperiode: u32,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<String>> {
+    let parlament = station.gremium.parlament.to_string();
+    let boundary = sqlx::query!(
+        "SELECT wb.beginn, wb.ende FROM wahlperiode_boundaries wb
+        INNER JOIN parlament p ON p.id = wb.parl
+        WHERE p.value = $1 AND wb.wp = $2",
+        parlament,
+        wahlperiode as i32
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(boundary) = boundary else {
+        return Ok(vec![]);
+    };
+    let start = station.zp_start.date_naive();
+    if start < boundary.beginn || boundary.ende.is_some_and(|ende| start > ende) {
+        return Ok(vec![format!(
+            "Station {:?} starts on {} which is outside of Wahlperiode {} of {} ({} - {})",
+            station.api_id,
+            start,
+            wahlperiode,
+            parlament,
+            boundary.beginn,
+            boundary
+                .ende
+                .map(|e| e.to_string())
+                .unwrap_or("heute".to_string())
+        )]);
+    }
+    Ok(vec![])
+}
+
+/// checks that the identifier types of a vorgang are used by the parliaments of its
+/// stations, see `parl_ident_typ_allowed`. Parliaments without entries are not checked.
+pub async fn validate_ident_typen(
+    vorgang: &models::Vorgang,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<String>> {
+    let Some(ids) = vorgang.ids.as_ref().filter(|ids| !ids.is_empty()) else {
+        return Ok(vec![]);
+    };
+    let parlamente: Vec<String> = vorgang
+        .stationen
+        .iter()
+        .map(|s| s.gremium.parlament.to_string())
+        .collect();
+    let typen: Vec<String> = ids.iter().map(|id| id.typ.to_string()).collect();
+    let disallowed = sqlx::query!(
+        "SELECT DISTINCT p.value as parl, t.value as typ
+        FROM UNNEST($1::text[]) AS par(value)
+        CROSS JOIN UNNEST($2::text[]) AS ty(value)
+        INNER JOIN parlament p ON p.value = par.value
+        INNER JOIN vg_ident_typ t ON t.value = ty.value
+        WHERE EXISTS (SELECT 1 FROM parl_ident_typ_allowed a WHERE a.parl = p.id)
+        AND NOT EXISTS (SELECT 1 FROM parl_ident_typ_allowed a WHERE a.parl = p.id AND a.typ = t.id)
+        ORDER BY p.value, t.value",
+        &parlamente[..],
+        &typen[..]
+    )
+    .map(|r| format!("{}/{}", r.parl, r.typ))
+    .fetch_all(&mut **tx)
+    .await?;
+    if disallowed.is_empty() {
+        return Ok(vec![]);
+    }
+    Ok(vec![format!(
+        "Vorgang {} uses identifier types not used by its parliament (parlament/typ): {}",
+        vorgang.api_id,
+        disallowed.join(", ")
+    )])
+}
+
+/// checks all stations of a vorgang against the wahlperiode of the vorgang
+/// and its identifier types against the parliaments of the stations
+pub async fn validate_vorgang(
+    vorgang: &models::Vorgang,
+    tx: &mut sqlx::PgTransaction<'_>,
+) -> Result<Vec<String>> {
+    let mut warnings = vec![];
+    for station in vorgang.stationen.iter() {
+        warnings.extend(validate_station(station, vorgang.wahlperiode, tx).await?);
+    }
+    warnings.extend(validate_ident_typen(vorgang, tx).await?);
+    Ok(warnings)
+}
+
+#[cfg(test)]
+mod test_validate {
+    use openapi::models;
+
+    use super::{validate_ident_typen, validate_station};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_wahlperiode_boundaries() {
+        let scenario = TestSetup::new("test_wahlperiode_boundaries").await;
+        let mut tx = scenario.server.sqlx_db.begin().await.unwrap();
+        let station = |zp: &str, parlament: models::Parlament| models::Station {
+            zp_start: chrono::DateTime::parse_from_rfc3339(zp).unwrap().to_utc(),
+            gremium: models::Gremium {
+                parlament,
+                ..generate::default_gremium()
+            },
+            ..generate::default_station()
+        };
+        let inside = station("2022-05-01T12:00:00Z", models::Parlament::Bt);
+        assert!(
+            validate_station(&inside, 20, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        let outside = station("2010-05-01T12:00:00Z", models::Parlament::Bt);
+        assert_eq!(
+            validate_station(&outside, 20, &mut tx).await.unwrap().len(),
+            1
+        );
+
+        // ongoing wahlperiode has no end
+        let ongoing = station("2030-05-01T12:00:00Z", models::Parlament::Bt);
+        assert!(
+            validate_station(&ongoing, 21, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+
+        // no boundaries known for this parliament
+        let unknown = station("2010-05-01T12:00:00Z", models::Parlament::Br);
+        assert!(
+            validate_station(&unknown, 20, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_ident_typ_per_parlament() {
+        let scenario = TestSetup::new("test_ident_typ_per_parlament").await;
+        let mut tx = scenario.server.sqlx_db.begin().await.unwrap();
+        let vorgang = |parlament: models::Parlament, typ: models::VgIdentTyp| models::Vorgang {
+            ids: Some(vec![models::VgIdent {
+                id: "12345".to_string(),
+                typ,
+            }]),
+            stationen: vec![models::Station {
+                gremium: models::Gremium {
+                    parlament,
+                    ..generate::default_gremium()
+                },
+                ..generate::default_station()
+            }],
+            ..generate::default_vorgang()
+        };
+        let bt_vorgnr = vorgang(models::Parlament::Bt, models::VgIdentTyp::Vorgnr);
+        assert!(
+            validate_ident_typen(&bt_vorgnr, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        let by_vorgnr = vorgang(models::Parlament::By, models::VgIdentTyp::Vorgnr);
+        let warnings = validate_ident_typen(&by_vorgnr, &mut tx).await.unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("BY/vorgnr"), "{}", warnings[0]);
+        let by_initdrucks = vorgang(models::Parlament::By, models::VgIdentTyp::Initdrucks);
+        assert!(
+            validate_ident_typen(&by_initdrucks, &mut tx)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
@@ -32,8 +32,8 @@ pub enum DataValidationError {
     #[snafu(display("Incomplete Data supplied: Expected to find `{input}` in DB but didn't"))]
     IncompleteDataSupplied { input: String },
 
-    #[snafu(display("Duplicate API ID: {id}"))]
-    DuplicateApiId { id: Uuid },
+    #[snafu(display("Duplicate API ID: {api_id} is already used by a {existing_type}"))]
+    DuplicateApiId { existing_type: String, api_id: Uuid },
 
     #[snafu(display("Ambiguous match found: {message}"))]
     AmbiguousMatch { message: String },
@@ -46,6 +46,21 @@ pub enum DataValidationError {
 
     #[snafu(display(""))]
     QueryParametersNotSatisfied,
+
+    #[snafu(display("Invalid query parameter `{parameter}`: {message}"))]
+    InvalidQueryParameter { parameter: String, message: String },
+
+    #[snafu(display("Inline content could not be processed: {message}"))]
+    InlineContent { message: String },
+
+    #[snafu(display("Value `{typ_debug}` has no string representation: {context}"))]
+    TypeString { typ_debug: String, context: String },
+
+    #[snafu(display("IP_NOT_ALLOWED: {ip} is not within the allowed networks of key {keytag}"))]
+    IpNotAllowed { ip: String, keytag: String },
+
+    #[snafu(display("Stationen {stationen:?} have Abstimmungen and cannot be removed"))]
+    AbstimmungenOrphaned { stationen: Vec<Uuid> },
 }
 
 error_from!(uuid::Error, Validation, DataValidationError, UuidParse);
@@ -64,7 +79,6 @@ pub enum DatabaseError {
     },
 }
 
-error_from!(sqlx::Error, Database, DatabaseError, Sqlx);
 error_from!(
     sqlx::migrate::MigrateError,
     Database,
@@ -138,10 +152,98 @@ pub enum LTZFError {
     HeaderConversion {
         source: Box<axum::http::header::ToStrError>,
     },
+    #[snafu(display("Transaction conflicted with concurrent writes {attempts} times, giving up"))]
+    Conflict { attempts: u32 },
+
+    #[snafu(display(
+        "Constraint `{}` violated (SQLSTATE {code}): {}",
+        constraint.as_deref().unwrap_or("unknown"),
+        detail.as_deref().unwrap_or_default()
+    ))]
+    DatabaseConstraint {
+        code: String,
+        constraint: Option<String>,
+        table: Option<String>,
+        detail: Option<String>,
+    },
+
     #[allow(clippy::box_collection)]
     #[snafu(display("{message}"))]
     Other { message: Box<String> },
 }
+impl LTZFError {
+    /// true if the database aborted the transaction because of a concurrent write (SQLSTATE 40001)
+    pub fn is_serialization_failure(&self) -> bool {
+        match self {
+            LTZFError::Database { source } => match &**source {
+                DatabaseError::Sqlx {
+                    source: sqlx::Error::Database(db),
+                } => db.code().as_deref() == Some("40001"),
+                _ => false,
+            },
+            _ => false,
+        }
+    }
+    /// true if a query parameter cannot be used, which is answered with 400 Bad Request
+    pub fn is_bad_request(&self) -> bool {
+        match self {
+            LTZFError::Validation { source } => {
+                matches!(**source, DataValidationError::InvalidQueryParameter { .. })
+            }
+            _ => false,
+        }
+    }
+    /// true if the submission contradicts data that already exists, which is answered with 409 Conflict
+    pub fn is_conflict(&self) -> bool {
+        match self {
+            LTZFError::Validation { source } => {
+                matches!(**source, DataValidationError::AbstimmungenOrphaned { .. })
+            }
+            _ => false,
+        }
+    }
+    /// true if the submitted object is well-formed but its content cannot be processed,
+    /// which is answered with 422 Unprocessable Entity
+    pub fn is_unprocessable(&self) -> bool {
+        match self {
+            LTZFError::Validation { source } => matches!(
+                **source,
+                DataValidationError::InlineContent { .. } | DataValidationError::TypeString { .. }
+            ),
+            _ => false,
+        }
+    }
+}
+/// SQLSTATEs of constraint violations: unique (23505), foreign key (23503) and check (23514)
+pub const CONSTRAINT_VIOLATION_CODES: [&str; 3] = ["23505", "23503", "23514"];
+
+/// constraint violations are split off, since they are caused by the request
+/// (e.g. two scrapers submitting the same object) and are reported as client errors
+impl From<sqlx::Error> for LTZFError {
+    fn from(source: sqlx::Error) -> Self {
+        if let sqlx::Error::Database(db) = &source {
+            if let Some(code) = db
+                .code()
+                .filter(|c| CONSTRAINT_VIOLATION_CODES.contains(&c.as_ref()))
+            {
+                let detail = db
+                    .try_downcast_ref::<sqlx::postgres::PgDatabaseError>()
+                    .and_then(|pg| pg.detail())
+                    .unwrap_or(db.message())
+                    .to_string();
+                return Self::DatabaseConstraint {
+                    code: code.to_string(),
+                    constraint: db.constraint().map(str::to_string),
+                    table: db.table().map(str::to_string),
+                    detail: Some(detail),
+                };
+            }
+        }
+        Self::Database {
+            source: Box::new(DatabaseError::Sqlx { source }),
+        }
+    }
+}
 impl From<DataValidationError> for LTZFError {
     fn from(source: DataValidationError) -> Self {
         Self::Validation {
@@ -179,3 +281,131 @@ impl LTZFError {
         }
     }
 }
+
+#[cfg(test)]
+mod test_error {
+    use super::{DataValidationError, LTZFError};
+    use crate::db::insert::{insert_sitzung, insert_vorgang};
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[tokio::test]
+    async fn test_unique_violation_is_constraint_error() {
+        let scenario = TestSetup::new("test_unique_violation_is_constraint_error").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        insert_vorgang(&vorgang, uuid::Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let error = insert_vorgang(&vorgang, uuid::Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap_err();
+        match error {
+            LTZFError::DatabaseConstraint {
+                code,
+                constraint,
+                table,
+                detail,
+            } => {
+                assert_eq!(code, "23505");
+                assert_eq!(constraint.as_deref(), Some("vorgang_api_id_key"));
+                assert_eq!(table.as_deref(), Some("vorgang"));
+                let detail = detail.unwrap();
+                assert!(detail.contains(&vorgang.api_id.to_string()), "{detail}");
+            }
+            e => panic!("Expected a constraint violation, got {e}"),
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_foreign_key_violation_is_constraint_error() {
+        let scenario = TestSetup::new("test_foreign_key_violation_is_constraint_error").await;
+        let mut tx = scenario.server.sqlx_db.begin().await.unwrap();
+        let error: LTZFError =
+            sqlx::query!("INSERT INTO rel_sitzung_doks(sid, did) VALUES (-1, -1)")
+                .execute(&mut *tx)
+                .await
+                .unwrap_err()
+                .into();
+        match error {
+            LTZFError::DatabaseConstraint { code, table, .. } => {
+                assert_eq!(code, "23503");
+                assert_eq!(table.as_deref(), Some("rel_sitzung_doks"));
+            }
+            e => panic!("Expected a constraint violation, got {e}"),
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_unknown_enum_value_is_constraint_error() {
+        let scenario = TestSetup::new("test_unknown_enum_value_is_constraint_error").await;
+        let mut tx = scenario.server.sqlx_db.begin().await.unwrap();
+        let error: LTZFError = sqlx::query!(
+            "INSERT INTO vorgang(api_id, titel, wahlperiode, verfaend, typ)
+            VALUES ($1, 'Titel', 20, false, (SELECT id FROM vorgangstyp WHERE value = 'unbekannt'))",
+            uuid::Uuid::now_v7()
+        )
+        .execute(&mut *tx)
+        .await
+        .unwrap_err()
+        .into();
+        match error {
+            LTZFError::DatabaseConstraint {
+                code, constraint, ..
+            } => {
+                assert_eq!(code, "23514");
+                assert_eq!(constraint.as_deref(), Some("vorgang_typ_known"));
+            }
+            e => panic!("Expected a constraint violation, got {e}"),
+        }
+        tx.rollback().await.unwrap();
+
+        let mut tx = scenario.server.sqlx_db.begin().await.unwrap();
+        let error: LTZFError = sqlx::query!("INSERT INTO stationstyp(value) VALUES (' sonstig')")
+            .execute(&mut *tx)
+            .await
+            .unwrap_err()
+            .into();
+        assert!(matches!(
+            error,
+            LTZFError::DatabaseConstraint { ref code, .. } if code == "23514"
+        ));
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_api_id_unique_across_types() {
+        let scenario = TestSetup::new("test_api_id_unique_across_types").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let mut sitzung = generate::default_sitzung();
+        sitzung.api_id = Some(vorgang.api_id);
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        insert_vorgang(&vorgang, uuid::Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap();
+        let error = insert_sitzung(&sitzung, uuid::Uuid::nil(), 1, &mut tx, server)
+            .await
+            .unwrap_err();
+        match error {
+            LTZFError::Validation { source } => match *source {
+                DataValidationError::DuplicateApiId {
+                    existing_type,
+                    api_id,
+                } => {
+                    assert_eq!(existing_type, "vorgang");
+                    assert_eq!(api_id, vorgang.api_id);
+                }
+                e => panic!("Expected a duplicate api_id, got {e}"),
+            },
+            e => panic!("Expected a duplicate api_id, got {e}"),
+        }
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
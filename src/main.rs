@@ -13,7 +13,9 @@ use clap::Parser;
 use error::LTZFError;
 use lettre::{SmtpTransport, transport::smtp::authentication::Credentials};
 use tokio::net::TcpListener;
-use tower_governor::{governor::GovernorConfigBuilder, key_extractor::GlobalKeyExtractor, *};
+use tower_governor::{governor::GovernorConfigBuilder, *};
+use tower_http::compression::predicate::{NotForContentType, Predicate, SizeAbove};
+use tower_http::decompression::RequestDecompressionLayer;
 use tower_http::{compression::CompressionLayer, cors, limit};
 use tracing_subscriber::layer::SubscriberExt;
 use tracing_subscriber::util::SubscriberInitExt;
@@ -41,10 +43,63 @@ pub struct Configuration {
     pub mail_sender: Option<String>,
     #[arg(long, env = "MAIL_RECIPIENT")]
     pub mail_recipient: Option<String>,
+    #[arg(
+        long,
+        env = "MAIL_TLS_MODE",
+        value_enum,
+        default_value = "tls",
+        help = "How the connection to the mail server is secured.
+        tls: implicit TLS from the start (default port 465, the previous behaviour).
+        starttls: upgrade a plain connection (default port 587).
+        none: no encryption at all, only for relays in a trusted network (default port 25)."
+    )]
+    pub mail_tls_mode: MailTlsMode,
+    #[arg(
+        long,
+        env = "MAIL_PORT",
+        help = "Port of the mail server, derived from the TLS mode if not given"
+    )]
+    pub mail_port: Option<u16>,
+    #[arg(
+        long,
+        env = "MAIL_TIMEOUT_MS",
+        help = "Timeout in ms for the connection to the mail server",
+        default_value = "10000"
+    )]
+    pub mail_timeout_ms: u64,
+    #[arg(
+        long,
+        env = "LTZF_NOTIFY_TEMPLATES",
+        help = "Directory containing the templates for notification mails, missing templates fall back to the builtin ones",
+        default_value = "notify_templates"
+    )]
+    pub notify_template_dir: String,
+    #[arg(
+        long,
+        env = "LTZF_NOTIFY_DEDUP_WINDOW_HOURS",
+        help = "Repeated notifications about the same entities are suppressed for this many hours",
+        default_value = "24"
+    )]
+    pub notify_dedup_window_hours: u32,
     #[arg(long, env = "LTZF_HOST", default_value = "0.0.0.0")]
     pub host: String,
     #[arg(long, env = "LTZF_PORT", default_value = "80")]
     pub port: u16,
+    #[arg(
+        long,
+        env = "LTZF_PUBLIC_BASE_URL",
+        help = "Base URL under which clients reach the server, e.g. https://api.ltzf.example.com.
+        Used for absolute links in Link headers, without it the links are relative to the server"
+    )]
+    pub public_base_url: Option<String>,
+    #[arg(
+        long,
+        env = "LTZF_SWAGGER_UI_URL",
+        help = "Location of the swagger-ui-dist files for /api/v2/docs.
+        Point it to a self-hosted copy where the CDN is not reachable or not wanted",
+        default_value = "https://unpkg.com/swagger-ui-dist@5"
+    )]
+    pub swagger_ui_url: String,
     #[arg(long, short, env = "DATABASE_URL", help = "URL to the database")]
     pub db_url: String,
 
@@ -57,6 +112,45 @@ pub struct Configuration {
 
     #[arg(long, env = "MERGE_TITLE_SIMILARITY", default_value = "0.8")]
     pub merge_title_similarity: f32,
+    #[arg(
+        long,
+        env = "LTZF_SIMILARITY_FUNCTION",
+        value_enum,
+        default_value = "trgm",
+        help = "String similarity used to find near-duplicate Gremien and Autoren.
+        trgm: trigram similarity (pg_trgm), robust against typos and word order, good default.
+        metaphone: phonetic comparison, catches spelling variants of names but is coarse (only 5 distinct values).
+        levenshtein: normalised edit distance, precise for short strings but slowest. Strings over 255 characters are compared by trigrams."
+    )]
+    pub similarity_function: SimilarityFunction,
+    #[arg(
+        long,
+        env = "LTZF_MERGE_MODE",
+        value_enum,
+        default_value = "conservative",
+        help = "How optional Dokument fields are merged when a scraper submits them as null.
+        conservative: the stored value is kept.
+        aggressive: the stored value is cleared, for scrapers that send null deliberately to remove stale data."
+    )]
+    pub merge_mode: MergeMode,
+    #[arg(
+        long,
+        env = "LTZF_MERGE_ISOLATION",
+        value_enum,
+        default_value = "repeatable-read",
+        help = "Transaction isolation level of merges of incoming Vorgänge and Sitzungen.
+        read-committed: fastest and never retried, but a merge may see rows committed by a concurrent merge halfway through.
+        repeatable-read: every merge works on one snapshot, concurrent updates of the same rows abort and are retried. Good default.
+        serializable: additionally detects conflicts between merges reading each other's rows, most retries under load."
+    )]
+    pub merge_isolation: IsolationLevel,
+    #[arg(
+        long,
+        env = "LTZF_DEDUP_INTERVAL_HOURS",
+        help = "Interval in hours in which Dokumente with the same drucksnr and hash are merged, 0 disables the job",
+        default_value = "6"
+    )]
+    pub dedup_interval_hours: u32,
     #[arg(
         long,
         env = "REQUEST_LIMIT_COUNT",
@@ -79,6 +173,119 @@ pub struct Configuration {
     )]
     pub per_object_scraper_log_size: u32,
 
+    #[arg(
+        long,
+        env = "LTZF_MAX_INLINE_CONTENT_MB",
+        help = "Maximum size of base64 encoded document content submitted inline, in MB",
+        default_value = "50"
+    )]
+    pub max_inline_content_mb: u32,
+
+    #[arg(
+        long,
+        env = "DB_POOL_MAX_CONNECTIONS",
+        help = "Maximum number of connections held by the database pool",
+        default_value = "5"
+    )]
+    pub db_pool_max_connections: u32,
+    #[arg(
+        long,
+        env = "DB_POOL_MIN_CONNECTIONS",
+        help = "Number of connections the database pool keeps open even when idle",
+        default_value = "1"
+    )]
+    pub db_pool_min_connections: u32,
+    #[arg(
+        long,
+        env = "DB_POOL_CONNECT_TIMEOUT_MS",
+        help = "Time in ms to wait for a connection from the database pool before failing",
+        default_value = "5000"
+    )]
+    pub db_pool_connect_timeout_ms: u64,
+    #[arg(
+        long,
+        env = "DB_POOL_IDLE_TIMEOUT_MS",
+        help = "Time in ms after which idle connections above the minimum are closed",
+        default_value = "600000"
+    )]
+    pub db_pool_idle_timeout_ms: u64,
+    #[arg(
+        long,
+        env = "DB_WARMUP_TIMEOUT_MS",
+        help = "Time in ms the startup may spend on opening the minimum number of pool connections",
+        default_value = "5000"
+    )]
+    pub db_warmup_timeout_ms: u64,
+
+    #[arg(
+        long,
+        env = "LTZF_ACCESS_LOG_LEVEL",
+        help = "Level of the per-request access log (target `access_log`), `off` disables it",
+        default_value = "info"
+    )]
+    pub access_log_level: String,
+
+    #[arg(
+        long,
+        env = "LTZF_ALLOW_REQUEST_COMPRESSION",
+        help = "Accept request bodies with a Content-Encoding of gzip, deflate, br or zstd. Otherwise they are rejected with 415",
+        default_value_t = true,
+        action = clap::ArgAction::Set
+    )]
+    pub allow_request_compression: bool,
+    #[arg(
+        long,
+        env = "LTZF_COMPRESS_MIN_SIZE_BYTES",
+        help = "Responses smaller than this are sent uncompressed",
+        default_value = "1024"
+    )]
+    pub compress_min_size_bytes: u16,
+    #[arg(
+        long,
+        env = "LTZF_CACHE_TTL_SECONDS",
+        help = "Seconds a cached GET response is served before it is fetched again",
+        default_value = "30"
+    )]
+    pub cache_ttl_seconds: u64,
+    #[arg(
+        long,
+        env = "LTZF_CACHE_MAX_ENTRIES",
+        help = "Maximum number of GET responses held in the response cache",
+        default_value = "1000"
+    )]
+    pub cache_max_entries: u64,
+    #[arg(
+        long,
+        env = "LTZF_CACHE_MAX_BODY_BYTES",
+        help = "Responses with a larger body are passed through without being cached",
+        default_value = "4194304"
+    )]
+    pub cache_max_body_bytes: u64,
+    #[arg(
+        long,
+        env = "LTZF_NOTIFY_RECONNECT_INTERVAL_MS",
+        help = "Milliseconds to wait before the cache invalidation listener reconnects to the database",
+        default_value = "5000"
+    )]
+    pub notify_reconnect_interval_ms: u64,
+    #[arg(
+        long,
+        env = "LTZF_TRUSTED_PROXY_COUNT",
+        help = "Number of reverse proxies in front of the server that append to X-Forwarded-For.
+        0 takes the socket address as client address, otherwise that position counted from the end of X-Forwarded-For.
+        The client address is rate limited and checked against the allowlists of the API keys",
+        default_value = "0"
+    )]
+    pub trusted_proxy_count: usize,
+
+    #[arg(
+        long,
+        env = "LTZF_QUARANTINE_UNKNOWN_ENUMS",
+        help = "Add unknown values of Vorgangs-, Stations-, Dokumenten- and Identifikatortypen instead of rejecting the submission.
+        The values are recorded for review under /api/v2/admin/quarantine/enums"
+    )]
+    pub quarantine_unknown_enums: bool,
+
     #[arg(
         long,
         env = "LTZF_ERROR_LOG",
@@ -99,9 +306,109 @@ pub struct Configuration {
         the server will print it's configuration considering all inputs and then exit."
     )]
     pub dump_config: bool,
+
+    #[arg(
+        long,
+        help = "Applies the pending database migrations in a transaction that is rolled back,
+        prints the result and exits instead of starting the server"
+    )]
+    pub migrations_dry_run: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SimilarityFunction {
+    #[default]
+    Trgm,
+    Metaphone,
+    Levenshtein,
+}
+impl SimilarityFunction {
+    /// the method name as understood by the `ltzf_similarity` database function
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SimilarityFunction::Trgm => "trgm",
+            SimilarityFunction::Metaphone => "metaphone",
+            SimilarityFunction::Levenshtein => "levenshtein",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MergeMode {
+    #[default]
+    Conservative,
+    Aggressive,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IsolationLevel {
+    ReadCommitted,
+    #[default]
+    RepeatableRead,
+    Serializable,
+}
+impl IsolationLevel {
+    /// the level as written in `SET TRANSACTION ISOLATION LEVEL`
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            IsolationLevel::ReadCommitted => "READ COMMITTED",
+            IsolationLevel::RepeatableRead => "REPEATABLE READ",
+            IsolationLevel::Serializable => "SERIALIZABLE",
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MailTlsMode {
+    Starttls,
+    #[default]
+    Tls,
+    None,
+}
+impl MailTlsMode {
+    pub fn default_port(&self) -> u16 {
+        match self {
+            MailTlsMode::Starttls => 587,
+            MailTlsMode::Tls => 465,
+            MailTlsMode::None => 25,
+        }
+    }
 }
 
 impl Configuration {
+    fn config_error(&self, message: String) -> LTZFError {
+        LTZFError::Infrastructure {
+            source: Box::new(error::InfrastructureError::Configuration {
+                message,
+                config: Box::new(self.clone()),
+            }),
+        }
+    }
+    /// the port of the mail server, checked for consistency with the TLS mode
+    pub fn mail_port(&self) -> Result<u16> {
+        let port = self.mail_port.unwrap_or(self.mail_tls_mode.default_port());
+        let inconsistent = match self.mail_tls_mode {
+            MailTlsMode::Starttls => port == 465,
+            MailTlsMode::Tls => port == 587 || port == 25,
+            // relays in a trusted network commonly accept plain submission on 587 as well
+            MailTlsMode::None => port == 465,
+        };
+        if inconsistent {
+            return Err(self.config_error(format!(
+                "Mail port {port} does not fit the TLS mode {:?}, port {port} is commonly used for {}",
+                self.mail_tls_mode,
+                match port {
+                    465 => "implicit TLS (MAIL_TLS_MODE=tls)",
+                    587 => "STARTTLS (MAIL_TLS_MODE=starttls)",
+                    _ => "unencrypted relaying (MAIL_TLS_MODE=none)",
+                }
+            )));
+        }
+        if self.mail_timeout_ms == 0 {
+            return Err(self.config_error("MAIL_TIMEOUT_MS must be greater than 0".into()));
+        }
+        Ok(port)
+    }
     pub async fn build_mailer(&self) -> Result<SmtpTransport> {
         if self.mail_server.is_none()
             || self.mail_user.is_none()
@@ -109,14 +416,25 @@ impl Configuration {
             || self.mail_sender.is_none()
             || self.mail_recipient.is_none()
         {
-            return Err(LTZFError::Infrastructure {
-                source: Box::new(error::InfrastructureError::Configuration {
-                    message: "Mail Configuration is incomplete".into(),
-                    config: Box::new(self.clone()),
-                }),
-            });
+            return Err(self.config_error("Mail Configuration is incomplete".into()));
         }
-        let mailer = SmtpTransport::relay(self.mail_server.as_ref().unwrap().as_str())?
+        let port = self.mail_port()?;
+        let server = self.mail_server.as_ref().unwrap().as_str();
+        let builder = match self.mail_tls_mode {
+            MailTlsMode::Starttls => SmtpTransport::starttls_relay(server)?,
+            MailTlsMode::Tls => SmtpTransport::relay(server)?,
+            MailTlsMode::None => {
+                tracing::warn!(
+                    "Mail credentials are sent unencrypted to {}:{}",
+                    server,
+                    port
+                );
+                SmtpTransport::builder_dangerous(server)
+            }
+        };
+        let mailer = builder
+            .port(port)
+            .timeout(Some(std::time::Duration::from_millis(self.mail_timeout_ms)))
             .credentials(Credentials::new(
                 self.mail_user.clone().unwrap(),
                 self.mail_password.clone().unwrap(),
@@ -128,9 +446,24 @@ impl Configuration {
         Configuration::parse()
     }
 }
-async fn init_db_conn(db_url: &str) -> Result<sqlx::PgPool> {
+/// `migrate` runs the pending migrations once the database is reachable
+async fn init_db_conn(config: &Configuration, migrate: bool) -> Result<sqlx::PgPool> {
+    let db_url = config.db_url.as_str();
+    if config.db_pool_max_connections < 3 {
+        tracing::warn!(
+            "The database pool is limited to {} connections, requests are likely to wait for a free connection",
+            config.db_pool_max_connections
+        );
+    }
     let sqlx_db = sqlx::postgres::PgPoolOptions::new()
-        .max_connections(5)
+        .max_connections(config.db_pool_max_connections)
+        .min_connections(config.db_pool_min_connections)
+        .acquire_timeout(std::time::Duration::from_millis(
+            config.db_pool_connect_timeout_ms,
+        ))
+        .idle_timeout(std::time::Duration::from_millis(
+            config.db_pool_idle_timeout_ms,
+        ))
         .connect(db_url)
         .await?;
 
@@ -159,11 +492,40 @@ async fn init_db_conn(db_url: &str) -> Result<sqlx::PgPool> {
         });
     }
     tracing::debug!("Started Database Pool");
-    sqlx::migrate!().run(&sqlx_db).await?;
-    tracing::debug!("Executed Migrations");
+    if migrate {
+        MIGRATOR.run(&sqlx_db).await?;
+        tracing::debug!("Executed Migrations");
+    }
+    warm_up_pool(&sqlx_db, config).await;
     Ok(sqlx_db)
 }
 
+/// opens the minimum number of connections at once, so the first burst of requests
+/// after a deployment does not have to wait for connections to be established
+async fn warm_up_pool(sqlx_db: &sqlx::PgPool, config: &Configuration) {
+    let acquisitions = (0..config.db_pool_min_connections).map(|_| sqlx_db.acquire());
+    let timeout = std::time::Duration::from_millis(config.db_warmup_timeout_ms);
+    match tokio::time::timeout(timeout, futures::future::join_all(acquisitions)).await {
+        Ok(connections) => {
+            let failed = connections.iter().filter(|c| c.is_err()).count();
+            if failed > 0 {
+                tracing::warn!(
+                    "Pool warm-up could not establish {failed}/{} connections",
+                    connections.len()
+                );
+            } else {
+                tracing::debug!("Pool warm-up established {} connections", connections.len());
+            }
+        }
+        Err(_) => {
+            tracing::warn!(
+                "Pool warm-up did not finish within {} ms, continuing with a cold pool",
+                config.db_warmup_timeout_ms
+            );
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv::dotenv().ok();
@@ -177,23 +539,43 @@ async fn main() -> Result<()> {
         config.error_log_path.clone().into(),
         config.object_log_path.as_ref().map(|x| x.into()),
     );
+    let access_log_directive = format!("{}={}", utils::access_log::TARGET, config.access_log_level)
+        .parse()
+        .map_err(|_| {
+            config.config_error(format!(
+                "LTZF_ACCESS_LOG_LEVEL is not a valid level: {}",
+                config.access_log_level
+            ))
+        })?;
     tracing_subscriber::registry()
         .with(logging.error_layer())
         .with(logging.object_log_layer())
         .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "RUST_LOG=info".into()),
+                .unwrap_or_else(|_| "RUST_LOG=info".into())
+                .add_directive(access_log_directive),
         )
         .with(tracing_subscriber::fmt::layer())
         .init();
 
     tracing::debug!("Configuration: {:?}", &config);
 
+    if config.migrations_dry_run {
+        let sqlx_db = init_db_conn(&config, false).await?;
+        let results = api::migrations::dry_run_pending(&sqlx_db).await?;
+        let failed = results.iter().any(|r| r.error.is_some());
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&results).expect("dry run results are serialisable")
+        );
+        exit(if failed { 1 } else { 0 });
+    }
+
     tracing::info!("Starting the Initialisation process");
     let listener = TcpListener::bind(format!("{}:{}", config.host, config.port)).await?;
 
     tracing::debug!("Started Listener");
-    let sqlx_db = init_db_conn(&config.db_url).await?;
+    let sqlx_db = init_db_conn(&config, true).await?;
 
     // Run Key Administrative Functions
 
@@ -212,10 +594,17 @@ async fn main() -> Result<()> {
     .execute(&mut *tx).await?;
 
     tx.commit().await?;
+    if config.mail_server.is_some() {
+        // an inconsistent mail setup is a configuration mistake, not a missing feature
+        config.mail_port()?;
+    }
+    crate::utils::notify::load_templates(std::path::Path::new(&config.notify_template_dir))?;
     let mailbundle = crate::utils::notify::MailBundle::new(&config).await?;
 
     let state = Arc::new(LTZFServer::new(sqlx_db, config, mailbundle, logging));
     tracing::debug!("Constructed Server State");
+    db::dedup::spawn_dedup_job(state.clone());
+    api::import::resume_import_jobs(state.clone()).await?;
 
     // Init Axum router
     let (iv, cnt) = (
@@ -226,17 +615,21 @@ async fn main() -> Result<()> {
         GovernorConfigBuilder::default()
             .const_per_second(iv)
             .const_burst_size(cnt)
-            .key_extractor(GlobalKeyExtractor)
+            .key_extractor(utils::rate_limit::ProxyAwareKeyExtractor {
+                trusted_proxy_count: state.config.trusted_proxy_count,
+            })
             .finish()
             .unwrap(),
     );
     let limiter = rl_config.limiter().clone();
+    let (batch_router, retain_batch_limits) = api::batch::router(state.clone());
     let interval = std::time::Duration::from_secs(60);
     std::thread::spawn(move || {
         loop {
             std::thread::sleep(interval);
             tracing::info!("rate limiting storage size: {}", limiter.len());
             limiter.retain_recent();
+            retain_batch_limits();
         }
     });
     let rate_limiter = GovernorLayer { config: rl_config };
@@ -252,14 +645,69 @@ async fn main() -> Result<()> {
         .br(true)
         .deflate(true)
         .gzip(true)
-        .zstd(true);
+        .zstd(true)
+        .compress_when(
+            SizeAbove::new(state.config.compress_min_size_bytes)
+                .and(NotForContentType::GRPC)
+                .and(NotForContentType::IMAGES)
+                .and(NotForContentType::SSE),
+        );
+    // decompressing outside of the body limit, so it applies to the decompressed size
+    let allow_compressed = state.config.allow_request_compression;
+    let decompression_layer = RequestDecompressionLayer::new()
+        .br(allow_compressed)
+        .deflate(allow_compressed)
+        .gzip(allow_compressed)
+        .zstd(allow_compressed);
+
+    let response_cache = utils::response_cache::ResponseCache::new(state.clone());
+    utils::response_cache::spawn_invalidation_listener(response_cache.clone());
 
     let app = openapi::server::new(state.clone())
+        .merge(api::import::router(state.clone()))
+        .merge(api::abstimmung::router(state.clone()))
+        .merge(api::aenderungen::router(state.clone()))
+        .merge(api::wahlperiode::router(state.clone()))
+        .merge(api::top::router(state.clone()))
+        .merge(api::health::router(state.clone()))
+        .merge(api::export::router(state.clone()))
+        .merge(api::migrations::router(state.clone()))
+        .merge(api::preview::router(state.clone()))
+        .merge(api::keys::router(state.clone()))
+        .merge(api::schlagworte::router(state.clone()))
+        .merge(api::projection::router(state.clone()))
+        .merge(api::station_links::router(state.clone()))
+        .merge(api::db_audit::router(state.clone()))
+        .merge(api::enumeration::router(state.clone()))
+        .merge(api::review_queue::router(state.clone()))
+        .merge(api::quarantine::router(state.clone()))
+        .merge(api::stellungnahmen::router(state.clone()))
+        .merge(api::docs::router(state.clone()))
+        .merge(api::stats::router(state.clone()))
+        .merge(api::kalender::router(state.clone()))
+        .merge(batch_router)
+        .merge(api::staleness::router(state.clone()))
+        // inside the response cache, which stores the extended responses
+        .layer(axum::middleware::from_fn(utils::spec_ext::spec_ext))
         .layer(DefaultBodyLimit::max(body_size_limit))
         .layer(request_size_limit)
+        .layer(decompression_layer)
+        // inside the rate limiter, so cache hits are limited as well
+        .layer(axum::middleware::from_fn_with_state(
+            response_cache,
+            utils::response_cache::response_cache,
+        ))
         .layer(rate_limiter)
         .layer(cors_layer)
-        .layer(compression_layer);
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            utils::touched_by::touched_by_header,
+        ))
+        // outside of touched_by, which checks keys as well
+        .layer(axum::middleware::from_fn(utils::client_addr::client_addr))
+        .layer(axum::middleware::from_fn(utils::warnings::warnings_body))
+        .layer(compression_layer)
+        .layer(axum::middleware::from_fn(utils::access_log::access_log));
 
     tracing::debug!("Constructed Router");
     tracing::info!(
@@ -268,8 +716,12 @@ async fn main() -> Result<()> {
         state.config.port
     );
     // Run the server with graceful shutdown
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await?;
+    // the socket address is the client address unless LTZF_TRUSTED_PROXY_COUNT is set
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .with_graceful_shutdown(shutdown_signal())
+    .await?;
     Ok(())
 }
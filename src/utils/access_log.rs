@@ -0,0 +1,50 @@
+//! Access logging: one structured line per HTTP request on the `access_log` target,
+//! so it can be routed or silenced independently (see `LTZF_ACCESS_LOG_LEVEL`).
+
+use axum::extract::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::utils::auth::keytag_of;
+
+/// the tracing target of the access log lines
+pub const TARGET: &str = "access_log";
+
+/// the value of the header `name`, if it is valid UTF-8
+fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers()
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn`.
+/// An incoming `X-Request-Id` is reused, otherwise a fresh id is generated.
+/// The duration covers everything up to the response being handed back to the server,
+/// streaming the body is not included.
+pub async fn access_log(request: Request, next: Next) -> Response {
+    let start = tokio::time::Instant::now();
+    let request_id = header(&request, "x-request-id").unwrap_or_else(|| Uuid::now_v7().to_string());
+    let scraper_id = header(&request, "x-scraper-id");
+    let api_key_tag = header(&request, "x-api-key").map(|key| keytag_of(&key));
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+
+    info!(
+        target: TARGET,
+        method = %method,
+        path,
+        status_code = response.status().as_u16(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        request_id,
+        scraper_id,
+        api_key_tag,
+        "request served"
+    );
+    response
+}
@@ -1,6 +1,9 @@
+use std::net::IpAddr;
+
 use rand::distr::Alphanumeric;
 use rand::{Rng, rng};
 use sha256::digest;
+use tracing::debug;
 pub(crate) fn keytag_of(thing: &str) -> String {
     thing.chars().take(16).collect()
 }
@@ -11,6 +14,34 @@ pub(crate) fn hash_secret(salt: &str, secret: &str) -> String {
     digest(salt.chars().chain(secret.chars()).collect::<String>())
 }
 
+/// the address of the client as seen by the outermost of `trusted_proxies` reverse proxies,
+/// i.e. the `trusted_proxies`th entry from the end of `X-Forwarded-For`.
+/// Entries further to the left are set by the client and cannot be trusted.
+/// Without trusted proxies, or if the entry is missing or no address, this is the `socket` address.
+pub(crate) fn client_ip(
+    headers: &axum::http::HeaderMap,
+    socket: Option<IpAddr>,
+    trusted_proxies: usize,
+) -> Option<IpAddr> {
+    if trusted_proxies == 0 {
+        return socket;
+    }
+    let forwarded = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|forwarded| {
+            let chain: Vec<&str> = forwarded.split(',').map(str::trim).collect();
+            let entry = chain.len().checked_sub(trusted_proxies).map(|i| chain[i])?;
+            entry.parse().ok()
+        });
+    if forwarded.is_none() {
+        debug!(
+            "No valid address at position {trusted_proxies} of X-Forwarded-For, falling back to the socket address {socket:?}"
+        );
+    }
+    forwarded.or(socket)
+}
+
 pub fn generate_api_key() -> String {
     let key: String = "ltzf_"
         .chars()
@@ -62,3 +93,36 @@ pub(crate) async fn find_new_key(
         }
     }
 }
+
+#[cfg(test)]
+mod test_client_ip {
+    use axum::http::{HeaderMap, HeaderValue};
+
+    use super::client_ip;
+
+    #[test]
+    fn test_client_ip_from_forwarded_chain() {
+        let socket = Some("10.0.0.1".parse().unwrap());
+        let mut headers = HeaderMap::new();
+        assert_eq!(client_ip(&headers, None, 1), None);
+        assert_eq!(client_ip(&headers, socket, 1), socket);
+        headers.insert(
+            "x-forwarded-for",
+            HeaderValue::from_static("6.6.6.6, 10.1.2.3, 192.168.0.1"),
+        );
+        assert_eq!(
+            client_ip(&headers, socket, 1),
+            Some("192.168.0.1".parse().unwrap())
+        );
+        assert_eq!(
+            client_ip(&headers, socket, 2),
+            Some("10.1.2.3".parse().unwrap())
+        );
+        assert_eq!(client_ip(&headers, socket, 4), socket);
+        // without a proxy the header is set by the client itself
+        assert_eq!(client_ip(&headers, socket, 0), socket);
+
+        headers.insert("x-forwarded-for", HeaderValue::from_static("not-an-ip"));
+        assert_eq!(client_ip(&headers, socket, 1), socket);
+    }
+}
@@ -0,0 +1,126 @@
+//! The client address of a request for the IP allowlists of API keys, see `LTZF_TRUSTED_PROXY_COUNT`.
+//!
+//! Key checks happen deep inside the generated server, which neither sees the socket address
+//! nor lets the handlers choose the body of a 401/403. The middleware therefore provides the
+//! socket address via `PEER_ADDR` and answers with `{"code": "IP_NOT_ALLOWED", ...}`
+//! if the key of the request was used from outside its allowlist (see `reject_ip`).
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, Request};
+use axum::http::{HeaderValue, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const IP_NOT_ALLOWED: &str = "IP_NOT_ALLOWED";
+
+#[derive(Clone)]
+struct Client {
+    peer: Option<IpAddr>,
+    rejection: Arc<Mutex<Option<String>>>,
+}
+
+tokio::task_local! {
+    /// socket address and allowlist rejection of the current request
+    static CLIENT: Client;
+}
+
+/// the socket address of the current request, None outside of a request
+pub fn peer_addr() -> Option<IpAddr> {
+    CLIENT.try_with(|c| c.peer).ok().flatten()
+}
+
+/// records that the key of the current request is not allowed from the client address
+pub fn reject_ip(message: String) {
+    let _ = CLIENT.try_with(|c| *c.rejection.lock().unwrap() = Some(message));
+}
+
+/// replaces a 401/403 caused by the allowlist by a 403 carrying the `IP_NOT_ALLOWED` code.
+/// Other responses are kept, e.g. a public GET that was sent with a restricted key
+fn with_rejection(response: Response, message: Option<String>) -> Response {
+    let Some(message) = message else {
+        return response;
+    };
+    if !matches!(
+        response.status(),
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.status = StatusCode::FORBIDDEN;
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::json!({ "code": IP_NOT_ALLOWED, "message": message }).to_string();
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn`
+/// outside of every layer that checks API keys
+pub async fn client_addr(request: Request, next: Next) -> Response {
+    let client = Client {
+        peer: request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ci| ci.0.ip()),
+        rejection: Arc::new(Mutex::new(None)),
+    };
+    let rejection = client.rejection.clone();
+    let response = CLIENT.scope(client, next.run(request)).await;
+    let message = rejection.lock().unwrap().take();
+    with_rejection(response, message)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::StatusCode;
+    use axum::response::Response;
+
+    use super::{CLIENT, Client, peer_addr, reject_ip, with_rejection};
+
+    #[tokio::test]
+    async fn test_with_rejection() {
+        assert_eq!(peer_addr(), None);
+        let client = Client {
+            peer: Some("10.0.0.1".parse().unwrap()),
+            rejection: Arc::new(Mutex::new(None)),
+        };
+        let rejection = client.rejection.clone();
+        CLIENT
+            .scope(client, async {
+                assert_eq!(peer_addr(), Some("10.0.0.1".parse().unwrap()));
+                reject_ip("IP_NOT_ALLOWED: 10.0.0.1".to_string());
+            })
+            .await;
+        let message = rejection.lock().unwrap().take();
+        assert!(message.is_some());
+
+        let unauthorized = || {
+            Response::builder()
+                .status(StatusCode::UNAUTHORIZED)
+                .body(axum::body::Body::empty())
+                .unwrap()
+        };
+        let response = with_rejection(unauthorized(), message.clone());
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "IP_NOT_ALLOWED");
+
+        assert_eq!(
+            with_rejection(unauthorized(), None).status(),
+            StatusCode::UNAUTHORIZED
+        );
+        let ok = with_rejection(Response::new(axum::body::Body::empty()), message);
+        assert_eq!(ok.status(), StatusCode::OK);
+    }
+}
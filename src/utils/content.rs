@@ -0,0 +1,160 @@
+use base64::Engine;
+use openapi::models;
+use scraper::Html;
+use serde_json::Value;
+
+use crate::Result;
+use crate::error::DataValidationError;
+
+/// Raw document content as submitted inline via `content_b64` of a Dokument
+/// together with the plain text extracted from it.
+#[derive(Debug, Clone)]
+pub struct InlineContent {
+    pub mime: String,
+    pub raw: Vec<u8>,
+    pub text: String,
+}
+
+fn content_error(message: String) -> crate::error::LTZFError {
+    DataValidationError::InlineContent { message }.into()
+}
+
+/// decodes base64 encoded PDF or HTML content and extracts its plain text.
+/// Content larger than `max_mb` megabytes is rejected before decoding.
+/// PDFs are parsed on the blocking thread pool, a panicking parser rejects the content.
+pub async fn extract_inline_content(content_b64: &str, max_mb: u32) -> Result<InlineContent> {
+    let max_bytes = max_mb as usize * 1024 * 1024;
+    // every four base64 characters encode three bytes
+    if content_b64.len() / 4 * 3 > max_bytes {
+        return Err(content_error(format!(
+            "Inline content exceeds the limit of {max_mb} MB"
+        )));
+    }
+    let raw = base64::engine::general_purpose::STANDARD
+        .decode(content_b64.trim())
+        .map_err(|e| content_error(format!("Content is not valid base64: {e}")))?;
+    if raw.len() > max_bytes {
+        return Err(content_error(format!(
+            "Inline content exceeds the limit of {max_mb} MB"
+        )));
+    }
+    let mime = infer::get(&raw)
+        .map(|t| t.mime_type().to_string())
+        .unwrap_or_default();
+    let (raw, text) = match mime.as_str() {
+        "application/pdf" => extract_pdf_text(raw).await?,
+        "text/html" => {
+            let source = std::str::from_utf8(&raw)
+                .map_err(|e| content_error(format!("HTML content is not valid UTF-8: {e}")))?;
+            let text = html_to_text(source);
+            (raw, text)
+        }
+        _ => {
+            return Err(content_error(format!(
+                "Unsupported content type `{mime}`, expected PDF or HTML"
+            )));
+        }
+    };
+    if text.trim().is_empty() {
+        return Err(content_error(
+            "No text could be extracted from the content".into(),
+        ));
+    }
+    Ok(InlineContent { mime, raw, text })
+}
+
+/// the content submitted inline with `dok`, if any. `content_b64` is not a field of the
+/// generated `Dokument`, so it is looked up in the submitted body by the hash of the Dokument,
+/// see `utils::spec_ext::submitted`
+pub async fn submitted_inline_content(
+    dok: &models::Dokument,
+    max_mb: u32,
+) -> Result<Option<InlineContent>> {
+    let hash = Value::String(dok.hash.clone());
+    match super::spec_ext::submitted("content_b64", |o| o.get("hash") == Some(&hash)) {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::String(content_b64)) => {
+            Ok(Some(extract_inline_content(&content_b64, max_mb).await?))
+        }
+        Some(_) => Err(content_error("content_b64 is not a string".into())),
+    }
+}
+
+/// returns the raw content together with the extracted text
+async fn extract_pdf_text(raw: Vec<u8>) -> Result<(Vec<u8>, String)> {
+    let (raw, text) = tokio::task::spawn_blocking(move || {
+        let text = std::panic::catch_unwind(|| pdf_extract::extract_text_from_mem(&raw));
+        (raw, text)
+    })
+    .await
+    .map_err(|e| content_error(format!("PDF text extraction failed: {e}")))?;
+    match text {
+        Ok(Ok(text)) => Ok((raw, text)),
+        Ok(Err(e)) => Err(content_error(format!(
+            "Could not extract text from PDF: {e}"
+        ))),
+        Err(_) => Err(content_error(
+            "PDF text extraction panicked, the PDF is malformed".into(),
+        )),
+    }
+}
+
+fn html_to_text(source: &str) -> String {
+    let document = Html::parse_document(source);
+    let body = scraper::Selector::parse("body").unwrap();
+    let root = document
+        .select(&body)
+        .next()
+        .unwrap_or(document.root_element());
+    root.text()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod test_inline_content {
+    use base64::Engine;
+
+    use super::extract_inline_content;
+
+    fn encode(s: &str) -> String {
+        base64::engine::general_purpose::STANDARD.encode(s)
+    }
+
+    #[tokio::test]
+    async fn test_extract_html() {
+        let html = "<!DOCTYPE html><html><head><title>Ignoriert</title></head>
+        <body><h1>Gesetzentwurf</h1><p>Zur Änderung der Schuppenfärbeverordnung</p></body></html>";
+        let content = extract_inline_content(&encode(html), 1).await.unwrap();
+        assert_eq!(content.mime, "text/html");
+        assert_eq!(content.raw, html.as_bytes());
+        assert_eq!(
+            content.text,
+            "Gesetzentwurf\nZur Änderung der Schuppenfärbeverordnung"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reject_invalid_content() {
+        // not base64
+        assert!(
+            extract_inline_content("this is #not# base64", 1)
+                .await
+                .is_err()
+        );
+        // neither pdf nor html
+        assert!(
+            extract_inline_content(&encode("just some plain text"), 1)
+                .await
+                .is_err()
+        );
+        // too large
+        let large = format!("<html><body>{}</body></html>", "a".repeat(1024 * 1024));
+        assert!(extract_inline_content(&encode(&large), 1).await.is_err());
+        // looks like a PDF, but cannot be parsed
+        let broken_pdf = base64::engine::general_purpose::STANDARD.encode(b"%PDF-1.7\n%garbage");
+        assert!(extract_inline_content(&broken_pdf, 1).await.is_err());
+    }
+}
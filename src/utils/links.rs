@@ -0,0 +1,70 @@
+use tracing::warn;
+
+/// normalises a URL so that equivalent spellings are stored only once:
+/// scheme and host are lowercased, trailing slashes are stripped from the path
+/// and an empty query string is removed. The root path stays `/`.
+pub fn normalise_url(url: &str) -> Result<String, url::ParseError> {
+    // the parser already lowercases the scheme and the host of http(s) URLs
+    let mut parsed = url::Url::parse(url.trim())?;
+    let path = parsed.path().trim_end_matches('/').to_string();
+    if !path.is_empty() {
+        parsed.set_path(&path);
+    }
+    if parsed.query() == Some("") {
+        parsed.set_query(None);
+    }
+    Ok(parsed.to_string())
+}
+
+/// normalised and deduplicated links, links that are no valid URL are kept as submitted
+pub fn normalise_links(links: &[String]) -> Vec<String> {
+    let mut normalised: Vec<String> = Vec::with_capacity(links.len());
+    for link in links {
+        let link = normalise_url(link).unwrap_or_else(|e| {
+            warn!("Link `{link}` is no valid URL, storing it unchanged: {e}");
+            link.clone()
+        });
+        if !normalised.contains(&link) {
+            normalised.push(link);
+        }
+    }
+    normalised
+}
+
+#[cfg(test)]
+mod test_links {
+    use super::{normalise_links, normalise_url};
+
+    #[test]
+    fn test_normalise_url() {
+        let cases = [
+            ("https://example.com/a/b", "https://example.com/a/b"),
+            ("https://example.com/a/b/", "https://example.com/a/b"),
+            ("https://example.com/a/b//", "https://example.com/a/b"),
+            ("HTTPS://Example.COM/Pfad", "https://example.com/Pfad"),
+            ("https://example.com", "https://example.com/"),
+            ("https://example.com/", "https://example.com/"),
+            ("https://example.com/a?", "https://example.com/a"),
+            ("https://example.com/a/?x=1", "https://example.com/a?x=1"),
+            ("https://example.com/a/#top", "https://example.com/a#top"),
+            (" https://example.com/a ", "https://example.com/a"),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalise_url(input).unwrap(), expected, "{input}");
+        }
+        assert!(normalise_url("kein link").is_err());
+    }
+
+    #[test]
+    fn test_normalise_links() {
+        let links = [
+            "https://example.com/a/".to_string(),
+            "https://EXAMPLE.com/a".to_string(),
+            "kein link".to_string(),
+        ];
+        assert_eq!(
+            normalise_links(&links),
+            vec!["https://example.com/a".to_string(), "kein link".to_string()]
+        );
+    }
+}
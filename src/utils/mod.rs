@@ -1,10 +1,19 @@
 use tokio::signal;
 
+pub mod access_log;
 pub(crate) mod auth;
+pub mod client_addr;
+pub mod content;
+pub mod links;
 pub mod notify;
+pub mod rate_limit;
+pub mod response_cache;
+pub mod spec_ext;
 #[cfg(test)]
 pub mod testing;
+pub mod touched_by;
 pub mod tracing;
+pub mod warnings;
 
 pub async fn shutdown_signal() {
     let ctrl_c = async {
@@ -1,23 +1,119 @@
 use std::{
     fmt::Display,
-    sync::{Arc, RwLock},
+    path::Path,
+    sync::{Arc, OnceLock, RwLock},
 };
 
-use crate::{LTZFServer, Result, error::DataValidationError};
+use crate::api::DateRange;
+use crate::db::merge::candidates::CandidateReason;
+use crate::{LTZFServer, Result, error::DataValidationError, error::LTZFError};
 use lettre::{Message, Transport, message::header::ContentType};
+use serde_json::{Value, json};
 use uuid::Uuid;
 
-#[allow(unused)]
-enum MailNotificationType {
+/// The kinds of notifications sent to the administrators.
+/// Each one is rendered from its own template in `notify_templates`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NotificationTemplate {
+    AmbiguousMatch,
     EnumAdded,
     SonstigUnwrapped,
-    AmbiguousMatch,
-    Other,
 }
+impl NotificationTemplate {
+    const ALL: [NotificationTemplate; 3] = [
+        NotificationTemplate::AmbiguousMatch,
+        NotificationTemplate::EnumAdded,
+        NotificationTemplate::SonstigUnwrapped,
+    ];
+    fn file_name(&self) -> &'static str {
+        match self {
+            NotificationTemplate::AmbiguousMatch => "ambiguous_match.txt",
+            NotificationTemplate::EnumAdded => "enum_added.txt",
+            NotificationTemplate::SonstigUnwrapped => "sonstig_unwrapped.txt",
+        }
+    }
+    /// the template compiled into the server, used if the template directory does not provide one
+    fn builtin(&self) -> &'static str {
+        match self {
+            NotificationTemplate::AmbiguousMatch => {
+                include_str!("../../notify_templates/ambiguous_match.txt")
+            }
+            NotificationTemplate::EnumAdded => {
+                include_str!("../../notify_templates/enum_added.txt")
+            }
+            NotificationTemplate::SonstigUnwrapped => {
+                include_str!("../../notify_templates/sonstig_unwrapped.txt")
+            }
+        }
+    }
+    /// subject of the digest mail collecting `count` notifications of this kind
+    fn digest_subject(&self, count: usize) -> String {
+        match self {
+            NotificationTemplate::AmbiguousMatch => {
+                format!("Found {count} ambiguous matches since last check")
+            }
+            NotificationTemplate::EnumAdded => {
+                format!("Added {count} new variants since last check")
+            }
+            NotificationTemplate::SonstigUnwrapped => {
+                format!("{count} sonstig's unwrapped since last check")
+            }
+        }
+    }
+}
+
+static TEMPLATES: OnceLock<minijinja::Environment<'static>> = OnceLock::new();
+
+fn build_environment(dir: Option<&Path>) -> Result<minijinja::Environment<'static>> {
+    let mut env = minijinja::Environment::new();
+    for template in NotificationTemplate::ALL {
+        let path = dir.map(|d| d.join(template.file_name()));
+        let source = match path {
+            Some(path) if path.exists() => std::fs::read_to_string(&path)?,
+            _ => template.builtin().to_string(),
+        };
+        env.add_template_owned(template.file_name(), source)
+            .map_err(|e| template_error(template, e))?;
+    }
+    Ok(env)
+}
+
+fn template_error(template: NotificationTemplate, e: minijinja::Error) -> LTZFError {
+    DataValidationError::InvalidFormat {
+        field: format!("notification template {}", template.file_name()),
+        message: e.to_string(),
+    }
+    .into()
+}
+
+/// loads the notification templates from `dir`, falling back to the builtin ones
+/// for every template that is not present there. Has to be called before the first
+/// notification is rendered, otherwise the builtin templates are used.
+pub fn load_templates(dir: &Path) -> Result<()> {
+    let env = build_environment(Some(dir))?;
+    if TEMPLATES.set(env).is_err() {
+        tracing::warn!("Notification templates were already loaded, ignoring {dir:?}");
+    }
+    Ok(())
+}
+
+pub fn render_template(event: &NotificationTemplate, ctx: &Value) -> Result<String> {
+    let env = match TEMPLATES.get() {
+        Some(env) => env,
+        None => {
+            let env = build_environment(None)?;
+            TEMPLATES.get_or_init(|| env)
+        }
+    };
+    env.get_template(event.file_name())
+        .and_then(|t| t.render(ctx))
+        .map_err(|e| template_error(*event, e))
+}
+
 struct Mail {
     subject: String,
     body: String,
-    tp: MailNotificationType,
+    tp: NotificationTemplate,
 }
 
 pub struct MailBundle {
@@ -66,6 +162,7 @@ impl MailBundle {
             let mailer = cm.unwrap();
             let sender = sender;
             let recipient = recipient;
+            let mut last_digest = None;
             while !*mref.read().unwrap() {
                 tick_interval.tick().await;
                 if *mref.read().unwrap() {
@@ -74,83 +171,30 @@ impl MailBundle {
                 if cclone.read().unwrap().is_empty() {
                     continue;
                 }
-                let mut ambiguous_match = vec![];
-                let mut variant_added = vec![];
-                let mut sonstig_unwrapped = vec![];
-                let mut other = vec![];
+                let mut mails: Vec<Mail> = cclone.write().unwrap().drain(..).collect();
+                mails.sort_by_key(|m| m.tp);
+                let period = DateRange {
+                    since: last_digest,
+                    until: None,
+                };
+                last_digest = Some(chrono::Utc::now());
 
-                for mail in cclone.write().unwrap().drain(..) {
-                    match mail.tp {
-                        MailNotificationType::AmbiguousMatch => ambiguous_match.push(mail),
-                        MailNotificationType::EnumAdded => variant_added.push(mail),
-                        MailNotificationType::SonstigUnwrapped => sonstig_unwrapped.push(mail),
-                        MailNotificationType::Other => other.push(mail),
-                    }
-                }
-                let (s_am, s_va, s_su, s_ot) = (
-                    ambiguous_match.len(),
-                    variant_added.len(),
-                    sonstig_unwrapped.len(),
-                    other.len(),
-                );
-
-                if s_am != 0 {
-                    let ambiguous_match_body =
-                        ambiguous_match.iter().fold("".to_string(), |a, n| {
-                            format!("{a}\n=======================\n{}\n\n{}", n.subject, n.body)
-                        });
-                    let email = Message::builder()
-                        .from(sender.clone())
-                        .to(recipient.clone())
-                        .subject(format!("Found {s_am} ambiguous matches since last check"))
-                        .header(ContentType::TEXT_PLAIN)
-                        .body(ambiguous_match_body)
-                        .unwrap();
-                    mailer.send(&email).unwrap();
-                    tracing::info!("Sent Mail about {} new ambiguos matches", s_am);
-                }
-                if s_va != 0 {
-                    let variant_added_body = variant_added.iter().fold("".to_string(), |a, n| {
-                        format!("{a}\n=======================\n{}\n\n{}", n.subject, n.body)
-                    });
-                    let email = Message::builder()
-                        .from(sender.clone())
-                        .to(recipient.clone())
-                        .subject(format!("Added {s_va} new variants since last check"))
-                        .header(ContentType::TEXT_PLAIN)
-                        .body(variant_added_body)
-                        .unwrap();
-                    mailer.send(&email).unwrap();
-                    tracing::info!("Sent Mail about {} new ambiguos matches", s_va);
-                }
-                if s_su != 0 {
-                    let sonstig_unwrapped_body =
-                        sonstig_unwrapped.iter().fold("".to_string(), |a, n| {
-                            format!("{a}\n=======================\n{}\n\n{}", n.subject, n.body)
-                        });
+                // one digest mail per notification type
+                for group in mails.chunk_by(|a, b| a.tp == b.tp) {
+                    let tp = group[0].tp;
+                    let body = group.iter().fold(
+                        format!("Benachrichtigungen {}\n", period.fmt_de()),
+                        |a, n| format!("{a}\n=======================\n{}\n\n{}", n.subject, n.body),
+                    );
                     let email = Message::builder()
                         .from(sender.clone())
                         .to(recipient.clone())
-                        .subject(format!("{s_su} sonstig's unwrapped since last check"))
+                        .subject(tp.digest_subject(group.len()))
                         .header(ContentType::TEXT_PLAIN)
-                        .body(sonstig_unwrapped_body)
+                        .body(body)
                         .unwrap();
                     mailer.send(&email).unwrap();
-                    tracing::info!("Sent Mail about {s_su} new sonstig variants");
-                }
-                if s_ot != 0 {
-                    let other_body = other.iter().fold("".to_string(), |a, n| {
-                        format!("{a}\n=======================\n{}\n\n{}", n.subject, n.body)
-                    });
-                    let email = Message::builder()
-                        .from(sender.clone())
-                        .to(recipient.clone())
-                        .subject(format!("{s_ot} Other messages since last check"))
-                        .header(ContentType::TEXT_PLAIN)
-                        .body(other_body)
-                        .unwrap();
-                    mailer.send(&email).unwrap();
-                    tracing::info!("Sent Mail about {s_ot} new other messages");
+                    tracing::info!("Sent Mail about {} new {:?} notifications", group.len(), tp);
                 }
             }
         });
@@ -160,8 +204,15 @@ impl MailBundle {
             kill,
         }))
     }
-    fn send(&self, mail: Mail) -> Result<()> {
-        self.cache.write().unwrap().push(mail);
+    /// renders the template with the given context and queues the result for the next digest mail
+    pub fn send(&self, template: NotificationTemplate, ctx: &Value) -> Result<()> {
+        let rendered = render_template(&template, ctx)?;
+        let (subject, body) = rendered.split_once('\n').unwrap_or((&rendered, ""));
+        self.cache.write().unwrap().push(Mail {
+            subject: subject.trim().to_string(),
+            body: body.to_string(),
+            tp: template,
+        });
         Ok(())
     }
 }
@@ -176,11 +227,30 @@ impl Drop for MailBundle {
 }
 
 impl LTZFServer {
-    /// guarded to String conversion
-    pub fn guard_ts<T: ToString>(&self, input: T, api_id: Uuid, object: &str) -> Result<String> {
+    /// Guarded to String conversion of the enumerations that have a `sonstig` catch-all:
+    /// `Vorgangstyp`, `VgIdentTyp`, `Stationstyp` and `Doktyp`.
+    /// `sonstig` is passed through, but the administrators are notified since it
+    /// usually means the scraper could not map a value.
+    /// A value without string representation cannot be stored and is rejected with
+    /// `TypeString`, carrying the calling location for the logs.
+    #[track_caller]
+    pub fn guard_ts<T: ToString + std::fmt::Debug>(
+        &self,
+        input: T,
+        api_id: Uuid,
+        object: &str,
+    ) -> Result<String> {
+        let location = std::panic::Location::caller();
         let temp = input.to_string();
+        if temp.is_empty() {
+            return Err(crate::error::DataValidationError::TypeString {
+                typ_debug: format!("{input:?}"),
+                context: format!("{object} {api_id} at {location}"),
+            }
+            .into());
+        }
         if temp == "sonstig" {
-            notify_unknown_variant::<T>(api_id, object, self)?
+            notify_unknown_variant::<T>(api_id, &format!("{object} ({location})"), self)?
         }
         Ok(temp)
     }
@@ -194,52 +264,96 @@ pub fn notify_new_enum_entry<T: std::fmt::Debug + Display>(
     if server.mailbundle.is_none() {
         return Ok(());
     }
-    let subject = format!(
-        "Für Typ `{}` wurde ein neuer Eintrag `{:?}` erstellt. ",
-        std::any::type_name::<T>(),
-        new_entry
+    tracing::warn!(
+        "Notify: New Enum Entry `{:?}` with {} similar entries!",
+        new_entry,
+        similarity.len()
     );
-
-    let simstr = similarity
-        .iter()
-        .map(|(p, t)| format!("{p}: {t}"))
-        .fold("".to_string(), |a, n| format!("{a}\n{n}"));
-
-    let body = format!("Es gibt {} ähnliche Einträge: {simstr}", similarity.len());
-    tracing::warn!("Notify: New Enum Entry: {subject}\n{body}!");
-    server.mailbundle.as_ref().unwrap().send(Mail {
-        subject,
-        body,
-        tp: MailNotificationType::EnumAdded,
-    })?;
+    server.mailbundle.as_ref().unwrap().send(
+        NotificationTemplate::EnumAdded,
+        &json!({
+            "type_name": std::any::type_name::<T>(),
+            "entry": format!("{new_entry:?}"),
+            "similar": similarity
+                .iter()
+                .map(|(p, t)| json!({"similarity": p, "value": t.to_string()}))
+                .collect::<Vec<_>>(),
+        }),
+    )?;
 
     Ok(())
 }
-pub fn notify_ambiguous_match<T: std::fmt::Debug + serde::Serialize>(
+/// records that a notification about `api_ids` is sent and returns false if the same
+/// notification was already sent within the dedup window.
+/// Runs on the transaction of the caller, so a merge does not wait for a second connection
+/// of the pool while it holds one.
+pub async fn should_notify(
+    event: &str,
+    api_ids: &[Uuid],
+    tx: &mut sqlx::PgTransaction<'_>,
+    server: &LTZFServer,
+) -> Result<bool> {
+    let mut ids: Vec<_> = api_ids.iter().map(|id| id.to_string()).collect();
+    ids.sort();
+    let hash = sha256::digest(format!("{event}{}", ids.join(",")));
+    let window = server.config.notify_dedup_window_hours as i32;
+    sqlx::query!(
+        "DELETE FROM notification_dedup_cache WHERE sent_at < NOW() - make_interval(hours => $1)",
+        window
+    )
+    .execute(&mut **tx)
+    .await?;
+    let fresh = sqlx::query!(
+        "INSERT INTO notification_dedup_cache(entity_hash, sent_at) VALUES ($1, NOW())
+        ON CONFLICT(entity_hash) DO UPDATE SET sent_at = NOW()
+        WHERE notification_dedup_cache.sent_at < NOW() - make_interval(hours => $2)
+        RETURNING entity_hash",
+        hash,
+        window
+    )
+    .fetch_optional(&mut **tx)
+    .await?
+    .is_some();
+    Ok(fresh)
+}
+
+/// `reasons` explains why each candidate matched and `diff` shows how the candidates differ
+/// from `object`, both are empty where no explanation is available
+pub async fn notify_ambiguous_match<T: std::fmt::Debug + serde::Serialize>(
     api_ids: Vec<Uuid>,
+    reasons: &[CandidateReason],
+    diff: &str,
     object: &T,
     during_operation: &str,
+    tx: &mut sqlx::PgTransaction<'_>,
     server: &LTZFServer,
 ) -> Result<()> {
     if server.mailbundle.is_none() {
         return Ok(());
     }
-    let subject = format!("Ambiguous Match: Während {during_operation}");
-    let body = format!(
-        "Während: `{during_operation}` wurde folgendes Objekt wurde hochgeladen: {}.
-        Folgende Objekte in der Datenbank sind ähnlich: {:#?}",
+    if !should_notify("ambiguous_match", &api_ids, tx, server).await? {
+        tracing::debug!(
+            "Suppressed repeated ambiguous match notification for {:?}",
+            api_ids
+        );
+        return Ok(());
+    }
+    let object =
         serde_json::to_string_pretty(object).map_err(|e| DataValidationError::InvalidFormat {
             field: "passed obj for ambiguous match".to_string(),
-            message: e.to_string()
-        })?,
-        api_ids
-    );
+            message: e.to_string(),
+        })?;
     tracing::error!("Notify: Ambiguous Match!");
-    server.mailbundle.as_ref().unwrap().send(Mail {
-        subject,
-        body,
-        tp: MailNotificationType::AmbiguousMatch,
-    })?;
+    server.mailbundle.as_ref().unwrap().send(
+        NotificationTemplate::AmbiguousMatch,
+        &json!({
+            "operation": during_operation,
+            "object": object,
+            "candidates": api_ids,
+            "reasons": reasons,
+            "diff": diff,
+        }),
+    )?;
     Ok(())
 }
 
@@ -247,15 +361,187 @@ pub fn notify_unknown_variant<T>(api_id: Uuid, object: &str, server: &LTZFServer
     if server.mailbundle.is_none() {
         return Ok(());
     }
-    let subject = format!(
-        "Für {object} `{api_id}` wurde `sonstig` angegeben als Wert für `{}`",
-        std::any::type_name::<T>()
-    );
     tracing::warn!("Notify: Unknown Variant in Guarded Enumeration Field");
-    server.mailbundle.as_ref().unwrap().send(Mail {
-        subject,
-        body: "".to_string(),
-        tp: MailNotificationType::SonstigUnwrapped,
-    })?;
+    server.mailbundle.as_ref().unwrap().send(
+        NotificationTemplate::SonstigUnwrapped,
+        &json!({
+            "object": object,
+            "api_id": api_id,
+            "type_name": std::any::type_name::<T>(),
+        }),
+    )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod test_notify {
+    use serde_json::json;
+    use uuid::Uuid;
+
+    use super::{NotificationTemplate, build_environment, render_template};
+    use crate::db::merge::candidates::CandidateReason;
+
+    #[test]
+    fn test_builtin_templates_render() {
+        let rendered = render_template(
+            &NotificationTemplate::AmbiguousMatch,
+            &json!({
+                "operation": "merging vorgang",
+                "object": "{}",
+                "candidates": [Uuid::nil()],
+            }),
+        )
+        .unwrap();
+        let (subject, body) = rendered.split_once('\n').unwrap();
+        assert_eq!(subject, "Ambiguous Match: Während merging vorgang");
+        assert!(body.contains(&Uuid::nil().to_string()));
+
+        let rendered = render_template(
+            &NotificationTemplate::AmbiguousMatch,
+            &json!({
+                "operation": "merging vorgang",
+                "object": "{}",
+                "candidates": [Uuid::nil()],
+                "reasons": [CandidateReason {
+                    api_id: Uuid::nil(),
+                    api_id_match: false,
+                    wp_type_ident_match: true,
+                    similarity_score: 0.5,
+                }],
+            }),
+        )
+        .unwrap();
+        assert!(rendered.contains("Titelähnlichkeit: 0.5"));
+        assert!(!rendered.contains("```"));
+
+        let rendered = render_template(
+            &NotificationTemplate::AmbiguousMatch,
+            &json!({
+                "operation": "merging vorgang",
+                "object": "{}",
+                "candidates": [Uuid::nil()],
+                "diff": "-titel: alt\n+titel: neu\n",
+            }),
+        )
+        .unwrap();
+        assert!(rendered.contains("```\n-titel: alt\n+titel: neu\n```"));
+    }
+
+    #[test]
+    fn test_template_directory_overrides() {
+        let dir = std::env::temp_dir().join(format!("ltzf-templates-{}", Uuid::now_v7()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(
+            dir.join("sonstig_unwrapped.txt"),
+            "Sonstig bei {{ api_id }}\n",
+        )
+        .unwrap();
+        let env = build_environment(Some(&dir)).unwrap();
+        let rendered = env
+            .get_template("sonstig_unwrapped.txt")
+            .unwrap()
+            .render(json!({"api_id": "abc"}))
+            .unwrap();
+        assert_eq!(rendered, "Sonstig bei abc");
+        // not overridden: builtin
+        assert!(env.get_template("ambiguous_match.txt").is_ok());
+
+        std::fs::write(dir.join("enum_added.txt"), "{% if %}").unwrap();
+        assert!(build_environment(Some(&dir)).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_guard_ts() {
+        use openapi::models;
+        let scenario = crate::utils::testing::TestSetup::new("test_guard_ts").await;
+        let server = &scenario.server;
+        let api_id = Uuid::nil();
+        // the catch-all variants pass, notifying is skipped without mailbundle
+        assert_eq!(
+            server
+                .guard_ts(models::Vorgangstyp::Sonstig, api_id, "test")
+                .unwrap(),
+            "sonstig"
+        );
+        assert_eq!(
+            server
+                .guard_ts(models::VgIdentTyp::Sonstig, api_id, "test")
+                .unwrap(),
+            "sonstig"
+        );
+        assert_eq!(
+            server
+                .guard_ts(models::Stationstyp::Sonstig, api_id, "test")
+                .unwrap(),
+            "sonstig"
+        );
+        assert_eq!(
+            server
+                .guard_ts(models::Doktyp::Sonstig, api_id, "test")
+                .unwrap(),
+            "sonstig"
+        );
+
+        #[derive(Debug)]
+        struct Unrepresentable;
+        impl std::fmt::Display for Unrepresentable {
+            fn fmt(&self, _f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                Ok(())
+            }
+        }
+        match server.guard_ts(Unrepresentable, api_id, "test") {
+            Err(crate::error::LTZFError::Validation { source }) => match *source {
+                crate::error::DataValidationError::TypeString { typ_debug, context } => {
+                    assert_eq!(typ_debug, "Unrepresentable");
+                    // the location of the caller, not of guard_ts
+                    assert!(context.contains(file!()), "{context}");
+                }
+                other => panic!("Expected TypeString, got {other:?}"),
+            },
+            other => panic!("Expected a validation error, got {other:?}"),
+        }
+        scenario.teardown().await;
+    }
+
+    #[tokio::test]
+    async fn test_notification_dedup() {
+        let scenario = crate::utils::testing::TestSetup::new("test_notification_dedup").await;
+        let server = &scenario.server;
+        let mut tx = server.sqlx_db.begin().await.unwrap();
+        let (a, b) = (Uuid::now_v7(), Uuid::now_v7());
+        assert!(
+            super::should_notify("ambiguous_match", &[a, b], &mut tx, server)
+                .await
+                .unwrap()
+        );
+        // the order of the candidates does not matter
+        assert!(
+            !super::should_notify("ambiguous_match", &[b, a], &mut tx, server)
+                .await
+                .unwrap()
+        );
+        assert!(
+            super::should_notify("ambiguous_match", &[a], &mut tx, server)
+                .await
+                .unwrap()
+        );
+        assert!(
+            super::should_notify("other_event", &[a, b], &mut tx, server)
+                .await
+                .unwrap()
+        );
+
+        sqlx::query!("UPDATE notification_dedup_cache SET sent_at = NOW() - '25 hours'::interval")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+        assert!(
+            super::should_notify("ambiguous_match", &[a, b], &mut tx, server)
+                .await
+                .unwrap()
+        );
+        tx.rollback().await.unwrap();
+        scenario.teardown().await;
+    }
+}
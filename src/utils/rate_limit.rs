@@ -0,0 +1,91 @@
+//! Keys for the rate limiter. Every client is limited by its own address,
+//! which is either the socket address or, behind reverse proxies (see `LTZF_TRUSTED_PROXY_COUNT`),
+//! the address the outermost trusted proxy saw in `X-Forwarded-For`.
+//! The IP allowlists of API keys use the same address.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use tower_governor::GovernorError;
+use tower_governor::key_extractor::KeyExtractor;
+
+#[derive(Clone, Copy, Debug)]
+pub struct ProxyAwareKeyExtractor {
+    /// number of trusted proxies appending to `X-Forwarded-For`, 0 uses the socket address
+    pub trusted_proxy_count: usize,
+}
+
+impl KeyExtractor for ProxyAwareKeyExtractor {
+    type Key = IpAddr;
+
+    fn extract<T>(&self, req: &Request<T>) -> Result<Self::Key, GovernorError> {
+        let socket = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ci| ci.0.ip());
+        super::auth::client_ip(req.headers(), socket, self.trusted_proxy_count)
+            .ok_or(GovernorError::UnableToExtractKey)
+    }
+}
+
+#[cfg(test)]
+mod test_rate_limit_key {
+    use std::net::SocketAddr;
+
+    use axum::extract::ConnectInfo;
+    use axum::http::Request;
+    use tower_governor::key_extractor::KeyExtractor;
+
+    use super::ProxyAwareKeyExtractor;
+
+    fn request(forwarded: &str) -> Request<()> {
+        let mut req = Request::builder()
+            .header("x-forwarded-for", forwarded)
+            .body(())
+            .unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo("10.0.0.1:4000".parse::<SocketAddr>().unwrap()));
+        req
+    }
+
+    #[test]
+    fn test_trusted_proxy_count() {
+        let req = request("6.6.6.6, 1.2.3.4");
+        let direct = ProxyAwareKeyExtractor {
+            trusted_proxy_count: 0,
+        };
+        assert_eq!(
+            direct.extract(&req).unwrap(),
+            "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+        );
+        let proxied = ProxyAwareKeyExtractor {
+            trusted_proxy_count: 1,
+        };
+        assert_eq!(
+            proxied.extract(&req).unwrap(),
+            "1.2.3.4".parse::<std::net::IpAddr>().unwrap()
+        );
+        let spoofable = ProxyAwareKeyExtractor {
+            trusted_proxy_count: 2,
+        };
+        assert_eq!(
+            spoofable.extract(&req).unwrap(),
+            "6.6.6.6".parse::<std::net::IpAddr>().unwrap()
+        );
+
+        // garbage or a too short chain falls back to the socket address
+        let req = request("not-an-ip");
+        assert_eq!(
+            proxied.extract(&req).unwrap(),
+            "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+        );
+        assert_eq!(
+            spoofable.extract(&req).unwrap(),
+            "10.0.0.1".parse::<std::net::IpAddr>().unwrap()
+        );
+
+        let no_socket = Request::builder().body(()).unwrap();
+        assert!(direct.extract(&no_socket).is_err());
+    }
+}
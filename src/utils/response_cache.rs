@@ -0,0 +1,425 @@
+//! In-memory caching of GET responses (see `LTZF_CACHE_TTL_SECONDS` and `LTZF_CACHE_MAX_ENTRIES`).
+//! Entries are keyed by the normalised URL and the scope of the requesting key.
+//! Requests by Admin or KeyAdder keys are never cached, since they see `touched_by`.
+//! Conditional requests, the health and version endpoints and bodies larger than
+//! `LTZF_CACHE_MAX_BODY_BYTES` bypass the cache.
+//! Any successful write invalidates the cached entries of the same entity type, writes through
+//! the side routers (TOPs, Station links, Autoren, enumerations, imports, ...) those of the
+//! Vorgänge, Sitzungen and Dokumente as well, since these show what they change.
+//! Changes made by other instances or directly in the database are picked up from the
+//! `entity_changed` notifications sent by the database triggers, see `spawn_invalidation_listener`.
+//! Every response carries an `X-Cache: HIT|MISS` header.
+
+use axum::body::{Body, Bytes, HttpBody};
+use axum::extract::{Request, State};
+use axum::http::{HeaderMap, HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use openapi::apis::ApiKeyAuthHeader;
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::api::LTZFArc;
+use crate::api::auth::{APIScope, ReadingClaims};
+
+pub const CACHE_HEADER: &str = "x-cache";
+/// postgres channel the triggers on vorgang, sitzung and dokument notify on
+pub const ENTITY_CHANGED_CHANNEL: &str = "entity_changed";
+/// POST endpoints that only read, they do not invalidate anything
+const READ_ONLY_POSTS: [&str; 2] = ["/api/v2/vorgang/batch", "/api/v2/vorgang/preview_merge"];
+/// GET endpoints reporting the live state of the server
+const UNCACHED_PATHS: [&str; 2] = ["/api/v2/health", "/api/v2/version"];
+/// the answer to these depends on the header, not only on the URL
+const CONDITIONAL_HEADERS: [&str; 2] = ["if-modified-since", "if-none-match"];
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+struct CacheKey {
+    entity: String,
+    scope: String,
+    uri: String,
+}
+
+#[derive(Clone)]
+struct CachedResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Clone)]
+pub struct ResponseCache {
+    server: LTZFArc,
+    entries: moka::future::Cache<CacheKey, CachedResponse>,
+}
+
+impl ResponseCache {
+    pub fn new(server: LTZFArc) -> Self {
+        let entries = moka::future::Cache::builder()
+            .max_capacity(server.config.cache_max_entries)
+            .time_to_live(std::time::Duration::from_secs(
+                server.config.cache_ttl_seconds,
+            ))
+            .support_invalidation_closures()
+            .build();
+        Self { server, entries }
+    }
+
+    /// drops all cached responses of the entity type
+    pub fn invalidate_entity(&self, entity: &str) {
+        debug!("Invalidating cached responses for `{entity}`");
+        let invalidated = entity.to_string();
+        if let Err(e) = self
+            .entries
+            .invalidate_entries_if(move |k, _| k.entity == invalidated)
+        {
+            warn!("Could not invalidate cached responses for `{entity}`: {e}");
+        }
+    }
+}
+
+/// payload of a notification on `ENTITY_CHANGED_CHANNEL`
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct EntityChanged {
+    #[serde(rename = "type")]
+    pub entity: String,
+    pub api_id: Option<Uuid>,
+}
+
+/// the cached entity types showing the changed entity.
+/// Dokumente are served as part of Vorgänge and Sitzungen, the statistics count Vorgänge.
+fn affected_entities(entity: &str) -> Vec<&str> {
+    match entity {
+        "dokument" => vec!["dokument", "vorgang", "sitzung"],
+        "vorgang" => vec!["vorgang", "stats"],
+        x => vec![x],
+    }
+}
+
+/// the cached entity types a successful write to `path` may change.
+/// The side routers write the tables of the parts of Vorgänge and Sitzungen, which the
+/// database triggers do not report.
+fn written_entities(path: &str) -> Vec<String> {
+    let entity = entity_of(path);
+    match entity.as_str() {
+        "vorgang" | "sitzung" | "dokument" => affected_entities(&entity)
+            .into_iter()
+            .map(str::to_string)
+            .collect(),
+        _ => ["vorgang", "sitzung", "dokument", "stats"]
+            .into_iter()
+            .map(str::to_string)
+            .chain(Some(entity))
+            .collect(),
+    }
+}
+
+/// listens for `entity_changed` notifications and invalidates the affected cached responses.
+/// If the connection is lost it is reestablished every `LTZF_NOTIFY_RECONNECT_INTERVAL_MS`,
+/// since notifications sent in the meantime are lost the whole cache is dropped on reconnect.
+pub fn spawn_invalidation_listener(cache: ResponseCache) {
+    let interval =
+        std::time::Duration::from_millis(cache.server.config.notify_reconnect_interval_ms);
+    tokio::spawn(async move {
+        let mut reconnect = false;
+        loop {
+            let mut listener =
+                match sqlx::postgres::PgListener::connect_with(&cache.server.sqlx_db).await {
+                    Ok(listener) => listener,
+                    Err(e) => {
+                        warn!("Could not connect the cache invalidation listener: {e}");
+                        tokio::time::sleep(interval).await;
+                        continue;
+                    }
+                };
+            if let Err(e) = listener.listen(ENTITY_CHANGED_CHANNEL).await {
+                warn!("Could not listen on `{ENTITY_CHANGED_CHANNEL}`: {e}");
+                tokio::time::sleep(interval).await;
+                continue;
+            }
+            if reconnect {
+                cache.entries.invalidate_all();
+            }
+            info!("Listening for changes on `{ENTITY_CHANGED_CHANNEL}`");
+            reconnect = true;
+            loop {
+                let notification = match listener.try_recv().await {
+                    Ok(Some(notification)) => notification,
+                    Ok(None) => {
+                        warn!("Lost the connection of the cache invalidation listener");
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("Cache invalidation listener failed: {e}");
+                        break;
+                    }
+                };
+                match serde_json::from_str::<EntityChanged>(notification.payload()) {
+                    Ok(changed) => {
+                        debug!("Received change of {} {:?}", changed.entity, changed.api_id);
+                        for entity in affected_entities(&changed.entity) {
+                            cache.invalidate_entity(entity);
+                        }
+                    }
+                    Err(e) => warn!(
+                        "Malformed notification `{}` on `{ENTITY_CHANGED_CHANNEL}`: {e}",
+                        notification.payload()
+                    ),
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// the entity type a path belongs to, e.g. `vorgang` for `/api/v2/vorgang/{id}`.
+/// The kalender is a view on the sitzungen.
+fn entity_of(path: &str) -> String {
+    let entity = path
+        .trim_start_matches("/api/v2/")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    match entity {
+        "kalender" => "sitzung".to_string(),
+        x => x.to_string(),
+    }
+}
+
+/// path without trailing slash and the query parameters in sorted order
+fn normalised_uri(request: &Request) -> String {
+    let path = request.uri().path().trim_end_matches('/');
+    let mut query: Vec<&str> = request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .collect();
+    query.sort_unstable();
+    format!("{path}?{}", query.join("&"))
+}
+
+/// GET requests that are always passed on to the handler
+fn bypasses_cache(request: &Request) -> bool {
+    let uncached = UNCACHED_PATHS.contains(&request.uri().path().trim_end_matches('/'));
+    let conditional = CONDITIONAL_HEADERS
+        .iter()
+        .any(|h| request.headers().contains_key(*h));
+    uncached || conditional
+}
+
+fn with_cache_header(mut response: Response, value: &'static str) -> Response {
+    response
+        .headers_mut()
+        .insert(CACHE_HEADER, HeaderValue::from_static(value));
+    response
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn_with_state`
+pub async fn response_cache(
+    State(cache): State<ResponseCache>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    if method != Method::GET {
+        let path = request.uri().path().trim_end_matches('/').to_string();
+        let response = next.run(request).await;
+        let read_only = method == Method::HEAD
+            || method == Method::OPTIONS
+            || (method == Method::POST && READ_ONLY_POSTS.contains(&path.as_str()));
+        if !read_only && response.status().is_success() {
+            for entity in written_entities(&path) {
+                cache.invalidate_entity(&entity);
+            }
+        }
+        return with_cache_header(response, "MISS");
+    }
+    if bypasses_cache(&request) {
+        return with_cache_header(next.run(request).await, "MISS");
+    }
+
+    let scope = if request.headers().contains_key("x-api-key") {
+        // already authenticated by the `touched_by` middleware for some paths
+        let claims = match request.extensions().get::<ReadingClaims>() {
+            Some(ReadingClaims(claims)) => *claims,
+            None => {
+                cache
+                    .server
+                    .extract_claims_from_header(request.headers(), "x-api-key")
+                    .await
+            }
+        };
+        match claims {
+            Some((APIScope::Collector, _)) => APIScope::Collector.to_string(),
+            // Admin and KeyAdder as well as invalid keys bypass the cache
+            _ => return with_cache_header(next.run(request).await, "MISS"),
+        }
+    } else {
+        "anonymous".to_string()
+    };
+    let key = CacheKey {
+        entity: entity_of(request.uri().path()),
+        scope,
+        uri: normalised_uri(&request),
+    };
+
+    if let Some(cached) = cache.entries.get(&key).await {
+        let mut response = Response::new(Body::from(cached.body));
+        *response.status_mut() = cached.status;
+        *response.headers_mut() = cached.headers;
+        return with_cache_header(response, "HIT");
+    }
+
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return with_cache_header(response, "MISS");
+    }
+    let (parts, body) = response.into_parts();
+    let max_body = cache.server.config.cache_max_body_bytes;
+    if body.size_hint().upper().is_none_or(|size| size > max_body) {
+        debug!("Response body is too large or of unknown size, not caching it");
+        return with_cache_header(Response::from_parts(parts, body), "MISS");
+    }
+    let body = match axum::body::to_bytes(body, max_body as usize).await {
+        Ok(body) => body,
+        Err(e) => {
+            warn!("Could not buffer response for caching: {e}");
+            return with_cache_header(
+                Response::builder()
+                    .status(StatusCode::INTERNAL_SERVER_ERROR)
+                    .body(Body::empty())
+                    .unwrap(),
+                "MISS",
+            );
+        }
+    };
+    cache
+        .entries
+        .insert(
+            key,
+            CachedResponse {
+                status: parts.status,
+                headers: parts.headers.clone(),
+                body: body.clone(),
+            },
+        )
+        .await;
+    with_cache_header(Response::from_parts(parts, Body::from(body)), "MISS")
+}
+
+#[cfg(test)]
+mod test {
+    use uuid::Uuid;
+
+    use axum::body::Body;
+    use axum::extract::Request;
+
+    use super::{
+        ENTITY_CHANGED_CHANNEL, EntityChanged, affected_entities, bypasses_cache, entity_of,
+        written_entities,
+    };
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_entity_of() {
+        assert_eq!(entity_of("/api/v2/vorgang"), "vorgang");
+        assert_eq!(entity_of("/api/v2/vorgang/abc"), "vorgang");
+        assert_eq!(entity_of("/api/v2/kalender/bt/2024-01-01"), "sitzung");
+        assert_eq!(entity_of("/api/v2/sitzung/abc"), "sitzung");
+        assert_eq!(entity_of("/api/v2/stats/vorgaenge"), "stats");
+    }
+
+    #[test]
+    fn test_bypasses_cache() {
+        let request = |uri: &str, header: Option<&str>| {
+            let mut builder = Request::builder().uri(uri);
+            if let Some(header) = header {
+                builder = builder.header(header, "Wed, 21 Oct 2015 07:28:00 GMT");
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+        assert!(!bypasses_cache(&request("/api/v2/vorgang?page=2", None)));
+        assert!(bypasses_cache(&request("/api/v2/health", None)));
+        assert!(bypasses_cache(&request("/api/v2/version/", None)));
+        assert!(bypasses_cache(&request(
+            "/api/v2/vorgang",
+            Some("If-Modified-Since")
+        )));
+        assert!(bypasses_cache(&request(
+            "/api/v2/kalender",
+            Some("if-none-match")
+        )));
+    }
+
+    #[test]
+    fn test_affected_entities() {
+        assert_eq!(affected_entities("vorgang"), vec!["vorgang", "stats"]);
+        assert_eq!(affected_entities("sitzung"), vec!["sitzung"]);
+        assert_eq!(
+            affected_entities("dokument"),
+            vec!["dokument", "vorgang", "sitzung"]
+        );
+    }
+
+    #[test]
+    fn test_written_entities() {
+        assert_eq!(
+            written_entities("/api/v2/vorgang"),
+            vec!["vorgang", "stats"]
+        );
+        assert_eq!(
+            written_entities("/api/v2/vorgang/abc/stellungnahmen"),
+            vec!["vorgang", "stats"]
+        );
+        assert_eq!(
+            written_entities("/api/v2/kalender/BT/2024-01-01"),
+            vec!["sitzung"]
+        );
+        // side routers change what Vorgänge and Sitzungen show
+        for path in [
+            "/api/v2/top/abc/1",
+            "/api/v2/station/abc/links",
+            "/api/v2/autoren",
+            "/api/v2/enumeration/schlagworte",
+        ] {
+            let entities = written_entities(path);
+            for shown in ["vorgang", "sitzung", "dokument", "stats"] {
+                assert!(entities.iter().any(|e| e == shown), "{path}: {entities:?}");
+            }
+        }
+        assert!(written_entities("/api/v2/top/abc/1").contains(&"top".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_entity_changed_notification() {
+        let scenario = TestSetup::new("test_entity_changed_notification").await;
+        let server = &scenario.server;
+        let mut listener = sqlx::postgres::PgListener::connect_with(&server.sqlx_db)
+            .await
+            .unwrap();
+        listener.listen(ENTITY_CHANGED_CHANNEL).await.unwrap();
+
+        let vorgang = generate::default_vorgang();
+        run_integration(&vorgang, Uuid::nil(), 1, server)
+            .await
+            .unwrap();
+        let mut received = vec![];
+        while let Ok(notification) =
+            tokio::time::timeout(std::time::Duration::from_secs(1), listener.recv()).await
+        {
+            let changed: EntityChanged =
+                serde_json::from_str(notification.unwrap().payload()).unwrap();
+            received.push(changed);
+        }
+        assert!(received.contains(&EntityChanged {
+            entity: "vorgang".to_string(),
+            api_id: Some(vorgang.api_id),
+        }));
+        assert!(received.iter().any(|c| c.entity == "dokument"));
+        drop(listener);
+        scenario.teardown().await;
+    }
+}
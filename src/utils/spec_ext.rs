@@ -0,0 +1,482 @@
+//! Query parameters and response fields of the generated endpoints that are not part of the
+//! pinned specification yet, see the end of `api/openapi-extensions.yml`.
+//!
+//! The generated server ignores query parameters it does not know and serialises only the
+//! fields of its models. The middleware keeps the query string of the request in `EXTENSIONS`,
+//! handlers parse their additional parameters from it with `query`.
+//! Headers the generated header parameters lack are read with `header`.
+//! The JSON bodies of PUT requests to the paths in `SUBMITTING_PATHS` are kept as well, fields
+//! the generated models drop are read from them with `submitted`.
+//! The list endpoints in `PARLAMENT_LISTS` accept several Parlamente in `p`, which the
+//! generated server would reject, so it is removed from the query the generated server sees.
+//! Response fields the generated models lack are recorded with `extend_object` and written into
+//! the objects with the same `api_id` in the JSON body of the response.
+//! A status code the generated responses do not have is set with `set_status`, it replaces the
+//! status of a successful response.
+//! Dokument references a handler could not resolve are recorded with `mark_unresolved` and
+//! replaced by `{"error": "not_found", "api_id": ...}` in the JSON body of the response.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::Result;
+use crate::error::DataValidationError;
+
+/// keys of the lists holding Dokument references
+const REFERENCE_LISTS: [&str; 2] = ["dokumente", "stellungnahmen"];
+/// PUT endpoints whose bodies are kept for `submitted`, by path prefix
+const SUBMITTING_PATHS: [&str; 6] = [
+    "/api/v2/vorgang",
+    "/api/v2/autoren",
+    "/api/v2/dokument/",
+    "/api/v2/sitzung/",
+    "/api/v2/kalender/",
+    "/api/v2/enumeration/",
+];
+
+/// GET endpoints whose `p` parameter may be repeated or comma separated
+const PARLAMENT_LISTS: [&str; 2] = ["/api/v2/vorgang", "/api/v2/sitzung"];
+
+tokio::task_local! {
+    static EXTENSIONS: Arc<Extensions>;
+}
+
+#[derive(Debug, Default)]
+struct Extensions {
+    query: String,
+    headers: HeaderMap,
+    /// the submitted JSON body, see `SUBMITTING_PATHS`
+    body: Option<Value>,
+    response: Mutex<ResponseExtensions>,
+}
+
+/// what the handler of a request recorded for its response
+#[derive(Debug, Default)]
+pub struct ResponseExtensions {
+    unresolved: HashSet<String>,
+    /// additional fields by the `api_id` of the object they belong to
+    fields: HashMap<Uuid, Map<String, Value>>,
+    status: Option<StatusCode>,
+}
+
+impl ResponseExtensions {
+    fn is_empty(&self) -> bool {
+        self.unresolved.is_empty() && self.fields.is_empty()
+    }
+
+    /// the status replacing the one of a successful response
+    pub fn status(&self) -> Option<StatusCode> {
+        self.status
+    }
+
+    /// writes the recorded extensions into a JSON body
+    pub fn apply(&self, body: &mut Value) {
+        match body {
+            Value::Object(object) => {
+                let fields = object
+                    .get("api_id")
+                    .and_then(Value::as_str)
+                    .and_then(|api_id| Uuid::parse_str(api_id).ok())
+                    .and_then(|api_id| self.fields.get(&api_id));
+                if let Some(fields) = fields {
+                    object.extend(fields.clone());
+                }
+                for (key, value) in object.iter_mut() {
+                    if let (true, Value::Array(refs)) =
+                        (REFERENCE_LISTS.contains(&key.as_str()), &mut *value)
+                    {
+                        self.replace_unresolved(refs);
+                    }
+                    self.apply(value);
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(|v| self.apply(v)),
+            _ => {}
+        }
+    }
+
+    fn replace_unresolved(&self, refs: &mut [Value]) {
+        for r in refs.iter_mut() {
+            if let Value::String(api_id) = r {
+                if self.unresolved.contains(api_id.as_str()) {
+                    *r = serde_json::json!({ "error": "not_found", "api_id": api_id });
+                }
+            }
+        }
+    }
+}
+
+/// the additional query parameters of the current request.
+/// Outside of a request (e.g. in import jobs) none are set
+pub fn query<T: DeserializeOwned + Default>() -> Result<T> {
+    let Ok(query) = EXTENSIONS.try_with(|e| e.query.clone()) else {
+        return Ok(T::default());
+    };
+    serde_html_form::from_str(&query).map_err(|e| {
+        DataValidationError::InvalidQueryParameter {
+            parameter: query,
+            message: e.to_string(),
+        }
+        .into()
+    })
+}
+
+/// the value of the header `name` of the current request, if it is valid UTF-8.
+/// Outside of a request it is None
+pub fn header(name: &str) -> Option<String> {
+    EXTENSIONS
+        .try_with(|e| {
+            e.headers
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string)
+        })
+        .ok()
+        .flatten()
+}
+
+/// the value of `field` in the first object of the submitted body that has the field and
+/// satisfies `matching`. Outside of a request or for bodies that are not kept it is None
+pub fn submitted(field: &str, matching: impl Fn(&Map<String, Value>) -> bool) -> Option<Value> {
+    EXTENSIONS
+        .try_with(|e| {
+            e.body
+                .as_ref()
+                .and_then(|body| find_field(body, field, &matching))
+        })
+        .ok()
+        .flatten()
+}
+
+fn find_field(
+    value: &Value,
+    field: &str,
+    matching: &impl Fn(&Map<String, Value>) -> bool,
+) -> Option<Value> {
+    match value {
+        Value::Object(object) => object
+            .get(field)
+            .filter(|_| matching(object))
+            .cloned()
+            .or_else(|| object.values().find_map(|v| find_field(v, field, matching))),
+        Value::Array(values) => values.iter().find_map(|v| find_field(v, field, matching)),
+        _ => None,
+    }
+}
+
+/// records a Dokument reference that does not resolve to a stored Dokument,
+/// outside of a request it is dropped
+pub fn mark_unresolved(api_id: &str) {
+    let _ = EXTENSIONS.try_with(|e| {
+        e.response
+            .lock()
+            .unwrap()
+            .unresolved
+            .insert(api_id.to_string())
+    });
+}
+
+/// records `field` of the object with `api_id` in the response,
+/// outside of a request it is dropped
+pub fn extend_object(api_id: Uuid, field: &str, value: impl Serialize) {
+    let value = match serde_json::to_value(value) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Could not serialise `{field}` of {api_id}: {e}");
+            return;
+        }
+    };
+    let _ = EXTENSIONS.try_with(|e| {
+        e.response
+            .lock()
+            .unwrap()
+            .fields
+            .entry(api_id)
+            .or_default()
+            .insert(field.to_string(), value)
+    });
+}
+
+/// moves what was recorded so far into `body`, for handlers that transform their JSON
+/// response themselves. Outside of a request nothing is recorded
+pub fn apply_recorded(body: &mut Value) {
+    let Ok(recorded) = EXTENSIONS.try_with(|e| std::mem::take(&mut *e.response.lock().unwrap()))
+    else {
+        return;
+    };
+    recorded.apply(body);
+}
+
+/// answers the request with `status` instead of the status of the generated success response,
+/// outside of a request it is dropped
+pub fn set_status(status: StatusCode) {
+    let _ = EXTENSIONS.try_with(|e| e.response.lock().unwrap().status = Some(status));
+}
+
+fn keeps_body(method: &Method, path: &str) -> bool {
+    method == Method::PUT && SUBMITTING_PATHS.iter().any(|p| path.starts_with(p))
+}
+
+/// removes `p` from the query of the list endpoints in `PARLAMENT_LISTS`,
+/// the handlers read it from the kept query
+fn strip_parlamente(request: &mut Request) {
+    let path = request.uri().path();
+    if request.method() != Method::GET || !PARLAMENT_LISTS.contains(&path) {
+        return;
+    }
+    let Some(Ok(pairs)) = request
+        .uri()
+        .query()
+        .map(serde_html_form::from_str::<Vec<(String, String)>>)
+    else {
+        return;
+    };
+    let kept: Vec<_> = pairs.into_iter().filter(|(name, _)| name != "p").collect();
+    let uri = match serde_html_form::to_string(&kept) {
+        Ok(query) if !query.is_empty() => format!("{path}?{query}"),
+        _ => path.to_string(),
+    };
+    match uri.parse() {
+        Ok(uri) => *request.uri_mut() = uri,
+        Err(e) => warn!("Could not remove the Parlamente from the query: {e}"),
+    }
+}
+
+/// the extensions of `request`, whose body is buffered if it is kept
+async fn capture(mut request: Request) -> std::result::Result<(Extensions, Request), StatusCode> {
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let headers = request.headers().clone();
+    strip_parlamente(&mut request);
+    if !keeps_body(request.method(), request.uri().path()) {
+        let extensions = Extensions {
+            query,
+            headers,
+            ..Default::default()
+        };
+        return Ok((extensions, request));
+    }
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.map_err(|e| {
+        warn!("Could not buffer the submitted body: {e}");
+        StatusCode::PAYLOAD_TOO_LARGE
+    })?;
+    // bodies that are no valid JSON are rejected by the handler
+    let extensions = Extensions {
+        query,
+        headers,
+        body: serde_json::from_slice(&bytes).ok(),
+        ..Default::default()
+    };
+    Ok((extensions, Request::from_parts(parts, Body::from(bytes))))
+}
+
+/// runs `future` as if it handled `request` and returns what it recorded for the response
+#[cfg(test)]
+pub(crate) async fn with_request<F: Future>(
+    request: Request,
+    future: F,
+) -> (F::Output, ResponseExtensions) {
+    let (extensions, _) = capture(request)
+        .await
+        .expect("test requests can be buffered");
+    let extensions = Arc::new(extensions);
+    let output = EXTENSIONS.scope(extensions.clone(), future).await;
+    let recorded = std::mem::take(&mut *extensions.response.lock().unwrap());
+    (output, recorded)
+}
+
+/// writes the recorded extensions into the JSON body of a successful response
+async fn with_extensions(mut response: Response, recorded: ResponseExtensions) -> Response {
+    if let (Some(status), true) = (recorded.status, response.status().is_success()) {
+        *response.status_mut() = status;
+    }
+    if recorded.is_empty() || response.status() != StatusCode::OK {
+        return response;
+    }
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            warn!("Could not buffer the response to extend it: {e}");
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    recorded.apply(&mut value);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn`
+pub async fn spec_ext(request: Request, next: Next) -> Response {
+    let (extensions, request) = match capture(request).await {
+        Ok(captured) => captured,
+        Err(status) => return status.into_response(),
+    };
+    let extensions = Arc::new(extensions);
+    let response = EXTENSIONS
+        .scope(extensions.clone(), next.run(request))
+        .await;
+    let recorded = std::mem::take(&mut *extensions.response.lock().unwrap());
+    with_extensions(response, recorded).await
+}
+
+#[cfg(test)]
+mod test {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use uuid::Uuid;
+
+    use super::{extend_object, header, mark_unresolved, query, submitted, with_request};
+
+    #[derive(Debug, Default, Deserialize, PartialEq)]
+    struct Params {
+        expand_dokumente: Option<bool>,
+    }
+
+    #[tokio::test]
+    async fn test_query() {
+        // outside of a request nothing is set
+        assert_eq!(query::<Params>().unwrap(), Params::default());
+        let request = |uri: &str| Request::builder().uri(uri).body(Body::empty()).unwrap();
+        let (params, _) = with_request(
+            request("/api/v2/vorgang/x?page=2&expand_dokumente=true"),
+            async { query::<Params>() },
+        )
+        .await;
+        assert_eq!(params.unwrap().expand_dokumente, Some(true));
+        let (params, _) = with_request(request("/api/v2/vorgang/x?expand_dokumente=ja"), async {
+            query::<Params>()
+        })
+        .await;
+        assert!(params.unwrap_err().is_bad_request());
+    }
+
+    #[tokio::test]
+    async fn test_header() {
+        assert_eq!(header("x-merge-candidate-id"), None);
+        let request = Request::builder()
+            .uri("/api/v2/vorgang")
+            .header("X-Merge-Candidate-Id", "abc")
+            .body(Body::empty())
+            .unwrap();
+        let (found, _) = with_request(request, async {
+            (header("x-merge-candidate-id"), header("x-scraper-id"))
+        })
+        .await;
+        assert_eq!(found, (Some("abc".to_string()), None));
+    }
+
+    #[test]
+    fn test_strip_parlamente() {
+        let uri_seen = |uri: &str| {
+            let mut request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+            super::strip_parlamente(&mut request);
+            request.uri().to_string()
+        };
+        assert_eq!(
+            uri_seen("/api/v2/vorgang?p=BT&wp=20&p=BR,BY"),
+            "/api/v2/vorgang?wp=20"
+        );
+        assert_eq!(uri_seen("/api/v2/sitzung?p=BT"), "/api/v2/sitzung");
+        // other endpoints keep their single Parlament
+        assert_eq!(uri_seen("/api/v2/kalender?p=BT"), "/api/v2/kalender?p=BT");
+    }
+
+    #[tokio::test]
+    async fn test_submitted() {
+        let body = json!({
+            "api_id": "vg",
+            "stationen": [{ "dokumente": [
+                { "hash": "a", "content_b64": "YQ==" },
+                { "hash": "b", "content_b64": "Yg==" }
+            ] }]
+        });
+        let request = |method: &str, uri: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let content_of = |hash: &'static str| {
+            submitted("content_b64", move |o| o.get("hash") == Some(&json!(hash)))
+        };
+        let (found, _) = with_request(request("PUT", "/api/v2/vorgang"), async {
+            (content_of("b"), content_of("c"))
+        })
+        .await;
+        assert_eq!(found, (Some(json!("Yg==")), None));
+        // only the bodies of the listed PUT endpoints are kept
+        let (found, _) = with_request(request("POST", "/api/v2/vorgang/batch"), async {
+            content_of("b")
+        })
+        .await;
+        assert_eq!(found, None);
+        assert_eq!(content_of("b"), None);
+    }
+
+    #[tokio::test]
+    async fn test_apply_unresolved() {
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let ((), recorded) = with_request(request, async { mark_unresolved("weg") }).await;
+        let mut body = json!({
+            "api_id": "vg",
+            "stationen": [{ "dokumente": ["weg", { "api_id": "da" }], "stellungnahmen": ["da"] }],
+            "links": ["weg"]
+        });
+        recorded.apply(&mut body);
+        assert_eq!(
+            body,
+            json!({
+                "api_id": "vg",
+                "stationen": [{
+                    "dokumente": [{ "error": "not_found", "api_id": "weg" }, { "api_id": "da" }],
+                    "stellungnahmen": ["da"]
+                }],
+                "links": ["weg"]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_apply_fields() {
+        let (vg, st) = (Uuid::now_v7(), Uuid::now_v7());
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let ((), recorded) = with_request(request, async {
+            extend_object(vg, "trojanergefahr_max", Some(4));
+            extend_object(st, "created_at", "2025-03-02T00:00:00Z");
+        })
+        .await;
+        let mut body =
+            json!([{ "api_id": vg.to_string(), "stationen": [{ "api_id": st.to_string() }] }]);
+        recorded.apply(&mut body);
+        assert_eq!(
+            body,
+            json!([{
+                "api_id": vg.to_string(),
+                "trojanergefahr_max": 4,
+                "stationen": [{ "api_id": st.to_string(), "created_at": "2025-03-02T00:00:00Z" }]
+            }])
+        );
+    }
+}
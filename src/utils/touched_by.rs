@@ -0,0 +1,223 @@
+//! Exposes which scrapers last touched a Vorgang or Sitzung as `X-Touched-By: uuid1, uuid2`,
+//! most recent first. Only Admin and KeyAdder keys see it, like the `touched_by` body field.
+//! Since it is a header, scrapers can check for conflicts with a HEAD request.
+//!
+//! The generated handlers do not see the key of GET requests, so for the paths above,
+//! `GET /api/v2/vorgang` and `/api/v2/dokument/{api_id}` the middleware tells the handler via
+//! `PRIVILEGED` whether to include admin-only fields (`touched_by`, `created_at`) in the body.
+//! Handlers that look up the scrapers for the body hand them on with `record_touched_by`,
+//! so the header does not need a second query.
+//! The key is authenticated here once, see `auth::ReadingClaims`.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::{Request, State};
+use axum::http::{HeaderValue, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use openapi::apis::ApiKeyAuthHeader;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::Result;
+use crate::api::LTZFArc;
+use crate::api::auth::{APIScope, ReadingClaims};
+
+pub const TOUCHED_BY_HEADER: &str = "x-touched-by";
+/// list endpoint whose Vorgänge carry `touched_by` for privileged keys
+const VORGANG_LIST: &str = "/api/v2/vorgang";
+/// Dokumente carry admin-only fields as well, but have no scraper log for the header
+const DOKUMENT_PREFIX: &str = "/api/v2/dokument/";
+
+tokio::task_local! {
+    /// whether the current request was made with an Admin or KeyAdder key
+    pub(crate) static PRIVILEGED: bool;
+    /// the scrapers of the requested entity, as looked up by its handler
+    static TOUCHED: Arc<Mutex<Option<Vec<Uuid>>>>;
+}
+
+/// true inside a request made with an Admin or KeyAdder key, see `PRIVILEGED`
+pub fn is_privileged() -> bool {
+    PRIVILEGED.try_with(|p| *p).unwrap_or(false)
+}
+
+/// hands the scrapers of the requested entity, most recent first, on to the header.
+/// Outside of a request they are dropped
+pub fn record_touched_by(scrapers: Vec<Uuid>) {
+    let _ = TOUCHED.try_with(|t| *t.lock().unwrap() = Some(scrapers));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TouchedEntity {
+    Vorgang,
+    Sitzung,
+}
+
+/// the entity of `/api/v2/vorgang/{vorgang_id}` and `/api/v2/sitzung/{sid}`, None for other paths
+fn entity_of(path: &str) -> Option<(TouchedEntity, Uuid)> {
+    let mut segments = path
+        .strip_prefix("/api/v2/")?
+        .trim_end_matches('/')
+        .split('/');
+    let entity = match segments.next()? {
+        "vorgang" => TouchedEntity::Vorgang,
+        "sitzung" => TouchedEntity::Sitzung,
+        _ => return None,
+    };
+    let api_id = Uuid::from_str(segments.next()?).ok()?;
+    if segments.next().is_some() {
+        return None;
+    }
+    Some((entity, api_id))
+}
+
+/// the scrapers in the log of the entity, most recent first
+pub async fn touched_by(
+    entity: TouchedEntity,
+    api_id: Uuid,
+    executor: impl sqlx::PgExecutor<'_>,
+) -> Result<Vec<Uuid>> {
+    let scrapers = match entity {
+        TouchedEntity::Vorgang => {
+            sqlx::query!(
+                "SELECT stv.scraper FROM scraper_touched_vorgang stv
+                INNER JOIN vorgang v ON v.id = stv.vg_id
+                WHERE v.api_id = $1 ORDER BY stv.time_stamp DESC",
+                api_id
+            )
+            .map(|r| r.scraper)
+            .fetch_all(executor)
+            .await?
+        }
+        TouchedEntity::Sitzung => {
+            sqlx::query!(
+                "SELECT sts.scraper FROM scraper_touched_sitzung sts
+                INNER JOIN sitzung s ON s.id = sts.sid
+                WHERE s.api_id = $1 ORDER BY sts.time_stamp DESC",
+                api_id
+            )
+            .map(|r| r.scraper)
+            .fetch_all(executor)
+            .await?
+        }
+    };
+    Ok(scrapers)
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn_with_state`
+pub async fn touched_by_header(
+    State(server): State<LTZFArc>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let reading = request.method() == Method::GET || request.method() == Method::HEAD;
+    let entity = entity_of(request.uri().path());
+    let path = request.uri().path();
+    let scoped = path.trim_end_matches('/') == VORGANG_LIST || path.starts_with(DOKUMENT_PREFIX);
+    if !reading || !(entity.is_some() || scoped) || !request.headers().contains_key("x-api-key") {
+        return next.run(request).await;
+    }
+    let claims = server
+        .extract_claims_from_header(request.headers(), "x-api-key")
+        .await;
+    request.extensions_mut().insert(ReadingClaims(claims));
+    let privileged = matches!(claims, Some((APIScope::Admin | APIScope::KeyAdder, _)));
+    let touched = Arc::new(Mutex::new(None));
+    let mut response = PRIVILEGED
+        .scope(
+            privileged,
+            TOUCHED.scope(touched.clone(), next.run(request)),
+        )
+        .await;
+    let Some((entity, api_id)) = entity else {
+        return response;
+    };
+    if !privileged
+        || !(response.status() == StatusCode::OK || response.status() == StatusCode::NOT_MODIFIED)
+    {
+        return response;
+    }
+    let recorded = touched.lock().unwrap().take();
+    let scrapers = match recorded {
+        Some(scrapers) => Ok(scrapers),
+        // e.g. not modified, the handler did not look them up
+        None => touched_by(entity, api_id, &server.sqlx_db).await,
+    };
+    match scrapers {
+        Ok(scrapers) if !scrapers.is_empty() => {
+            let value = scrapers
+                .iter()
+                .map(Uuid::to_string)
+                .collect::<Vec<_>>()
+                .join(", ");
+            response.headers_mut().insert(
+                TOUCHED_BY_HEADER,
+                HeaderValue::from_str(&value).expect("uuids are valid header values"),
+            );
+        }
+        Ok(_) => {}
+        Err(e) => warn!("Could not look up the scrapers of {api_id}: {e}"),
+    }
+    response
+}
+
+#[cfg(test)]
+mod test_touched_by {
+    use uuid::Uuid;
+
+    use std::sync::{Arc, Mutex};
+
+    use super::{TOUCHED, TouchedEntity, entity_of, record_touched_by, touched_by};
+    use crate::db::merge::execute::run_integration;
+    use crate::utils::testing::{TestSetup, generate};
+
+    #[test]
+    fn test_entity_of() {
+        let id = Uuid::now_v7();
+        assert_eq!(
+            entity_of(&format!("/api/v2/vorgang/{id}")),
+            Some((TouchedEntity::Vorgang, id))
+        );
+        assert_eq!(
+            entity_of(&format!("/api/v2/sitzung/{id}/")),
+            Some((TouchedEntity::Sitzung, id))
+        );
+        assert_eq!(entity_of(&format!("/api/v2/vorgang/{id}/aendert")), None);
+        assert_eq!(entity_of("/api/v2/vorgang/batch"), None);
+        assert_eq!(entity_of(&format!("/api/v2/dokument/{id}")), None);
+    }
+
+    #[tokio::test]
+    async fn test_record_touched_by() {
+        let scraper = Uuid::now_v7();
+        // outside of a request nothing is recorded
+        record_touched_by(vec![scraper]);
+        let touched = Arc::new(Mutex::new(None));
+        TOUCHED
+            .scope(touched.clone(), async { record_touched_by(vec![scraper]) })
+            .await;
+        assert_eq!(touched.lock().unwrap().take(), Some(vec![scraper]));
+    }
+
+    #[tokio::test]
+    async fn test_touched_by() {
+        let scenario = TestSetup::new("test_touched_by").await;
+        let server = &scenario.server;
+        let vorgang = generate::default_vorgang();
+        let (first, second) = (Uuid::now_v7(), Uuid::now_v7());
+        run_integration(&vorgang, first, 1, server).await.unwrap();
+        run_integration(&vorgang, second, 1, server).await.unwrap();
+        let scrapers = touched_by(TouchedEntity::Vorgang, vorgang.api_id, &server.sqlx_db)
+            .await
+            .unwrap();
+        assert_eq!(scrapers, vec![second, first]);
+        assert!(
+            touched_by(TouchedEntity::Sitzung, vorgang.api_id, &server.sqlx_db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        scenario.teardown().await;
+    }
+}
@@ -0,0 +1,133 @@
+//! Plausibility warnings (see `db::validate`) are returned to the submitter of a Vorgang
+//! as `{"warnings": [...]}` in the body of a successful `PUT /api/v2/vorgang` or
+//! `PUT /api/v2/vorgang/{vorgang_id}`.
+//!
+//! The generated responses of these endpoints carry no body, so the handlers record the
+//! warnings in `WARNINGS` via `push_warning` and the middleware writes the body.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::{HeaderValue, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::Response;
+use uuid::Uuid;
+
+tokio::task_local! {
+    /// warnings of the current request, only set for the endpoints in `collects_warnings`
+    static WARNINGS: Arc<Mutex<Vec<String>>>;
+}
+
+/// records a warning for the response of the current request, outside of a
+/// request collecting warnings (e.g. in import jobs) it is dropped
+pub fn push_warning(warning: String) {
+    let _ = WARNINGS.try_with(|w| w.lock().unwrap().push(warning));
+}
+
+/// runs `future` with its own warning collection and returns the warnings pushed by it
+#[cfg(test)]
+pub(crate) async fn collect_warnings<F: Future>(future: F) -> (F::Output, Vec<String>) {
+    let collected = Arc::new(Mutex::new(vec![]));
+    let output = WARNINGS.scope(collected.clone(), future).await;
+    let warnings = std::mem::take(&mut *collected.lock().unwrap());
+    (output, warnings)
+}
+
+fn collects_warnings(method: &Method, path: &str) -> bool {
+    if method != Method::PUT {
+        return false;
+    }
+    match path.trim_end_matches('/').strip_prefix("/api/v2/vorgang") {
+        Some("") => true,
+        Some(rest) => rest
+            .strip_prefix('/')
+            .is_some_and(|id| Uuid::from_str(id).is_ok()),
+        None => false,
+    }
+}
+
+/// replaces the (empty) body of a successful response by the warnings
+fn with_warnings(response: Response, warnings: Vec<String>) -> Response {
+    if warnings.is_empty() || !matches!(response.status(), StatusCode::OK | StatusCode::CREATED) {
+        return response;
+    }
+    let (mut parts, _) = response.into_parts();
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = serde_json::json!({ "warnings": warnings }).to_string();
+    Response::from_parts(parts, Body::from(body))
+}
+
+/// axum middleware, to be installed with `axum::middleware::from_fn`
+pub async fn warnings_body(request: Request, next: Next) -> Response {
+    if !collects_warnings(request.method(), request.uri().path()) {
+        return next.run(request).await;
+    }
+    let collected = Arc::new(Mutex::new(vec![]));
+    let response = WARNINGS.scope(collected.clone(), next.run(request)).await;
+    let warnings = std::mem::take(&mut *collected.lock().unwrap());
+    with_warnings(response, warnings)
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::{Method, StatusCode};
+    use axum::response::Response;
+
+    use super::{WARNINGS, collects_warnings, push_warning, with_warnings};
+
+    #[test]
+    fn test_collects_warnings() {
+        let id = uuid::Uuid::now_v7();
+        assert!(collects_warnings(&Method::PUT, "/api/v2/vorgang"));
+        assert!(collects_warnings(
+            &Method::PUT,
+            &format!("/api/v2/vorgang/{id}")
+        ));
+        assert!(!collects_warnings(&Method::GET, "/api/v2/vorgang"));
+        assert!(!collects_warnings(&Method::PUT, "/api/v2/vorgang/batch"));
+        assert!(!collects_warnings(&Method::PUT, "/api/v2/sitzung"));
+    }
+
+    #[tokio::test]
+    async fn test_with_warnings() {
+        let collected = Arc::new(Mutex::new(vec![]));
+        WARNINGS
+            .scope(collected.clone(), async {
+                push_warning("Station außerhalb der Wahlperiode".to_string());
+            })
+            .await;
+        // outside of a scope warnings are dropped
+        push_warning("verworfen".to_string());
+        let warnings = collected.lock().unwrap().clone();
+        assert_eq!(warnings.len(), 1);
+
+        let created = Response::builder()
+            .status(StatusCode::CREATED)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = with_warnings(created, warnings.clone());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({ "warnings": warnings }));
+
+        let not_modified = Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = with_warnings(not_modified, warnings);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert!(body.is_empty());
+    }
+}